@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use sds011::emulator::Emulator;
+use sds011::observer::Observer;
+use sds011::{Transport, SDS011};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// Endless stream of the same data frame, as seen by a listener
+struct Replay {
+    frame: [u8; 10],
+    pos: usize,
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for b in buf.iter_mut() {
+            *b = self.frame[self.pos];
+            self.pos = (self.pos + 1) % self.frame.len();
+        }
+        Ok(buf.len())
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Replay {
+    fn set_timeout(&mut self, _timeout: Duration) -> sds011::Result<()> {
+        Ok(())
+    }
+}
+
+fn sensor() -> SDS011 {
+    let mut emulator = Emulator::new(0xa160);
+    emulator.set_reading(12.3, 45.6);
+    SDS011::from_transport(Box::new(emulator)).unwrap()
+}
+
+fn roundtrip(c: &mut Criterion) {
+    let mut s = sensor();
+    c.bench_function("query", |b| b.iter(|| black_box(s.query().unwrap())));
+
+    let mut s = sensor();
+    c.bench_function("set_work_period", |b| {
+        b.iter(|| s.set_work_period(black_box(5)).unwrap())
+    });
+
+    let mut s = sensor();
+    c.bench_function("device_id", |b| {
+        b.iter(|| black_box(s.device_id().unwrap()))
+    });
+}
+
+fn parse(c: &mut Criterion) {
+    let frame = [0xaa, 0xc0, 0x7b, 0x00, 0xc8, 0x01, 0xa1, 0x60, 0x45, 0xab];
+    let mut observer = Observer::from_transport(Box::new(Replay { frame, pos: 0 }));
+    c.bench_function("observer_frame", |b| {
+        b.iter(|| black_box(observer.next_frame().unwrap()))
+    });
+}
+
+criterion_group!(benches, roundtrip, parse);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+//! Generates the gRPC service from `proto/sds011.proto` in builds with the
+//! `grpc` feature. The proto is compiled with protox, no `protoc` needed.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/sds011.proto");
+        let descriptors = protox::compile(["proto/sds011.proto"], ["proto"])
+            .expect("can't compile proto/sds011.proto");
+        // Without `connect()`, which needs the 2021 prelude's `TryInto`
+        tonic_build::configure()
+            .build_transport(false)
+            .compile_fds(descriptors)
+            .expect("can't generate the gRPC service");
+    }
+}
@@ -0,0 +1,238 @@
+//! Windowed statistics over measurement streams.
+//!
+//! Windows are based on measurement timestamps, so messages must be
+//! pushed in time order.
+
+use crate::Message;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Minimum, maximum and mean of a single pollutant
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Summary {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+/// Statistics of a window
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Stats {
+    /// Window start in UNIX seconds, inclusive
+    pub start: u64,
+    /// Window end in UNIX seconds, exclusive
+    pub end: u64,
+    /// Number of measurements in the window
+    pub count: usize,
+    /// PM2.5 statistics
+    pub pm25: Summary,
+    /// PM10 statistics
+    pub pm10: Summary,
+}
+
+/// Running min/max/sum of one pollutant
+#[derive(Debug, Clone, Copy)]
+struct Acc {
+    min: f32,
+    max: f32,
+    sum: f64,
+}
+
+impl Acc {
+    fn new(v: f32) -> Acc {
+        Acc {
+            min: v,
+            max: v,
+            sum: v as f64,
+        }
+    }
+
+    fn add(&mut self, v: f32) {
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        self.sum += v as f64;
+    }
+
+    fn summary(&self, count: usize) -> Summary {
+        Summary {
+            min: self.min,
+            max: self.max,
+            mean: (self.sum / count as f64) as f32,
+        }
+    }
+}
+
+/// Computes statistics of `messages` within `[start, end)`
+/// Returns `None` if there are no messages
+pub fn stats<'a, I>(messages: I, start: u64, end: u64) -> Option<Stats>
+where
+    I: IntoIterator<Item = &'a Message>,
+{
+    let mut iter = messages.into_iter();
+    let first = iter.next()?;
+    let mut pm25 = Acc::new(first.pm25.value());
+    let mut pm10 = Acc::new(first.pm10.value());
+    let mut count = 1;
+
+    for m in iter {
+        pm25.add(m.pm25.value());
+        pm10.add(m.pm10.value());
+        count += 1;
+    }
+
+    Some(Stats {
+        start,
+        end,
+        count,
+        pm25: pm25.summary(count),
+        pm10: pm10.summary(count),
+    })
+}
+
+/// Non-overlapping windows aligned to multiples of the window width,
+/// e.g. an hourly window always starts at the top of the hour
+///
+/// # Example
+/// ```
+/// use sds011::aggregate::Tumbling;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let mut hourly = Tumbling::new(Duration::from_secs(3600));
+/// let m = |t: u64, pm: f32| Message { timestamp: UNIX_EPOCH + Duration::from_secs(t), pm25: MicrogramsPerCubicMeter(pm), pm10: MicrogramsPerCubicMeter(pm) };
+///
+/// assert!(hourly.push(&m(0, 1.0)).is_none());
+/// assert!(hourly.push(&m(1800, 3.0)).is_none());
+/// let stats = hourly.push(&m(3600, 5.0)).unwrap();
+/// assert_eq!(stats.count, 2);
+/// assert_eq!(stats.pm25.mean, 2.0);
+/// ```
+pub struct Tumbling {
+    width: u64,
+    start: Option<u64>,
+    count: usize,
+    pm25: Option<Acc>,
+    pm10: Option<Acc>,
+}
+
+impl Tumbling {
+    /// Creates windows of `width`, rounded to whole seconds and at least one second
+    pub fn new(width: Duration) -> Tumbling {
+        Tumbling {
+            width: width.as_secs().max(1),
+            start: None,
+            count: 0,
+            pm25: None,
+            pm10: None,
+        }
+    }
+
+    /// Adds a measurement, returns statistics of the previous window once
+    /// `m` falls into a new one
+    /// Measurements older than the current window or without a valid
+    /// timestamp are ignored
+    pub fn push(&mut self, m: &Message) -> Option<Stats> {
+        let ts = m.timestamp_secs()?;
+        let window = ts - ts % self.width;
+
+        let mut closed = None;
+        match self.start {
+            Some(start) if window < start => return None,
+            Some(start) if window > start => closed = self.flush(),
+            _ => {}
+        }
+
+        self.start = Some(window);
+        self.count += 1;
+        match (self.pm25.as_mut(), self.pm10.as_mut()) {
+            (Some(pm25), Some(pm10)) => {
+                pm25.add(m.pm25.value());
+                pm10.add(m.pm10.value());
+            }
+            _ => {
+                self.pm25 = Some(Acc::new(m.pm25.value()));
+                self.pm10 = Some(Acc::new(m.pm10.value()));
+            }
+        }
+        closed
+    }
+
+    /// Returns statistics of the current, possibly incomplete, window
+    /// and starts a new one
+    pub fn flush(&mut self) -> Option<Stats> {
+        let start = self.start?;
+        let stats = match (self.pm25.take(), self.pm10.take()) {
+            (Some(pm25), Some(pm10)) => Some(Stats {
+                start,
+                end: start + self.width,
+                count: self.count,
+                pm25: pm25.summary(self.count),
+                pm10: pm10.summary(self.count),
+            }),
+            _ => None,
+        };
+        self.count = 0;
+        stats
+    }
+}
+
+/// Window of fixed width ending at the latest measurement
+///
+/// # Example
+/// ```
+/// use sds011::aggregate::Sliding;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let mut last_10_min = Sliding::new(Duration::from_secs(600));
+/// let m = |t: u64, pm: f32| Message { timestamp: UNIX_EPOCH + Duration::from_secs(t), pm25: MicrogramsPerCubicMeter(pm), pm10: MicrogramsPerCubicMeter(pm) };
+///
+/// last_10_min.push(&m(0, 10.0));
+/// last_10_min.push(&m(300, 20.0));
+/// let stats = last_10_min.push(&m(800, 30.0)).unwrap();
+/// assert_eq!(stats.count, 2);
+/// assert_eq!(stats.pm25.min, 20.0);
+/// ```
+pub struct Sliding {
+    width: u64,
+    window: VecDeque<Message>,
+}
+
+impl Sliding {
+    /// Creates a window of `width`, rounded to whole seconds and at least one second
+    pub fn new(width: Duration) -> Sliding {
+        Sliding {
+            width: width.as_secs().max(1),
+            window: VecDeque::new(),
+        }
+    }
+
+    /// Adds a measurement and returns statistics of the window ending at it
+    /// Measurements without a valid timestamp are ignored
+    pub fn push(&mut self, m: &Message) -> Option<Stats> {
+        let ts = m.timestamp_secs()?;
+        self.window.push_back(m.clone());
+
+        let start = (ts + 1).saturating_sub(self.width);
+        while let Some(front) = self.window.front() {
+            match front.timestamp_secs() {
+                Some(t) if t >= start => break,
+                _ => {
+                    self.window.pop_front();
+                }
+            }
+        }
+
+        self.stats()
+    }
+
+    /// Statistics of the window ending at the latest measurement
+    pub fn stats(&self) -> Option<Stats> {
+        let end = self.window.back()?.timestamp_secs()? + 1;
+        stats(self.window.iter(), end.saturating_sub(self.width), end)
+    }
+}
@@ -0,0 +1,319 @@
+//! Threshold alerts with hysteresis and cooldown.
+//!
+//! A rule enters when a pollutant stays beyond its threshold for a while,
+//! e.g. PM2.5 above 35 µg/m³ for 10 minutes, and exits once the value is
+//! back past the threshold by the hysteresis margin, so readings hovering
+//! around the threshold don't flap. After entering, a rule waits for its
+//! cooldown before it enters again.
+//!
+//! Time is taken from the measurements' timestamps, so logged readings
+//! can be replayed through the rules with the same result.
+
+use crate::aqi::Pollutant;
+use crate::events::Event;
+use crate::Message;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, SystemTime};
+
+/// Side of the threshold a rule alerts on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+/// Condition alerted on
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rule {
+    pub name: String,
+    pub pollutant: Pollutant,
+    pub comparison: Comparison,
+    /// Threshold in µg/m³
+    pub threshold: f32,
+    /// Margin past the threshold the value has to return by to exit
+    pub hysteresis: f32,
+    /// Time the value has to stay beyond the threshold to enter
+    pub duration: Duration,
+    /// Shortest time between two entries
+    pub cooldown: Duration,
+}
+
+impl Rule {
+    fn new(name: &str, pollutant: Pollutant, comparison: Comparison, threshold: f32) -> Rule {
+        Rule {
+            name: name.to_string(),
+            pollutant,
+            comparison,
+            threshold,
+            hysteresis: 0.0,
+            duration: Duration::from_secs(0),
+            cooldown: Duration::from_secs(0),
+        }
+    }
+
+    /// Alerts when `pollutant` rises above `threshold`
+    pub fn above(name: &str, pollutant: Pollutant, threshold: f32) -> Rule {
+        Rule::new(name, pollutant, Comparison::Above, threshold)
+    }
+
+    /// Alerts when `pollutant` falls below `threshold`
+    pub fn below(name: &str, pollutant: Pollutant, threshold: f32) -> Rule {
+        Rule::new(name, pollutant, Comparison::Below, threshold)
+    }
+
+    /// Enters only after the value stayed beyond the threshold for
+    /// `duration`, immediately by default
+    pub fn lasting(mut self, duration: Duration) -> Rule {
+        self.duration = duration;
+        self
+    }
+
+    /// Exits only once the value is back past the threshold by `margin`
+    pub fn hysteresis(mut self, margin: f32) -> Rule {
+        self.hysteresis = margin.abs();
+        self
+    }
+
+    /// Enters at most once per `cooldown`
+    pub fn cooldown(mut self, cooldown: Duration) -> Rule {
+        self.cooldown = cooldown;
+        self
+    }
+
+    fn value(&self, m: &Message) -> f32 {
+        match self.pollutant {
+            Pollutant::Pm25 => m.pm25.value(),
+            Pollutant::Pm10 => m.pm10.value(),
+        }
+    }
+
+    fn beyond(&self, value: f32) -> bool {
+        match self.comparison {
+            Comparison::Above => value > self.threshold,
+            Comparison::Below => value < self.threshold,
+        }
+    }
+
+    fn back(&self, value: f32) -> bool {
+        match self.comparison {
+            Comparison::Above => value <= self.threshold - self.hysteresis,
+            Comparison::Below => value >= self.threshold + self.hysteresis,
+        }
+    }
+}
+
+/// Whether an alert started or ended
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Transition {
+    Enter,
+    Exit,
+}
+
+/// A rule entering or exiting
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Alert {
+    pub rule: String,
+    pub transition: Transition,
+    pub pollutant: Pollutant,
+    pub comparison: Comparison,
+    /// Value of the measurement that caused the transition
+    pub value: f32,
+    pub threshold: f32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::time::unix_secs"))]
+    pub timestamp: SystemTime,
+}
+
+impl Alert {
+    /// Describes the alert, e.g. `PM2.5 above 35 µg/m³: 41.2 µg/m³`
+    pub fn text(&self) -> String {
+        let pollutant = match self.pollutant {
+            Pollutant::Pm25 => "PM2.5",
+            Pollutant::Pm10 => "PM10",
+        };
+        let side = match self.comparison {
+            Comparison::Above => "above",
+            Comparison::Below => "below",
+        };
+        match self.transition {
+            Transition::Enter => format!(
+                "{} {} {} µg/m³: {} µg/m³",
+                pollutant, side, self.threshold, self.value
+            ),
+            Transition::Exit => format!("{} back to {} µg/m³", pollutant, self.value),
+        }
+    }
+}
+
+impl From<&Alert> for Event {
+    fn from(alert: &Alert) -> Event {
+        let state = match alert.transition {
+            Transition::Enter => "entered",
+            Transition::Exit => "exited",
+        };
+        Event::Alert {
+            rule: alert.rule.clone(),
+            message: format!("{}: {}", state, alert.text()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Beyond the threshold since, waiting for the duration
+    since: Option<SystemTime>,
+    active: bool,
+    entered: Option<SystemTime>,
+}
+
+/// Receives alerts
+pub type Handler = Box<dyn FnMut(&Alert) + Send>;
+
+/// Set of rules checked against every measurement
+///
+/// # Example
+/// ```
+/// use sds011::alerts::{Alerts, Rule, Transition};
+/// use sds011::aqi::Pollutant;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let mut alerts = Alerts::new();
+/// alerts.add(
+///     Rule::above("unhealthy", Pollutant::Pm25, 35.0)
+///         .lasting(Duration::from_secs(600))
+///         .hysteresis(5.0),
+/// );
+/// let received = alerts.subscribe();
+///
+/// let reading = |minute: u64, pm25: f32| Message { timestamp: UNIX_EPOCH + Duration::from_secs(minute * 60), pm25: MicrogramsPerCubicMeter(pm25), pm10: MicrogramsPerCubicMeter(pm25 * 2.0) };
+/// assert!(alerts.check(&reading(0, 40.0)).is_empty());
+/// assert!(alerts.check(&reading(5, 38.0)).is_empty());
+///
+/// // Above 35 for 10 minutes
+/// let fired = alerts.check(&reading(10, 41.0));
+/// assert_eq!(fired[0].transition, Transition::Enter);
+/// assert_eq!(fired[0].text(), "PM2.5 above 35 µg/m³: 41 µg/m³");
+/// assert_eq!(alerts.active(), vec!["unhealthy"]);
+///
+/// // Within the hysteresis, still active
+/// assert!(alerts.check(&reading(11, 32.0)).is_empty());
+/// assert_eq!(alerts.check(&reading(12, 29.0))[0].transition, Transition::Exit);
+///
+/// assert_eq!(received.try_iter().count(), 2);
+/// ```
+#[derive(Default)]
+pub struct Alerts {
+    rules: Vec<(Rule, State)>,
+    handlers: Vec<Handler>,
+    subscribers: Vec<Sender<Alert>>,
+}
+
+impl Alerts {
+    /// Creates a set without rules
+    pub fn new() -> Alerts {
+        Alerts::default()
+    }
+
+    /// Adds a rule, replacing one of the same name
+    pub fn add(&mut self, rule: Rule) {
+        self.remove(&rule.name);
+        self.rules.push((rule, State::default()));
+    }
+
+    /// Removes the rule `name`, returns `false` if there's none
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.rules.len();
+        self.rules.retain(|(rule, _)| rule.name != name);
+        self.rules.len() != count
+    }
+
+    /// Rules in the order they were added
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().map(|(rule, _)| rule)
+    }
+
+    /// Names of the rules that entered and haven't exited yet
+    pub fn active(&self) -> Vec<&str> {
+        self.rules
+            .iter()
+            .filter(|(_, state)| state.active)
+            .map(|(rule, _)| rule.name.as_str())
+            .collect()
+    }
+
+    /// Calls `handler` with every alert
+    pub fn on_alert<F: FnMut(&Alert) + Send + 'static>(&mut self, handler: F) {
+        self.handlers.push(Box::new(handler));
+    }
+
+    /// Returns a receiver of every alert from now on
+    /// Dropping the receiver unsubscribes
+    pub fn subscribe(&mut self) -> Receiver<Alert> {
+        let (tx, rx) = channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Checks a measurement against every rule, hands the resulting
+    /// alerts to the handlers and subscribers and returns them
+    pub fn check(&mut self, m: &Message) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for (rule, state) in self.rules.iter_mut() {
+            if let Some(transition) = step(rule, state, m) {
+                alerts.push(Alert {
+                    rule: rule.name.clone(),
+                    transition,
+                    pollutant: rule.pollutant,
+                    comparison: rule.comparison,
+                    value: rule.value(m),
+                    threshold: rule.threshold,
+                    timestamp: m.timestamp,
+                });
+            }
+        }
+        for alert in alerts.iter() {
+            for handler in self.handlers.iter_mut() {
+                handler(alert);
+            }
+            self.subscribers.retain(|tx| tx.send(alert.clone()).is_ok());
+        }
+        alerts
+    }
+}
+
+/// Advances a rule's state with a measurement
+fn step(rule: &Rule, state: &mut State, m: &Message) -> Option<Transition> {
+    let value = rule.value(m);
+    let now = m.timestamp;
+    let elapsed = |since: SystemTime| now.duration_since(since).unwrap_or_default();
+
+    if state.active {
+        if rule.back(value) {
+            state.active = false;
+            state.since = None;
+            return Some(Transition::Exit);
+        }
+        return None;
+    }
+
+    if !rule.beyond(value) {
+        state.since = None;
+        return None;
+    }
+    let since = *state.since.get_or_insert(now);
+    let cooled = !matches!(state.entered, Some(t) if elapsed(t) < rule.cooldown);
+    if elapsed(since) >= rule.duration && cooled {
+        state.active = true;
+        state.entered = Some(now);
+        return Some(Transition::Enter);
+    }
+    None
+}
@@ -0,0 +1,368 @@
+//! Air Quality Index computation.
+//!
+//! Implements the US EPA AQI with the PM2.5 breakpoints revised in 2024
+//! and the European Common Air Quality Index (CAQI) with its hourly bands.
+//! The EPA defines its index for 24-hour averages and CAQI for hourly ones,
+//! applying them to a single reading gives an instantaneous indication only.
+
+use crate::aggregate::Tumbling;
+use crate::history::History;
+use crate::{Message, MicrogramsPerCubicMeter};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+/// Pollutant an index was computed from
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Pollutant {
+    Pm25,
+    Pm10,
+}
+
+/// Index scale
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AqiScale {
+    /// US EPA AQI, 0 to 500
+    Us,
+    /// European CAQI, 0 to 100 and above
+    Caqi,
+}
+
+/// Health concern category, the first six belong to the US scale and the
+/// rest to CAQI, so comparisons are only meaningful within a scale
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Category {
+    Good,
+    Moderate,
+    UnhealthyForSensitiveGroups,
+    Unhealthy,
+    VeryUnhealthy,
+    Hazardous,
+    VeryLow,
+    Low,
+    Medium,
+    High,
+    VeryHigh,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Category::Good => "Good",
+            Category::Moderate => "Moderate",
+            Category::UnhealthyForSensitiveGroups => "Unhealthy for Sensitive Groups",
+            Category::Unhealthy => "Unhealthy",
+            Category::VeryUnhealthy => "Very Unhealthy",
+            Category::Hazardous => "Hazardous",
+            Category::VeryLow => "Very low",
+            Category::Low => "Low",
+            Category::Medium => "Medium",
+            Category::High => "High",
+            Category::VeryHigh => "Very high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Index value with its category
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Aqi {
+    pub scale: AqiScale,
+    pub value: u16,
+    pub category: Category,
+    /// Pollutant the value was computed from
+    pub pollutant: Pollutant,
+}
+
+/// `(C_low, C_high, I_low, I_high, category)`
+type Breakpoint = (f32, f32, u16, u16, Category);
+
+const PM25_US: [Breakpoint; 6] = [
+    (0.0, 9.0, 0, 50, Category::Good),
+    (9.1, 35.4, 51, 100, Category::Moderate),
+    (35.5, 55.4, 101, 150, Category::UnhealthyForSensitiveGroups),
+    (55.5, 125.4, 151, 200, Category::Unhealthy),
+    (125.5, 225.4, 201, 300, Category::VeryUnhealthy),
+    (225.5, 325.4, 301, 500, Category::Hazardous),
+];
+
+const PM10_US: [Breakpoint; 6] = [
+    (0.0, 54.0, 0, 50, Category::Good),
+    (55.0, 154.0, 51, 100, Category::Moderate),
+    (
+        155.0,
+        254.0,
+        101,
+        150,
+        Category::UnhealthyForSensitiveGroups,
+    ),
+    (255.0, 354.0, 151, 200, Category::Unhealthy),
+    (355.0, 424.0, 201, 300, Category::VeryUnhealthy),
+    (425.0, 604.0, 301, 500, Category::Hazardous),
+];
+
+/// Hourly CAQI bands, the same for background and roadside stations
+const PM25_CAQI: [Breakpoint; 5] = [
+    (0.0, 15.0, 0, 25, Category::VeryLow),
+    (15.0, 30.0, 25, 50, Category::Low),
+    (30.0, 55.0, 50, 75, Category::Medium),
+    (55.0, 110.0, 75, 100, Category::High),
+    (110.0, f32::INFINITY, 100, 100, Category::VeryHigh),
+];
+
+const PM10_CAQI: [Breakpoint; 5] = [
+    (0.0, 25.0, 0, 25, Category::VeryLow),
+    (25.0, 50.0, 25, 50, Category::Low),
+    (50.0, 90.0, 50, 75, Category::Medium),
+    (90.0, 180.0, 75, 100, Category::High),
+    (180.0, f32::INFINITY, 100, 100, Category::VeryHigh),
+];
+
+/// Linear interpolation within the breakpoint containing `c`
+///
+/// Concentrations above a US table give 500. The last CAQI band is open
+/// ended and continues the slope of the band below it.
+fn index(scale: AqiScale, table: &[Breakpoint], c: f32, pollutant: Pollutant) -> Aqi {
+    let c = c.max(0.0);
+    let mut below: Option<&Breakpoint> = None;
+    for bp in table.iter() {
+        let &(c_lo, c_hi, i_lo, i_hi, category) = bp;
+        if c <= c_hi {
+            let value = match below {
+                Some(&(b_lo, b_hi, bi_lo, bi_hi, _)) if c_hi.is_infinite() => {
+                    let slope = (bi_hi - bi_lo) as f32 / (b_hi - b_lo);
+                    i_lo as f32 + slope * (c - c_lo)
+                }
+                _ => (i_hi - i_lo) as f32 / (c_hi - c_lo) * (c.max(c_lo) - c_lo) + i_lo as f32,
+            };
+            return Aqi {
+                scale,
+                value: value.round().min(u16::MAX as f32) as u16,
+                category,
+                pollutant,
+            };
+        }
+        below = Some(bp);
+    }
+    Aqi {
+        scale,
+        value: 500,
+        category: Category::Hazardous,
+        pollutant,
+    }
+}
+
+/// US AQI of a PM2.5 concentration in µg/m³, truncated to 0.1 µg/m³
+pub fn pm25_us(c: MicrogramsPerCubicMeter) -> Aqi {
+    // The small epsilon keeps e.g. 35.4 from truncating to 35.3
+    let truncated = (c.value() * 10.0 + 1e-3).floor() / 10.0;
+    index(AqiScale::Us, &PM25_US, truncated, Pollutant::Pm25)
+}
+
+/// US AQI of a PM10 concentration in µg/m³, truncated to 1 µg/m³
+pub fn pm10_us(c: MicrogramsPerCubicMeter) -> Aqi {
+    index(AqiScale::Us, &PM10_US, c.value().floor(), Pollutant::Pm10)
+}
+
+/// Hourly CAQI of a PM2.5 concentration in µg/m³
+pub fn pm25_caqi(c: MicrogramsPerCubicMeter) -> Aqi {
+    index(AqiScale::Caqi, &PM25_CAQI, c.value(), Pollutant::Pm25)
+}
+
+/// Hourly CAQI of a PM10 concentration in µg/m³
+pub fn pm10_caqi(c: MicrogramsPerCubicMeter) -> Aqi {
+    index(AqiScale::Caqi, &PM10_CAQI, c.value(), Pollutant::Pm10)
+}
+
+/// The higher of two indexes
+fn worst(pm25: Aqi, pm10: Aqi) -> Aqi {
+    if pm10.value > pm25.value {
+        pm10
+    } else {
+        pm25
+    }
+}
+
+impl Message {
+    /// US EPA AQI of this measurement, the higher of the PM2.5 and PM10 indexes
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::aqi::{Category, Pollutant};
+    /// use sds011::{Message, MicrogramsPerCubicMeter};
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(35.4), pm10: MicrogramsPerCubicMeter(40.0) };
+    /// let aqi = m.aqi_us();
+    /// assert_eq!(aqi.value, 100);
+    /// assert_eq!(aqi.category, Category::Moderate);
+    /// assert_eq!(aqi.pollutant, Pollutant::Pm25);
+    /// ```
+    pub fn aqi_us(&self) -> Aqi {
+        worst(pm25_us(self.pm25), pm10_us(self.pm10))
+    }
+
+    /// Index of this measurement on `scale`, the higher of the PM2.5 and
+    /// PM10 indexes
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::aqi::{AqiScale, Category};
+    /// use sds011::{Message, MicrogramsPerCubicMeter};
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(20.0), pm10: MicrogramsPerCubicMeter(30.0) };
+    /// let caqi = m.aqi(AqiScale::Caqi);
+    /// assert_eq!(caqi.value, 33);
+    /// assert_eq!(caqi.category, Category::Low);
+    /// ```
+    pub fn aqi(&self, scale: AqiScale) -> Aqi {
+        match scale {
+            AqiScale::Us => self.aqi_us(),
+            AqiScale::Caqi => worst(pm25_caqi(self.pm25), pm10_caqi(self.pm10)),
+        }
+    }
+}
+
+/// EPA NowCast of hourly averages, most recent hour first, `None` for
+/// hours without data
+///
+/// Uses up to 12 hours and needs at least 2 of the 3 most recent ones.
+/// Hours are weighted by `w^age` with `w = max(min / max, 0.5)`, so the
+/// result follows rapid changes like wildfire smoke.
+///
+/// # Example
+/// ```
+/// use sds011::aqi::nowcast;
+///
+/// assert_eq!(nowcast(&[Some(10.0), Some(10.0), None]), Some(10.0));
+/// assert_eq!(nowcast(&[Some(10.0), None, None]), None);
+/// ```
+pub fn nowcast(hourly: &[Option<f32>]) -> Option<f32> {
+    let hours = &hourly[..hourly.len().min(12)];
+    if hours.iter().take(3).filter(|c| c.is_some()).count() < 2 {
+        return None;
+    }
+
+    let values = hours.iter().filter_map(|c| *c);
+    let min = values.clone().fold(f32::INFINITY, f32::min);
+    let max = values.fold(0.0, f32::max);
+    let w = if max > 0.0 { (min / max).max(0.5) } else { 1.0 };
+
+    let (mut sum, mut weights) = (0.0, 0.0);
+    for (age, c) in hours.iter().enumerate() {
+        if let Some(c) = c {
+            let weight = w.powi(age as i32);
+            sum += weight * c;
+            weights += weight;
+        }
+    }
+    Some(sum / weights)
+}
+
+/// Hours of history NowCast looks at
+const NOWCAST_HOURS: u64 = 12;
+
+/// Tracks hourly averages of a measurement stream and computes the
+/// NowCast and NowCast based AQI from completed hours
+///
+/// # Example
+/// ```
+/// use sds011::aqi::NowCast;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let mut nowcast = NowCast::new();
+/// for hour in 0..4 {
+///     let m = Message { timestamp: UNIX_EPOCH + Duration::from_secs(hour * 3600), pm25: MicrogramsPerCubicMeter(12.0), pm10: MicrogramsPerCubicMeter(20.0) };
+///     nowcast.push(&m);
+/// }
+/// assert_eq!(nowcast.pm25(), Some(MicrogramsPerCubicMeter(12.0)));
+/// assert_eq!(nowcast.aqi_us().unwrap().value, 56);
+/// ```
+pub struct NowCast {
+    current: Tumbling,
+    /// `(hour start, PM2.5 mean, PM10 mean)` of completed hours, oldest first
+    hours: VecDeque<(u64, f32, f32)>,
+}
+
+impl Default for NowCast {
+    fn default() -> Self {
+        NowCast::new()
+    }
+}
+
+impl NowCast {
+    /// Creates a tracker without history
+    pub fn new() -> NowCast {
+        NowCast {
+            current: Tumbling::new(Duration::from_secs(3600)),
+            hours: VecDeque::new(),
+        }
+    }
+
+    /// Creates a tracker from measurements kept in `history`, which should
+    /// cover the last 12 hours
+    pub fn from_history(history: &History) -> NowCast {
+        let mut nowcast = NowCast::new();
+        for m in history.iter() {
+            nowcast.push(m);
+        }
+        nowcast
+    }
+
+    /// Adds a measurement, they must be pushed in time order
+    pub fn push(&mut self, m: &Message) {
+        if let Some(stats) = self.current.push(m) {
+            self.hours
+                .push_back((stats.start, stats.pm25.mean, stats.pm10.mean));
+            let oldest = stats.start.saturating_sub((NOWCAST_HOURS - 1) * 3600);
+            while let Some(h) = self.hours.front() {
+                if h.0 >= oldest {
+                    break;
+                }
+                self.hours.pop_front();
+            }
+        }
+    }
+
+    /// Completed hours most recent first, with gaps as `None`
+    fn hourly<F: Fn(&(u64, f32, f32)) -> f32>(&self, value: F) -> Vec<Option<f32>> {
+        let latest = match self.hours.back() {
+            Some(h) => h.0,
+            None => return Vec::new(),
+        };
+        let mut hourly = vec![None; NOWCAST_HOURS as usize];
+        for h in self.hours.iter() {
+            let age = ((latest - h.0) / 3600) as usize;
+            if age < hourly.len() {
+                hourly[age] = Some(value(h));
+            }
+        }
+        hourly
+    }
+
+    /// PM2.5 NowCast
+    pub fn pm25(&self) -> Option<MicrogramsPerCubicMeter> {
+        nowcast(&self.hourly(|h| h.1)).map(MicrogramsPerCubicMeter)
+    }
+
+    /// PM10 NowCast
+    pub fn pm10(&self) -> Option<MicrogramsPerCubicMeter> {
+        nowcast(&self.hourly(|h| h.2)).map(MicrogramsPerCubicMeter)
+    }
+
+    /// US AQI of the NowCast, the higher of the PM2.5 and PM10 indexes
+    pub fn aqi_us(&self) -> Option<Aqi> {
+        Some(worst(pm25_us(self.pm25()?), pm10_us(self.pm10()?)))
+    }
+}
@@ -0,0 +1,90 @@
+//! Rolling baseline (background level) estimation.
+
+use crate::{Message, MicrogramsPerCubicMeter};
+use std::collections::VecDeque;
+
+/// Estimates the background particle level as a low percentile over the
+/// last `window` measurements.
+///
+/// Short pollution events (cooking, smoking, etc.) barely move a low
+/// percentile, so the estimate follows the "clean air" level of a room
+/// and makes it easy to tell background from events.
+///
+/// # Example
+/// ```
+/// use sds011::baseline::Baseline;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let mut baseline = Baseline::new(60, 10.0);
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.0), pm10: MicrogramsPerCubicMeter(8.0) };
+/// let b = baseline.push(&m);
+/// assert_eq!(b.pm25.value(), 4.0);
+/// ```
+pub struct Baseline {
+    window: usize,
+    percentile: f32,
+    pm25: VecDeque<f32>,
+    pm10: VecDeque<f32>,
+}
+
+impl Baseline {
+    /// Creates new estimator
+    /// `window` is a number of measurements to keep, `percentile` must be between 0 and 100
+    pub fn new(window: usize, percentile: f32) -> Baseline {
+        Baseline {
+            window: window.max(1),
+            percentile: percentile.clamp(0.0, 100.0),
+            pm25: VecDeque::new(),
+            pm10: VecDeque::new(),
+        }
+    }
+
+    /// Adds a measurement and returns the updated baseline
+    /// with the same timestamp as `m`
+    pub fn push(&mut self, m: &Message) -> Message {
+        if self.pm25.len() == self.window {
+            self.pm25.pop_front();
+            self.pm10.pop_front();
+        }
+        self.pm25.push_back(m.pm25.value());
+        self.pm10.push_back(m.pm10.value());
+
+        Message {
+            timestamp: m.timestamp,
+            pm25: MicrogramsPerCubicMeter(percentile(&self.pm25, self.percentile)),
+            pm10: MicrogramsPerCubicMeter(percentile(&self.pm10, self.percentile)),
+        }
+    }
+
+    /// Returns current baseline values as `(pm25, pm10)`
+    /// or `None` if no measurements were pushed yet
+    pub fn current(&self) -> Option<(f32, f32)> {
+        if self.pm25.is_empty() {
+            return None;
+        }
+        Some((
+            percentile(&self.pm25, self.percentile),
+            percentile(&self.pm10, self.percentile),
+        ))
+    }
+
+    /// Number of measurements in the window
+    pub fn len(&self) -> usize {
+        self.pm25.len()
+    }
+
+    /// Returns `true` if no measurements were pushed yet
+    pub fn is_empty(&self) -> bool {
+        self.pm25.is_empty()
+    }
+}
+
+/// Nearest-rank percentile of `values`, `values` must not be empty
+fn percentile(values: &VecDeque<f32>, p: f32) -> f32 {
+    let mut sorted: Vec<f32> = values.iter().cloned().collect();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let rank = ((p / 100.0) * (sorted.len() - 1) as f32).round() as usize;
+    sorted[rank]
+}
@@ -0,0 +1,94 @@
+//! Threshold alerts and their notifications, see `sds011::alerts` and
+//! `sds011::notify`.
+
+use crate::config::Effective;
+use sds011::alerts::{Alerts, Rule};
+use sds011::aqi::Pollutant;
+use sds011::events::EventBus;
+use std::time::Duration;
+
+/// Default seconds a threshold has to be exceeded for
+const DURATION: u64 = 600;
+/// Hysteresis, as a fraction of the threshold
+const HYSTERESIS: f32 = 0.1;
+/// Shortest time between two alerts of a rule
+const COOLDOWN: Duration = Duration::from_secs(3600);
+
+/// Builds the alert rules configured in `settings`, printing alerts and
+/// publishing them on `bus`, `None` without rules
+/// Rules of a sensor with a `label` are named after it, e.g. "High PM2.5
+/// on kitchen"
+pub fn open(
+    settings: &Effective,
+    bus: &EventBus,
+    label: Option<&str>,
+) -> Result<Option<Alerts>, String> {
+    let secs = settings
+        .alert_duration
+        .as_ref()
+        .map_or(DURATION, |s| s.value);
+    let mut alerts = Alerts::new();
+    let thresholds = [
+        ("High PM2.5", Pollutant::Pm25, &settings.alert_pm25),
+        ("High PM10", Pollutant::Pm10, &settings.alert_pm10),
+    ];
+    for (name, pollutant, threshold) in thresholds.iter() {
+        if let Some(threshold) = threshold {
+            let name = match label {
+                Some(label) => format!("{} on {}", name, label),
+                None => name.to_string(),
+            };
+            let rule = Rule::above(&name, *pollutant, threshold.value)
+                .lasting(Duration::from_secs(secs))
+                .hysteresis(threshold.value * HYSTERESIS)
+                .cooldown(COOLDOWN);
+            alerts.add(rule);
+        }
+    }
+    if alerts.rules().next().is_none() {
+        return Ok(None);
+    }
+
+    let bus = bus.clone();
+    alerts.on_alert(move |alert| {
+        eprintln!("alert: {}: {}", sds011::notify::title(alert), alert.text());
+        bus.publish(alert.into());
+    });
+    if let Some(token) = settings.telegram_token.as_ref() {
+        let chat = settings
+            .telegram_chat
+            .as_ref()
+            .ok_or("telegram_chat must be set with telegram_token")?;
+        telegram(&mut alerts, &token.value, &chat.value)?;
+    }
+    if let Some(token) = settings.pushover_token.as_ref() {
+        let user = settings
+            .pushover_user
+            .as_ref()
+            .ok_or("pushover_user must be set with pushover_token")?;
+        pushover(&mut alerts, &token.value, &user.value)?;
+    }
+    Ok(Some(alerts))
+}
+
+#[cfg(feature = "telegram")]
+fn telegram(alerts: &mut Alerts, token: &str, chat: &str) -> Result<(), String> {
+    alerts.notify(sds011::notify::telegram::Telegram::new(token, chat));
+    Ok(())
+}
+
+#[cfg(not(feature = "telegram"))]
+fn telegram(_alerts: &mut Alerts, _token: &str, _chat: &str) -> Result<(), String> {
+    Err("this build has no Telegram support, rebuild with --features telegram".to_string())
+}
+
+#[cfg(feature = "pushover")]
+fn pushover(alerts: &mut Alerts, token: &str, user: &str) -> Result<(), String> {
+    alerts.notify(sds011::notify::pushover::Pushover::new(token, user));
+    Ok(())
+}
+
+#[cfg(not(feature = "pushover"))]
+fn pushover(_alerts: &mut Alerts, _token: &str, _user: &str) -> Result<(), String> {
+    Err("this build has no Pushover support, rebuild with --features pushover".to_string())
+}
@@ -0,0 +1,239 @@
+//! `bench-pipeline` subcommand: drives the sink and aggregation pipeline
+//! with emulated sensors, to size multi-sensor gateways before buying
+//! hardware.
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sds011::aggregate::Tumbling;
+use sds011::calibration::Calibration;
+use sds011::durable::SyncPolicy;
+use sds011::emulator::Emulator;
+use sds011::sink::file::FileLogger;
+use sds011::sink::{Sink, SinkSet};
+use sds011::{Message, SDS011};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("bench-pipeline")
+        .about("Measures throughput, latency and memory of the pipeline fed by emulated sensors")
+        .arg(
+            Arg::with_name("sensors")
+                .long("sensors")
+                .takes_value(true)
+                .default_value("50")
+                .help("Number of emulated sensors"),
+        )
+        .arg(
+            Arg::with_name("rate")
+                .long("rate")
+                .takes_value(true)
+                .default_value("1hz")
+                .help("Readings per second of each sensor, e.g. 1hz or 0.2hz"),
+        )
+        .arg(
+            Arg::with_name("duration")
+                .long("duration")
+                .takes_value(true)
+                .default_value("30")
+                .help("Run time in seconds"),
+        )
+        .arg(
+            Arg::with_name("dir")
+                .long("dir")
+                .takes_value(true)
+                .help("Directory the CSV and JSON Lines outputs are written to [default: a temporary directory]"),
+        )
+        .arg(
+            Arg::with_name("fsync")
+                .long("fsync")
+                .takes_value(true)
+                .default_value("always")
+                .help("When outputs are synced to the disk: always, never or every N seconds"),
+        )
+}
+
+/// Parses a rate like `1hz`, `0.5Hz` or `2`
+fn parse_rate(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let number = match s.len().checked_sub(2) {
+        Some(i) if s.is_char_boundary(i) && s[i..].eq_ignore_ascii_case("hz") => &s[..i],
+        _ => s,
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|r| *r > 0.0 && r.is_finite())
+}
+
+/// Peak resident memory of this process in KiB, Linux only
+fn peak_memory_kib() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Value below which `p` percent of the sorted `values` fall
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::from_secs(0);
+    }
+    sorted[((sorted.len() - 1) * p / 100).min(sorted.len() - 1)]
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// Runs the subcommand and returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let sensors = match m.value_of("sensors").unwrap().parse::<u16>() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            eprintln!("error: --sensors: expected a positive number");
+            return 1;
+        }
+    };
+    let rate = match parse_rate(m.value_of("rate").unwrap()) {
+        Some(r) => r,
+        None => {
+            eprintln!("error: --rate: expected a positive rate like 1hz");
+            return 1;
+        }
+    };
+    let duration = match m.value_of("duration").unwrap().parse::<u64>() {
+        Ok(d) if d > 0 => Duration::from_secs(d),
+        _ => {
+            eprintln!("error: --duration: expected a positive number of seconds");
+            return 1;
+        }
+    };
+    let policy = match m.value_of("fsync").unwrap().parse::<SyncPolicy>() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("error: --fsync: {}", e);
+            return 1;
+        }
+    };
+    let dir = match m.value_of("dir") {
+        Some(d) => std::path::PathBuf::from(d),
+        None => std::env::temp_dir().join(format!("sds011-bench-{}", std::process::id())),
+    };
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        eprintln!("error: {}: {}", dir.display(), e);
+        return 1;
+    }
+
+    let mut sinks = SinkSet::new();
+    let jsonl = dir.join("readings.ndjson").display().to_string();
+    sinks.add(
+        "jsonl",
+        Box::new(move || {
+            let logger = FileLogger::new(&jsonl)?.sync_policy(policy);
+            Ok(Box::new(logger) as Box<dyn Sink>)
+        }),
+    );
+    #[cfg(feature = "csv")]
+    {
+        use sds011::aqi::AqiScale;
+        use sds011::sink::csv::CsvWriter;
+
+        let csv = dir.join("readings.csv").display().to_string();
+        sinks.add(
+            "csv",
+            Box::new(move || {
+                let writer = CsvWriter::new(&csv).aqi(AqiScale::Us).sync_policy(policy);
+                Ok(Box::new(writer) as Box<dyn Sink>)
+            }),
+        );
+    }
+
+    eprintln!(
+        "info: {} sensors at {} Hz for {} s, writing to {}",
+        sensors,
+        rate,
+        duration.as_secs(),
+        dir.display()
+    );
+
+    let period = Duration::from_secs_f64(1.0 / rate);
+    let deadline = Instant::now() + duration;
+    let (tx, rx) = channel::<(u16, Message, Instant)>();
+    for i in 0..sensors {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            let mut emulator = Emulator::new(0x1000 + i);
+            emulator.set_reading(5.0 + (i % 20) as f32, 10.0 + (i % 30) as f32);
+            let mut sensor = match SDS011::from_transport(Box::new(emulator)) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("error: sensor {}: {}", i, e);
+                    return;
+                }
+            };
+            // Spread the sensors over the period like unsynchronized hardware
+            let mut next = Instant::now() + period.mul_f64(i as f64 / sensors as f64);
+            while next < deadline {
+                if let Some(wait) = next.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+                let started = Instant::now();
+                if let Ok(m) = sensor.query() {
+                    if tx.send((i, m, started)).is_err() {
+                        return;
+                    }
+                }
+                next += period;
+            }
+        });
+    }
+    drop(tx);
+
+    // The pipeline of a gateway: calibrate, aggregate per sensor, publish
+    let calibration = Calibration::default();
+    let mut windows: Vec<Tumbling> = (0..sensors)
+        .map(|_| Tumbling::new(Duration::from_secs(60)))
+        .collect();
+    let mut latencies = Vec::new();
+    let mut windows_closed = 0;
+    let started = Instant::now();
+    for (i, m, queried) in rx {
+        let m = calibration.apply(&m);
+        if windows[i as usize].push(&m).is_some() {
+            windows_closed += 1;
+        }
+        sinks.publish(&m);
+        latencies.push(queried.elapsed());
+    }
+    sinks.flush();
+    let elapsed = started.elapsed();
+
+    latencies.sort();
+    let count = latencies.len();
+    let expected = (sensors as f64 * rate * duration.as_secs_f64()).round() as u64;
+    println!("readings:    {} of {} expected", count, expected);
+    println!(
+        "throughput:  {:.1} readings/s",
+        count as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "latency:     p50 {:.3} ms, p95 {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+        millis(percentile(&latencies, 50)),
+        millis(percentile(&latencies, 95)),
+        millis(percentile(&latencies, 99)),
+        millis(latencies.last().copied().unwrap_or_default())
+    );
+    println!("windows:     {} closed", windows_closed);
+    match peak_memory_kib() {
+        Some(kib) => println!("peak memory: {:.1} MiB", kib as f64 / 1024.0),
+        None => println!("peak memory: unknown on this platform"),
+    }
+    if sinks.is_degraded() {
+        eprintln!("warning: some outputs failed, results understate the pipeline cost");
+    }
+
+    if m.value_of("dir").is_none() {
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+    0
+}
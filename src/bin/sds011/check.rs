@@ -0,0 +1,173 @@
+//! `check` subcommand: a Nagios-compatible plugin, for monitoring systems
+//! supervising the sensor.
+//!
+//! It either takes a reading or checks that a file the monitor writes,
+//! e.g. the CSV log, was modified recently. It prints one status line with
+//! performance data and exits with 0 for OK, 1 for WARNING, 2 for CRITICAL
+//! and 3 for UNKNOWN, e.g. when the configuration can't be read.
+
+use crate::config::Effective;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sds011::SDS011;
+use std::fmt;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+/// Result of a check, its exit code
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("check")
+        .about("Checks that the sensor answers or a log file is recent, exits like a Nagios plugin")
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .takes_value(true)
+                .help("Port the sensor is connected to, or auto [default: the port setting]"),
+        )
+        .arg(
+            Arg::with_name("wake")
+                .long("wake")
+                .help("Wake the sensor for the reading and put it back to sleep afterwards"),
+        )
+        .arg(
+            Arg::with_name("file")
+                .long("file")
+                .takes_value(true)
+                .conflicts_with_all(&["port", "wake"])
+                .help("Check the age of this file instead, e.g. the CSV log, without opening the port"),
+        )
+        .arg(
+            Arg::with_name("warning")
+                .long("warning")
+                .takes_value(true)
+                .default_value("300")
+                .help("Seconds since the file was modified for a WARNING"),
+        )
+        .arg(
+            Arg::with_name("critical")
+                .long("critical")
+                .takes_value(true)
+                .default_value("900")
+                .help("Seconds since the file was modified for a CRITICAL"),
+        )
+}
+
+/// Runs `check`, `matches` are the top-level ones the configuration is
+/// resolved from, returns the exit code
+pub fn run(m: &ArgMatches, matches: &ArgMatches) -> i32 {
+    let (status, text) = match m.value_of("file") {
+        Some(path) => match thresholds(m) {
+            Ok((warning, critical)) => file(path, warning, critical),
+            Err(e) => (Status::Unknown, e),
+        },
+        None => sensor(m, matches),
+    };
+    println!("SDS011 {} - {}", status, text);
+    status as i32
+}
+
+/// Warning and critical ages
+fn thresholds(m: &ArgMatches) -> Result<(u64, u64), String> {
+    let secs = |name: &str| {
+        let value = m.value_of(name).unwrap_or_default();
+        value
+            .parse::<u64>()
+            .map_err(|_| format!("--{}: expected seconds, got \"{}\"", name, value))
+    };
+    let (warning, critical) = (secs("warning")?, secs("critical")?);
+    if warning > critical {
+        return Err("--warning must not be above --critical".to_string());
+    }
+    Ok((warning, critical))
+}
+
+/// Checks the modification time of `path`
+fn file(path: &str, warning: u64, critical: u64) -> (Status, String) {
+    let modified = match std::fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(t) => t,
+        Err(e) => return (Status::Critical, format!("{}: {}", path, e)),
+    };
+    // A clock set back makes the file look new
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default()
+        .as_secs();
+    let status = if age > critical {
+        Status::Critical
+    } else if age > warning {
+        Status::Warning
+    } else {
+        Status::Ok
+    };
+    (
+        status,
+        format!(
+            "{} modified {}s ago | age={}s;{};{};0",
+            path, age, age, warning, critical
+        ),
+    )
+}
+
+/// Takes a reading
+fn sensor(m: &ArgMatches, matches: &ArgMatches) -> (Status, String) {
+    let settings = match Effective::resolve(matches) {
+        Ok(s) => s,
+        Err(e) => return (Status::Unknown, e),
+    };
+    let port = match crate::device::resolve_port(m.value_of("port").unwrap_or(&settings.port.value))
+    {
+        Ok(p) => p,
+        Err(e) => return (Status::Critical, e),
+    };
+    let mut sensor = match crate::frames::open(&port) {
+        Ok(s) => s,
+        Err(e) => return (Status::Critical, format!("{}: {}", port, e)),
+    };
+    match query(&mut sensor, m.is_present("wake")) {
+        Ok(reading) => (
+            Status::Ok,
+            format!(
+                "{}: PM2.5 {:.1} µg/m³, PM10 {:.1} µg/m³ | pm25={};;;0 pm10={};;;0",
+                port,
+                reading.pm25.value(),
+                reading.pm10.value(),
+                reading.pm25.value(),
+                reading.pm10.value()
+            ),
+        ),
+        Err(e) => (Status::Critical, format!("{}: {}", port, e)),
+    }
+}
+
+/// Queries the sensor, waking it for the reading with `wake`
+pub fn query(sensor: &mut SDS011, wake: bool) -> sds011::Result<sds011::Message> {
+    if wake {
+        sensor.wake()?;
+        sleep(sensor.warm_up_remaining().unwrap_or(Duration::from_secs(0)));
+    }
+    let reading = sensor.query();
+    if wake {
+        sensor.sleep()?;
+    }
+    reading
+}
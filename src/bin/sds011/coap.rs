@@ -0,0 +1,20 @@
+//! Optional CoAP server, see `sds011::coap`.
+
+use sds011::events::EventBus;
+
+/// Serves the latest reading published on `bus` at `addr` in the
+/// background
+#[cfg(feature = "coap")]
+pub fn start(addr: &str, bus: &EventBus) -> Result<(), String> {
+    use sds011::coap::CoapServer;
+
+    CoapServer::bind(addr)
+        .map_err(|e| e.to_string())?
+        .spawn(bus);
+    Ok(())
+}
+
+#[cfg(not(feature = "coap"))]
+pub fn start(_addr: &str, _bus: &EventBus) -> Result<(), String> {
+    Err("this build has no CoAP server, rebuild with --features coap".to_string())
+}
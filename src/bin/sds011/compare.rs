@@ -0,0 +1,160 @@
+//! `compare` subcommand: pairs readings of the sensor with those of a
+//! nearby sensor.community station and reports the indoor/outdoor ratios
+//! and infiltration, see `sds011::reference`.
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("compare")
+        .about("Compares readings with an outdoor sensor.community station, printing ratios and infiltration")
+        .arg(
+            Arg::with_name("station")
+                .long("station")
+                .takes_value(true)
+                .required(true)
+                .help("ID of the sensor.community sensor, see https://maps.sensor.community"),
+        )
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .takes_value(true)
+                .help("Port the sensor is connected to, or auto [default: the port setting]"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .takes_value(true)
+                .default_value("5")
+                .help("Minutes between pairs, the station sends a reading about every 2.5"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .long("count")
+                .takes_value(true)
+                .default_value("12")
+                .help("Pairs to take before the report"),
+        )
+}
+
+/// Runs the subcommand, `matches` are the top-level ones the configuration
+/// is resolved from, returns the exit code
+#[cfg(feature = "reference")]
+pub fn run(m: &ArgMatches, matches: &ArgMatches) -> i32 {
+    match compare(m, matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+#[cfg(not(feature = "reference"))]
+pub fn run(_m: &ArgMatches, _matches: &ArgMatches) -> i32 {
+    eprintln!(
+        "error: this build can't fetch reference readings, rebuild with --features reference"
+    );
+    1
+}
+
+#[cfg(feature = "reference")]
+fn compare(m: &ArgMatches, matches: &ArgMatches) -> Result<(), String> {
+    use crate::config::Effective;
+    use crate::shutdown;
+    use sds011::reference;
+    use std::time::Duration;
+
+    let settings = Effective::resolve(matches)?;
+    let number = |name: &str| -> Result<u64, String> {
+        let value = m.value_of(name).unwrap_or_default();
+        value
+            .parse()
+            .map_err(|_| format!("--{}: expected a number, got \"{}\"", name, value))
+    };
+    let station = number("station")? as u32;
+    let interval = Duration::from_secs(number("interval")? * 60);
+    let count = number("count")?;
+    let port = crate::device::resolve_port(m.value_of("port").unwrap_or(&settings.port.value))?;
+    let mut sensor = crate::frames::open(&port).map_err(|e| format!("{}: {}", port, e))?;
+    let (calibration, _) = crate::load_files(&settings)?;
+    let device = crate::lookup(&settings, sensor.device_id().ok())?.unwrap_or_default();
+    sensor.set_calibration(calibration.or(device.calibration));
+    shutdown::install()?;
+
+    let mut pairs = Vec::new();
+    for n in 1..=count {
+        if n > 1 && shutdown::wait(interval) {
+            break;
+        }
+        // Fetched first, the sensor isn't woken if the station is down
+        let outdoor = match reference::fetch_sensor_community(station) {
+            Ok(reading) => reading,
+            Err(e) => {
+                eprintln!("warning: station {}: {}", station, e);
+                continue;
+            }
+        };
+        // The same outdoor reading twice would weigh it double
+        if matches!(pairs.last(), Some((_, o)) if *o == outdoor) {
+            eprintln!(
+                "warning: station {}: no reading since the last pair",
+                station
+            );
+            continue;
+        }
+        let indoor = match crate::check::query(&mut sensor, true) {
+            Ok(reading) => reading,
+            Err(e) => {
+                eprintln!("warning: {}: {}", port, e);
+                continue;
+            }
+        };
+        let (pm25, pm10) = reference::ratio(&indoor, &outdoor);
+        println!(
+            "{}/{}: PM2.5 {:.1} indoors, {:.1} outdoors, ratio {}; PM10 {:.1} indoors, {:.1} outdoors, ratio {}",
+            n,
+            count,
+            indoor.pm25.value(),
+            outdoor.pm25.value(),
+            format_ratio(pm25),
+            indoor.pm10.value(),
+            outdoor.pm10.value(),
+            format_ratio(pm10)
+        );
+        pairs.push((indoor, outdoor));
+    }
+
+    let comparison = reference::compare(&pairs);
+    println!("{} pairs", pairs.len());
+    println!(
+        "PM2.5: mean ratio {}, {}",
+        format_ratio(comparison.ratio_pm25),
+        format_infiltration(comparison.pm25)
+    );
+    println!(
+        "PM10: mean ratio {}, {}",
+        format_ratio(comparison.ratio_pm10),
+        format_infiltration(comparison.pm10)
+    );
+    Ok(())
+}
+
+#[cfg(feature = "reference")]
+fn format_ratio(ratio: Option<f32>) -> String {
+    match ratio {
+        Some(r) => format!("{:.2}", r),
+        None => "n/a".to_string(),
+    }
+}
+
+#[cfg(feature = "reference")]
+fn format_infiltration(infiltration: Option<sds011::reference::Infiltration>) -> String {
+    match infiltration {
+        Some(i) => format!(
+            "infiltration {:.2}, indoor sources {:.1} µg/m³, correlation {:.2}",
+            i.factor, i.indoor_sources, i.correlation
+        ),
+        None => "infiltration n/a, it needs two pairs with different outdoor values".to_string(),
+    }
+}
@@ -0,0 +1,1367 @@
+//! TOML configuration file and settings resolution.
+//!
+//! Every setting is resolved from, in increasing priority: built-in
+//! defaults, the configuration file, `SDS011_*` environment variables
+//! and command line flags.
+
+use crate::output::Format;
+use clap::ArgMatches;
+use sds011::calibration::Calibration;
+use sds011::directory::FileDirectory;
+use sds011::durable::SyncPolicy;
+use sds011::sink::file::FileLogger;
+use sds011::timestamp::{TimestampFormat, Zone};
+use sds011::SDS011;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Time a sink's server has to accept a connection when probed
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Configuration file contents, unset keys fall back to CLI defaults
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Port a sensor is connected to
+    pub port: Option<String>,
+    /// Work period in minutes
+    pub work_period: Option<u8>,
+    /// User to switch to after opening the port
+    pub user: Option<String>,
+    /// Restrict system calls with seccomp after starting up
+    pub seccomp: Option<bool>,
+    /// Calibration TOML file applied to readings
+    pub calibration: Option<String>,
+    /// Rhai script transforming readings and raising alerts
+    pub script: Option<String>,
+    /// Timestamp format: unix, rfc3339 or a strftime pattern
+    pub time_format: Option<String>,
+    /// Write timestamps in local time instead of UTC
+    pub local_time: Option<bool>,
+    /// Format of printed measurements: plain, json, csv or influx
+    pub format: Option<String>,
+    /// MQTT command topic for remote management, mqtt[s]://host[:port]/topic
+    pub remote: Option<String>,
+    /// Token remote commands must carry
+    pub remote_token: Option<String>,
+    /// PEM file with the CAs the remote broker is checked against, implies TLS
+    pub remote_ca: Option<String>,
+    /// Gateway to push measurements to, host:port
+    pub forward: Option<String>,
+    /// Token the gateway expects
+    pub forward_token: Option<String>,
+    /// Name of this station at the gateway
+    pub station: Option<String>,
+    /// File measurements the gateway didn't acknowledge are kept in
+    pub spool: Option<String>,
+    /// When written files are synced: always, never or every N seconds
+    pub fsync: Option<String>,
+    /// Device directory TOML file, see `sds011::directory`
+    pub directory: Option<String>,
+    /// CSV file measurements are appended to
+    pub csv: Option<String>,
+    /// JSON Lines file measurements are appended to, may be a strftime
+    /// pattern to start a new file every day
+    pub jsonl: Option<String>,
+    /// Size in MiB the JSON Lines file is rotated at
+    pub jsonl_max_mb: Option<u64>,
+    /// SQLite database measurements are stored in, see `sds011::store`
+    pub sqlite: Option<String>,
+    /// InfluxDB server URL measurements are written to
+    pub influx: Option<String>,
+    /// InfluxDB 2.x organization
+    pub influx_org: Option<String>,
+    /// InfluxDB 2.x bucket
+    pub influx_bucket: Option<String>,
+    /// InfluxDB 1.x database
+    pub influx_database: Option<String>,
+    /// InfluxDB 2.x API token, or user:password for 1.x
+    pub influx_token: Option<String>,
+    /// HTTP API of the IPFS node batches of readings are added to
+    pub ipfs: Option<String>,
+    /// Format of the documents added to IPFS: json or csv
+    pub ipfs_format: Option<String>,
+    /// Readings per document added to IPFS
+    pub ipfs_batch: Option<usize>,
+    /// Graphite plaintext listener measurements are written to, host:port
+    pub graphite: Option<String>,
+    /// StatsD server measurements are sent to as gauges, host:port
+    pub statsd: Option<String>,
+    /// Prefix of Graphite and StatsD metric names
+    pub metric_prefix: Option<String>,
+    /// MQTT topic measurements are published to, mqtt[s]://host[:port]/topic
+    pub mqtt: Option<String>,
+    /// MQTT user name
+    pub mqtt_user: Option<String>,
+    /// MQTT password
+    pub mqtt_password: Option<String>,
+    /// PEM file with the CAs the MQTT broker is checked against, implies TLS
+    pub mqtt_ca: Option<String>,
+    /// MQTT quality of service: 0, 1 or 2
+    pub mqtt_qos: Option<u8>,
+    /// Ask the MQTT broker to retain the last measurement
+    pub mqtt_retain: Option<bool>,
+    /// Announce the sensor to Home Assistant with MQTT discovery
+    pub mqtt_discovery: Option<bool>,
+    /// Retained topic the sensor's online/offline status is published to,
+    /// backed by a last will
+    pub mqtt_availability: Option<String>,
+    /// NATS subject measurements are published to, nats://host[:port]/subject
+    pub nats: Option<String>,
+    /// NATS user name
+    pub nats_user: Option<String>,
+    /// NATS password
+    pub nats_password: Option<String>,
+    /// NATS authentication token
+    pub nats_token: Option<String>,
+    /// Wait for JetStream to store every measurement published to NATS
+    pub nats_jetstream: Option<bool>,
+    /// Address the HTTP server listens on, e.g. 0.0.0.0:8080
+    pub http: Option<String>,
+    /// Address the CoAP server listens on, e.g. [::]:5683
+    pub coap: Option<String>,
+    /// Address the gRPC server listens on, e.g. [::]:50051
+    pub grpc: Option<String>,
+    /// Node ID readings are uploaded to sensor.community as, e.g.
+    /// raspi-00000000a1b2c3d4
+    pub sensor_community: Option<String>,
+    /// openSenseMap box ID readings are uploaded to
+    pub opensensemap: Option<String>,
+    /// ID of the box's PM2.5 sensor
+    pub opensensemap_pm25: Option<String>,
+    /// ID of the box's PM10 sensor
+    pub opensensemap_pm10: Option<String>,
+    /// Access token of the box
+    pub opensensemap_token: Option<String>,
+    /// Seconds between openSenseMap uploads
+    pub opensensemap_interval: Option<u64>,
+    /// Write API key of the ThingSpeak channel readings are written to
+    pub thingspeak: Option<String>,
+    /// Seconds between ThingSpeak updates
+    pub thingspeak_interval: Option<u64>,
+    /// URL of the Robonomics datalog gateway records are sent to
+    pub robonomics: Option<String>,
+    /// File with the station's hex encoded Ed25519 secret key
+    pub robonomics_key: Option<String>,
+    /// Bearer token of the gateway
+    pub robonomics_token: Option<String>,
+    /// Seconds between datalog records
+    pub robonomics_interval: Option<u64>,
+    /// URL every measurement is POSTed to
+    pub webhook: Option<String>,
+    /// Headers of webhook requests, `Name: value`, one per line
+    pub webhook_header: Option<String>,
+    /// Hex encoded public key webhook bodies are sealed to
+    pub webhook_encrypt: Option<String>,
+    /// PM2.5 in µg/m³ above which an alert is raised
+    pub alert_pm25: Option<f32>,
+    /// PM10 in µg/m³ above which an alert is raised
+    pub alert_pm10: Option<f32>,
+    /// Seconds a threshold has to be exceeded for before alerting
+    pub alert_duration: Option<u64>,
+    /// Telegram bot token alerts are sent with
+    pub telegram_token: Option<String>,
+    /// Telegram chat alerts are sent to
+    pub telegram_chat: Option<String>,
+    /// Pushover application token alerts are pushed with
+    pub pushover_token: Option<String>,
+    /// Pushover user or group key alerts are pushed to
+    pub pushover_user: Option<String>,
+    /// JSON file the laser's working hours are kept in
+    pub hours_file: Option<String>,
+    /// Fraction of the laser's rated 8000 hours from which a warning is
+    /// raised
+    pub laser_warning: Option<f64>,
+    /// Sensors polled by one process, `port` is used when there are none
+    pub sensors: Option<Vec<SensorConfig>>,
+}
+
+/// A sensor of the `[[sensors]]` list
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SensorConfig {
+    /// Name readings are labeled with, the device ID by default
+    pub name: Option<String>,
+    /// Port the sensor is connected to
+    pub port: String,
+}
+
+impl Config {
+    /// Reads and parses a config file
+    pub fn load(path: &str) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Checks the values and returns a list of problems
+    /// With `probe` the sensor is opened and queried once, and the servers
+    /// of the sinks are connected to
+    pub fn validate(&self, probe: bool) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Some(w) = self.work_period {
+            if w > 30 {
+                problems.push(format!(
+                    "work_period = {}: must be between 0 and 30 minutes",
+                    w
+                ));
+            }
+        }
+
+        if let Some(port) = &self.port {
+            if let Err(e) = check_port(port, probe) {
+                problems.push(format!("port = \"{}\": {}", port, e));
+            }
+        }
+
+        let sensors = self.sensors.as_deref().unwrap_or_default();
+        for (i, sensor) in sensors.iter().enumerate() {
+            if let Err(e) = check_port(&sensor.port, probe) {
+                problems.push(format!("sensors: port = \"{}\": {}", sensor.port, e));
+            }
+            if sensors[..i].iter().any(|s| s.port == sensor.port) {
+                problems.push(format!("sensors: port \"{}\" is listed twice", sensor.port));
+            }
+            match &sensor.name {
+                Some(name) if name.is_empty() => {
+                    problems.push("sensors: name must not be empty".to_string())
+                }
+                Some(name) if sensors[..i].iter().any(|s| s.name.as_ref() == Some(name)) => {
+                    problems.push(format!("sensors: name \"{}\" is used twice", name))
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(user) = &self.user {
+            if crate::sandbox::lookup_user(user).is_err() {
+                problems.push(format!("user = \"{}\": no such user", user));
+            }
+        }
+
+        if let Some(path) = &self.calibration {
+            if let Err(e) = Calibration::load(path) {
+                problems.push(format!("calibration: {}", e));
+            }
+        }
+
+        if let Some(path) = &self.script {
+            if let Err(e) = crate::scripting::load(path) {
+                problems.push(format!("script: {}", e));
+            }
+        }
+
+        if let Some(format) = &self.time_format {
+            if let Err(e) = format.parse::<TimestampFormat>() {
+                problems.push(format!("time_format: {}", e));
+            }
+        }
+
+        if let Some(format) = &self.format {
+            if let Err(e) = format.parse::<Format>() {
+                problems.push(format!("format: {}", e));
+            }
+        }
+
+        if self.remote.is_some() && self.remote_token.as_deref().unwrap_or("").is_empty() {
+            problems.push("remote: remote_token must be set".to_string());
+        }
+
+        if let Some(policy) = &self.fsync {
+            if let Err(e) = policy.parse::<SyncPolicy>() {
+                problems.push(format!("fsync: {}", e));
+            }
+        }
+
+        if let Some(path) = &self.directory {
+            if let Err(e) = FileDirectory::load(path) {
+                problems.push(format!("directory: {}", e));
+            }
+        }
+
+        if let Some(path) = &self.jsonl {
+            if let Err(e) = FileLogger::new(path) {
+                problems.push(format!("jsonl: {}", e));
+            }
+        }
+
+        if self.influx.is_some() && self.influx_bucket.is_some() == self.influx_database.is_some() {
+            problems.push(
+                "influx: set either influx_bucket (2.x) or influx_database (1.x)".to_string(),
+            );
+        }
+
+        if let Some(url) = &self.ipfs {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                problems.push(format!("ipfs = {}: expected an http(s) URL", url));
+            }
+        }
+
+        if let Some(format) = &self.ipfs_format {
+            if format != "json" && format != "csv" {
+                problems.push(format!("ipfs_format = {}: expected json or csv", format));
+            }
+        }
+
+        if self.ipfs_batch == Some(0) {
+            problems.push("ipfs_batch = 0: expected at least 1 reading".to_string());
+        }
+
+        for (name, addr) in [("graphite", &self.graphite), ("statsd", &self.statsd)].iter() {
+            if let Some(addr) = addr {
+                if !matches!(addr.rsplit_once(':'), Some((_, port)) if port.parse::<u16>().is_ok())
+                {
+                    problems.push(format!("{} = {}: expected host:port", name, addr));
+                }
+            }
+        }
+
+        if let Some(url) = &self.mqtt {
+            if let Err(e) = crate::mqtt::parse_url(url) {
+                problems.push(format!("mqtt: {}", e));
+            }
+        }
+
+        if let Some(topic) = &self.mqtt_availability {
+            if let Err(e) = crate::mqtt::check_topic(topic) {
+                problems.push(format!("mqtt_availability: {}", e));
+            }
+        }
+
+        if let Some(qos) = self.mqtt_qos {
+            if qos > 2 {
+                problems.push(format!("mqtt_qos = {}: expected 0, 1 or 2", qos));
+            }
+        }
+
+        if let Some(url) = &self.nats {
+            if let Err(e) = crate::nats::parse_url(url) {
+                problems.push(format!("nats: {}", e));
+            }
+        }
+
+        if self.nats_token.is_some() && self.nats_user.is_some() {
+            problems.push("nats: set either nats_token or nats_user".to_string());
+        }
+
+        if self.opensensemap.is_some()
+            && (self.opensensemap_pm25.is_none() || self.opensensemap_pm10.is_none())
+        {
+            problems.push(
+                "opensensemap: opensensemap_pm25 and opensensemap_pm10 must be set".to_string(),
+            );
+        }
+
+        if self.opensensemap_interval == Some(0) {
+            problems.push("opensensemap_interval = 0: expected at least 1 second".to_string());
+        }
+
+        if self.thingspeak_interval == Some(0) {
+            problems.push("thingspeak_interval = 0: expected at least 1 second".to_string());
+        }
+
+        if let Some(url) = &self.robonomics {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                problems.push(format!("robonomics = {}: expected an http(s) URL", url));
+            }
+            if self.robonomics_key.is_none() {
+                problems.push("robonomics: robonomics_key must be set".to_string());
+            }
+        }
+
+        if self.robonomics_interval == Some(0) {
+            problems.push("robonomics_interval = 0: expected at least 1 second".to_string());
+        }
+
+        if let Some(url) = &self.webhook {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                problems.push(format!("webhook = {}: expected an http(s) URL", url));
+            }
+        }
+
+        if let Some(headers) = &self.webhook_header {
+            if let Err(e) = crate::webhook::parse_headers(headers) {
+                problems.push(format!("webhook_header: {}", e));
+            }
+        }
+
+        for (name, threshold) in [
+            ("alert_pm25", self.alert_pm25),
+            ("alert_pm10", self.alert_pm10),
+        ]
+        .iter()
+        {
+            if let Some(threshold) = threshold {
+                if *threshold <= 0.0 || threshold.is_nan() {
+                    problems.push(format!(
+                        "{} = {}: expected a positive µg/m³ value",
+                        name, threshold
+                    ));
+                }
+            }
+        }
+
+        let notifiers = [
+            (
+                "telegram_token",
+                self.telegram_token.is_some(),
+                "telegram_chat",
+                self.telegram_chat.is_some(),
+            ),
+            (
+                "pushover_token",
+                self.pushover_token.is_some(),
+                "pushover_user",
+                self.pushover_user.is_some(),
+            ),
+        ];
+        for (name, set, needed, needed_set) in notifiers.iter() {
+            if *set && !needed_set {
+                problems.push(format!("{}: {} must be set", name, needed));
+            }
+            if *set && self.alert_pm25.is_none() && self.alert_pm10.is_none() {
+                problems.push(format!(
+                    "{}: no alert_pm25 or alert_pm10 to notify of",
+                    name
+                ));
+            }
+        }
+
+        if let Some(fraction) = self.laser_warning {
+            if !(fraction > 0.0 && fraction <= 1.0) {
+                problems.push(format!(
+                    "laser_warning = {}: expected a fraction above 0 and at most 1",
+                    fraction
+                ));
+            }
+        }
+
+        if self.forward.is_some() && self.station.is_none() {
+            problems.push("forward: station must be set".to_string());
+        }
+
+        if probe {
+            for (name, addr) in self.endpoints() {
+                if let Err(e) = check_reachable(&addr) {
+                    problems.push(format!("{}: {} is unreachable: {}", name, addr, e));
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Servers the configured sinks, notifiers and gateway connect to, as
+    /// `(setting, host:port)`
+    /// StatsD is left out, there's no connection to probe over UDP.
+    fn endpoints(&self) -> Vec<(&'static str, String)> {
+        let mut endpoints = Vec::new();
+        let urls = [
+            ("influx", &self.influx),
+            ("ipfs", &self.ipfs),
+            ("robonomics", &self.robonomics),
+            ("webhook", &self.webhook),
+        ];
+        for (name, url) in urls.iter() {
+            if let Some(addr) = url.as_deref().and_then(http_addr) {
+                endpoints.push((*name, addr));
+            }
+        }
+        for (name, url) in [("mqtt", &self.mqtt), ("remote", &self.remote)].iter() {
+            if let Some(url) = url.as_deref().and_then(|u| crate::mqtt::parse_url(u).ok()) {
+                endpoints.push((*name, format!("{}:{}", url.host, url.port)));
+            }
+        }
+        if let Some(url) = self
+            .nats
+            .as_deref()
+            .and_then(|u| crate::nats::parse_url(u).ok())
+        {
+            endpoints.push(("nats", url.addr));
+        }
+        for (name, addr) in [("graphite", &self.graphite), ("forward", &self.forward)].iter() {
+            if let Some(addr) = addr {
+                endpoints.push((*name, addr.clone()));
+            }
+        }
+        let services = [
+            (
+                "sensor_community",
+                self.sensor_community.is_some(),
+                "api.sensor.community:443",
+            ),
+            (
+                "opensensemap",
+                self.opensensemap.is_some(),
+                "api.opensensemap.org:443",
+            ),
+            (
+                "thingspeak",
+                self.thingspeak.is_some(),
+                "api.thingspeak.com:443",
+            ),
+            (
+                "telegram_token",
+                self.telegram_token.is_some(),
+                "api.telegram.org:443",
+            ),
+            (
+                "pushover_token",
+                self.pushover_token.is_some(),
+                "api.pushover.net:443",
+            ),
+        ];
+        for (name, set, addr) in services.iter() {
+            if *set {
+                endpoints.push((*name, addr.to_string()));
+            }
+        }
+        endpoints
+    }
+}
+
+/// `host:port` of an http(s) URL, the port defaults to the scheme's
+fn http_addr(url: &str) -> Option<String> {
+    let (rest, port) = match url.strip_prefix("https://") {
+        Some(rest) => (rest, 443),
+        None => (url.strip_prefix("http://")?, 80),
+    };
+    let authority = rest.split('/').next()?;
+    let host = authority.rsplit('@').next()?;
+    if host.is_empty() {
+        return None;
+    }
+    // An IPv6 address has colons of its own, in brackets
+    match host.rsplit_once(':') {
+        Some((_, p)) if !p.ends_with(']') => Some(host.to_string()),
+        _ => Some(format!("{}:{}", host, port)),
+    }
+}
+
+/// Checks that a server accepts TCP connections on `addr`
+fn check_reachable(addr: &str) -> Result<(), String> {
+    let addrs = addr.to_socket_addrs().map_err(|e| e.to_string())?;
+    let mut last = "no address".to_string();
+    for a in addrs {
+        match TcpStream::connect_timeout(&a, PROBE_TIMEOUT) {
+            Ok(_) => return Ok(()),
+            Err(e) => last = e.to_string(),
+        }
+    }
+    Err(last)
+}
+
+/// Checks that `port` exists, with `probe` that a sensor answers on it
+fn check_port(port: &str, probe: bool) -> Result<(), String> {
+    if port == "auto" {
+        if probe {
+            crate::device::resolve_port(port)?;
+        }
+    } else if port.contains("://") {
+        if probe {
+            SDS011::open(port)
+                .and_then(|mut s| s.query())
+                .map_err(|e| format!("sensor probe failed: {}", e))?;
+        }
+    } else if !port_exists(port) {
+        return Err("no such port, check the path and the cable".to_string());
+    } else if probe {
+        SDS011::open(port)
+            .and_then(|mut s| s.query())
+            .map_err(|e| format!("sensor probe failed: {}", e))?;
+    }
+    Ok(())
+}
+
+fn port_exists(port: &str) -> bool {
+    if Path::new(port).exists() {
+        return true;
+    }
+    sds011::available_ports()
+        .map(|ports| ports.iter().any(|p| p.path == port))
+        .unwrap_or(false)
+}
+
+/// Where a setting value comes from
+#[derive(Debug, PartialEq, Clone)]
+pub enum Source {
+    Default,
+    File(String),
+    Env(&'static str),
+    Flag(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "file {}", path),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Flag(flag) => write!(f, "flag --{}", flag),
+        }
+    }
+}
+
+/// Setting value with its provenance
+#[derive(Debug, PartialEq, Clone)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+/// Fully merged configuration
+#[derive(Debug, PartialEq, Clone)]
+pub struct Effective {
+    pub port: Setting<String>,
+    pub sensors: Vec<Setting<SensorConfig>>,
+    pub work_period: Setting<u8>,
+    pub user: Option<Setting<String>>,
+    pub seccomp: Option<Setting<bool>>,
+    pub calibration: Option<Setting<String>>,
+    pub script: Option<Setting<String>>,
+    pub time_format: Option<Setting<String>>,
+    pub local_time: Option<Setting<bool>>,
+    pub format: Setting<String>,
+    pub remote: Option<Setting<String>>,
+    pub remote_token: Option<Setting<String>>,
+    pub remote_ca: Option<Setting<String>>,
+    pub forward: Option<Setting<String>>,
+    pub forward_token: Option<Setting<String>>,
+    pub station: Option<Setting<String>>,
+    pub spool: Setting<String>,
+    pub fsync: Setting<String>,
+    pub directory: Option<Setting<String>>,
+    pub csv: Option<Setting<String>>,
+    pub jsonl: Option<Setting<String>>,
+    pub jsonl_max_mb: Option<Setting<u64>>,
+    pub sqlite: Option<Setting<String>>,
+    pub influx: Option<Setting<String>>,
+    pub influx_org: Option<Setting<String>>,
+    pub influx_bucket: Option<Setting<String>>,
+    pub influx_database: Option<Setting<String>>,
+    pub influx_token: Option<Setting<String>>,
+    pub ipfs: Option<Setting<String>>,
+    pub ipfs_format: Option<Setting<String>>,
+    pub ipfs_batch: Option<Setting<usize>>,
+    pub graphite: Option<Setting<String>>,
+    pub statsd: Option<Setting<String>>,
+    pub metric_prefix: Option<Setting<String>>,
+    pub mqtt: Option<Setting<String>>,
+    pub mqtt_user: Option<Setting<String>>,
+    pub mqtt_password: Option<Setting<String>>,
+    pub mqtt_ca: Option<Setting<String>>,
+    pub mqtt_qos: Option<Setting<u8>>,
+    pub mqtt_retain: Option<Setting<bool>>,
+    pub mqtt_discovery: Option<Setting<bool>>,
+    pub mqtt_availability: Option<Setting<String>>,
+    pub nats: Option<Setting<String>>,
+    pub nats_user: Option<Setting<String>>,
+    pub nats_password: Option<Setting<String>>,
+    pub nats_token: Option<Setting<String>>,
+    pub nats_jetstream: Option<Setting<bool>>,
+    pub http: Option<Setting<String>>,
+    pub coap: Option<Setting<String>>,
+    pub grpc: Option<Setting<String>>,
+    pub sensor_community: Option<Setting<String>>,
+    pub opensensemap: Option<Setting<String>>,
+    pub opensensemap_pm25: Option<Setting<String>>,
+    pub opensensemap_pm10: Option<Setting<String>>,
+    pub opensensemap_token: Option<Setting<String>>,
+    pub opensensemap_interval: Option<Setting<u64>>,
+    pub thingspeak: Option<Setting<String>>,
+    pub thingspeak_interval: Option<Setting<u64>>,
+    pub robonomics: Option<Setting<String>>,
+    pub robonomics_key: Option<Setting<String>>,
+    pub robonomics_token: Option<Setting<String>>,
+    pub robonomics_interval: Option<Setting<u64>>,
+    pub webhook: Option<Setting<String>>,
+    pub webhook_header: Option<Setting<String>>,
+    pub webhook_encrypt: Option<Setting<String>>,
+    pub alert_pm25: Option<Setting<f32>>,
+    pub alert_pm10: Option<Setting<f32>>,
+    pub alert_duration: Option<Setting<u64>>,
+    pub telegram_token: Option<Setting<String>>,
+    pub telegram_chat: Option<Setting<String>>,
+    pub pushover_token: Option<Setting<String>>,
+    pub pushover_user: Option<Setting<String>>,
+    pub hours_file: Option<Setting<String>>,
+    pub laser_warning: Option<Setting<f64>>,
+}
+
+impl Effective {
+    /// Merges defaults, the `--config` file, environment and flags
+    pub fn resolve(matches: &ArgMatches) -> Result<Effective, String> {
+        let path = matches.value_of("config");
+        let file = match path {
+            Some(p) => Some(Config::load(p)?),
+            None => None,
+        };
+        let layers = Layers {
+            matches,
+            file_source: Source::File(path.unwrap_or_default().to_string()),
+        };
+        let file = file.unwrap_or_default();
+
+        let port = layers.required("port", "port", "SDS011_PORT", file.port)?;
+        let bridge = layers.bridge()?;
+        let work_period = layers.required(
+            "work_period",
+            "work",
+            "SDS011_WORK_PERIOD",
+            file.work_period,
+        )?;
+        // The sensor rejects longer periods
+        if work_period.value > 30 {
+            return Err(format!(
+                "work_period = {} ({}): must be between 0 and 30 minutes",
+                work_period.value, work_period.source
+            ));
+        }
+        Ok(Effective {
+            sensors: layers.sensors(&port, file.sensors),
+            port,
+            work_period,
+            user: layers.optional("user", "user", "SDS011_USER", file.user)?,
+            seccomp: layers.optional("seccomp", "seccomp", "SDS011_SECCOMP", file.seccomp)?,
+            calibration: layers.optional(
+                "calibration",
+                "calibration",
+                "SDS011_CALIBRATION",
+                file.calibration,
+            )?,
+            script: layers.optional("script", "script", "SDS011_SCRIPT", file.script)?,
+            time_format: layers.optional(
+                "time_format",
+                "time-format",
+                "SDS011_TIME_FORMAT",
+                file.time_format,
+            )?,
+            local_time: layers.optional(
+                "local_time",
+                "local-time",
+                "SDS011_LOCAL_TIME",
+                file.local_time,
+            )?,
+            format: layers.required("format", "format", "SDS011_FORMAT", file.format)?,
+            remote: layers.optional("remote", "remote", "SDS011_REMOTE", file.remote)?,
+            remote_token: layers.optional(
+                "remote_token",
+                "remote-token",
+                "SDS011_REMOTE_TOKEN",
+                file.remote_token,
+            )?,
+            remote_ca: layers.optional(
+                "remote_ca",
+                "remote-ca",
+                "SDS011_REMOTE_CA",
+                file.remote_ca,
+            )?,
+            forward: layers.optional("forward", "forward", "SDS011_FORWARD", file.forward)?,
+            forward_token: layers.optional(
+                "forward_token",
+                "forward-token",
+                "SDS011_FORWARD_TOKEN",
+                file.forward_token,
+            )?,
+            station: layers.optional("station", "station", "SDS011_STATION", file.station)?,
+            spool: layers.required("spool", "spool", "SDS011_SPOOL", file.spool)?,
+            fsync: layers.required("fsync", "fsync", "SDS011_FSYNC", file.fsync)?,
+            directory: layers.optional(
+                "directory",
+                "directory",
+                "SDS011_DIRECTORY",
+                file.directory,
+            )?,
+            csv: layers.optional("csv", "csv", "SDS011_CSV", file.csv)?,
+            jsonl: layers.optional("jsonl", "jsonl", "SDS011_JSONL", file.jsonl)?,
+            jsonl_max_mb: layers.optional(
+                "jsonl_max_mb",
+                "jsonl-max-mb",
+                "SDS011_JSONL_MAX_MB",
+                file.jsonl_max_mb,
+            )?,
+            sqlite: layers.optional("sqlite", "sqlite", "SDS011_SQLITE", file.sqlite)?,
+            influx: layers.optional("influx", "influx", "SDS011_INFLUX", file.influx)?,
+            influx_org: layers.optional(
+                "influx_org",
+                "influx-org",
+                "SDS011_INFLUX_ORG",
+                file.influx_org,
+            )?,
+            influx_bucket: layers.optional(
+                "influx_bucket",
+                "influx-bucket",
+                "SDS011_INFLUX_BUCKET",
+                file.influx_bucket,
+            )?,
+            influx_database: layers.optional(
+                "influx_database",
+                "influx-database",
+                "SDS011_INFLUX_DATABASE",
+                file.influx_database,
+            )?,
+            influx_token: layers.optional(
+                "influx_token",
+                "influx-token",
+                "SDS011_INFLUX_TOKEN",
+                file.influx_token,
+            )?,
+            ipfs: layers.optional("ipfs", "ipfs", "SDS011_IPFS", file.ipfs)?,
+            ipfs_format: layers.optional(
+                "ipfs_format",
+                "ipfs-format",
+                "SDS011_IPFS_FORMAT",
+                file.ipfs_format,
+            )?,
+            ipfs_batch: layers.optional(
+                "ipfs_batch",
+                "ipfs-batch",
+                "SDS011_IPFS_BATCH",
+                file.ipfs_batch,
+            )?,
+            graphite: layers.optional("graphite", "graphite", "SDS011_GRAPHITE", file.graphite)?,
+            statsd: layers.optional("statsd", "statsd", "SDS011_STATSD", file.statsd)?,
+            metric_prefix: layers.optional(
+                "metric_prefix",
+                "metric-prefix",
+                "SDS011_METRIC_PREFIX",
+                file.metric_prefix,
+            )?,
+            mqtt: match &bridge {
+                Some((url, _)) => Some(url.clone()),
+                None => layers.optional("mqtt", "mqtt", "SDS011_MQTT", file.mqtt)?,
+            },
+            mqtt_user: layers.optional(
+                "mqtt_user",
+                "mqtt-user",
+                "SDS011_MQTT_USER",
+                file.mqtt_user,
+            )?,
+            mqtt_password: layers.optional(
+                "mqtt_password",
+                "mqtt-password",
+                "SDS011_MQTT_PASSWORD",
+                file.mqtt_password,
+            )?,
+            mqtt_ca: layers.optional("mqtt_ca", "mqtt-ca", "SDS011_MQTT_CA", file.mqtt_ca)?,
+            mqtt_qos: layers.optional("mqtt_qos", "mqtt-qos", "SDS011_MQTT_QOS", file.mqtt_qos)?,
+            mqtt_retain: layers.optional(
+                "mqtt_retain",
+                "mqtt-retain",
+                "SDS011_MQTT_RETAIN",
+                file.mqtt_retain,
+            )?,
+            mqtt_discovery: layers.optional(
+                "mqtt_discovery",
+                "mqtt-discovery",
+                "SDS011_MQTT_DISCOVERY",
+                file.mqtt_discovery,
+            )?,
+            mqtt_availability: layers
+                .optional(
+                    "mqtt_availability",
+                    "mqtt-availability",
+                    "SDS011_MQTT_AVAILABILITY",
+                    file.mqtt_availability,
+                )?
+                .or(bridge.map(|(_, availability)| availability)),
+            nats: layers.optional("nats", "nats", "SDS011_NATS", file.nats)?,
+            nats_user: layers.optional(
+                "nats_user",
+                "nats-user",
+                "SDS011_NATS_USER",
+                file.nats_user,
+            )?,
+            nats_password: layers.optional(
+                "nats_password",
+                "nats-password",
+                "SDS011_NATS_PASSWORD",
+                file.nats_password,
+            )?,
+            nats_token: layers.optional(
+                "nats_token",
+                "nats-token",
+                "SDS011_NATS_TOKEN",
+                file.nats_token,
+            )?,
+            nats_jetstream: layers.optional(
+                "nats_jetstream",
+                "nats-jetstream",
+                "SDS011_NATS_JETSTREAM",
+                file.nats_jetstream,
+            )?,
+            http: layers.optional("http", "http", "SDS011_HTTP", file.http)?,
+            coap: layers.optional("coap", "coap", "SDS011_COAP", file.coap)?,
+            grpc: layers.optional("grpc", "grpc", "SDS011_GRPC", file.grpc)?,
+            sensor_community: layers.optional(
+                "sensor_community",
+                "sensor-community",
+                "SDS011_SENSOR_COMMUNITY",
+                file.sensor_community,
+            )?,
+            opensensemap: layers.optional(
+                "opensensemap",
+                "opensensemap",
+                "SDS011_OPENSENSEMAP",
+                file.opensensemap,
+            )?,
+            opensensemap_pm25: layers.optional(
+                "opensensemap_pm25",
+                "opensensemap-pm25",
+                "SDS011_OPENSENSEMAP_PM25",
+                file.opensensemap_pm25,
+            )?,
+            opensensemap_pm10: layers.optional(
+                "opensensemap_pm10",
+                "opensensemap-pm10",
+                "SDS011_OPENSENSEMAP_PM10",
+                file.opensensemap_pm10,
+            )?,
+            opensensemap_token: layers.optional(
+                "opensensemap_token",
+                "opensensemap-token",
+                "SDS011_OPENSENSEMAP_TOKEN",
+                file.opensensemap_token,
+            )?,
+            opensensemap_interval: layers.optional(
+                "opensensemap_interval",
+                "opensensemap-interval",
+                "SDS011_OPENSENSEMAP_INTERVAL",
+                file.opensensemap_interval,
+            )?,
+            thingspeak: layers.optional(
+                "thingspeak",
+                "thingspeak",
+                "SDS011_THINGSPEAK",
+                file.thingspeak,
+            )?,
+            thingspeak_interval: layers.optional(
+                "thingspeak_interval",
+                "thingspeak-interval",
+                "SDS011_THINGSPEAK_INTERVAL",
+                file.thingspeak_interval,
+            )?,
+            robonomics: layers.optional(
+                "robonomics",
+                "robonomics",
+                "SDS011_ROBONOMICS",
+                file.robonomics,
+            )?,
+            robonomics_key: layers.optional(
+                "robonomics_key",
+                "robonomics-key",
+                "SDS011_ROBONOMICS_KEY",
+                file.robonomics_key,
+            )?,
+            robonomics_token: layers.optional(
+                "robonomics_token",
+                "robonomics-token",
+                "SDS011_ROBONOMICS_TOKEN",
+                file.robonomics_token,
+            )?,
+            robonomics_interval: layers.optional(
+                "robonomics_interval",
+                "robonomics-interval",
+                "SDS011_ROBONOMICS_INTERVAL",
+                file.robonomics_interval,
+            )?,
+            webhook: layers.optional("webhook", "webhook", "SDS011_WEBHOOK", file.webhook)?,
+            webhook_header: layers.optional(
+                "webhook_header",
+                "webhook-header",
+                "SDS011_WEBHOOK_HEADER",
+                file.webhook_header,
+            )?,
+            webhook_encrypt: layers.optional(
+                "webhook_encrypt",
+                "webhook-encrypt",
+                "SDS011_WEBHOOK_ENCRYPT",
+                file.webhook_encrypt,
+            )?,
+            alert_pm25: layers.optional(
+                "alert_pm25",
+                "alert-pm25",
+                "SDS011_ALERT_PM25",
+                file.alert_pm25,
+            )?,
+            alert_pm10: layers.optional(
+                "alert_pm10",
+                "alert-pm10",
+                "SDS011_ALERT_PM10",
+                file.alert_pm10,
+            )?,
+            alert_duration: layers.optional(
+                "alert_duration",
+                "alert-duration",
+                "SDS011_ALERT_DURATION",
+                file.alert_duration,
+            )?,
+            telegram_token: layers.optional(
+                "telegram_token",
+                "telegram-token",
+                "SDS011_TELEGRAM_TOKEN",
+                file.telegram_token,
+            )?,
+            telegram_chat: layers.optional(
+                "telegram_chat",
+                "telegram-chat",
+                "SDS011_TELEGRAM_CHAT",
+                file.telegram_chat,
+            )?,
+            pushover_token: layers.optional(
+                "pushover_token",
+                "pushover-token",
+                "SDS011_PUSHOVER_TOKEN",
+                file.pushover_token,
+            )?,
+            pushover_user: layers.optional(
+                "pushover_user",
+                "pushover-user",
+                "SDS011_PUSHOVER_USER",
+                file.pushover_user,
+            )?,
+            hours_file: layers.optional(
+                "hours_file",
+                "hours-file",
+                "SDS011_HOURS_FILE",
+                file.hours_file,
+            )?,
+            laser_warning: layers.optional(
+                "laser_warning",
+                "laser-warning",
+                "SDS011_LASER_WARNING",
+                file.laser_warning,
+            )?,
+        })
+    }
+
+    /// Timestamp format of printed measurements
+    pub fn timestamp_format(&self) -> Result<TimestampFormat, String> {
+        let format = match &self.time_format {
+            Some(s) => s.value.parse().map_err(|e| format!("{}", e))?,
+            None => TimestampFormat::default(),
+        };
+        match &self.local_time {
+            Some(s) if s.value => Ok(format.zone(Zone::Local)),
+            _ => Ok(format),
+        }
+    }
+
+    /// Format of printed measurements
+    pub fn output_format(&self) -> Result<Format, String> {
+        self.format.value.parse()
+    }
+
+    /// Sync policy of written files
+    pub fn sync_policy(&self) -> Result<SyncPolicy, String> {
+        self.fsync.value.parse().map_err(|e| format!("{}", e))
+    }
+
+    /// Prints settings as TOML with provenance comments
+    pub fn print(&self) {
+        print_setting("port", Some(&self.port));
+        print_setting("work_period", Some(&self.work_period));
+        print_setting("user", self.user.as_ref());
+        print_setting("seccomp", self.seccomp.as_ref());
+        print_setting("calibration", self.calibration.as_ref());
+        print_setting("script", self.script.as_ref());
+        print_setting("time_format", self.time_format.as_ref());
+        print_setting("local_time", self.local_time.as_ref());
+        print_setting("format", Some(&self.format));
+        print_setting("remote", self.remote.as_ref());
+        print_setting("remote_token", redact(self.remote_token.as_ref()).as_ref());
+        print_setting("remote_ca", self.remote_ca.as_ref());
+        print_setting("forward", self.forward.as_ref());
+        print_setting(
+            "forward_token",
+            redact(self.forward_token.as_ref()).as_ref(),
+        );
+        print_setting("station", self.station.as_ref());
+        print_setting("spool", Some(&self.spool));
+        print_setting("fsync", Some(&self.fsync));
+        print_setting("directory", self.directory.as_ref());
+        print_setting("csv", self.csv.as_ref());
+        print_setting("jsonl", self.jsonl.as_ref());
+        print_setting("jsonl_max_mb", self.jsonl_max_mb.as_ref());
+        print_setting("sqlite", self.sqlite.as_ref());
+        print_setting("influx", self.influx.as_ref());
+        print_setting("influx_org", self.influx_org.as_ref());
+        print_setting("influx_bucket", self.influx_bucket.as_ref());
+        print_setting("influx_database", self.influx_database.as_ref());
+        print_setting("influx_token", redact(self.influx_token.as_ref()).as_ref());
+        print_setting("ipfs", self.ipfs.as_ref());
+        print_setting("ipfs_format", self.ipfs_format.as_ref());
+        print_setting("ipfs_batch", self.ipfs_batch.as_ref());
+        print_setting("graphite", self.graphite.as_ref());
+        print_setting("statsd", self.statsd.as_ref());
+        print_setting("metric_prefix", self.metric_prefix.as_ref());
+        print_setting("mqtt", self.mqtt.as_ref());
+        print_setting("mqtt_user", self.mqtt_user.as_ref());
+        print_setting(
+            "mqtt_password",
+            redact(self.mqtt_password.as_ref()).as_ref(),
+        );
+        print_setting("mqtt_ca", self.mqtt_ca.as_ref());
+        print_setting("mqtt_qos", self.mqtt_qos.as_ref());
+        print_setting("mqtt_retain", self.mqtt_retain.as_ref());
+        print_setting("mqtt_discovery", self.mqtt_discovery.as_ref());
+        print_setting("mqtt_availability", self.mqtt_availability.as_ref());
+        print_setting("nats", self.nats.as_ref());
+        print_setting("nats_user", self.nats_user.as_ref());
+        print_setting(
+            "nats_password",
+            redact(self.nats_password.as_ref()).as_ref(),
+        );
+        print_setting("nats_token", redact(self.nats_token.as_ref()).as_ref());
+        print_setting("nats_jetstream", self.nats_jetstream.as_ref());
+        print_setting("http", self.http.as_ref());
+        print_setting("coap", self.coap.as_ref());
+        print_setting("grpc", self.grpc.as_ref());
+        print_setting("sensor_community", self.sensor_community.as_ref());
+        print_setting("opensensemap", self.opensensemap.as_ref());
+        print_setting("opensensemap_pm25", self.opensensemap_pm25.as_ref());
+        print_setting("opensensemap_pm10", self.opensensemap_pm10.as_ref());
+        print_setting(
+            "opensensemap_token",
+            redact(self.opensensemap_token.as_ref()).as_ref(),
+        );
+        print_setting("opensensemap_interval", self.opensensemap_interval.as_ref());
+        print_setting("thingspeak", redact(self.thingspeak.as_ref()).as_ref());
+        print_setting("thingspeak_interval", self.thingspeak_interval.as_ref());
+        print_setting("robonomics", self.robonomics.as_ref());
+        print_setting("robonomics_key", self.robonomics_key.as_ref());
+        print_setting(
+            "robonomics_token",
+            redact(self.robonomics_token.as_ref()).as_ref(),
+        );
+        print_setting("robonomics_interval", self.robonomics_interval.as_ref());
+        print_setting("webhook", self.webhook.as_ref());
+        print_setting(
+            "webhook_header",
+            redact(self.webhook_header.as_ref()).as_ref(),
+        );
+        print_setting("webhook_encrypt", self.webhook_encrypt.as_ref());
+        print_setting("alert_pm25", self.alert_pm25.as_ref());
+        print_setting("alert_pm10", self.alert_pm10.as_ref());
+        print_setting("alert_duration", self.alert_duration.as_ref());
+        print_setting(
+            "telegram_token",
+            redact(self.telegram_token.as_ref()).as_ref(),
+        );
+        print_setting("telegram_chat", self.telegram_chat.as_ref());
+        print_setting(
+            "pushover_token",
+            redact(self.pushover_token.as_ref()).as_ref(),
+        );
+        print_setting(
+            "pushover_user",
+            redact(self.pushover_user.as_ref()).as_ref(),
+        );
+        print_setting("hours_file", self.hours_file.as_ref());
+        print_setting("laser_warning", self.laser_warning.as_ref());
+        if self.sensors.len() > 1 || self.sensors.iter().any(|s| s.value.name.is_some()) {
+            for sensor in self.sensors.iter() {
+                println!("\n[[sensors]]  # {}", sensor.source);
+                if let Some(name) = &sensor.value.name {
+                    println!("name = {:?}", name);
+                }
+                println!("port = {:?}", sensor.value.port);
+            }
+        }
+    }
+}
+
+/// Hides the value of a secret, never print them
+fn redact(setting: Option<&Setting<String>>) -> Option<Setting<&'static str>> {
+    setting.map(|s| Setting {
+        value: "<redacted>",
+        source: s.source.clone(),
+    })
+}
+
+fn print_setting<T: fmt::Debug>(key: &str, setting: Option<&Setting<T>>) {
+    match setting {
+        Some(s) => println!("{} = {:?}  # {}", key, s.value, s.source),
+        None => println!("# {} is not set", key),
+    }
+}
+
+/// Resolves settings from flags, environment, the file and defaults
+struct Layers<'a> {
+    matches: &'a ArgMatches<'a>,
+    file_source: Source,
+}
+
+impl<'a> Layers<'a> {
+    /// Resolves a setting whose `arg` has a default value
+    fn required<T: FromStr>(
+        &self,
+        arg: &str,
+        flag: &'static str,
+        env: &'static str,
+        file: Option<T>,
+    ) -> Result<Setting<T>, String> {
+        if let Some(s) = self.optional(arg, flag, env, file)? {
+            return Ok(s);
+        }
+
+        let default = self.matches.value_of(arg).unwrap();
+        Ok(Setting {
+            value: parse(default, &"default")?,
+            source: Source::Default,
+        })
+    }
+
+    /// Resolves `--broker` and `--topic` of the `mqtt` subcommand into the
+    /// `mqtt` URL and the availability topic it defaults to,
+    /// `<topic>/status`
+    fn bridge(&self) -> Result<Option<Bridge>, String> {
+        let broker = match self.matches.value_of("broker") {
+            Some(b) => b,
+            None => return Ok(None),
+        };
+        let topic = self.matches.value_of("topic").unwrap_or("sds011");
+        let url = crate::mqtt::broker_url(broker, topic)?;
+        let availability = format!("{}/status", crate::mqtt::parse_url(&url)?.topic);
+        Ok(Some((
+            Setting {
+                value: url,
+                source: Source::Flag("broker"),
+            },
+            Setting {
+                value: availability,
+                source: Source::Default,
+            },
+        )))
+    }
+
+    /// Resolves the sensors to poll: every `--port` if there are several,
+    /// else the file's `[[sensors]]` unless a flag or the environment set
+    /// `port`, else just `port`
+    fn sensors(
+        &self,
+        port: &Setting<String>,
+        file: Option<Vec<SensorConfig>>,
+    ) -> Vec<Setting<SensorConfig>> {
+        if self.matches.occurrences_of("port") > 1 {
+            let ports = self.matches.values_of("port").unwrap_or_default();
+            return ports
+                .map(|p| Setting {
+                    value: SensorConfig {
+                        name: None,
+                        port: p.to_string(),
+                    },
+                    source: Source::Flag("port"),
+                })
+                .collect();
+        }
+        let from_file = matches!(port.source, Source::Default | Source::File(_));
+        match file {
+            Some(sensors) if from_file && !sensors.is_empty() => sensors
+                .into_iter()
+                .map(|value| Setting {
+                    value,
+                    source: self.file_source.clone(),
+                })
+                .collect(),
+            _ => vec![Setting {
+                value: SensorConfig {
+                    name: None,
+                    port: port.value.clone(),
+                },
+                source: port.source.clone(),
+            }],
+        }
+    }
+
+    /// Resolves a setting without a default value,
+    /// an `arg` taking no value is a boolean flag
+    fn optional<T: FromStr>(
+        &self,
+        arg: &str,
+        flag: &'static str,
+        env: &'static str,
+        file: Option<T>,
+    ) -> Result<Option<Setting<T>>, String> {
+        if self.matches.occurrences_of(arg) > 0 {
+            let value = self.matches.value_of(arg).unwrap_or("true");
+            return Ok(Some(Setting {
+                value: parse(value, &format!("--{}", flag))?,
+                source: Source::Flag(flag),
+            }));
+        }
+        if let Ok(s) = std::env::var(env) {
+            return Ok(Some(Setting {
+                value: parse(&s, &env)?,
+                source: Source::Env(env),
+            }));
+        }
+        Ok(file.map(|value| Setting {
+            value,
+            source: self.file_source.clone(),
+        }))
+    }
+}
+
+/// `mqtt` URL and availability topic of the `mqtt` subcommand
+type Bridge = (Setting<String>, Setting<String>);
+
+fn parse<T: FromStr>(s: &str, what: &dyn fmt::Display) -> Result<T, String> {
+    s.parse::<T>()
+        .map_err(|_| format!("{}: invalid value \"{}\"", what, s))
+}
+
+/// Runs `config show`, prints the file or, with `effective`,
+/// the merged configuration
+pub fn show(matches: &ArgMatches, effective: bool) -> i32 {
+    if effective {
+        return match Effective::resolve(matches) {
+            Ok(e) => {
+                e.print();
+                0
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                1
+            }
+        };
+    }
+
+    let path = matches.value_of("config").unwrap_or("sds011.toml");
+    match Config::load(path).and_then(|c| toml::to_string(&c).map_err(|e| e.to_string())) {
+        Ok(text) => {
+            print!("{}", text);
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+/// Runs `config validate`, returns the process exit code
+pub fn validate(path: &str, probe: bool) -> i32 {
+    let config = match Config::load(path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 1;
+        }
+    };
+
+    let problems = config.validate(probe);
+    if problems.is_empty() {
+        println!("{}: OK", path);
+        return 0;
+    }
+
+    for p in problems.iter() {
+        eprintln!("error: {}", p);
+    }
+    1
+}
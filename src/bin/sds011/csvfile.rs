@@ -0,0 +1,43 @@
+//! Optional CSV file measurements are appended to, see `sds011::sink::csv`.
+
+use sds011::durable::SyncPolicy;
+use sds011::sink::Sink;
+use sds011::timestamp::TimestampFormat;
+use serde_json::Value;
+
+/// Opens the CSV sink with the daemon's columns: timestamp, device ID,
+/// PM values, US AQI and `fields`
+#[cfg(feature = "csv")]
+pub fn open(
+    path: &str,
+    device_id: Option<u16>,
+    fields: &[(&str, Value)],
+    format: TimestampFormat,
+    policy: SyncPolicy,
+) -> Result<Box<dyn Sink>, String> {
+    use sds011::aqi::AqiScale;
+    use sds011::sink::csv::CsvWriter;
+
+    let mut writer = CsvWriter::new(path)
+        .aqi(AqiScale::Us)
+        .timestamp_format(format)
+        .sync_policy(policy);
+    if let Some(id) = device_id {
+        writer = writer.device_id(id);
+    }
+    for (name, value) in fields.iter() {
+        writer = writer.field(name, value.clone());
+    }
+    Ok(Box::new(writer))
+}
+
+#[cfg(not(feature = "csv"))]
+pub fn open(
+    _path: &str,
+    _device_id: Option<u16>,
+    _fields: &[(&str, Value)],
+    _format: TimestampFormat,
+    _policy: SyncPolicy,
+) -> Result<Box<dyn Sink>, String> {
+    Err("this build has no CSV support, rebuild with --features csv".to_string())
+}
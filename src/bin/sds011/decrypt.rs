@@ -0,0 +1,102 @@
+//! `decrypt` subcommand: the receiving side of encrypted sink payloads.
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sds011::encryption::Decryptor;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("decrypt")
+        .about("Decrypts sealed payloads, one base64 payload per line")
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .takes_value(true)
+                .required_unless("new_key")
+                .help("File with the hex encoded secret key"),
+        )
+        .arg(
+            Arg::with_name("new_key")
+                .long("new-key")
+                .takes_value(true)
+                .conflicts_with("key")
+                .help("Generate a key pair, write the secret key here and print the public key"),
+        )
+        .arg(Arg::with_name("input").help("File with payloads [default: stdin]"))
+}
+
+/// Runs the subcommand and returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    if let Some(path) = m.value_of("new_key") {
+        return new_key(path);
+    }
+
+    let decryptor = match Decryptor::load(m.value_of("key").unwrap()) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 1;
+        }
+    };
+
+    let input: Box<dyn BufRead> = match m.value_of("input") {
+        Some(path) => match File::open(path) {
+            Ok(f) => Box::new(BufReader::new(f)),
+            Err(e) => {
+                eprintln!("error: {}: {}", path, e);
+                return 1;
+            }
+        },
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+
+    let mut code = 0;
+    for (n, line) in input.lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return 1;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match decryptor.open(&line) {
+            Ok(plain) => println!("{}", String::from_utf8_lossy(&plain)),
+            Err(e) => {
+                eprintln!("line {}: {}", n + 1, e);
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+fn new_key(path: &str) -> i32 {
+    let decryptor = Decryptor::generate();
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let written = options
+        .open(path)
+        .and_then(|mut f| writeln!(f, "{}", decryptor.secret_key()));
+    if let Err(e) = written {
+        eprintln!("error: {}: {}", path, e);
+        // Don't leave an empty key file behind
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            let _ = fs::remove_file(path);
+        }
+        return 1;
+    }
+
+    println!("{}", decryptor.public_key());
+    0
+}
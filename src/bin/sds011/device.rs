@@ -0,0 +1,249 @@
+//! Subcommands running a single sensor operation: `query`, `sleep`,
+//! `wake`, `version`, `set-id`, `set-mode` and `set-work-period`, and
+//! finding the sensor: `list-ports` and `--port auto`.
+
+use crate::config::Effective;
+use crate::output::Output;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sds011::aqi::Pollutant;
+use sds011::discovery::{candidate_ports, find_sensor};
+use sds011::{Message, ReportMode, SDS011};
+use std::thread::sleep;
+
+/// Names of the subcommands
+pub const NAMES: [&str; 7] = [
+    "query",
+    "sleep",
+    "wake",
+    "version",
+    "set-id",
+    "set-mode",
+    "set-work-period",
+];
+
+fn port<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("port")
+        .short("p")
+        .long("port")
+        .takes_value(true)
+        .help("Port the sensor is connected to, or auto [default: the port setting]")
+}
+
+pub fn subcommands<'a, 'b>() -> Vec<App<'a, 'b>> {
+    vec![
+        SubCommand::with_name("query")
+            .about("Prints a single reading")
+            .arg(port())
+            .arg(Arg::with_name("wake").long("wake").help(
+                "Wake the sensor, wait for it to warm up and put it back to sleep afterwards",
+            ))
+            .arg(
+                Arg::with_name("no_color")
+                    .long("no-color")
+                    .help("Don't color the reading by AQI category"),
+            )
+            .arg(
+                Arg::with_name("fail_above")
+                    .long("fail-above")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Exit with 2 if the reading is above a threshold in µg/m³, pm25=35 or pm10=50, may be repeated"),
+            ),
+        SubCommand::with_name("sleep")
+            .about("Puts the sensor to sleep, stopping the fan and the laser")
+            .arg(port()),
+        SubCommand::with_name("wake")
+            .about("Wakes the sensor up")
+            .arg(port()),
+        SubCommand::with_name("version")
+            .about("Prints the device ID and the firmware version")
+            .arg(port()),
+        SubCommand::with_name("set-id")
+            .about("Changes the device ID")
+            .arg(port())
+            .arg(
+                Arg::with_name("id")
+                    .required(true)
+                    .help("New device ID, 4 hex digits, e.g. a160"),
+            ),
+        SubCommand::with_name("set-mode")
+            .about("Sets the report mode")
+            .arg(port())
+            .arg(
+                Arg::with_name("mode")
+                    .required(true)
+                    .possible_values(&["active", "query"])
+                    .help("active: the sensor sends readings on its own, query: only when asked"),
+            ),
+        SubCommand::with_name("set-work-period")
+            .about("Sets the work period, the sensor sleeps between readings")
+            .arg(port())
+            .arg(
+                Arg::with_name("minutes")
+                    .required(true)
+                    .help("Minutes between readings, 0 to 30, 0 is continuous"),
+            ),
+        SubCommand::with_name("list-ports")
+            .about("Lists serial ports a sensor may be connected to, likely ones first"),
+    ]
+}
+
+/// Returns `port`, or with `auto` the first port a sensor answers on
+pub fn resolve_port(port: &str) -> Result<String, String> {
+    if port != "auto" {
+        return Ok(port.to_string());
+    }
+    match find_sensor() {
+        Ok(p) => {
+            eprintln!("info: found a sensor on {}", p);
+            Ok(p.path)
+        }
+        Err(sds011::Error::DeviceNotFound) => {
+            Err("auto: no sensor found, see sds011 list-ports".to_string())
+        }
+        Err(e) => Err(format!("auto: {}", e)),
+    }
+}
+
+/// Runs `list-ports`, returns the exit code
+pub fn list_ports() -> i32 {
+    let ports = match candidate_ports() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 1;
+        }
+    };
+    if ports.is_empty() {
+        eprintln!("No serial ports found. Is the sensor plugged in?");
+        return 1;
+    }
+    for p in ports.iter() {
+        println!("{}", p);
+        if let Some(manufacturer) = p.usb.as_ref().and_then(|u| u.manufacturer.as_ref()) {
+            println!("    manufacturer: {}", manufacturer);
+        }
+    }
+    0
+}
+
+/// Exit code of `query --fail-above` when a threshold is exceeded, errors
+/// exit with 1
+const ABOVE: i32 = 2;
+
+/// Parses a `--fail-above` threshold, e.g. `pm25=35`
+fn threshold(s: &str) -> Result<(Pollutant, f32), String> {
+    let bad = || format!("--fail-above {}: expected pm25=<µg/m³> or pm10=<µg/m³>", s);
+    let (name, value) = s.split_once('=').ok_or_else(bad)?;
+    let pollutant = match name.trim().to_lowercase().as_str() {
+        "pm25" | "pm2.5" => Pollutant::Pm25,
+        "pm10" => Pollutant::Pm10,
+        _ => return Err(bad()),
+    };
+    let value = value.trim().parse().map_err(|_| bad())?;
+    Ok((pollutant, value))
+}
+
+/// Runs the subcommand `name` and returns the exit code, `matches` are
+/// the top-level ones the configuration is resolved from
+pub fn run(name: &str, m: &ArgMatches, matches: &ArgMatches) -> i32 {
+    match execute(name, m, matches) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+fn execute(name: &str, m: &ArgMatches, matches: &ArgMatches) -> Result<i32, String> {
+    let settings = Effective::resolve(matches)?;
+    let thresholds = m
+        .values_of("fail_above")
+        .unwrap_or_default()
+        .map(threshold)
+        .collect::<Result<Vec<_>, _>>()?;
+    let port = resolve_port(m.value_of("port").unwrap_or(&settings.port.value))?;
+    let mut sensor = crate::frames::open(&port).map_err(|e| format!("{}: {}", port, e))?;
+    let e = |e: sds011::Error| e.to_string();
+
+    match name {
+        "query" => {
+            let reading = query(&settings, &mut sensor, m)?;
+            let mut above = false;
+            for (pollutant, threshold) in thresholds.iter() {
+                let (name, value) = match pollutant {
+                    Pollutant::Pm25 => ("PM2.5", reading.pm25.value()),
+                    Pollutant::Pm10 => ("PM10", reading.pm10.value()),
+                };
+                if value > *threshold {
+                    eprintln!("info: {} {:.1} µg/m³ is above {}", name, value, threshold);
+                    above = true;
+                }
+            }
+            if above {
+                return Ok(ABOVE);
+            }
+        }
+        "sleep" => sensor.sleep().map_err(e)?,
+        "wake" => sensor.wake().map_err(e)?,
+        "version" => {
+            let id = sensor.device_id().map_err(e)?;
+            let firmware = sensor.firmware_version().map_err(e)?;
+            println!("device {:04x}, firmware {}", id, firmware);
+        }
+        "set-id" => {
+            let id = m.value_of("id").unwrap();
+            let id = u16::from_str_radix(id, 16)
+                .map_err(|_| format!("\"{}\": expected 4 hex digits", id))?;
+            sensor.set_device_id(id).map_err(e)?;
+        }
+        "set-mode" => {
+            let mode = match m.value_of("mode") {
+                Some("active") => ReportMode::Active,
+                _ => ReportMode::Query,
+            };
+            sensor.set_mode(mode).map_err(e)?;
+        }
+        "set-work-period" => {
+            let minutes = m.value_of("minutes").unwrap();
+            let minutes = minutes
+                .parse()
+                .ok()
+                .filter(|m| *m <= 30)
+                .ok_or_else(|| format!("\"{}\": expected 0 to 30 minutes", minutes))?;
+            sensor.set_work_period(minutes).map_err(e)?;
+        }
+        _ => unreachable!(),
+    }
+    Ok(0)
+}
+
+/// Prints and returns a calibrated reading, waking the sensor for it with
+/// `--wake`
+fn query(settings: &Effective, sensor: &mut SDS011, m: &ArgMatches) -> Result<Message, String> {
+    let wake = m.is_present("wake");
+    let time_format = settings.timestamp_format()?;
+    let format = settings.output_format()?;
+    let (calibration, _) = crate::load_files(settings)?;
+    let device_id = sensor.device_id().ok();
+    let device = crate::lookup(settings, device_id)?.unwrap_or_default();
+    sensor.set_calibration(calibration.or(device.calibration));
+    let color = !m.is_present("no_color") && crate::output::color_supported();
+    let mut output = Output::new(format, time_format, device_id, &device.fields())?.color(color);
+
+    if wake {
+        sensor.wake().map_err(|e| e.to_string())?;
+        if let Some(left) = sensor.warm_up_remaining() {
+            sleep(left);
+        }
+    }
+    let reading = sensor.query();
+    if wake {
+        sensor.sleep().map_err(|e| e.to_string())?;
+    }
+    let m = reading.map_err(|e| e.to_string())?;
+    output.print(&m);
+    Ok(m)
+}
@@ -0,0 +1,338 @@
+//! `doctor` subcommand: diagnoses the most common reasons the sensor
+//! can't be read, from port permissions to slow replies, and prints a
+//! pass/fail report with hints.
+
+use crate::config::Effective;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sds011::{ReportMode, SDS011};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Replies timed for the latency
+const SAMPLES: usize = 5;
+/// Reply latency above which the connection is suspect, e.g. a flaky
+/// adapter or a slow network bridge
+const SLOW: Duration = Duration::from_millis(500);
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Pass,
+    Warn,
+    Fail,
+    /// Not run, a check it depends on failed
+    Skip,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Outcome::Pass => "PASS",
+            Outcome::Warn => "WARN",
+            Outcome::Fail => "FAIL",
+            Outcome::Skip => "SKIP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Checks run so far, printed as they're added
+#[derive(Default)]
+struct Report {
+    outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    fn add(&mut self, outcome: Outcome, check: &str, detail: &str) {
+        println!("{:<4}  {:<12} {}", outcome, check, detail);
+        self.outcomes.push(outcome);
+    }
+
+    fn count(&self, outcome: Outcome) -> usize {
+        self.outcomes.iter().filter(|o| **o == outcome).count()
+    }
+}
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("doctor")
+        .about("Diagnoses problems reading the sensor and prints a pass/fail report")
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .takes_value(true)
+                .help("Port the sensor is connected to, or auto [default: the port setting]"),
+        )
+}
+
+/// Runs `doctor`, `matches` are the top-level ones the configuration is
+/// resolved from, returns 1 if a check failed
+pub fn run(m: &ArgMatches, matches: &ArgMatches) -> i32 {
+    let mut report = Report::default();
+    diagnose(&mut report, m, matches);
+
+    let (failed, warned) = (report.count(Outcome::Fail), report.count(Outcome::Warn));
+    println!();
+    match (failed, warned) {
+        (0, 0) => println!("All checks passed."),
+        (0, w) => println!("No check failed, {} warning(s).", w),
+        (f, _) => println!("{} check(s) failed, see the hints above.", f),
+    }
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+fn diagnose(report: &mut Report, m: &ArgMatches, matches: &ArgMatches) {
+    let settings = match Effective::resolve(matches) {
+        Ok(s) => s,
+        Err(e) => return report.add(Outcome::Fail, "config", &e),
+    };
+    let port = match crate::device::resolve_port(m.value_of("port").unwrap_or(&settings.port.value))
+    {
+        Ok(p) => p,
+        Err(e) => return report.add(Outcome::Fail, "port", &e),
+    };
+
+    let network = port.starts_with("tcp://") || port.starts_with("rfc2217://");
+    if network {
+        report.add(
+            Outcome::Pass,
+            "port",
+            &format!("{}, over the network", port),
+        );
+    } else {
+        match std::fs::metadata(&port) {
+            Ok(_) => report.add(Outcome::Pass, "port", &port),
+            Err(e) => {
+                report.add(
+                    Outcome::Fail,
+                    "port",
+                    &format!("{}: {}, see sds011 list-ports", port, e),
+                );
+                return;
+            }
+        }
+        let (outcome, detail) = permissions(&port);
+        report.add(outcome, "permissions", &detail);
+        let (outcome, detail) = users(&port);
+        report.add(outcome, "in use", &detail);
+    }
+
+    let started = Instant::now();
+    let mut sensor = match crate::frames::open(&port) {
+        Ok(s) => s,
+        Err(e) => {
+            report.add(Outcome::Fail, "open", &format!("{}: {}", port, e));
+            for check in [
+                "device id",
+                "firmware",
+                "latency",
+                "mode",
+                "work period",
+                "reading",
+            ] {
+                report.add(Outcome::Skip, check, "the port couldn't be opened");
+            }
+            return;
+        }
+    };
+    report.add(
+        Outcome::Pass,
+        "open",
+        &format!(
+            "the sensor answered in {} ms",
+            started.elapsed().as_millis()
+        ),
+    );
+
+    match sensor.device_id() {
+        Ok(id) => report.add(Outcome::Pass, "device id", &format!("{:04x}", id)),
+        Err(e) => report.add(Outcome::Fail, "device id", &e.to_string()),
+    }
+    match sensor.firmware_version() {
+        Ok(v) => report.add(Outcome::Pass, "firmware", &v.to_string()),
+        Err(e) => report.add(Outcome::Fail, "firmware", &e.to_string()),
+    }
+    let (outcome, detail) = latency(&mut sensor);
+    report.add(outcome, "latency", &detail);
+    match sensor.mode() {
+        Ok(ReportMode::Query) => report.add(Outcome::Pass, "mode", "query"),
+        Ok(ReportMode::Active) => report.add(
+            Outcome::Warn,
+            "mode",
+            "active, the sensor sends readings on its own, sds011 set-mode query",
+        ),
+        Err(e) => report.add(Outcome::Fail, "mode", &e.to_string()),
+    }
+    match sensor.work_period() {
+        Ok(0) => report.add(Outcome::Pass, "work period", "continuous"),
+        Ok(n) => report.add(
+            Outcome::Pass,
+            "work period",
+            &format!("{} minutes, readings may take that long", n),
+        ),
+        Err(e) => report.add(Outcome::Fail, "work period", &e.to_string()),
+    }
+    match sensor.query() {
+        Ok(r) => report.add(
+            Outcome::Pass,
+            "reading",
+            &format!(
+                "PM2.5 {:.1} µg/m³, PM10 {:.1} µg/m³",
+                r.pm25.value(),
+                r.pm10.value()
+            ),
+        ),
+        Err(e) => report.add(
+            Outcome::Fail,
+            "reading",
+            &format!("{}, if the sensor sleeps: sds011 wake", e),
+        ),
+    }
+}
+
+/// Times replies to device ID queries
+fn latency(sensor: &mut SDS011) -> (Outcome, String) {
+    let mut times = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let started = Instant::now();
+        if let Err(e) = sensor.device_id() {
+            return (Outcome::Fail, e.to_string());
+        }
+        times.push(started.elapsed());
+    }
+    let min = times.iter().min().copied().unwrap_or_default();
+    let max = times.iter().max().copied().unwrap_or_default();
+    let avg = times.iter().sum::<Duration>() / SAMPLES as u32;
+    let detail = format!(
+        "min {} ms, avg {} ms, max {} ms",
+        min.as_millis(),
+        avg.as_millis(),
+        max.as_millis()
+    );
+    if max > SLOW {
+        (
+            Outcome::Warn,
+            format!("{}, slow replies, check the adapter and the cable", detail),
+        )
+    } else {
+        (Outcome::Pass, detail)
+    }
+}
+
+/// Checks that the serial port at `path` can be read and written
+#[cfg(unix)]
+fn permissions(path: &str) -> (Outcome, String) {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::fs::MetadataExt;
+
+    let gid = match std::fs::metadata(path) {
+        Ok(m) => m.gid(),
+        Err(e) => return (Outcome::Fail, e.to_string()),
+    };
+    let c_path = match CString::new(path) {
+        Ok(p) => p,
+        Err(e) => return (Outcome::Fail, e.to_string()),
+    };
+    if unsafe { libc::access(c_path.as_ptr(), libc::R_OK | libc::W_OK) } == 0 {
+        return (Outcome::Pass, "read and write access".to_string());
+    }
+
+    // The group owning the port, `dialout` on Debian and Raspberry Pi OS,
+    // `uucp` on Arch
+    let group = unsafe { libc::getgrgid(gid) };
+    if group.is_null() {
+        return (
+            Outcome::Fail,
+            format!("no access, the port belongs to group {}", gid),
+        );
+    }
+    let (name, members) = unsafe {
+        let name = CStr::from_ptr((*group).gr_name)
+            .to_string_lossy()
+            .into_owned();
+        let mut members = Vec::new();
+        let mut member = (*group).gr_mem;
+        while !member.is_null() && !(*member).is_null() {
+            members.push(CStr::from_ptr(*member).to_string_lossy().into_owned());
+            member = member.add(1);
+        }
+        (name, members)
+    };
+    let user = std::env::var("USER").unwrap_or_default();
+    if members.contains(&user) {
+        (
+            Outcome::Fail,
+            format!(
+                "no access, {} was added to group {} after this session started, log out and in again",
+                user, name
+            ),
+        )
+    } else {
+        (
+            Outcome::Fail,
+            format!(
+                "no access, add your user to group {}: sudo usermod -aG {} $USER, then log in again",
+                name, name
+            ),
+        )
+    }
+}
+
+#[cfg(not(unix))]
+fn permissions(_path: &str) -> (Outcome, String) {
+    (Outcome::Skip, "only checked on Unix".to_string())
+}
+
+/// Looks for other processes with the port at `path` open, e.g.
+/// ModemManager probing new serial ports
+#[cfg(target_os = "linux")]
+fn users(path: &str) -> (Outcome, String) {
+    let port = match std::fs::canonicalize(path) {
+        Ok(p) => p,
+        Err(e) => return (Outcome::Skip, e.to_string()),
+    };
+    let me = std::process::id().to_string();
+    let mut users = Vec::new();
+    let processes = match std::fs::read_dir("/proc") {
+        Ok(p) => p,
+        Err(e) => return (Outcome::Skip, format!("/proc: {}", e)),
+    };
+    for process in processes.flatten() {
+        let pid = process.file_name().to_string_lossy().into_owned();
+        if pid == me || !pid.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        // Other users' processes can't be inspected without root
+        let fds = match std::fs::read_dir(process.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        let open = fds
+            .flatten()
+            .any(|fd| matches!(std::fs::read_link(fd.path()), Ok(target) if target == port));
+        if open {
+            let name = std::fs::read_to_string(process.path().join("comm")).unwrap_or_default();
+            users.push(format!("{} ({})", name.trim(), pid));
+        }
+    }
+    if users.is_empty() {
+        (
+            Outcome::Pass,
+            "no other process of yours has it open".to_string(),
+        )
+    } else {
+        (
+            Outcome::Warn,
+            format!("also open by {}, readings may get lost", users.join(", ")),
+        )
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn users(_path: &str) -> (Outcome, String) {
+    (Outcome::Skip, "only checked on Linux".to_string())
+}
@@ -0,0 +1,180 @@
+//! `export` subcommand: converts a log of readings to Apache Parquet, see
+//! `sds011::export::parquet`.
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("export")
+        .about("Converts a CSV, JSON Lines or SQLite log of readings to Parquet")
+        .arg(
+            Arg::with_name("input")
+                .required(true)
+                .help("Log to convert, by extension: .csv, .db/.sqlite or JSON Lines"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .required(true)
+                .help("Parquet file to write"),
+        )
+}
+
+/// Runs the subcommand and returns the exit code
+#[cfg(feature = "parquet")]
+pub fn run(m: &ArgMatches) -> i32 {
+    use sds011::export::parquet::ParquetWriter;
+    use std::fs::File;
+
+    let input = m.value_of("input").unwrap();
+    let output = m.value_of("output").unwrap();
+    let exported = File::create(output)
+        .map_err(|e| format!("{}: {}", output, e))
+        .and_then(|f| ParquetWriter::new(f).map_err(|e| e.to_string()))
+        .and_then(|mut writer| {
+            let mut push = |m: &sds011::Message, id: Option<u16>| {
+                writer.push(m, id).map_err(|e| e.to_string())
+            };
+            match extension(input) {
+                "csv" => read_csv(input, &mut push)?,
+                "db" | "sqlite" | "sqlite3" => read_sqlite(input, &mut push)?,
+                _ => read_jsonl(input, &mut push)?,
+            }
+            let rows = writer.rows();
+            writer.finish().map_err(|e| e.to_string())?;
+            Ok(rows)
+        });
+    match exported {
+        Ok(rows) => {
+            println!("{} readings written to {}", rows, output);
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            let _ = std::fs::remove_file(output);
+            1
+        }
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn run(_m: &ArgMatches) -> i32 {
+    eprintln!("error: this build has no Parquet support, rebuild with --features parquet");
+    1
+}
+
+/// Receives every reading of a log with its device ID
+#[cfg(feature = "parquet")]
+type Push<'a> = dyn FnMut(&sds011::Message, Option<u16>) -> Result<(), String> + 'a;
+
+#[cfg(feature = "parquet")]
+fn extension(path: &str) -> &str {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+}
+
+/// Parses UNIX seconds or an RFC 3339 date, the `--time-format`s a log
+/// can be read back from
+#[cfg(all(feature = "parquet", feature = "csv"))]
+fn parse_timestamp(s: &str) -> Option<std::time::SystemTime> {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    match s.parse::<u64>() {
+        Ok(secs) => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+        Err(_) => chrono::DateTime::parse_from_rfc3339(s).ok().map(Into::into),
+    }
+}
+
+/// Reads a log written with `--csv`
+#[cfg(all(feature = "parquet", feature = "csv"))]
+fn read_csv(path: &str, push: &mut Push) -> Result<(), String> {
+    use sds011::{Message, MicrogramsPerCubicMeter};
+
+    let e = |e: csv::Error| format!("{}: {}", path, e);
+    let mut reader = csv::Reader::from_path(path).map_err(e)?;
+    let header = reader.headers().map_err(e)?.clone();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let (timestamp, pm25, pm10) = match (column("timestamp"), column("pm25"), column("pm10")) {
+        (Some(t), Some(pm25), Some(pm10)) => (t, pm25, pm10),
+        _ => {
+            return Err(format!(
+                "{}: expected timestamp, pm25 and pm10 columns",
+                path
+            ))
+        }
+    };
+    let device_id = column("device_id");
+
+    for (n, record) in reader.records().enumerate() {
+        let record = record.map_err(e)?;
+        // The header is line 1
+        let bad = |what: &str| format!("{}: line {}: bad {}", path, n + 2, what);
+        let field = |i: usize| record.get(i).unwrap_or("");
+        let m = Message {
+            timestamp: parse_timestamp(field(timestamp))
+                .ok_or_else(|| bad("timestamp, expected UNIX seconds or RFC 3339"))?,
+            pm25: MicrogramsPerCubicMeter(field(pm25).parse().map_err(|_| bad("pm25"))?),
+            pm10: MicrogramsPerCubicMeter(field(pm10).parse().map_err(|_| bad("pm10"))?),
+        };
+        let id = match device_id.map(field) {
+            Some(id) if !id.is_empty() => {
+                Some(u16::from_str_radix(id, 16).map_err(|_| bad("device_id"))?)
+            }
+            _ => None,
+        };
+        push(&m, id)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "parquet", not(feature = "csv")))]
+fn read_csv(_path: &str, _push: &mut Push) -> Result<(), String> {
+    Err("this build has no CSV support, rebuild with --features csv".to_string())
+}
+
+/// Reads a database written with `--sqlite`
+#[cfg(all(feature = "parquet", feature = "sqlite"))]
+fn read_sqlite(path: &str, push: &mut Push) -> Result<(), String> {
+    use sds011::store::Store;
+
+    if !std::path::Path::new(path).exists() {
+        return Err(format!("{}: no such file", path));
+    }
+    let store = Store::open(path).map_err(|e| e.to_string())?;
+    for r in store.range(None, 0, u64::MAX).map_err(|e| e.to_string())? {
+        push(&r.message, r.device_id)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "parquet", not(feature = "sqlite")))]
+fn read_sqlite(_path: &str, _push: &mut Push) -> Result<(), String> {
+    Err("this build has no SQLite support, rebuild with --features sqlite".to_string())
+}
+
+/// Reads a log written with `--jsonl`, taking the device ID from a
+/// `device_id` field if there is one
+#[cfg(feature = "parquet")]
+fn read_jsonl(path: &str, push: &mut Push) -> Result<(), String> {
+    use sds011::{schema, Message};
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader};
+
+    let file = std::fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bad = |e: &dyn std::fmt::Display| format!("{}: line {}: {}", path, n + 1, e);
+        let value: Value = schema::from_json(&line).map_err(|e| bad(&e))?;
+        let id = match value.get("device_id") {
+            Some(Value::String(hex)) => u16::from_str_radix(hex, 16).ok(),
+            Some(Value::Number(id)) => id.as_u64().map(|id| id as u16),
+            _ => None,
+        };
+        let m: Message = serde_json::from_value(value).map_err(|e| bad(&e))?;
+        push(&m, id)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,20 @@
+//! `exporter` subcommand, see `sds011::exporter`.
+
+use sds011::events::EventBus;
+
+/// Serves metrics of readings published on `bus` at `addr` in the
+/// background
+#[cfg(all(feature = "metrics", feature = "http"))]
+pub fn start(addr: &str, bus: &EventBus) -> Result<(), String> {
+    let exporter = sds011::exporter::Exporter::bind(addr).map_err(|e| e.to_string())?;
+    if let Some(addr) = exporter.local_addr() {
+        eprintln!("info: serving metrics at http://{}/metrics", addr);
+    }
+    exporter.spawn(bus);
+    Ok(())
+}
+
+#[cfg(not(all(feature = "metrics", feature = "http")))]
+pub fn start(_addr: &str, _bus: &EventBus) -> Result<(), String> {
+    Err("this build has no Prometheus exporter, rebuild with --features metrics,http".to_string())
+}
@@ -0,0 +1,60 @@
+//! `--debug-frames`: hexdumps of the frames exchanged with the sensor on
+//! the standard error, with their direction and timing, to diagnose
+//! protocol problems with clones or flaky adapters.
+
+use sds011::transport::{self, Direction, Tap};
+use sds011::SDS011;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// When frames started being printed, set by `enable()`
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// Prints the frames of the sensors opened with `open()` from now on
+pub fn enable() {
+    START.get_or_init(Instant::now);
+}
+
+/// Opens the sensor on `port` like `SDS011::open()`, printing its frames
+/// once enabled
+pub fn open(port: &str) -> sds011::Result<SDS011> {
+    let start = match START.get() {
+        Some(start) => *start,
+        None => return SDS011::open(port),
+    };
+    let inner = transport::open(port, transport::DEFAULT_TIMEOUT)?;
+    let name = port.to_string();
+    let mut sent: Option<Instant> = None;
+    let tap = Tap::new(inner, move |direction, frame: &[u8]| {
+        let now = Instant::now();
+        let (arrow, latency) = match direction {
+            Direction::Sent => {
+                sent = Some(now);
+                ('>', String::new())
+            }
+            // Time since the last command, the reply latency
+            Direction::Received => match sent.take() {
+                Some(t) => (
+                    '<',
+                    format!(" ({:.1} ms)", now.duration_since(t).as_secs_f64() * 1000.0),
+                ),
+                None => ('<', String::new()),
+            },
+        };
+        eprintln!(
+            "frame: {:>9.3}s {} {} {}{}",
+            now.duration_since(start).as_secs_f64(),
+            name,
+            arrow,
+            hex(frame),
+            latency
+        );
+    });
+    SDS011::from_transport(Box::new(tap))
+}
+
+/// Bytes in hex separated by spaces, e.g. `aa c0 d4 04`
+pub fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    bytes.join(" ")
+}
@@ -0,0 +1,120 @@
+//! `gateway` subcommand: collects records pushed by edge instances.
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sds011::calibration::Calibration;
+use sds011::durable::SyncPolicy;
+use sds011::gateway::{Gateway, Rollup};
+use sds011::schema;
+use std::time::Duration;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("gateway")
+        .about("Receives measurements from edges started with --forward and prints per-station rollups")
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .takes_value(true)
+                .default_value("0.0.0.0:7100")
+                .help("Address to accept edges on"),
+        )
+        .arg(
+            Arg::with_name("journal")
+                .long("journal")
+                .takes_value(true)
+                .default_value("gateway.ndjson")
+                .help("File every received record is appended to"),
+        )
+        .arg(
+            Arg::with_name("fsync")
+                .long("fsync")
+                .takes_value(true)
+                .default_value("always")
+                .help("When the journal is synced: always, never or every N seconds; anything but always may lose acknowledged records on power loss"),
+        )
+        .arg(
+            Arg::with_name("token")
+                .long("token")
+                .takes_value(true)
+                .env("SDS011_GATEWAY_TOKEN")
+                .hide_env_values(true)
+                .help("Token edges must authenticate with, prefer SDS011_GATEWAY_TOKEN"),
+        )
+        .arg(
+            Arg::with_name("window")
+                .long("window")
+                .takes_value(true)
+                .default_value("60")
+                .help("Rollup window in minutes"),
+        )
+        .arg(
+            Arg::with_name("calibrate")
+                .long("calibrate")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Calibration of one station as STATION=FILE, may be repeated"),
+        )
+}
+
+/// Runs the subcommand and returns the exit code
+pub fn run(m: &ArgMatches) -> i32 {
+    let window = match m.value_of("window").unwrap().parse::<u64>() {
+        Ok(w) if w > 0 => Duration::from_secs(w * 60),
+        _ => {
+            eprintln!("error: --window: expected a positive number of minutes");
+            return 1;
+        }
+    };
+
+    let policy = match m.value_of("fsync").unwrap().parse::<SyncPolicy>() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("error: --fsync: {}", e);
+            return 1;
+        }
+    };
+
+    let mut rollup = Rollup::new(window);
+    for spec in m.values_of("calibrate").into_iter().flatten() {
+        let (station, path) = match spec.split_once('=') {
+            Some(parts) => parts,
+            None => {
+                eprintln!("error: --calibrate {}: expected STATION=FILE", spec);
+                return 1;
+            }
+        };
+        match Calibration::load(path) {
+            Ok(c) => rollup = rollup.calibrate(station, c),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return 1;
+            }
+        }
+    }
+
+    let listen = m.value_of("listen").unwrap();
+    let gateway = match Gateway::bind_with(listen, m.value_of("journal").unwrap(), policy) {
+        Ok(g) => match m.value_of("token") {
+            Some(token) => g.token(token),
+            None => {
+                log::warn!("gateway: no --token, any host can send records");
+                g
+            }
+        },
+        Err(e) => {
+            eprintln!("error: {}: {}", listen, e);
+            return 1;
+        }
+    };
+    eprintln!("info: gateway listening on {}", listen);
+
+    gateway.serve(|record| {
+        if let Some(closed) = rollup.push(&record) {
+            match schema::to_json(&closed) {
+                Ok(line) => println!("{}", line),
+                Err(e) => eprintln!("error: {}", e),
+            }
+        }
+    });
+    0
+}
@@ -0,0 +1,19 @@
+//! Optional gRPC server, see `sds011::grpc`.
+
+use sds011::events::EventBus;
+
+/// Serves the readings published on `bus` at `addr` in the background
+#[cfg(feature = "grpc")]
+pub fn start(addr: &str, bus: &EventBus) -> Result<(), String> {
+    use sds011::grpc::GrpcServer;
+
+    GrpcServer::bind(addr)
+        .map_err(|e| e.to_string())?
+        .spawn(bus);
+    Ok(())
+}
+
+#[cfg(not(feature = "grpc"))]
+pub fn start(_addr: &str, _bus: &EventBus) -> Result<(), String> {
+    Err("this build has no gRPC server, rebuild with --features grpc".to_string())
+}
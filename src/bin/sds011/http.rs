@@ -0,0 +1,40 @@
+//! Optional HTTP server, see `sds011::server`.
+
+use crate::config::Effective;
+use sds011::events::EventBus;
+use sds011::sink::SinkSet;
+use std::sync::Arc;
+
+/// Serves readings published on `bus` at `addr` in the background, with
+/// the health of `sinks` on `/healthz`
+#[cfg(feature = "http")]
+pub fn start(
+    settings: &Effective,
+    addr: &str,
+    bus: &EventBus,
+    sinks: &[Arc<SinkSet>],
+) -> Result<(), String> {
+    use sds011::directory::FileDirectory;
+    use sds011::server::Server;
+
+    let mut server = Server::bind(addr).map_err(|e| e.to_string())?;
+    if let Some(path) = settings.directory.as_ref() {
+        let directory = FileDirectory::load(&path.value).map_err(|e| e.to_string())?;
+        server = server.directory(Arc::new(directory));
+    }
+    for set in sinks {
+        server = server.sinks(Arc::clone(set));
+    }
+    server.spawn(bus);
+    Ok(())
+}
+
+#[cfg(not(feature = "http"))]
+pub fn start(
+    _settings: &Effective,
+    _addr: &str,
+    _bus: &EventBus,
+    _sinks: &[Arc<SinkSet>],
+) -> Result<(), String> {
+    Err("this build has no HTTP server, rebuild with --features http".to_string())
+}
@@ -0,0 +1,49 @@
+//! Optional InfluxDB output, see `sds011::sink::influx`.
+
+use crate::config::Effective;
+use sds011::sink::Sink;
+
+/// Opens the InfluxDB sink configured in `settings`, tagging lines with
+/// `tags`
+#[cfg(feature = "influx")]
+pub fn open(
+    settings: &Effective,
+    url: &str,
+    tags: &[(&str, String)],
+) -> Result<Box<dyn Sink>, String> {
+    use sds011::sink::influx::InfluxSink;
+
+    let value = |s: &Option<crate::config::Setting<String>>| s.as_ref().map(|s| s.value.clone());
+    let token = value(&settings.influx_token).unwrap_or_default();
+    let mut sink = match (
+        value(&settings.influx_bucket),
+        value(&settings.influx_database),
+    ) {
+        (Some(bucket), None) => {
+            let org =
+                value(&settings.influx_org).ok_or("influx_org must be set with influx_bucket")?;
+            InfluxSink::v2(url, &org, &bucket, &token)
+        }
+        (None, Some(database)) => {
+            let sink = InfluxSink::v1(url, &database);
+            match token.split_once(':') {
+                Some((user, password)) => sink.credentials(user, password),
+                None => sink,
+            }
+        }
+        _ => return Err("set either influx_bucket (2.x) or influx_database (1.x)".to_string()),
+    };
+    for (key, value) in tags.iter() {
+        sink = sink.tag(key, value);
+    }
+    Ok(Box::new(sink))
+}
+
+#[cfg(not(feature = "influx"))]
+pub fn open(
+    _settings: &Effective,
+    _url: &str,
+    _tags: &[(&str, String)],
+) -> Result<Box<dyn Sink>, String> {
+    Err("this build has no InfluxDB support, rebuild with --features influx".to_string())
+}
@@ -0,0 +1,25 @@
+//! Optional IPFS publishing, see `sds011::sink::ipfs`.
+
+use crate::config::Effective;
+use sds011::sink::Sink;
+
+/// Opens the sink adding documents through the IPFS API at `url`
+/// configured in `settings`
+#[cfg(feature = "ipfs")]
+pub fn open(settings: &Effective, url: &str) -> Result<Box<dyn Sink>, String> {
+    use sds011::sink::ipfs::{Format, Ipfs};
+
+    let mut sink = Ipfs::new(url).on_cid(|cid| log::info!("ipfs: added {}", cid));
+    if let Some(format) = settings.ipfs_format.as_ref() {
+        sink = sink.format(format.value.parse::<Format>().map_err(|e| e.to_string())?);
+    }
+    if let Some(readings) = settings.ipfs_batch.as_ref() {
+        sink = sink.batch(readings.value);
+    }
+    Ok(Box::new(sink))
+}
+
+#[cfg(not(feature = "ipfs"))]
+pub fn open(_settings: &Effective, _url: &str) -> Result<Box<dyn Sink>, String> {
+    Err("this build has no IPFS support, rebuild with --features ipfs".to_string())
+}
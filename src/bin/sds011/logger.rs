@@ -0,0 +1,46 @@
+//! `--log-level`: the library's log records on the standard error, e.g.
+//! `debug` for every command and its duration, `trace` for the frames.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::io::Write;
+
+/// Names of the levels, from quietest to most verbose
+pub const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+struct Stderr;
+
+impl Log for Stderr {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = record.level().to_string().to_lowercase();
+        let _ = writeln!(
+            std::io::stderr(),
+            "{}: {}: {}",
+            level,
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+/// Prints records up to `level`, one of `LEVELS`
+pub fn init(level: &str) -> Result<(), String> {
+    let filter = level.parse::<LevelFilter>().map_err(|_| {
+        format!(
+            "unknown log level \"{}\", expected one of {}",
+            level,
+            LEVELS.join(", ")
+        )
+    })?;
+    log::set_logger(&Stderr).map_err(|e| e.to_string())?;
+    log::set_max_level(filter);
+    Ok(())
+}
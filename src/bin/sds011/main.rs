@@ -0,0 +1,1519 @@
+extern crate sds011;
+use sds011::alerts::Alerts;
+use sds011::calibration::Calibration;
+use sds011::directory::{Device, Directory, FileDirectory};
+use sds011::durable::SyncPolicy;
+use sds011::events::{Event, EventBus};
+use sds011::gateway::Forwarder;
+use sds011::lifetime::OperatingHours;
+use sds011::observer::{Frame, Observer};
+use sds011::sink::file::FileLogger;
+use sds011::sink::graphite::{Graphite, StatsD};
+use sds011::sink::rate::RateLimiter;
+use sds011::sink::{Sink, SinkSet};
+use sds011::timestamp::{TimestampFormat, Zone};
+use sds011::SDS011;
+
+use clap::{App, AppSettings, Arg, SubCommand};
+use serde_json::Value;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+mod alerts;
+mod bench;
+mod check;
+mod coap;
+mod compare;
+mod config;
+mod csvfile;
+#[cfg(feature = "encryption")]
+mod decrypt;
+mod device;
+mod doctor;
+mod export;
+mod exporter;
+mod frames;
+mod gateway;
+mod grpc;
+mod http;
+mod influx;
+mod ipfs;
+mod logger;
+mod mqtt;
+mod nats;
+mod opensensemap;
+mod output;
+mod remote;
+mod repl;
+mod robonomics;
+mod sandbox;
+mod scripting;
+mod sensor_community;
+mod setup;
+mod shutdown;
+mod sqlite;
+mod systemd;
+mod thingspeak;
+#[cfg(feature = "tui")]
+mod tui;
+mod update;
+mod webhook;
+
+/// Prints frames exchanged by another program and the sensor
+fn listen(port: &str) {
+    let mut observer = match Observer::open(port) {
+        Ok(o) => o,
+        Err(e) => {
+            println!("{:?}", e);
+            return;
+        }
+    };
+
+    loop {
+        match observer.next_frame() {
+            Ok(Frame::Data { message, .. }) => println!("{:?}", message),
+            Ok(frame) => println!("{:?}", frame),
+            // Timeouts are expected while nobody is talking
+            Err(_) => continue,
+        }
+    }
+}
+
+/// Adds the options of monitoring, the default command, to `app`
+fn monitor_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .default_value("/dev/ttyUSB0")
+                .help("Specify port a sensor is connected to, tcp://host:port, rfc2217://host:port or auto to find it; repeat to poll several sensors"),
+        )
+        .arg(
+            Arg::with_name("work_period")
+                .short("w")
+                .long("work")
+                .takes_value(true)
+                .default_value("5")
+                .help("Work period in minutes"),
+        )
+        .arg(
+            Arg::with_name("calibration")
+                .long("calibration")
+                .takes_value(true)
+                .help("Calibration file with scale factors and offsets"),
+        )
+        .arg(
+            Arg::with_name("script")
+                .long("script")
+                .takes_value(true)
+                .help("Rhai script transforming readings and raising alerts"),
+        )
+        .arg(
+            Arg::with_name("time_format")
+                .long("time-format")
+                .takes_value(true)
+                .help("Timestamp format: unix, rfc3339 or a strftime pattern [default: unix]"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&output::NAMES)
+                .default_value("plain")
+                .help("Format of printed measurements: plain, json (JSON Lines), csv, influx (line protocol), lpp (hex Cayenne LPP) or compact (hex)"),
+        )
+        .arg(
+            Arg::with_name("no_color")
+                .long("no-color")
+                .help("Don't color plain measurements by AQI category, the default when not printing to a terminal or with NO_COLOR set"),
+        )
+        .arg(
+            Arg::with_name("local_time")
+                .long("local-time")
+                .help("Print timestamps in local time instead of UTC"),
+        )
+        .arg(
+            Arg::with_name("remote")
+                .long("remote")
+                .takes_value(true)
+                .help("Accept commands from an MQTT topic, mqtt[s]://host[:port]/topic"),
+        )
+        .arg(
+            Arg::with_name("remote_token")
+                .long("remote-token")
+                .takes_value(true)
+                .help("Token remote commands must carry, prefer SDS011_REMOTE_TOKEN"),
+        )
+        .arg(
+            Arg::with_name("remote_ca")
+                .long("remote-ca")
+                .takes_value(true)
+                .help("PEM file with the CAs the remote broker's certificate is checked against, implies TLS"),
+        )
+        .arg(
+            Arg::with_name("forward")
+                .long("forward")
+                .takes_value(true)
+                .help("Push measurements to a gateway at host:port"),
+        )
+        .arg(
+            Arg::with_name("forward_token")
+                .long("forward-token")
+                .takes_value(true)
+                .help("Token of the gateway, prefer SDS011_FORWARD_TOKEN"),
+        )
+        .arg(
+            Arg::with_name("station")
+                .long("station")
+                .takes_value(true)
+                .help("Name of this station at the gateway"),
+        )
+        .arg(
+            Arg::with_name("spool")
+                .long("spool")
+                .takes_value(true)
+                .default_value("sds011-spool.ndjson")
+                .help("File keeping measurements until the gateway acknowledges them"),
+        )
+        .arg(
+            Arg::with_name("directory")
+                .long("directory")
+                .takes_value(true)
+                .help("Device directory whose location and calibration of this sensor are used"),
+        )
+        .arg(
+            Arg::with_name("csv")
+                .long("csv")
+                .takes_value(true)
+                .help("Append measurements to a CSV file"),
+        )
+        .arg(
+            Arg::with_name("jsonl")
+                .long("jsonl")
+                .takes_value(true)
+                .help("Append measurements to a JSON Lines file, a strftime pattern like readings-%Y-%m-%d.ndjson starts a new file every day"),
+        )
+        .arg(
+            Arg::with_name("jsonl_max_mb")
+                .long("jsonl-max-mb")
+                .takes_value(true)
+                .help("Rotate the JSON Lines file at this size in MiB, keeping 5 old files"),
+        )
+        .arg(
+            Arg::with_name("influx")
+                .long("influx")
+                .takes_value(true)
+                .help("Write measurements to the InfluxDB server at this URL, e.g. http://localhost:8086"),
+        )
+        .arg(
+            Arg::with_name("influx_org")
+                .long("influx-org")
+                .takes_value(true)
+                .help("InfluxDB 2.x organization"),
+        )
+        .arg(
+            Arg::with_name("influx_bucket")
+                .long("influx-bucket")
+                .takes_value(true)
+                .help("InfluxDB 2.x bucket"),
+        )
+        .arg(
+            Arg::with_name("influx_database")
+                .long("influx-database")
+                .takes_value(true)
+                .help("InfluxDB 1.x database"),
+        )
+        .arg(
+            Arg::with_name("influx_token")
+                .long("influx-token")
+                .takes_value(true)
+                .help("InfluxDB 2.x API token or 1.x user:password, prefer SDS011_INFLUX_TOKEN"),
+        )
+        .arg(
+            Arg::with_name("ipfs")
+                .long("ipfs")
+                .takes_value(true)
+                .help("Add batches of readings to IPFS through the HTTP API at this URL, e.g. http://127.0.0.1:5001"),
+        )
+        .arg(
+            Arg::with_name("ipfs_format")
+                .long("ipfs-format")
+                .takes_value(true)
+                .possible_values(&["json", "csv"])
+                .help("Format of the documents added to IPFS [default: json]"),
+        )
+        .arg(
+            Arg::with_name("ipfs_batch")
+                .long("ipfs-batch")
+                .takes_value(true)
+                .help("Readings per document added to IPFS [default: 60]"),
+        )
+        .arg(
+            Arg::with_name("graphite")
+                .long("graphite")
+                .takes_value(true)
+                .help("Write readings to this Graphite plaintext listener, host:port"),
+        )
+        .arg(
+            Arg::with_name("statsd")
+                .long("statsd")
+                .takes_value(true)
+                .help("Send readings as gauges to this StatsD server, host:port"),
+        )
+        .arg(
+            Arg::with_name("metric_prefix")
+                .long("metric-prefix")
+                .takes_value(true)
+                .help("Prefix of Graphite and StatsD metric names [default: sds011]"),
+        )
+        .arg(
+            Arg::with_name("mqtt")
+                .long("mqtt")
+                .takes_value(true)
+                .help("Publish measurements to an MQTT topic, mqtt://host[:port]/topic or mqtts:// for TLS"),
+        )
+        .arg(
+            Arg::with_name("mqtt_user")
+                .long("mqtt-user")
+                .takes_value(true)
+                .help("MQTT user name"),
+        )
+        .arg(
+            Arg::with_name("mqtt_password")
+                .long("mqtt-password")
+                .takes_value(true)
+                .help("MQTT password, prefer SDS011_MQTT_PASSWORD"),
+        )
+        .arg(
+            Arg::with_name("mqtt_ca")
+                .long("mqtt-ca")
+                .takes_value(true)
+                .help("PEM file with the CAs the MQTT broker's certificate is checked against, implies TLS"),
+        )
+        .arg(
+            Arg::with_name("mqtt_qos")
+                .long("mqtt-qos")
+                .takes_value(true)
+                .help("MQTT quality of service: 0, 1 or 2 [default: 0]"),
+        )
+        .arg(
+            Arg::with_name("mqtt_retain")
+                .long("mqtt-retain")
+                .help("Ask the MQTT broker to retain the last measurement"),
+        )
+        .arg(
+            Arg::with_name("mqtt_discovery")
+                .long("mqtt-discovery")
+                .help("Announce the sensor to Home Assistant with MQTT discovery"),
+        )
+        .arg(
+            Arg::with_name("mqtt_availability")
+                .long("mqtt-availability")
+                .takes_value(true)
+                .help("Publish online or offline to this retained MQTT topic, backed by a last will"),
+        )
+        .arg(
+            Arg::with_name("nats")
+                .long("nats")
+                .takes_value(true)
+                .help("Publish readings to this NATS subject, nats://host[:port]/subject"),
+        )
+        .arg(
+            Arg::with_name("nats_user")
+                .long("nats-user")
+                .takes_value(true)
+                .help("NATS user name"),
+        )
+        .arg(
+            Arg::with_name("nats_password")
+                .long("nats-password")
+                .takes_value(true)
+                .help("NATS password, prefer SDS011_NATS_PASSWORD"),
+        )
+        .arg(
+            Arg::with_name("nats_token")
+                .long("nats-token")
+                .takes_value(true)
+                .help("NATS authentication token, prefer SDS011_NATS_TOKEN"),
+        )
+        .arg(
+            Arg::with_name("nats_jetstream")
+                .long("nats-jetstream")
+                .help("Wait for JetStream to store every reading published to NATS"),
+        )
+        .arg(
+            Arg::with_name("http")
+                .long("http")
+                .takes_value(true)
+                .help("Serve readings over HTTP on this address, e.g. 0.0.0.0:8080"),
+        )
+        .arg(
+            Arg::with_name("coap")
+                .long("coap")
+                .takes_value(true)
+                .help("Serve the latest reading over CoAP on this address, e.g. [::]:5683"),
+        )
+        .arg(
+            Arg::with_name("grpc")
+                .long("grpc")
+                .takes_value(true)
+                .help("Serve readings over gRPC on this address, e.g. [::]:50051"),
+        )
+        .arg(
+            Arg::with_name("sqlite")
+                .long("sqlite")
+                .takes_value(true)
+                .help("Store measurements in this SQLite database"),
+        )
+        .arg(
+            Arg::with_name("sensor_community")
+                .long("sensor-community")
+                .takes_value(true)
+                .help("Upload readings to sensor.community as this node, e.g. raspi-00000000a1b2c3d4"),
+        )
+        .arg(
+            Arg::with_name("opensensemap")
+                .long("opensensemap")
+                .takes_value(true)
+                .help("Upload readings to this openSenseMap box ID"),
+        )
+        .arg(
+            Arg::with_name("opensensemap_pm25")
+                .long("opensensemap-pm25")
+                .takes_value(true)
+                .help("Sensor ID of the box's PM2.5 sensor"),
+        )
+        .arg(
+            Arg::with_name("opensensemap_pm10")
+                .long("opensensemap-pm10")
+                .takes_value(true)
+                .help("Sensor ID of the box's PM10 sensor"),
+        )
+        .arg(
+            Arg::with_name("opensensemap_token")
+                .long("opensensemap-token")
+                .takes_value(true)
+                .help("Access token of the box, prefer SDS011_OPENSENSEMAP_TOKEN"),
+        )
+        .arg(
+            Arg::with_name("opensensemap_interval")
+                .long("opensensemap-interval")
+                .takes_value(true)
+                .help("Seconds between openSenseMap uploads [default: 60]"),
+        )
+        .arg(
+            Arg::with_name("thingspeak")
+                .long("thingspeak")
+                .takes_value(true)
+                .help("Write readings to the ThingSpeak channel of this write API key, prefer SDS011_THINGSPEAK"),
+        )
+        .arg(
+            Arg::with_name("thingspeak_interval")
+                .long("thingspeak-interval")
+                .takes_value(true)
+                .help("Seconds between ThingSpeak updates [default: 15]"),
+        )
+        .arg(
+            Arg::with_name("robonomics")
+                .long("robonomics")
+                .takes_value(true)
+                .help("Send signed readings to the Robonomics datalog through the gateway at this URL"),
+        )
+        .arg(
+            Arg::with_name("robonomics_key")
+                .long("robonomics-key")
+                .takes_value(true)
+                .help("File with the station's hex encoded Ed25519 secret key"),
+        )
+        .arg(
+            Arg::with_name("robonomics_token")
+                .long("robonomics-token")
+                .takes_value(true)
+                .help("Bearer token of the gateway, prefer SDS011_ROBONOMICS_TOKEN"),
+        )
+        .arg(
+            Arg::with_name("robonomics_interval")
+                .long("robonomics-interval")
+                .takes_value(true)
+                .help("Seconds between datalog records [default: 300]"),
+        )
+        .arg(
+            Arg::with_name("webhook")
+                .long("webhook")
+                .takes_value(true)
+                .help("POST every reading as JSON to this URL"),
+        )
+        .arg(
+            Arg::with_name("webhook_header")
+                .long("webhook-header")
+                .takes_value(true)
+                .help("Header of webhook requests, \"Name: value\", prefer SDS011_WEBHOOK_HEADER for secrets"),
+        )
+        .arg(
+            Arg::with_name("webhook_encrypt")
+                .long("webhook-encrypt")
+                .takes_value(true)
+                .help("Seal webhook bodies to this hex encoded public key, see decrypt"),
+        )
+        .arg(
+            Arg::with_name("alert_pm25")
+                .long("alert-pm25")
+                .takes_value(true)
+                .help("Raise an alert when PM2.5 stays above this many µg/m³"),
+        )
+        .arg(
+            Arg::with_name("alert_pm10")
+                .long("alert-pm10")
+                .takes_value(true)
+                .help("Raise an alert when PM10 stays above this many µg/m³"),
+        )
+        .arg(
+            Arg::with_name("alert_duration")
+                .long("alert-duration")
+                .takes_value(true)
+                .help("Seconds a threshold has to be exceeded for before alerting [default: 600]"),
+        )
+        .arg(
+            Arg::with_name("telegram_token")
+                .long("telegram-token")
+                .takes_value(true)
+                .help("Send alerts from the Telegram bot with this token, prefer SDS011_TELEGRAM_TOKEN"),
+        )
+        .arg(
+            Arg::with_name("telegram_chat")
+                .long("telegram-chat")
+                .takes_value(true)
+                .help("Telegram chat alerts are sent to"),
+        )
+        .arg(
+            Arg::with_name("pushover_token")
+                .long("pushover-token")
+                .takes_value(true)
+                .help("Push alerts with this Pushover application token, prefer SDS011_PUSHOVER_TOKEN"),
+        )
+        .arg(
+            Arg::with_name("pushover_user")
+                .long("pushover-user")
+                .takes_value(true)
+                .help("Pushover user or group key alerts are pushed to"),
+        )
+        .arg(
+            Arg::with_name("hours_file")
+                .long("hours-file")
+                .takes_value(true)
+                .help("Keep the laser's working hours in this JSON file, one per sensor"),
+        )
+        .arg(
+            Arg::with_name("laser_warning")
+                .long("laser-warning")
+                .takes_value(true)
+                .help("Warn once the laser used this fraction of its rated 8000 hours [default: 0.9]"),
+        )
+        .arg(
+            Arg::with_name("fsync")
+                .long("fsync")
+                .takes_value(true)
+                .default_value("always")
+                .help("When written files are synced to the disk: always, never or every N seconds"),
+        )
+        .arg(
+            Arg::with_name("listen_only")
+                .long("listen-only")
+                .help("Never write to the port, only print frames passing by"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .long("count")
+                .takes_value(true)
+                .value_name("N")
+                .help("Exit after N readings, failed queries are retried"),
+        )
+        .arg(
+            Arg::with_name("once")
+                .long("once")
+                .conflicts_with("count")
+                .help("Exit after one reading, same as --count 1"),
+        )
+        .arg(
+            Arg::with_name("user")
+                .long("user")
+                .takes_value(true)
+                .help("Switch to this user after opening the port"),
+        )
+        .arg(
+            Arg::with_name("seccomp")
+                .long("seccomp")
+                .help("Restrict system calls after opening the port (Linux only)"),
+        )
+}
+
+fn main() {
+    let app = monitor_args(
+        App::new("SDS011 Driver")
+            .version(env!("CARGO_PKG_VERSION"))
+            .author("Vadim Manaenko <vadim.razorq@gmail.com>")
+            .about("Reads data from Nova SDS011 Sensor")
+            .arg(
+                Arg::with_name("config")
+                    .short("c")
+                    .long("config")
+                    .takes_value(true)
+                    .global(true)
+                    .env("SDS011_CONFIG")
+                    .help("Configuration file"),
+            )
+            .arg(
+                Arg::with_name("debug_frames")
+                    .long("debug-frames")
+                    .global(true)
+                    .help("Print every frame sent (>) and received (<) to the standard error, with timing"),
+            )
+            .arg(
+                Arg::with_name("log_level")
+                    .long("log-level")
+                    .takes_value(true)
+                    .global(true)
+                    .possible_values(&logger::LEVELS)
+                    .env("SDS011_LOG")
+                    .default_value("info")
+                    .help("Print the driver's log records up to this level to the standard error"),
+            ),
+    )
+    .subcommand(monitor_args(SubCommand::with_name("monitor").about(
+        "Reads the sensor every work period and publishes the readings, the default",
+    )))
+    .subcommand(
+        monitor_args(SubCommand::with_name("exporter").about(
+            "Reads the sensor like monitor and serves the readings to Prometheus at /metrics",
+        ))
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .takes_value(true)
+                .default_value("0.0.0.0:9184")
+                .help("Address to serve /metrics on"),
+        ),
+    )
+    .subcommand(
+        monitor_args(SubCommand::with_name("mqtt").about(
+            "Reads the sensor like monitor and publishes the readings to an MQTT broker",
+        ))
+        .arg(
+            Arg::with_name("broker")
+                .long("broker")
+                .takes_value(true)
+                .required(true)
+                .conflicts_with("mqtt")
+                .help("MQTT broker, host[:port] or mqtts://host[:port] for TLS"),
+        )
+        .arg(
+            Arg::with_name("topic")
+                .long("topic")
+                .takes_value(true)
+                .default_value("sds011")
+                .help("Topic readings are published to, the status goes to <topic>/status unless --mqtt-availability is set"),
+        ),
+    )
+    .subcommands(device::subcommands())
+    .subcommand(check::subcommand())
+    .subcommand(compare::subcommand())
+    .subcommand(repl::subcommand())
+    .subcommand(doctor::subcommand())
+    .subcommand(
+        SubCommand::with_name("config")
+            .about("Configuration file tools")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("validate")
+                    .about("Checks a configuration file for errors")
+                    .arg(
+                        Arg::with_name("file")
+                            .help("Configuration file [default: --config or sds011.toml]"),
+                    )
+                    .arg(
+                        Arg::with_name("probe")
+                            .long("probe")
+                            .help("Also query the sensor and connect to the sinks' servers"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("show")
+                    .about("Prints the configuration")
+                    .arg(Arg::with_name("effective").long("effective").help(
+                        "Print merged defaults, file, environment and flags with provenance",
+                    )),
+            ),
+    )
+    .subcommand(gateway::subcommand())
+    .subcommand(bench::subcommand())
+    .subcommand(update::subcommand())
+    .subcommand(export::subcommand())
+    .subcommand(
+        SubCommand::with_name("setup")
+            .about("Interactive first-run setup: finds the sensor and writes a configuration"),
+    );
+    #[cfg(feature = "encryption")]
+    let app = app.subcommand(decrypt::subcommand());
+    #[cfg(feature = "tui")]
+    let app = app.subcommand(tui::subcommand());
+    let matches = app.get_matches();
+    if matches.is_present("debug_frames") {
+        frames::enable();
+    }
+    if let Some(level) = matches.value_of("log_level") {
+        if let Err(e) = logger::init(level) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if matches.subcommand_matches("setup").is_some() {
+        std::process::exit(setup::run());
+    }
+
+    if let ("gateway", Some(m)) = matches.subcommand() {
+        std::process::exit(gateway::run(m));
+    }
+
+    if let ("bench-pipeline", Some(m)) = matches.subcommand() {
+        std::process::exit(bench::run(m));
+    }
+
+    if let ("check-update", Some(m)) = matches.subcommand() {
+        std::process::exit(update::run(m));
+    }
+
+    if let ("export", Some(m)) = matches.subcommand() {
+        std::process::exit(export::run(m));
+    }
+
+    #[cfg(feature = "encryption")]
+    {
+        if let ("decrypt", Some(m)) = matches.subcommand() {
+            std::process::exit(decrypt::run(m));
+        }
+    }
+
+    if let ("config", Some(config)) = matches.subcommand() {
+        let code = match config.subcommand() {
+            ("validate", Some(m)) => {
+                let file = m
+                    .value_of("file")
+                    .or_else(|| matches.value_of("config"))
+                    .unwrap_or("sds011.toml");
+                config::validate(file, m.is_present("probe"))
+            }
+            ("show", Some(m)) => config::show(&matches, m.is_present("effective")),
+            _ => unreachable!(),
+        };
+        std::process::exit(code);
+    }
+
+    if let ("check", Some(m)) = matches.subcommand() {
+        std::process::exit(check::run(m, &matches));
+    }
+
+    if let ("compare", Some(m)) = matches.subcommand() {
+        std::process::exit(compare::run(m, &matches));
+    }
+
+    #[cfg(feature = "tui")]
+    {
+        if let ("tui", Some(m)) = matches.subcommand() {
+            std::process::exit(tui::run(m, &matches));
+        }
+    }
+
+    if let ("doctor", Some(m)) = matches.subcommand() {
+        std::process::exit(doctor::run(m, &matches));
+    }
+
+    if let ("repl", Some(m)) = matches.subcommand() {
+        std::process::exit(repl::run(m, &matches));
+    }
+
+    if matches.subcommand_matches("list-ports").is_some() {
+        std::process::exit(device::list_ports());
+    }
+
+    if let (name, Some(m)) = matches.subcommand() {
+        if device::NAMES.contains(&name) {
+            std::process::exit(device::run(name, m, &matches));
+        }
+    }
+
+    // `monitor`, `exporter` and `mqtt` take the options of running without
+    // a subcommand, the latter two serve or publish the readings instead of
+    // printing them
+    let (matches, exporter, print) = match matches.subcommand() {
+        ("monitor", Some(m)) => (m.clone(), None, true),
+        ("exporter", Some(m)) => (m.clone(), m.value_of("listen").map(String::from), false),
+        ("mqtt", Some(m)) => (m.clone(), None, false),
+        _ => (matches, None, true),
+    };
+
+    let settings = match config::Effective::resolve(&matches) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let user = settings.user.as_ref().map(|s| s.value.as_str());
+    let seccomp = settings.seccomp.as_ref().map(|s| s.value).unwrap_or(false);
+    if let Err(e) = sandbox::prepare(user, seccomp) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+    let mut ports = Vec::new();
+    for sensor in settings.sensors.iter() {
+        match device::resolve_port(&sensor.value.port) {
+            Ok(port) => ports.push((sensor.value.name.clone(), port)),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut work_period = settings.work_period.value;
+
+    if matches.is_present("listen_only") {
+        if ports.len() > 1 {
+            eprintln!("error: --listen-only watches a single port");
+            std::process::exit(1);
+        }
+        listen(&ports[0].1);
+        return;
+    }
+
+    let count = match matches.value_of("count") {
+        Some(n) => match n.parse::<u64>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => {
+                eprintln!("error: --count: expected a positive number, got \"{}\"", n);
+                std::process::exit(1);
+            }
+        },
+        None if matches.is_present("once") => Some(1),
+        None => None,
+    };
+
+    let time_format = match settings.timestamp_format() {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("error: time_format: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let format = match settings.output_format() {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("error: format: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let (calibration, mut script) = match load_files(&settings) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let remote = match settings.remote.as_ref() {
+        Some(_) if ports.len() > 1 => {
+            eprintln!("error: remote: only a single sensor can be managed remotely");
+            std::process::exit(1);
+        }
+        Some(url) => {
+            let token = settings.remote_token.as_ref().map(|t| t.value.as_str());
+            let ca = settings.remote_ca.as_ref().map(|c| c.value.as_str());
+            match remote::connect(&url.value, token.unwrap_or_default(), ca) {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    eprintln!("error: remote: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let sync_policy = match settings.sync_policy() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("error: fsync: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut forwarder = match settings.forward.as_ref() {
+        Some(addr) => match settings.station.as_ref() {
+            Some(station) => {
+                let mut forwarder =
+                    Forwarder::new(&addr.value, &station.value, &settings.spool.value)
+                        .sync_policy(sync_policy);
+                if let Some(token) = settings.forward_token.as_ref() {
+                    forwarder = forwarder.token(&token.value);
+                }
+                Some(forwarder)
+            }
+            None => {
+                eprintln!("error: forward: station must be set");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // Every port is opened before dropping privileges
+    let several = ports.len() > 1;
+    let laser_warning = settings
+        .laser_warning
+        .as_ref()
+        .map_or(sds011::lifetime::DEFAULT_WARNING, |s| s.value);
+    if !(laser_warning > 0.0 && laser_warning <= 1.0) {
+        eprintln!(
+            "error: laser warning {}: expected a fraction above 0 and at most 1",
+            laser_warning
+        );
+        std::process::exit(1);
+    }
+    let color = !matches.is_present("no_color") && output::color_supported();
+    let mut sensors = Vec::new();
+    for (name, port) in ports {
+        let mut sensor = match crate::frames::open(&port) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("error: {}: {}", port, e);
+                std::process::exit(1);
+            }
+        };
+        // A sensor put to sleep by the last shutdown stays asleep
+        if let Err(e) = sensor.wake() {
+            eprintln!("error: {}: {}", port, e);
+            std::process::exit(1);
+        }
+        if let Err(e) = sensor.set_work_period(work_period) {
+            eprintln!("error: {}: {}", port, e);
+            std::process::exit(1);
+        }
+
+        let device_id = sensor.device_id().ok();
+        let device = match lookup(&settings, device_id) {
+            Ok(d) => d.unwrap_or_default(),
+            Err(e) => {
+                eprintln!("error: directory: {}", e);
+                std::process::exit(1);
+            }
+        };
+        sensor.set_calibration(calibration.or(device.calibration));
+
+        // Readings of several sensors are told apart by name or device ID
+        let label = match (name, device_id) {
+            (Some(name), _) => Some(name),
+            (None, Some(id)) if several => Some(format!("{:04x}", id)),
+            (None, None) if several => Some(port.clone()),
+            (None, _) => None,
+        };
+        if let Some(path) = settings.hours_file.as_ref() {
+            let path = match (&label, several) {
+                (Some(label), true) => hours_path(&path.value, label),
+                _ => path.value.clone(),
+            };
+            match OperatingHours::open(&path) {
+                Ok(hours) => sensor.set_operating_hours(Some(hours.warning(laser_warning))),
+                Err(e) => {
+                    eprintln!("error: hours file: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        let mut fields = device.fields();
+        if let Some(label) = &label {
+            fields.push(("sensor", Value::String(label.clone())));
+        }
+        let output = match output::Output::new(format, time_format.clone(), device_id, &fields) {
+            Ok(o) => o.color(color),
+            Err(e) => {
+                eprintln!("error: format: {}", e);
+                std::process::exit(1);
+            }
+        };
+        sensors.push(Monitored {
+            output: match &label {
+                Some(label) => output.label(label),
+                None => output,
+            },
+            port,
+            label,
+            sensor,
+            device_id,
+            fields,
+            sinks: Arc::new(SinkSet::new()),
+            taken: 0,
+            worn: false,
+        });
+    }
+
+    // Opened before the HTTP server, which reports their health
+    let bus = EventBus::new();
+    let limiters = Limiters::new(&settings);
+    let mut consumers = Vec::new();
+    for m in sensors.iter_mut() {
+        let sinks = open_sinks(&settings, m, &time_format, sync_policy, &bus, &limiters);
+        m.sinks = Arc::new(sinks);
+        let alerts = match alerts::open(&settings, &bus, m.label.as_deref()) {
+            Ok(alerts) => alerts,
+            Err(e) => {
+                eprintln!("error: alerts: {}", e);
+                std::process::exit(1);
+            }
+        };
+        consumers.push(consume(&bus, &m.port, Arc::clone(&m.sinks), alerts));
+    }
+
+    // Bound before dropping privileges, so port 80 works
+    if let Some(addr) = settings.http.as_ref() {
+        let sinks: Vec<_> = sensors.iter().map(|m| Arc::clone(&m.sinks)).collect();
+        if let Err(e) = http::start(&settings, &addr.value, &bus, &sinks) {
+            eprintln!("error: http: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(addr) = settings.coap.as_ref() {
+        if let Err(e) = coap::start(&addr.value, &bus) {
+            eprintln!("error: coap: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(addr) = settings.grpc.as_ref() {
+        if let Err(e) = grpc::start(&addr.value, &bus) {
+            eprintln!("error: grpc: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(addr) = exporter.as_ref() {
+        if let Err(e) = exporter::start(addr, &bus) {
+            eprintln!("error: exporter: {}", e);
+            std::process::exit(1);
+        }
+    }
+    for m in sensors.iter_mut() {
+        bus.publish(Event::SensorAttached {
+            port: m.port.clone(),
+            device_id: m.device_id,
+        });
+        if let Ok(firmware) = m.sensor.firmware_version() {
+            bus.publish(Event::FirmwareInfo {
+                port: m.port.clone(),
+                version: firmware.to_string(),
+            });
+        }
+        bus.publish(Event::WorkPeriodChanged {
+            port: m.port.clone(),
+            minutes: work_period,
+        });
+    }
+
+    if let Err(e) = shutdown::install() {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+    let mut notifier = match systemd::Notifier::open() {
+        Ok(n) => n,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = sandbox::apply(user, seccomp) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+
+    // Readings of a sensor just woken up are off until its fan settled
+    let warm_up = sensors
+        .iter()
+        .filter_map(|m| m.sensor.warm_up_remaining())
+        .max()
+        .unwrap_or_default();
+    if warm_up > Duration::from_secs(0) {
+        eprintln!("info: warming up for {:.0}s", warm_up.as_secs_f64());
+        if let Some(n) = notifier.as_ref() {
+            n.status("Warming up the sensor");
+        }
+        shutdown::wait(warm_up);
+    }
+
+    loop {
+        if shutdown::requested() {
+            break;
+        }
+        // Whether a sensor was read, the watchdog is pinged only then
+        let mut healthy = false;
+        for m in sensors.iter_mut() {
+            if matches!(count, Some(n) if m.taken >= n) {
+                continue;
+            }
+            let reading = m.sensor.query();
+            if let Err(e) = &reading {
+                bus.publish(Event::SensorError {
+                    port: m.port.clone(),
+                    error: e.to_string(),
+                });
+            }
+            if let Ok(reading) = reading {
+                healthy = true;
+                if let Some(n) = notifier.as_mut() {
+                    n.ready(&format!(
+                        "{}PM2.5 {:.1} µg/m³, PM10 {:.1} µg/m³",
+                        m.label
+                            .as_ref()
+                            .map_or(String::new(), |l| format!("{}: ", l)),
+                        reading.pm25.value(),
+                        reading.pm10.value()
+                    ));
+                }
+                let reading = match &script {
+                    Some(script) => scripting::apply(script, reading),
+                    None => Some(reading),
+                };
+                if let Some(reading) = reading {
+                    if print {
+                        m.output.print(&reading);
+                    }
+                    bus.publish(Event::Measurement {
+                        port: m.port.clone(),
+                        message: reading.clone(),
+                    });
+                    if let Some(f) = forwarder.as_mut() {
+                        if let Err(e) = f.send(&reading) {
+                            eprintln!("error: forward: {}", e);
+                        }
+                    }
+                    m.taken += 1;
+                    if let Some(hours) = m.sensor.operating_hours() {
+                        if hours.worn() && !m.worn {
+                            m.worn = true;
+                            eprintln!(
+                                "warning: {}: the laser worked {:.0} hours, {:.0}% of its rated lifetime, plan a replacement",
+                                m.port,
+                                hours.hours(),
+                                hours.fraction() * 100.0
+                            );
+                            bus.publish(Event::LaserWorn {
+                                port: m.port.clone(),
+                                hours: hours.hours(),
+                                fraction: hours.fraction(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        if matches!(count, Some(n) if sensors.iter().all(|m| m.taken >= n)) {
+            break;
+        }
+        let ping_interval = match notifier.as_ref() {
+            Some(n) if healthy => {
+                n.ping();
+                n.ping_interval()
+            }
+            _ => None,
+        };
+
+        let period = Duration::from_secs(work_period as u64 * 60);
+        let before = work_period;
+        let deadline = Instant::now() + period;
+        let reload = loop {
+            // Waits in slices, pinging the watchdog in between
+            let left = deadline.saturating_duration_since(Instant::now());
+            let slice = ping_interval.map_or(left, |i| left.min(i));
+            let reload = match &remote {
+                Some(r) => remote::serve(r, &mut sensors[0].sensor, slice, &mut work_period),
+                None => {
+                    shutdown::wait(slice);
+                    false
+                }
+            };
+            if reload
+                || shutdown::requested()
+                || work_period != before
+                || Instant::now() >= deadline
+            {
+                break reload;
+            }
+            if let (Some(n), Some(_)) = (notifier.as_ref(), ping_interval) {
+                n.ping();
+            }
+        };
+        if work_period != before {
+            for m in sensors.iter() {
+                bus.publish(Event::WorkPeriodChanged {
+                    port: m.port.clone(),
+                    minutes: work_period,
+                });
+            }
+        }
+        if reload {
+            // The ports and the sandbox are kept, they can't change
+            // without a restart
+            let reloaded = config::Effective::resolve(&matches).and_then(|s| {
+                let format = s.timestamp_format()?;
+                let (calibration, script) = load_files(&s)?;
+                let mut calibrations = Vec::new();
+                for m in sensors.iter() {
+                    let device = lookup(&s, m.device_id)?.unwrap_or_default();
+                    calibrations.push(calibration.or(device.calibration));
+                }
+                Ok((s.work_period.value, format, calibrations, script))
+            });
+            match reloaded {
+                Ok((w, format, calibrations, s)) => {
+                    for (m, calibration) in sensors.iter_mut().zip(calibrations) {
+                        match m.sensor.set_work_period(w) {
+                            Ok(()) if w != work_period => bus.publish(Event::WorkPeriodChanged {
+                                port: m.port.clone(),
+                                minutes: w,
+                            }),
+                            Ok(()) => {}
+                            Err(e) => eprintln!("error: reload: {}: {}", m.port, e),
+                        }
+                        m.output.set_time_format(format.clone());
+                        m.sensor.set_calibration(calibration);
+                    }
+                    work_period = w;
+                    script = s;
+                    eprintln!("info: configuration reloaded");
+                }
+                Err(e) => eprintln!("error: reload: {}", e),
+            }
+        }
+    }
+
+    if let Some(n) = notifier.as_ref() {
+        n.stopping();
+    }
+    // Let the sinks and alerts catch up with the readings published so far
+    bus.close();
+    for consumer in consumers {
+        let _ = consumer.join();
+    }
+    for m in sensors.iter_mut() {
+        // Batching outputs hold readings back
+        m.sinks.flush();
+        if shutdown::requested() {
+            if let Err(e) = m.sensor.sleep() {
+                eprintln!("error: can't put the sensor on {} to sleep: {}", m.port, e);
+            }
+        }
+    }
+    if shutdown::requested() {
+        eprintln!("info: stopped");
+    }
+}
+
+/// A sensor being monitored and where its readings go
+struct Monitored {
+    port: String,
+    /// Name or device ID readings are labeled with when there are several
+    /// sensors or it's named
+    label: Option<String>,
+    sensor: SDS011,
+    device_id: Option<u16>,
+    /// Directory fields and the label added to outputs
+    fields: Vec<(&'static str, Value)>,
+    output: output::Output,
+    /// Outputs, skipped by their circuit breaker while they keep failing
+    sinks: Arc<SinkSet>,
+    /// Readings taken, for `--count`
+    taken: u64,
+    /// Whether the laser wear warning was given
+    worn: bool,
+}
+
+/// Feeds the readings of the sensor on `port` published on `bus` to its
+/// `sinks` and `alerts`, until the bus is closed
+fn consume(
+    bus: &EventBus,
+    port: &str,
+    sinks: Arc<SinkSet>,
+    mut alerts: Option<Alerts>,
+) -> JoinHandle<()> {
+    let events = bus.subscribe();
+    let port = port.to_string();
+    thread::spawn(move || {
+        for event in events {
+            match &event {
+                Event::Measurement {
+                    port: from,
+                    message,
+                } if *from == port => {
+                    sinks.handle(&event);
+                    if let Some(alerts) = alerts.as_mut() {
+                        alerts.check(message);
+                    }
+                }
+                _ => {}
+            }
+        }
+    })
+}
+
+/// Hours file of the sensor `label` when there are several, the label
+/// goes before the extension, e.g. `hours-kitchen.json`
+fn hours_path(path: &str, label: &str) -> String {
+    let path = std::path::Path::new(path);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // A port used as the label has slashes
+    let label: String = label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, label, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, label),
+    };
+    path.with_file_name(name).display().to_string()
+}
+
+/// Rate limiters of the upstream services, shared by the sinks of every
+/// sensor so that together they stay within the service's limits
+struct Limiters {
+    sensor_community: RateLimiter,
+    opensensemap: RateLimiter,
+    thingspeak: RateLimiter,
+    robonomics: RateLimiter,
+}
+
+impl Limiters {
+    fn new(settings: &config::Effective) -> Limiters {
+        Limiters {
+            sensor_community: sensor_community::limiter(),
+            opensensemap: opensensemap::limiter(settings),
+            thingspeak: thingspeak::limiter(settings),
+            robonomics: robonomics::limiter(settings),
+        }
+    }
+}
+
+/// Adds the sink `open` creates to `sinks`, reopened in the background
+/// while it fails
+/// With several sensors, `label` tells their sinks apart, e.g. `kitchen/csv`
+fn add<F>(sinks: &mut SinkSet, label: Option<&str>, name: &str, mut open: F)
+where
+    F: FnMut() -> Result<Box<dyn Sink>, String> + Send + 'static,
+{
+    let name = match label {
+        Some(label) => format!("{}/{}", label, name),
+        None => name.to_string(),
+    };
+    sinks.add(
+        &name,
+        Box::new(move || open().map_err(sds011::Error::SinkError)),
+    );
+}
+
+/// Sinks configured in `settings` for the sensor `m`
+fn open_sinks(
+    settings: &config::Effective,
+    m: &Monitored,
+    time_format: &TimestampFormat,
+    sync_policy: SyncPolicy,
+    bus: &EventBus,
+    limiters: &Limiters,
+) -> SinkSet {
+    let (device_id, fields) = (m.device_id, m.fields.clone());
+    let label = m.label.as_deref();
+    let mut sinks = SinkSet::new().with_events(bus.clone());
+    if let Some(path) = settings.csv.as_ref() {
+        let (path, fields, format) = (path.value.clone(), fields.clone(), time_format.clone());
+        add(&mut sinks, label, "csv", move || {
+            csvfile::open(&path, device_id, &fields, format.clone(), sync_policy)
+        });
+    }
+    if let Some(path) = settings.jsonl.as_ref() {
+        let (settings, path, fields) = (settings.clone(), path.value.clone(), fields.clone());
+        add(&mut sinks, label, "jsonl", move || {
+            let logger =
+                jsonl(&settings, &path, &fields, sync_policy).map_err(|e| e.to_string())?;
+            Ok(Box::new(logger) as Box<dyn Sink>)
+        });
+    }
+
+    if let Some(path) = settings.sqlite.as_ref() {
+        let path = path.value.clone();
+        add(&mut sinks, label, "sqlite", move || {
+            sqlite::open(&path, device_id, sync_policy)
+        });
+    }
+
+    // Tags of the metric outputs: the device ID and directory fields
+    let mut tags: Vec<(&str, String)> = Vec::new();
+    if let Some(id) = device_id {
+        tags.push(("device_id", format!("{:04x}", id)));
+    }
+    for (name, value) in fields.iter() {
+        match value {
+            Value::String(s) => tags.push((name, s.clone())),
+            other => tags.push((name, other.to_string())),
+        }
+    }
+
+    if let Some(url) = settings.influx.as_ref() {
+        let (settings, url, tags) = (settings.clone(), url.value.clone(), tags.clone());
+        add(&mut sinks, label, "influx", move || {
+            influx::open(&settings, &url, &tags)
+        });
+    }
+
+    if let Some(url) = settings.ipfs.as_ref() {
+        let (settings, url) = (settings.clone(), url.value.clone());
+        add(&mut sinks, label, "ipfs", move || {
+            ipfs::open(&settings, &url)
+        });
+    }
+
+    let prefix = settings.metric_prefix.as_ref().map(|s| s.value.clone());
+    if let Some(addr) = settings.graphite.as_ref() {
+        let (addr, prefix, tags) = (addr.value.clone(), prefix.clone(), tags.clone());
+        add(&mut sinks, label, "graphite", move || {
+            let mut sink = Graphite::new(&addr);
+            if let Some(prefix) = prefix.as_ref() {
+                sink = sink.prefix(prefix);
+            }
+            for (key, value) in tags.iter() {
+                sink = sink.tag(key, value);
+            }
+            Ok(Box::new(sink) as Box<dyn Sink>)
+        });
+    }
+
+    if let Some(addr) = settings.statsd.as_ref() {
+        let (addr, prefix, tags) = (addr.value.clone(), prefix, tags);
+        add(&mut sinks, label, "statsd", move || {
+            let mut sink = StatsD::new(&addr);
+            if let Some(prefix) = prefix.as_ref() {
+                sink = sink.prefix(prefix);
+            }
+            for (key, value) in tags.iter() {
+                sink = sink.tag(key, value);
+            }
+            Ok(Box::new(sink) as Box<dyn Sink>)
+        });
+    }
+
+    if let Some(url) = settings.mqtt.as_ref() {
+        let (settings, url, fields) = (settings.clone(), url.value.clone(), fields.clone());
+        add(&mut sinks, label, "mqtt", move || {
+            mqtt::open(&settings, &url, device_id, &fields)
+        });
+    }
+
+    if let Some(url) = settings.nats.as_ref() {
+        let (settings, url, fields) = (settings.clone(), url.value.clone(), fields.clone());
+        add(&mut sinks, label, "nats", move || {
+            nats::open(&settings, &url, device_id, &fields)
+        });
+    }
+
+    if let Some(id) = settings.sensor_community.as_ref() {
+        let (id, limiter) = (id.value.clone(), limiters.sensor_community.clone());
+        add(&mut sinks, label, "sensor.community", move || {
+            sensor_community::open(&id, limiter.clone())
+        });
+    }
+
+    if let Some(box_id) = settings.opensensemap.as_ref() {
+        let (settings, box_id) = (settings.clone(), box_id.value.clone());
+        let limiter = limiters.opensensemap.clone();
+        add(&mut sinks, label, "openSenseMap", move || {
+            opensensemap::open(&settings, &box_id, limiter.clone())
+        });
+    }
+
+    if let Some(key) = settings.thingspeak.as_ref() {
+        let (key, limiter) = (key.value.clone(), limiters.thingspeak.clone());
+        add(&mut sinks, label, "ThingSpeak", move || {
+            thingspeak::open(&key, limiter.clone())
+        });
+    }
+
+    if let Some(url) = settings.robonomics.as_ref() {
+        let (settings, url) = (settings.clone(), url.value.clone());
+        let limiter = limiters.robonomics.clone();
+        add(&mut sinks, label, "Robonomics", move || {
+            robonomics::open(&settings, &url, limiter.clone())
+        });
+    }
+
+    if let Some(url) = settings.webhook.as_ref() {
+        let (settings, url, fields) = (settings.clone(), url.value.clone(), fields.clone());
+        add(&mut sinks, label, "webhook", move || {
+            webhook::open(&settings, &url, device_id, &fields)
+        });
+    }
+    sinks
+}
+
+/// Creates the JSON Lines logger writing to `path`
+fn jsonl(
+    settings: &config::Effective,
+    path: &str,
+    fields: &[(&str, Value)],
+    policy: SyncPolicy,
+) -> sds011::Result<FileLogger> {
+    let mut logger = FileLogger::new(path)?.sync_policy(policy);
+    for (name, value) in fields.iter() {
+        logger = logger.field(name, value.clone());
+    }
+    if let Some(mb) = settings.jsonl_max_mb.as_ref() {
+        logger = logger.max_size(mb.value * 1024 * 1024);
+    }
+    if matches!(&settings.local_time, Some(s) if s.value) {
+        logger = logger.zone(Zone::Local);
+    }
+    Ok(logger)
+}
+
+/// Looks the sensor with `id` up in the directory named in the settings
+fn lookup(settings: &config::Effective, id: Option<u16>) -> Result<Option<Device>, String> {
+    let path = match settings.directory.as_ref() {
+        Some(p) => &p.value,
+        None => return Ok(None),
+    };
+    let directory = FileDirectory::load(path).map_err(|e| e.to_string())?;
+    let device = id.and_then(|id| directory.lookup(id));
+    match (id, &device) {
+        (Some(id), None) => eprintln!("warning: directory: no device {:04x} in {}", id, path),
+        (None, _) => eprintln!("warning: directory: can't read the device ID"),
+        _ => {}
+    }
+    Ok(device)
+}
+
+/// Loads the calibration and script files named in the settings
+fn load_files(
+    settings: &config::Effective,
+) -> Result<(Option<Calibration>, Option<scripting::Script>), String> {
+    let calibration = match settings.calibration.as_ref() {
+        Some(s) => Some(Calibration::load(&s.value).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    let script = match settings.script.as_ref() {
+        Some(s) => Some(scripting::load(&s.value)?),
+        None => None,
+    };
+    Ok((calibration, script))
+}
@@ -0,0 +1,143 @@
+//! Optional MQTT output, see `sds011::sink::mqtt`.
+
+use crate::config::Effective;
+use sds011::sink::Sink;
+use serde_json::Value;
+
+/// Broker address and topic of an `mqtt://` or `mqtts://` URL
+#[derive(Debug, PartialEq, Clone)]
+pub struct Url {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+}
+
+/// Splits `mqtt[s]://host[:port]/topic`, the port defaults to 1883, or
+/// 8883 for TLS
+pub fn parse_url(url: &str) -> Result<Url, String> {
+    let bad = || format!("\"{}\": expected mqtt[s]://host[:port]/topic", url);
+    let (tls, rest) = match url.strip_prefix("mqtts://") {
+        Some(rest) => (true, rest),
+        None => (false, url.strip_prefix("mqtt://").ok_or_else(bad)?),
+    };
+    let (authority, topic) = rest.split_once('/').ok_or_else(bad)?;
+    if topic.is_empty() || authority.is_empty() {
+        return Err(bad());
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| bad())?),
+        None => (authority, if tls { 8883 } else { 1883 }),
+    };
+    Ok(Url {
+        tls,
+        host: host.to_string(),
+        port,
+        topic: topic.to_string(),
+    })
+}
+
+/// URL of `topic` on `broker`, `host[:port]` or `mqtt[s]://host[:port]`
+pub fn broker_url(broker: &str, topic: &str) -> Result<String, String> {
+    let broker = broker.trim_end_matches('/');
+    let url = if broker.contains("://") {
+        format!("{}/{}", broker, topic)
+    } else {
+        format!("mqtt://{}/{}", broker, topic)
+    };
+    check_topic(topic)?;
+    parse_url(&url).map_err(|_| {
+        format!(
+            "\"{}\": expected host[:port] or mqtt[s]://host[:port]",
+            broker
+        )
+    })?;
+    Ok(url)
+}
+
+/// Checks that `topic` can be published to, i.e. it's not empty and has
+/// no wildcards
+pub fn check_topic(topic: &str) -> Result<(), String> {
+    if topic.is_empty() || topic.contains(['+', '#']) {
+        return Err(format!("\"{}\": expected a topic without wildcards", topic));
+    }
+    Ok(())
+}
+
+/// Opens the MQTT sink configured in `settings`, adding the sensor's
+/// `device_id` and `fields` to every measurement
+#[cfg(feature = "mqtt")]
+pub fn open(
+    settings: &Effective,
+    url: &str,
+    device_id: Option<u16>,
+    fields: &[(&str, Value)],
+) -> Result<Box<dyn Sink>, String> {
+    use sds011::aqi::AqiScale;
+    use sds011::sink::mqtt::{Discovery, MqttSink};
+
+    let url = parse_url(url)?;
+    let mut sink = MqttSink::new(&url.host, url.port, &url.topic);
+    if let Some(user) = settings.mqtt_user.as_ref() {
+        let password = settings.mqtt_password.as_ref().map(|s| s.value.as_str());
+        sink = sink.credentials(&user.value, password.unwrap_or_default());
+    }
+    match settings.mqtt_ca.as_ref() {
+        Some(ca) => sink = sink.ca_file(&ca.value),
+        None if url.tls => sink = sink.tls(),
+        None => {}
+    }
+    if let Some(level) = settings.mqtt_qos.as_ref() {
+        sink = sink.qos(qos(level.value)?);
+    }
+    if let Some(retain) = settings.mqtt_retain.as_ref() {
+        sink = sink.retain(retain.value);
+    }
+    if let Some(topic) = settings.mqtt_availability.as_ref() {
+        sink = sink.availability(&topic.value);
+    }
+    if let Some(id) = device_id {
+        // Sinks of several sensors in one process need their own client ID
+        let client_id = format!("sds011-sink-{}-{:04x}", std::process::id(), id);
+        sink = sink
+            .client_id(&client_id)
+            .field("device_id", format!("{:04x}", id));
+    }
+    for (name, value) in fields.iter() {
+        sink = sink.field(name, value.clone());
+    }
+    if matches!(&settings.mqtt_discovery, Some(s) if s.value) {
+        let id = device_id.ok_or("mqtt_discovery: can't read the device ID")?;
+        let mut discovery = Discovery::new(id);
+        let location = fields.iter().find(|(name, _)| *name == "location");
+        if let Some((_, Value::String(area))) = location {
+            discovery = discovery.area(area);
+        }
+        sink = sink.aqi(AqiScale::Us).discovery(discovery);
+    }
+    Ok(Box::new(sink))
+}
+
+#[cfg(feature = "mqtt")]
+/// Quality of service of a level number
+fn qos(level: u8) -> Result<sds011::sink::mqtt::QoS, String> {
+    use sds011::sink::mqtt::QoS;
+
+    match level {
+        0 => Ok(QoS::AtMostOnce),
+        1 => Ok(QoS::AtLeastOnce),
+        2 => Ok(QoS::ExactlyOnce),
+        _ => Err(format!("mqtt_qos = {}: expected 0, 1 or 2", level)),
+    }
+}
+
+#[cfg(not(feature = "mqtt"))]
+pub fn open(
+    _settings: &Effective,
+    url: &str,
+    _device_id: Option<u16>,
+    _fields: &[(&str, Value)],
+) -> Result<Box<dyn Sink>, String> {
+    parse_url(url)?;
+    Err("this build has no MQTT support, rebuild with --features mqtt".to_string())
+}
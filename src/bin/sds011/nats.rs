@@ -0,0 +1,78 @@
+//! Optional NATS output, see `sds011::sink::nats`.
+
+use crate::config::Effective;
+use sds011::sink::Sink;
+use serde_json::Value;
+
+/// Server address and subject of a `nats://` URL
+#[derive(Debug, PartialEq, Clone)]
+pub struct Url {
+    pub addr: String,
+    pub subject: String,
+}
+
+/// Splits `nats://host[:port]/subject`, the port defaults to 4222
+pub fn parse_url(url: &str) -> Result<Url, String> {
+    let bad = || format!("\"{}\": expected nats://host[:port]/subject", url);
+    let rest = url.strip_prefix("nats://").ok_or_else(bad)?;
+    let (authority, subject) = rest.split_once('/').ok_or_else(bad)?;
+    if authority.is_empty()
+        || subject.is_empty()
+        || subject.contains(['*', '>', ' '])
+        || subject.split('.').any(str::is_empty)
+    {
+        return Err(bad());
+    }
+    let addr = match authority.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_err() => return Err(bad()),
+        Some(_) => authority.to_string(),
+        None => format!("{}:4222", authority),
+    };
+    Ok(Url {
+        addr,
+        subject: subject.to_string(),
+    })
+}
+
+/// Opens the NATS sink configured in `settings`, adding the sensor's
+/// `device_id` and `fields` to every measurement
+#[cfg(feature = "nats")]
+pub fn open(
+    settings: &Effective,
+    url: &str,
+    device_id: Option<u16>,
+    fields: &[(&str, Value)],
+) -> Result<Box<dyn Sink>, String> {
+    use sds011::sink::nats::Nats;
+
+    let url = parse_url(url)?;
+    let mut sink = Nats::new(&url.addr, &url.subject);
+    if let Some(token) = settings.nats_token.as_ref() {
+        sink = sink.token(&token.value);
+    }
+    if let Some(user) = settings.nats_user.as_ref() {
+        let password = settings.nats_password.as_ref().map(|s| s.value.as_str());
+        sink = sink.credentials(&user.value, password.unwrap_or_default());
+    }
+    if matches!(&settings.nats_jetstream, Some(s) if s.value) {
+        sink = sink.jetstream(true);
+    }
+    if let Some(id) = device_id {
+        sink = sink.field("device_id", format!("{:04x}", id));
+    }
+    for (name, value) in fields.iter() {
+        sink = sink.field(name, value.clone());
+    }
+    Ok(Box::new(sink))
+}
+
+#[cfg(not(feature = "nats"))]
+pub fn open(
+    _settings: &Effective,
+    url: &str,
+    _device_id: Option<u16>,
+    _fields: &[(&str, Value)],
+) -> Result<Box<dyn Sink>, String> {
+    parse_url(url)?;
+    Err("this build has no NATS support, rebuild with --features nats".to_string())
+}
@@ -0,0 +1,58 @@
+//! Optional openSenseMap uploads, see `sds011::sink::opensensemap`.
+
+use crate::config::Effective;
+use sds011::sink::rate::RateLimiter;
+use sds011::sink::Sink;
+
+/// Opens the upload to the box `box_id` configured in `settings`, limited
+/// by `limiter`
+#[cfg(feature = "opensensemap")]
+pub fn open(
+    settings: &Effective,
+    box_id: &str,
+    limiter: RateLimiter,
+) -> Result<Box<dyn Sink>, String> {
+    use sds011::sink::opensensemap::OpenSenseMap;
+
+    let pm25 = settings
+        .opensensemap_pm25
+        .as_ref()
+        .ok_or("opensensemap_pm25 must be set")?;
+    let pm10 = settings
+        .opensensemap_pm10
+        .as_ref()
+        .ok_or("opensensemap_pm10 must be set")?;
+    let mut sink = OpenSenseMap::new(box_id, &pm25.value, &pm10.value, limiter);
+    if let Some(token) = settings.opensensemap_token.as_ref() {
+        sink = sink.token(&token.value);
+    }
+    Ok(Box::new(sink))
+}
+
+#[cfg(not(feature = "opensensemap"))]
+pub fn open(
+    _settings: &Effective,
+    _box_id: &str,
+    _limiter: RateLimiter,
+) -> Result<Box<dyn Sink>, String> {
+    Err("this build has no openSenseMap support, rebuild with --features opensensemap".to_string())
+}
+
+/// Rate limiter of the uploads of every sensor, at `opensensemap_interval`
+#[cfg(feature = "opensensemap")]
+pub fn limiter(settings: &Effective) -> RateLimiter {
+    use sds011::sink::opensensemap::INTERVAL;
+    use std::time::Duration;
+
+    let interval = settings
+        .opensensemap_interval
+        .as_ref()
+        .map_or(INTERVAL, |s| Duration::from_secs(s.value.max(1)));
+    RateLimiter::new(1, interval)
+}
+
+#[cfg(not(feature = "opensensemap"))]
+pub fn limiter(_settings: &Effective) -> RateLimiter {
+    // Never used, opening fails
+    RateLimiter::new(1, std::time::Duration::from_secs(1))
+}
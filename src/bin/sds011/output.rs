@@ -0,0 +1,290 @@
+//! Measurements printed to the standard output, in the format selected
+//! with `--format`.
+
+use sds011::aqi::{AqiScale, Category};
+use sds011::export::lpp::LppType;
+use sds011::timestamp::TimestampFormat;
+use sds011::{schema, Message};
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::str::FromStr;
+#[cfg(feature = "csv")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Measurement name of the line protocol, the one of the Influx sink
+const MEASUREMENT: &str = "sds011";
+
+/// Output format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// `Message { .. }`, like its `Debug` output
+    Plain,
+    /// JSON Lines, the records of the `jsonl` log
+    Json,
+    /// CSV with a header, the columns of the `csv` log
+    Csv,
+    /// InfluxDB line protocol, e.g. for Telegraf's `inputs.execd`
+    Influx,
+    /// Hex Cayenne LPP payload, PM2.5 as analog input 1 and PM10 as
+    /// analog input 2, for a LoRaWAN module's send command
+    Lpp,
+    /// Hex compact payload, see `Message::to_compact`
+    Compact,
+}
+
+/// Names of the formats
+pub const NAMES: [&str; 6] = ["plain", "json", "csv", "influx", "lpp", "compact"];
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "influx" => Ok(Format::Influx),
+            "lpp" => Ok(Format::Lpp),
+            "compact" => Ok(Format::Compact),
+            _ => Err(format!(
+                "unknown format \"{}\", expected one of {}",
+                s,
+                NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Prints measurements of one sensor
+pub struct Output {
+    format: Format,
+    time_format: TimestampFormat,
+    /// Device ID and directory fields, the tags of the line protocol
+    tags: Vec<(String, String)>,
+    /// Directory fields added to JSON records
+    fields: Map<String, Value>,
+    /// Name of the sensor plain lines start with
+    label: Option<String>,
+    /// Whether plain lines are colored by AQI category
+    color: bool,
+    csv: Option<Csv>,
+}
+
+impl Output {
+    /// Prints in `format` the measurements of the sensor `device_id`,
+    /// described by the directory `fields`
+    pub fn new(
+        format: Format,
+        time_format: TimestampFormat,
+        device_id: Option<u16>,
+        fields: &[(&str, Value)],
+    ) -> Result<Output, String> {
+        let mut tags = Vec::new();
+        if let Some(id) = device_id {
+            tags.push(("device_id".to_string(), format!("{:04x}", id)));
+        }
+        for (name, value) in fields.iter() {
+            match value {
+                Value::String(s) => tags.push((name.to_string(), s.clone())),
+                other => tags.push((name.to_string(), other.to_string())),
+            }
+        }
+        let csv = match format {
+            Format::Csv => Some(Csv::new(device_id, fields, time_format.clone())?),
+            _ => None,
+        };
+        Ok(Output {
+            format,
+            time_format,
+            tags,
+            fields: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            label: None,
+            color: false,
+            csv,
+        })
+    }
+
+    /// Starts plain lines with the sensor's `label`, the other formats
+    /// label readings with a `sensor` field
+    pub fn label(mut self, label: &str) -> Output {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    /// Colors plain lines by the US AQI category of the reading, from
+    /// green for good to maroon for hazardous
+    pub fn color(mut self, color: bool) -> Output {
+        self.color = color;
+        self
+    }
+
+    /// Uses `format` for timestamps from now on, JSON and the line
+    /// protocol have their own
+    pub fn set_time_format(&mut self, format: TimestampFormat) {
+        if let Some(csv) = self.csv.as_mut() {
+            csv.set_time_format(format.clone());
+        }
+        self.time_format = format;
+    }
+
+    /// Prints a measurement
+    pub fn print(&mut self, m: &Message) {
+        let line = match self.format {
+            Format::Plain => {
+                let line = format!(
+                    "{}Message {{ timestamp: {:?}, pm25: {:?}, pm10: {:?} }}",
+                    self.label
+                        .as_ref()
+                        .map_or(String::new(), |l| format!("{}: ", l)),
+                    m.format_timestamp(&self.time_format),
+                    m.pm25.value(),
+                    m.pm10.value()
+                );
+                if self.color {
+                    let code = ansi(m.aqi(AqiScale::Us).category);
+                    Ok(format!("\x1b[{}m{}\x1b[0m", code, line))
+                } else {
+                    Ok(line)
+                }
+            }
+            Format::Json => schema::to_json(m)
+                .map(|line| sds011::sink::with_fields(line, &self.fields))
+                .map_err(|e| e.to_string()),
+            Format::Csv => match self.csv.as_mut() {
+                Some(csv) => csv.line(m),
+                None => Err("not open".to_string()),
+            },
+            Format::Influx => {
+                let tags: Vec<(&str, &str)> = self
+                    .tags
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect();
+                Ok(m.to_line_protocol(MEASUREMENT, &tags))
+            }
+            Format::Lpp => Ok(hex(&m.to_cayenne_lpp(LppType::AnalogInput, 1, 2))),
+            Format::Compact => Ok(hex(&m.to_compact())),
+        };
+        match line {
+            Ok(line) => {
+                // Flushed per line, readers of a pipe get every reading
+                // as it's taken
+                let stdout = std::io::stdout();
+                let mut stdout = stdout.lock();
+                let _ = writeln!(stdout, "{}", line).and_then(|_| stdout.flush());
+            }
+            Err(e) => eprintln!("error: output: {}", e),
+        }
+    }
+}
+
+/// Lowercase hex of `bytes`
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Whether printed lines can be colored: the standard output is a
+/// terminal and `NO_COLOR` isn't set, see https://no-color.org
+pub fn color_supported() -> bool {
+    if matches!(std::env::var_os("NO_COLOR"), Some(v) if !v.is_empty()) {
+        return false;
+    }
+    is_terminal()
+}
+
+#[cfg(unix)]
+fn is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+fn is_terminal() -> bool {
+    false
+}
+
+/// SGR parameters of the color of an AQI category
+fn ansi(category: Category) -> &'static str {
+    match category {
+        Category::Good | Category::VeryLow | Category::Low => "32",
+        Category::Moderate | Category::Medium => "33",
+        // Orange and maroon from the 256 color palette
+        Category::UnhealthyForSensitiveGroups | Category::High => "38;5;208",
+        Category::Unhealthy => "31",
+        Category::VeryUnhealthy | Category::VeryHigh => "35",
+        Category::Hazardous => "38;5;88",
+    }
+}
+
+/// Whether the CSV header was printed, sensors polled by one process
+/// share it
+#[cfg(feature = "csv")]
+static HEADER: AtomicBool = AtomicBool::new(false);
+
+/// CSV rows, the header is printed before the first one
+#[cfg(feature = "csv")]
+struct Csv {
+    writer: Option<sds011::sink::csv::CsvWriter>,
+}
+
+#[cfg(feature = "csv")]
+impl Csv {
+    fn new(
+        device_id: Option<u16>,
+        fields: &[(&str, Value)],
+        time_format: TimestampFormat,
+    ) -> Result<Csv, String> {
+        use sds011::aqi::AqiScale;
+        use sds011::sink::csv::CsvWriter;
+
+        // Only describes the columns, nothing is written to the path
+        let mut writer = CsvWriter::new("-")
+            .aqi(AqiScale::Us)
+            .timestamp_format(time_format);
+        if let Some(id) = device_id {
+            writer = writer.device_id(id);
+        }
+        for (name, value) in fields.iter() {
+            writer = writer.field(name, value.clone());
+        }
+        Ok(Csv {
+            writer: Some(writer),
+        })
+    }
+
+    fn set_time_format(&mut self, format: TimestampFormat) {
+        self.writer = self.writer.take().map(|w| w.timestamp_format(format));
+    }
+
+    fn line(&mut self, m: &Message) -> Result<String, String> {
+        let writer = self.writer.as_ref().ok_or("not open")?;
+        let row = writer.line(m).map_err(|e| e.to_string())?;
+        if HEADER.swap(true, Ordering::SeqCst) {
+            return Ok(row);
+        }
+        let header = writer.header_line().map_err(|e| e.to_string())?;
+        Ok(format!("{}\n{}", header, row))
+    }
+}
+
+#[cfg(not(feature = "csv"))]
+struct Csv;
+
+#[cfg(not(feature = "csv"))]
+impl Csv {
+    fn new(
+        _device_id: Option<u16>,
+        _fields: &[(&str, Value)],
+        _time_format: TimestampFormat,
+    ) -> Result<Csv, String> {
+        Err("this build has no CSV support, rebuild with --features csv".to_string())
+    }
+
+    fn set_time_format(&mut self, _format: TimestampFormat) {}
+
+    fn line(&mut self, _m: &Message) -> Result<String, String> {
+        unreachable!()
+    }
+}
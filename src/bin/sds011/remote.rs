@@ -0,0 +1,74 @@
+//! Optional remote management over MQTT, see `sds011::remote`.
+
+use sds011::SDS011;
+use std::time::Duration;
+
+#[cfg(feature = "mqtt")]
+pub use sds011::remote::MqttCommands as Remote;
+
+/// Placeholder for builds without the `mqtt` feature
+#[cfg(not(feature = "mqtt"))]
+pub struct Remote;
+
+/// Subscribes to the command topic of `url`, `mqtt[s]://host[:port]/topic`,
+/// over TLS for `mqtts://` or with the CAs in the PEM file `ca`
+#[cfg(feature = "mqtt")]
+pub fn connect(url: &str, token: &str, ca: Option<&str>) -> Result<Remote, String> {
+    use sds011::sink::mqtt::Tls;
+
+    let url = crate::mqtt::parse_url(url)?;
+    let tls = match ca {
+        Some(path) => Some(Tls::CaFile(path.to_string())),
+        None if url.tls => Some(Tls::System),
+        None => None,
+    };
+    match tls {
+        Some(tls) => Remote::connect_tls(&url.host, url.port, &url.topic, token, tls),
+        None => Remote::connect(&url.host, url.port, &url.topic, token),
+    }
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "mqtt"))]
+pub fn connect(url: &str, _token: &str, _ca: Option<&str>) -> Result<Remote, String> {
+    crate::mqtt::parse_url(url)?;
+    Err("this build has no MQTT support, rebuild with --features mqtt".to_string())
+}
+
+/// Executes commands until `period` elapses or shutdown is requested
+/// Updates `work_period` when it's changed remotely and returns `true`
+/// early if the configuration should be reloaded
+#[cfg(feature = "mqtt")]
+pub fn serve(remote: &Remote, sensor: &mut SDS011, period: Duration, work_period: &mut u8) -> bool {
+    use sds011::remote::{self, Command};
+    use std::time::Instant;
+
+    let deadline = Instant::now() + period;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0) || crate::shutdown::requested() {
+            return false;
+        }
+        if let Some(command) = remote.recv_timeout(left.min(crate::shutdown::POLL)) {
+            eprintln!("info: remote command {:?}", command);
+            let result = remote::execute(sensor, &command);
+            remote.reply(&result);
+            match (&command, &result) {
+                (Command::SetWorkPeriod { minutes }, Ok(_)) => *work_period = *minutes,
+                (Command::ReloadConfig, _) => return true,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "mqtt"))]
+pub fn serve(
+    _remote: &Remote,
+    _sensor: &mut SDS011,
+    period: Duration,
+    _work_period: &mut u8,
+) -> bool {
+    crate::shutdown::wait(period);
+    false
+}
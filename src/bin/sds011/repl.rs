@@ -0,0 +1,198 @@
+//! `repl` subcommand: an interactive prompt running sensor commands one
+//! at a time, optionally with hexdumps of the frames exchanged, to
+//! experiment with the sensor or debug a misbehaving unit.
+
+use crate::config::Effective;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use sds011::transport::{self, Direction, Tap};
+use sds011::{ReportMode, SDS011};
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+const HELP: &str = "\
+query              print a reading
+sleep              stop the fan and the laser
+wake               start them again
+mode [active|query]
+                   print or set the report mode
+period [MINUTES]   print or set the work period, 0 to 30, 0 is continuous
+id [XXXX]          print or set the device ID
+version            print the firmware version
+stats              print the counters of the traffic with the sensor
+hex [on|off]       show the frames sent (>) and received (<)
+help               print this help
+quit               exit, as does Ctrl-D";
+
+/// Frames exchanged since they were last printed
+type Frames = Arc<Mutex<Vec<(Direction, Vec<u8>)>>>;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("repl")
+        .about("Interactive prompt running sensor commands, for experimenting and debugging")
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .takes_value(true)
+                .help("Port the sensor is connected to, or auto [default: the port setting]"),
+        )
+        .arg(
+            Arg::with_name("hex")
+                .long("hex")
+                .help("Show the frames exchanged from the start, see the hex command"),
+        )
+}
+
+/// Runs the prompt until `quit` or the end of the input, `matches` are the
+/// top-level ones the configuration is resolved from, returns the exit
+/// code
+pub fn run(m: &ArgMatches, matches: &ArgMatches) -> i32 {
+    match repl(m, matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+fn repl(m: &ArgMatches, matches: &ArgMatches) -> Result<(), String> {
+    let settings = Effective::resolve(matches)?;
+    let port = crate::device::resolve_port(m.value_of("port").unwrap_or(&settings.port.value))?;
+    let frames = Frames::default();
+    let mut hex = m.is_present("hex");
+
+    let recorded = Arc::clone(&frames);
+    let tap = transport::open(&port, transport::DEFAULT_TIMEOUT)
+        .map(|t| {
+            Tap::new(t, move |direction, frame: &[u8]| {
+                if let Ok(mut frames) = recorded.lock() {
+                    frames.push((direction, frame.to_vec()));
+                }
+            })
+        })
+        .map_err(|e| format!("{}: {}", port, e))?;
+    let opened = SDS011::from_transport(Box::new(tap));
+    print_frames(&frames, hex);
+    let mut sensor = opened.map_err(|e| format!("{}: {}", port, e))?;
+    println!("Connected to {}, type help for the commands", port);
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("sds011> ");
+        let _ = std::io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => return Err(e.to_string()),
+            None => {
+                println!();
+                return Ok(());
+            }
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(None),
+            ["quit"] | ["exit"] => return Ok(()),
+            ["help"] => Ok(Some(HELP.to_string())),
+            ["hex"] => Ok(Some(format!("hex {}", if hex { "on" } else { "off" }))),
+            ["hex", "on"] => {
+                hex = true;
+                Ok(None)
+            }
+            ["hex", "off"] => {
+                hex = false;
+                Ok(None)
+            }
+            words => execute(&mut sensor, words),
+        };
+        print_frames(&frames, hex);
+        match result {
+            Ok(Some(text)) => println!("{}", text),
+            Ok(None) => {}
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
+
+/// Runs a sensor command, returns what it prints
+fn execute(sensor: &mut SDS011, words: &[&str]) -> Result<Option<String>, String> {
+    let e = |e: sds011::Error| e.to_string();
+    let text = match words {
+        ["query"] => {
+            let m = sensor.query().map_err(e)?;
+            format!(
+                "PM2.5 {:.1} µg/m³, PM10 {:.1} µg/m³",
+                m.pm25.value(),
+                m.pm10.value()
+            )
+        }
+        ["sleep"] => return sensor.sleep().map(|_| None).map_err(e),
+        ["wake"] => return sensor.wake().map(|_| None).map_err(e),
+        ["mode"] => match sensor.mode().map_err(e)? {
+            ReportMode::Active => "active".to_string(),
+            ReportMode::Query => "query".to_string(),
+        },
+        ["mode", "active"] => return sensor.set_mode(ReportMode::Active).map(|_| None).map_err(e),
+        ["mode", "query"] => return sensor.set_mode(ReportMode::Query).map(|_| None).map_err(e),
+        ["period"] => match sensor.work_period().map_err(e)? {
+            0 => "continuous".to_string(),
+            minutes => format!("{} minutes", minutes),
+        },
+        ["period", minutes] => {
+            let minutes = minutes
+                .parse()
+                .ok()
+                .filter(|m| *m <= 30)
+                .ok_or_else(|| format!("\"{}\": expected 0 to 30 minutes", minutes))?;
+            return sensor.set_work_period(minutes).map(|_| None).map_err(e);
+        }
+        ["id"] => format!("{:04x}", sensor.device_id().map_err(e)?),
+        ["id", id] => {
+            let id = u16::from_str_radix(id, 16)
+                .map_err(|_| format!("\"{}\": expected 4 hex digits", id))?;
+            return sensor.set_device_id(id).map(|_| None).map_err(e);
+        }
+        ["version"] => sensor.firmware_version().map_err(e)?.to_string(),
+        ["stats"] => {
+            let s = sensor.stats();
+            format!(
+                "queries {}, checksum failures {}, timeouts {}, retries {}, resyncs {}, \
+                 bytes read {}, written {}",
+                s.queries,
+                s.checksum_failures,
+                s.timeouts,
+                s.retries,
+                s.resyncs,
+                s.bytes_read,
+                s.bytes_written
+            )
+        }
+        words => {
+            return Err(format!(
+                "unknown command \"{}\", type help for the commands",
+                words.join(" ")
+            ))
+        }
+    };
+    Ok(Some(text))
+}
+
+/// Prints the frames exchanged since the last call with `hex`, drops
+/// them otherwise
+fn print_frames(frames: &Frames, hex: bool) {
+    let frames = match frames.lock() {
+        Ok(mut frames) => std::mem::take(&mut *frames),
+        Err(_) => return,
+    };
+    if !hex {
+        return;
+    }
+    for (direction, frame) in frames.iter() {
+        let arrow = match direction {
+            Direction::Sent => '>',
+            Direction::Received => '<',
+        };
+        println!("{} {}", arrow, crate::frames::hex(frame));
+    }
+}
@@ -0,0 +1,57 @@
+//! Optional Robonomics datalog records, see `sds011::sink::robonomics`.
+
+use crate::config::Effective;
+use sds011::sink::rate::RateLimiter;
+use sds011::sink::Sink;
+
+/// Opens the datalog sink sending to the gateway at `url` configured in
+/// `settings`, limited by `limiter`
+#[cfg(feature = "robonomics")]
+pub fn open(
+    settings: &Effective,
+    url: &str,
+    limiter: RateLimiter,
+) -> Result<Box<dyn Sink>, String> {
+    use sds011::signing::Signer;
+    use sds011::sink::robonomics::Robonomics;
+
+    let key = settings
+        .robonomics_key
+        .as_ref()
+        .ok_or("robonomics_key must be set")?;
+    let signer = Signer::load(&key.value).map_err(|e| e.to_string())?;
+    let mut sink = Robonomics::new(url, signer, limiter);
+    if let Some(token) = settings.robonomics_token.as_ref() {
+        sink = sink.token(&token.value);
+    }
+    log::info!("robonomics: station key {}", sink.public_key());
+    Ok(Box::new(sink))
+}
+
+#[cfg(not(feature = "robonomics"))]
+pub fn open(
+    _settings: &Effective,
+    _url: &str,
+    _limiter: RateLimiter,
+) -> Result<Box<dyn Sink>, String> {
+    Err("this build has no Robonomics support, rebuild with --features robonomics".to_string())
+}
+
+/// Rate limiter of the records of every sensor, at `robonomics_interval`
+#[cfg(feature = "robonomics")]
+pub fn limiter(settings: &Effective) -> RateLimiter {
+    use sds011::sink::robonomics::INTERVAL;
+    use std::time::Duration;
+
+    let interval = settings
+        .robonomics_interval
+        .as_ref()
+        .map_or(INTERVAL, |s| Duration::from_secs(s.value.max(1)));
+    RateLimiter::new(1, interval)
+}
+
+#[cfg(not(feature = "robonomics"))]
+pub fn limiter(_settings: &Effective) -> RateLimiter {
+    // Never used, opening fails
+    RateLimiter::new(1, std::time::Duration::from_secs(1))
+}
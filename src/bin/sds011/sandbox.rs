@@ -0,0 +1,291 @@
+//! Privilege dropping and sandboxing for long-running mode.
+//!
+//! The daemon often has to start as root just to open `/dev/ttyUSB0`.
+//! Once the port is open, it can switch to an unprivileged user, forbid
+//! gaining privileges again and, on Linux, restrict system calls to the
+//! set a running driver needs.
+
+/// Returns `(uid, gid)` of `name`
+#[cfg(unix)]
+pub fn lookup_user(name: &str) -> Result<(u32, u32), String> {
+    use std::ffi::CString;
+
+    let c_name = CString::new(name).map_err(|e| e.to_string())?;
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+    let ret = unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if ret != 0 || result.is_null() {
+        return Err(format!("no such user {}", name));
+    }
+    Ok((pwd.pw_uid, pwd.pw_gid))
+}
+
+#[cfg(not(unix))]
+pub fn lookup_user(name: &str) -> Result<(u32, u32), String> {
+    Err(format!("switching to user {} is not supported", name))
+}
+
+/// Switches to `name`, dropping supplementary groups
+#[cfg(unix)]
+fn drop_privileges(name: &str) -> Result<(), String> {
+    let (uid, gid) = lookup_user(name)?;
+    let err = |what: &str| format!("{}: {}", what, std::io::Error::last_os_error());
+
+    unsafe {
+        if libc::setgroups(0, std::ptr::null()) != 0 {
+            return Err(err("setgroups"));
+        }
+        if libc::setgid(gid) != 0 {
+            return Err(err("setgid"));
+        }
+        if libc::setuid(uid) != 0 {
+            return Err(err("setuid"));
+        }
+        // Make sure root can't be regained
+        if uid != 0 && libc::setuid(0) == 0 {
+            return Err("privileges were not dropped".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn drop_privileges(name: &str) -> Result<(), String> {
+    lookup_user(name).map(|_| ())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use libc::{sock_filter, sock_fprog, BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+    #[cfg(target_arch = "arm")]
+    const AUDIT_ARCH: u32 = 0x4000_0028;
+
+    // Offsets into struct seccomp_data
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+
+    /// System calls a running driver, its servers and its outputs need,
+    /// including SQLite's journal, file rotation, StatsD's socket, the
+    /// tokio runtimes of MQTT and gRPC and DNS lookups of outputs
+    /// reconnecting
+    const ALLOWED: &[libc::c_long] = &[
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_writev,
+        libc::SYS_readv,
+        libc::SYS_close,
+        libc::SYS_openat,
+        libc::SYS_fstat,
+        libc::SYS_statx,
+        libc::SYS_lseek,
+        libc::SYS_pread64,
+        libc::SYS_pwrite64,
+        libc::SYS_ftruncate,
+        libc::SYS_unlinkat,
+        libc::SYS_fcntl,
+        libc::SYS_fsync,
+        libc::SYS_fdatasync,
+        libc::SYS_ioctl,
+        libc::SYS_ppoll,
+        libc::SYS_pselect6,
+        libc::SYS_epoll_pwait,
+        libc::SYS_epoll_ctl,
+        libc::SYS_epoll_create1,
+        libc::SYS_eventfd2,
+        libc::SYS_futex,
+        libc::SYS_nanosleep,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_clock_gettime,
+        libc::SYS_gettimeofday,
+        libc::SYS_getrandom,
+        libc::SYS_sched_yield,
+        libc::SYS_sched_getaffinity,
+        libc::SYS_uname,
+        libc::SYS_brk,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_madvise,
+        libc::SYS_mprotect,
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_sigaltstack,
+        libc::SYS_socket,
+        libc::SYS_bind,
+        libc::SYS_connect,
+        libc::SYS_accept4,
+        libc::SYS_sendto,
+        libc::SYS_recvfrom,
+        libc::SYS_sendmsg,
+        libc::SYS_sendmmsg,
+        libc::SYS_recvmsg,
+        libc::SYS_getsockopt,
+        libc::SYS_setsockopt,
+        libc::SYS_getsockname,
+        libc::SYS_getpeername,
+        libc::SYS_shutdown,
+        libc::SYS_clone,
+        libc::SYS_clone3,
+        libc::SYS_set_robust_list,
+        libc::SYS_rseq,
+        libc::SYS_gettid,
+        libc::SYS_getpid,
+        libc::SYS_tgkill,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+        libc::SYS_renameat2,
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        libc::SYS_mmap,
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        libc::SYS_newfstatat,
+        #[cfg(any(target_arch = "x86_64", target_arch = "arm"))]
+        libc::SYS_renameat,
+        #[cfg(any(target_arch = "x86_64", target_arch = "arm"))]
+        libc::SYS_poll,
+        #[cfg(any(target_arch = "x86_64", target_arch = "arm"))]
+        libc::SYS_unlink,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_open,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_stat,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_rename,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_epoll_wait,
+        #[cfg(target_arch = "arm")]
+        libc::SYS_mmap2,
+        #[cfg(target_arch = "arm")]
+        libc::SYS_fstat64,
+        #[cfg(target_arch = "arm")]
+        libc::SYS_fstatat64,
+        #[cfg(target_arch = "arm")]
+        libc::SYS_fcntl64,
+        #[cfg(target_arch = "arm")]
+        libc::SYS__llseek,
+    ];
+
+    fn stmt(code: u32, k: u32) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    /// Forbids gaining privileges, inherited by threads spawned afterwards
+    pub fn no_new_privs() -> Result<(), String> {
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(format!("prctl: {}", std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// Installs the allowlist on every thread of the process, other system
+    /// calls fail with `EPERM`
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "arm"))]
+    pub fn seccomp() -> Result<(), String> {
+        let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+        let mut filter = vec![
+            stmt(BPF_LD | BPF_W | BPF_ABS, ARCH_OFFSET),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH, 1, 0),
+            stmt(BPF_RET | BPF_K, deny),
+            stmt(BPF_LD | BPF_W | BPF_ABS, NR_OFFSET),
+        ];
+        for nr in ALLOWED.iter() {
+            filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, *nr as u32, 0, 1));
+            filter.push(stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+        }
+        filter.push(stmt(BPF_RET | BPF_K, deny));
+
+        let prog = sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_mut_ptr(),
+        };
+        // Without TSYNC, only the calling thread would be filtered
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_SET_MODE_FILTER,
+                libc::SECCOMP_FILTER_FLAG_TSYNC,
+                &prog as *const sock_fprog,
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            tid if tid > 0 => Err(format!("seccomp: can't filter thread {}", tid)),
+            _ => Err(format!("seccomp: {}", std::io::Error::last_os_error())),
+        }
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "arm")))]
+    pub fn seccomp() -> Result<(), String> {
+        Err("seccomp is not supported on this architecture".to_string())
+    }
+}
+
+/// Forbids gaining privileges again if `user` or `seccomp` are set
+/// Must be called before any thread is spawned, so that they all inherit it
+pub fn prepare(user: Option<&str>, seccomp: bool) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    {
+        if user.is_some() || seccomp {
+            linux::no_new_privs()?;
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (user, seccomp);
+    }
+    Ok(())
+}
+
+/// Drops privileges to `user` and, with `seccomp`, restricts the system
+/// calls of every thread
+/// Must be called after `prepare()` and once all ports, sockets and
+/// outputs are open
+pub fn apply(user: Option<&str>, seccomp: bool) -> Result<(), String> {
+    if let Some(u) = user {
+        drop_privileges(u)?;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if seccomp {
+            linux::seccomp()?;
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        if seccomp {
+            return Err("seccomp is only supported on Linux".to_string());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Optional user script applied to readings, see `sds011::script`.
+
+use sds011::Message;
+
+#[cfg(feature = "scripting")]
+pub use sds011::script::Script;
+
+/// Placeholder for builds without the `scripting` feature
+#[cfg(not(feature = "scripting"))]
+pub struct Script;
+
+#[cfg(feature = "scripting")]
+pub fn load(path: &str) -> Result<Script, String> {
+    Script::load(path).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn load(_path: &str) -> Result<Script, String> {
+    Err("this build has no scripting support, rebuild with --features scripting".to_string())
+}
+
+/// Runs the script, prints its alert and returns the reading to output
+#[cfg(feature = "scripting")]
+pub fn apply(script: &Script, m: Message) -> Option<Message> {
+    match script.run(&m) {
+        Ok(outcome) => {
+            if let Some(alert) = outcome.alert {
+                eprintln!("alert: {}", alert);
+            }
+            outcome.message
+        }
+        Err(e) => {
+            eprintln!("error: script: {}", e);
+            Some(m)
+        }
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn apply(_script: &Script, m: Message) -> Option<Message> {
+    Some(m)
+}
@@ -0,0 +1,32 @@
+//! Optional sensor.community uploads, see `sds011::sink::sensor_community`.
+
+use sds011::sink::rate::RateLimiter;
+use sds011::sink::Sink;
+
+/// Opens the upload of node `sensor_id`, limited by `limiter`
+#[cfg(feature = "sensor-community")]
+pub fn open(sensor_id: &str, limiter: RateLimiter) -> Result<Box<dyn Sink>, String> {
+    use sds011::sink::sensor_community::SensorCommunity;
+
+    Ok(Box::new(SensorCommunity::new(sensor_id, limiter)))
+}
+
+#[cfg(not(feature = "sensor-community"))]
+pub fn open(_sensor_id: &str, _limiter: RateLimiter) -> Result<Box<dyn Sink>, String> {
+    Err(
+        "this build has no sensor.community support, rebuild with --features sensor-community"
+            .to_string(),
+    )
+}
+
+/// Rate limiter of the uploads of every sensor
+#[cfg(feature = "sensor-community")]
+pub fn limiter() -> RateLimiter {
+    RateLimiter::new(1, sds011::sink::sensor_community::INTERVAL)
+}
+
+#[cfg(not(feature = "sensor-community"))]
+pub fn limiter() -> RateLimiter {
+    // Never used, opening fails
+    RateLimiter::new(1, std::time::Duration::from_secs(1))
+}
@@ -0,0 +1,158 @@
+//! Interactive first-run setup.
+
+use crate::config::Config;
+use sds011::discovery::{candidate_ports, discover_ports, Filter};
+use sds011::durable;
+use sds011::SDS011;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+/// Prints `question` and returns the answer or `default` on an empty line
+fn ask(question: &str, default: &str) -> String {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    io::stdout().flush().ok();
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+        // EOF, e.g. stdin is not a terminal
+        return default.to_string();
+    }
+    match line.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    }
+}
+
+fn confirm(question: &str, default: bool) -> bool {
+    let answer = ask(question, if default { "Y/n" } else { "y/N" });
+    match answer.to_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    }
+}
+
+fn choose_port() -> String {
+    let known = discover_ports(Filter::KnownAdapters).unwrap_or_default();
+    let ports = candidate_ports().unwrap_or_default();
+
+    if ports.is_empty() {
+        println!("No serial ports found. Is the sensor plugged in?");
+        return ask("Port", "/dev/ttyUSB0");
+    }
+
+    println!("Serial ports found:");
+    for (i, p) in ports.iter().enumerate() {
+        let mark = if known.contains(p) { "*" } else { " " };
+        println!("  {}{}) {}", mark, i + 1, p);
+    }
+    if !known.is_empty() {
+        println!("  (* adapters commonly shipped with SDS011)");
+    }
+
+    let answer = ask("Port number or path", "1");
+    match answer.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= ports.len() => ports[n - 1].path.clone(),
+        _ => answer,
+    }
+}
+
+/// Runs `setup`, returns the process exit code
+pub fn run() -> i32 {
+    println!("SDS011 setup\n");
+
+    let mut port = choose_port();
+    let mut sensor = loop {
+        println!("Reading from {} ...", port);
+        match SDS011::open(&port).and_then(|mut s| s.query().map(|m| (s, m))) {
+            Ok((s, m)) => {
+                println!("OK: {}", m);
+                break Some(s);
+            }
+            Err(e) => {
+                println!("Test read failed: {}", e);
+                if Path::new(&port).exists() && cfg!(unix) {
+                    println!("Check that your user is in the `dialout` group.");
+                }
+                if confirm("Try another port?", true) {
+                    port = choose_port();
+                } else {
+                    break None;
+                }
+            }
+        }
+    };
+
+    let work_period = loop {
+        let answer = ask("Work period in minutes (0-30, 0 means continuous)", "5");
+        match answer.parse::<u8>() {
+            Ok(w) if w <= 30 => break w,
+            _ => println!("Enter a number between 0 and 30"),
+        }
+    };
+    if let Some(s) = sensor.as_mut() {
+        if let Err(e) = s.set_work_period(work_period) {
+            println!("Failed to set work period: {}", e);
+        }
+    }
+
+    let config = Config {
+        port: Some(port),
+        work_period: Some(work_period),
+        ..Config::default()
+    };
+
+    if !confirm("\nWrite a configuration file?", true) {
+        return 0;
+    }
+    let config_path = ask("Configuration file", "sds011.toml");
+    let text = toml::to_string(&config).expect("config is serializable");
+    if let Err(e) = durable::write_atomic(&config_path, text.as_bytes()) {
+        eprintln!("error: {}", e);
+        return 1;
+    }
+    println!("Wrote {}", config_path);
+
+    if !confirm("\nWrite a systemd unit?", cfg!(target_os = "linux")) {
+        return 0;
+    }
+    let unit_path = ask("Unit file", "sds011.service");
+    let exe = std::env::current_exe()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| "/usr/local/bin/sds011".to_string());
+    let config_abs = std::fs::canonicalize(&config_path)
+        .map(|p| p.display().to_string())
+        .unwrap_or(config_path);
+    let unit = format!(
+        "[Unit]\n\
+         Description=SDS011 air quality sensor\n\
+         After=network.target\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={} --config {}\n\
+         WatchdogSec=60\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=multi-user.target\n",
+        exe, config_abs
+    );
+    if let Err(e) = durable::write_atomic(&unit_path, unit.as_bytes()) {
+        eprintln!("error: {}", e);
+        return 1;
+    }
+    let unit_name = Path::new(&unit_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    println!("Wrote {}, install it with:", unit_path);
+    println!("  sudo cp {} /etc/systemd/system/", unit_path);
+    println!("  sudo systemctl enable --now {}", unit_name);
+
+    0
+}
@@ -0,0 +1,62 @@
+//! Graceful shutdown of monitoring on SIGINT and SIGTERM.
+//!
+//! The handlers only set a flag; the monitoring loop checks it between
+//! readings, flushes the outputs and puts the sensor to sleep, so Ctrl-C
+//! or `systemctl stop` doesn't leave the fan and the laser running.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Longest time a shutdown request goes unnoticed while waiting
+pub const POLL: Duration = Duration::from_millis(200);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the SIGINT and SIGTERM handlers
+#[cfg(unix)]
+pub fn install() -> Result<(), String> {
+    extern "C" fn handle(_signal: libc::c_int) {
+        REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // Without SA_RESTART, a blocking read of the port returns early
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe {
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(format!(
+                    "signal handler: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install() -> Result<(), String> {
+    Ok(())
+}
+
+/// Returns `true` once SIGINT or SIGTERM was received
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleeps for `period` or until shutdown is requested, returns `true` in
+/// the latter case
+pub fn wait(period: Duration) -> bool {
+    let deadline = Instant::now() + period;
+    while !requested() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0) {
+            return false;
+        }
+        sleep(left.min(POLL));
+    }
+    true
+}
@@ -0,0 +1,30 @@
+//! Optional SQLite database measurements are stored in, see `sds011::store`.
+
+use sds011::durable::SyncPolicy;
+use sds011::sink::Sink;
+
+/// Opens the database at `path`, storing the sensor's `device_id` with
+/// every measurement
+#[cfg(feature = "sqlite")]
+pub fn open(
+    path: &str,
+    device_id: Option<u16>,
+    policy: SyncPolicy,
+) -> Result<Box<dyn Sink>, String> {
+    use sds011::store::Store;
+
+    let store = Store::open_with(path, policy).map_err(|e| e.to_string())?;
+    match device_id {
+        Some(id) => Ok(Box::new(store.device_id(id))),
+        None => Ok(Box::new(store)),
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn open(
+    _path: &str,
+    _device_id: Option<u16>,
+    _policy: SyncPolicy,
+) -> Result<Box<dyn Sink>, String> {
+    Err("this build has no SQLite support, rebuild with --features sqlite".to_string())
+}
@@ -0,0 +1,119 @@
+//! systemd integration for `Type=notify` units: `READY=1` once the first
+//! reading was taken, `STOPPING=1` on shutdown and `WATCHDOG=1` pings when
+//! the unit sets `WatchdogSec=`.
+//!
+//! Pings come from the monitoring loop, after an iteration that took a
+//! reading and while it waits for the next one. A loop that hangs, e.g. on
+//! a serial port, or whose readings all fail stops pinging, so the service
+//! gets killed and, with `Restart=on-failure`, restarted.
+//!
+//! Without `$NOTIFY_SOCKET`, i.e. when not started by systemd, all of this
+//! does nothing.
+
+use std::time::Duration;
+
+/// Sends notifications to systemd
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    #[cfg(unix)]
+    addr: std::os::unix::net::SocketAddr,
+    ready: bool,
+    /// `WatchdogSec=` of the unit
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// Connects to `$NOTIFY_SOCKET`, `None` when it isn't set
+    #[cfg(unix)]
+    pub fn open() -> Result<Option<Notifier>, String> {
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        let path = match std::env::var("NOTIFY_SOCKET") {
+            Ok(p) if !p.is_empty() => p,
+            _ => return Ok(None),
+        };
+        let addr = match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name.as_bytes())
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(format!(
+                    "NOTIFY_SOCKET: {}: abstract sockets are Linux only",
+                    path
+                ))
+            }
+            None => SocketAddr::from_pathname(&path),
+        }
+        .map_err(|e| format!("NOTIFY_SOCKET: {}: {}", path, e))?;
+        let socket = UnixDatagram::unbound().map_err(|e| format!("NOTIFY_SOCKET: {}", e))?;
+        Ok(Some(Notifier {
+            socket,
+            addr,
+            ready: false,
+            watchdog: watchdog_timeout(),
+        }))
+    }
+
+    #[cfg(not(unix))]
+    pub fn open() -> Result<Option<Notifier>, String> {
+        Ok(None)
+    }
+
+    /// Sends `state`, e.g. `READY=1`, newline-separated assignments
+    #[cfg(unix)]
+    fn notify(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            eprintln!("error: systemd: {}", e);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn notify(&self, _state: &str) {}
+
+    /// Tells systemd the service started, once, along with `status`
+    pub fn ready(&mut self, status: &str) {
+        if self.ready {
+            self.status(status);
+        } else {
+            self.ready = true;
+            self.notify(&format!("READY=1\nSTATUS={}", status));
+        }
+    }
+
+    /// Shows `status` in `systemctl status`
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={}", status));
+    }
+
+    /// Tells systemd the service is stopping
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    /// Pings the watchdog if the unit has `WatchdogSec=`
+    pub fn ping(&self) {
+        if self.watchdog.is_some() {
+            self.notify("WATCHDOG=1");
+        }
+    }
+
+    /// Longest time between pings, half the watchdog timeout
+    pub fn ping_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|t| t / 2)
+    }
+}
+
+/// `WatchdogSec=` of the unit, if it's meant for this process
+fn watchdog_timeout() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    Some(Duration::from_micros(usec)).filter(|t| *t > Duration::from_secs(0))
+}
@@ -0,0 +1,37 @@
+//! Optional ThingSpeak updates, see `sds011::sink::thingspeak`.
+
+use crate::config::Effective;
+use sds011::sink::rate::RateLimiter;
+use sds011::sink::Sink;
+
+/// Opens the channel of the write API key `api_key`, limited by `limiter`
+#[cfg(feature = "thingspeak")]
+pub fn open(api_key: &str, limiter: RateLimiter) -> Result<Box<dyn Sink>, String> {
+    use sds011::sink::thingspeak::ThingSpeak;
+
+    Ok(Box::new(ThingSpeak::new(api_key, limiter)))
+}
+
+#[cfg(not(feature = "thingspeak"))]
+pub fn open(_api_key: &str, _limiter: RateLimiter) -> Result<Box<dyn Sink>, String> {
+    Err("this build has no ThingSpeak support, rebuild with --features thingspeak".to_string())
+}
+
+/// Rate limiter of the updates of every sensor, at `thingspeak_interval`
+#[cfg(feature = "thingspeak")]
+pub fn limiter(settings: &Effective) -> RateLimiter {
+    use sds011::sink::thingspeak::INTERVAL;
+    use std::time::Duration;
+
+    let interval = settings
+        .thingspeak_interval
+        .as_ref()
+        .map_or(INTERVAL, |s| Duration::from_secs(s.value.max(1)));
+    RateLimiter::new(1, interval)
+}
+
+#[cfg(not(feature = "thingspeak"))]
+pub fn limiter(_settings: &Effective) -> RateLimiter {
+    // Never used, opening fails
+    RateLimiter::new(1, std::time::Duration::from_secs(1))
+}
@@ -0,0 +1,314 @@
+//! `tui` subcommand: a live dashboard in the terminal, e.g. over SSH to a
+//! headless Raspberry Pi.
+//!
+//! A background thread reads the sensor every `--interval` seconds while
+//! the dashboard shows the last reading, its US AQI category, the baseline
+//! of the last hour, a sparkline of PM2.5 over it and the errors by kind.
+
+use crate::config::Effective;
+use crate::shutdown;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+use sds011::aqi::{AqiScale, Category};
+use sds011::baseline::Baseline;
+use sds011::{Message, SDS011};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time the sparkline covers
+const WINDOW: Duration = Duration::from_secs(3600);
+/// Percentile of the readings of the window the baseline is
+const BASELINE_PERCENTILE: f32 = 10.0;
+/// Longest time between two redraws
+const REFRESH: Duration = Duration::from_millis(250);
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("tui")
+        .about("Live dashboard of the readings in the terminal")
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .takes_value(true)
+                .help("Port the sensor is connected to, or auto [default: the port setting]"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .takes_value(true)
+                .default_value("5")
+                .help("Seconds between readings"),
+        )
+}
+
+/// Runs the dashboard until `q` is pressed, `matches` are the top-level
+/// ones the configuration is resolved from, returns the exit code
+pub fn run(m: &ArgMatches, matches: &ArgMatches) -> i32 {
+    match dashboard(m, matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+fn dashboard(m: &ArgMatches, matches: &ArgMatches) -> Result<(), String> {
+    let settings = Effective::resolve(matches)?;
+    let interval = m.value_of("interval").unwrap_or_default();
+    let interval = interval
+        .parse::<u64>()
+        .ok()
+        .filter(|s| *s > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("--interval: expected seconds, got \"{}\"", interval))?;
+    let port = crate::device::resolve_port(m.value_of("port").unwrap_or(&settings.port.value))?;
+    let mut sensor = crate::frames::open(&port).map_err(|e| format!("{}: {}", port, e))?;
+    let (calibration, _) = crate::load_files(&settings)?;
+    let device_id = sensor.device_id().ok();
+    let device = crate::lookup(&settings, device_id)?.unwrap_or_default();
+    sensor.set_calibration(calibration.or(device.calibration));
+    sensor.wake().map_err(|e| format!("{}: {}", port, e))?;
+    shutdown::install()?;
+
+    let title = match device_id {
+        Some(id) => format!(" SDS011 {:04x} on {} ", id, port),
+        None => format!(" SDS011 on {} ", port),
+    };
+    let (tx, rx) = channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let poller = poll(sensor, interval, tx, Arc::clone(&stop));
+
+    let mut terminal = ratatui::init();
+    let result = show(&mut terminal, &mut State::new(title, interval), &rx);
+    ratatui::restore();
+    stop.store(true, Ordering::SeqCst);
+    if let Ok(Err(e)) = poller.join() {
+        eprintln!("error: can't put the sensor on {} to sleep: {}", port, e);
+    }
+    result
+}
+
+/// Reads `sensor` every `interval` in a background thread until `stop`,
+/// then puts it to sleep
+fn poll(
+    mut sensor: SDS011,
+    interval: Duration,
+    tx: Sender<sds011::Result<Message>>,
+    stop: Arc<AtomicBool>,
+) -> thread::JoinHandle<sds011::Result<()>> {
+    thread::spawn(move || {
+        let mut next = Instant::now();
+        while !stop.load(Ordering::SeqCst) {
+            if Instant::now() >= next {
+                next += interval;
+                if tx.send(sensor.query()).is_err() {
+                    break;
+                }
+            }
+            thread::sleep(shutdown::POLL.min(next.saturating_duration_since(Instant::now())));
+        }
+        sensor.sleep()
+    })
+}
+
+/// What the dashboard shows
+struct State {
+    title: String,
+    last: Option<(Instant, Message)>,
+    /// PM2.5 readings of the last `WINDOW`
+    history: VecDeque<(Instant, f32)>,
+    /// Background level of the readings of the last `WINDOW`
+    baseline: Baseline,
+    readings: u64,
+    errors: BTreeMap<&'static str, u64>,
+    last_error: Option<(Instant, String)>,
+}
+
+impl State {
+    fn new(title: String, interval: Duration) -> State {
+        let window = (WINDOW.as_secs() / interval.as_secs()) as usize;
+        State {
+            title,
+            last: None,
+            history: VecDeque::new(),
+            baseline: Baseline::new(window, BASELINE_PERCENTILE),
+            readings: 0,
+            errors: BTreeMap::new(),
+            last_error: None,
+        }
+    }
+
+    fn record(&mut self, result: sds011::Result<Message>) {
+        let now = Instant::now();
+        match result {
+            Ok(m) => {
+                self.readings += 1;
+                self.history.push_back((now, m.pm25.value()));
+                self.baseline.push(&m);
+                self.last = Some((now, m));
+            }
+            Err(e) => {
+                *self.errors.entry(e.kind()).or_default() += 1;
+                self.last_error = Some((now, e.to_string()));
+            }
+        }
+        while matches!(self.history.front(), Some((t, _)) if now.duration_since(*t) > WINDOW) {
+            self.history.pop_front();
+        }
+    }
+
+    /// Highest PM2.5 of each of `columns` slices of the window, in tenths
+    /// of µg/m³, 0 for slices without readings
+    fn sparkline(&self, columns: usize) -> Vec<u64> {
+        let mut values = vec![0u64; columns];
+        let now = Instant::now();
+        for (t, pm25) in self.history.iter() {
+            let age = now.duration_since(*t).as_secs_f64() / WINDOW.as_secs_f64();
+            let column = ((1.0 - age) * columns as f64) as usize;
+            if let Some(v) = values.get_mut(column.min(columns.saturating_sub(1))) {
+                *v = (*v).max((pm25 * 10.0).round() as u64);
+            }
+        }
+        values
+    }
+}
+
+/// Draws the dashboard until `q`, Esc, Ctrl-C, SIGINT or SIGTERM
+fn show(
+    terminal: &mut DefaultTerminal,
+    state: &mut State,
+    rx: &Receiver<sds011::Result<Message>>,
+) -> Result<(), String> {
+    loop {
+        while let Ok(result) = rx.try_recv() {
+            state.record(result);
+        }
+        terminal
+            .draw(|frame| draw(frame, state))
+            .map_err(|e| e.to_string())?;
+
+        if shutdown::requested() {
+            return Ok(());
+        }
+        if !event::poll(REFRESH).map_err(|e| e.to_string())? {
+            continue;
+        }
+        if let Event::Key(key) = event::read().map_err(|e| e.to_string())? {
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c;
+            if key.kind == KeyEventKind::Press && quit {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, state: &State) {
+    let [current, history, errors, help] = Layout::vertical([
+        Constraint::Length(5),
+        Constraint::Min(5),
+        Constraint::Length(4),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let lines = match &state.last {
+        Some((_, m)) => {
+            let aqi = m.aqi(AqiScale::Us);
+            vec![
+                Line::from(vec![
+                    Span::raw("PM2.5 "),
+                    Span::raw(format!("{:>6.1} µg/m³", m.pm25.value())).bold(),
+                    Span::raw("    PM10 "),
+                    Span::raw(format!("{:>6.1} µg/m³", m.pm10.value())).bold(),
+                ]),
+                Line::from(vec![
+                    Span::raw("US AQI "),
+                    Span::raw(format!("{:>5} ", aqi.value)).bold(),
+                    Span::styled(
+                        aqi.category.to_string(),
+                        Style::new().fg(color(aqi.category)),
+                    ),
+                ]),
+                baseline(state),
+            ]
+        }
+        None => vec![Line::from("Waiting for the first reading...")],
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(state.title.as_str())),
+        current,
+    );
+
+    let columns = history.width.saturating_sub(2) as usize;
+    let data = state.sparkline(columns);
+    let peak = state.history.iter().map(|(_, v)| *v).fold(0.0f32, f32::max);
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(format!(" PM2.5, last hour, peak {:.1} µg/m³ ", peak)))
+            .data(&data)
+            .style(Style::new().fg(Color::Cyan)),
+        history,
+    );
+
+    let mut counts = vec![Span::raw(format!("readings {}", state.readings))];
+    for (kind, n) in state.errors.iter() {
+        counts.push(Span::raw(format!("   {} {}", kind, n)).fg(Color::Red));
+    }
+    let last_error = match &state.last_error {
+        Some((t, e)) => format!("last: {}, {}s ago", e, t.elapsed().as_secs()),
+        None => "no errors".to_string(),
+    };
+    frame.render_widget(
+        Paragraph::new(vec![Line::from(counts), Line::from(last_error)])
+            .block(Block::bordered().title(" Errors ")),
+        errors,
+    );
+
+    let age = match &state.last {
+        Some((t, _)) => format!("last reading {}s ago", t.elapsed().as_secs()),
+        None => String::new(),
+    };
+    frame.render_widget(Paragraph::new(format!(" q quit   {}", age)).dim(), help);
+}
+
+/// Background PM2.5 and PM10, readings above them are events
+fn baseline(state: &State) -> Line<'static> {
+    match state.baseline.current() {
+        Some((pm25, pm10)) => Line::from(vec![
+            Span::raw("Baseline PM2.5 "),
+            Span::raw(format!("{:>6.1}", pm25)),
+            Span::raw("    PM10 "),
+            Span::raw(format!("{:>6.1}", pm10)),
+            Span::raw(format!(
+                "    {:.0}th percentile, last hour",
+                BASELINE_PERCENTILE
+            )),
+        ])
+        .dim(),
+        None => Line::default(),
+    }
+}
+
+/// Color of an AQI category, close to the EPA's
+fn color(category: Category) -> Color {
+    match category {
+        Category::Good | Category::VeryLow | Category::Low => Color::Green,
+        Category::Moderate | Category::Medium => Color::Yellow,
+        Category::UnhealthyForSensitiveGroups | Category::High => Color::LightRed,
+        Category::Unhealthy => Color::Red,
+        Category::VeryUnhealthy | Category::VeryHigh => Color::Magenta,
+        Category::Hazardous => Color::Rgb(126, 0, 35),
+    }
+}
@@ -0,0 +1,99 @@
+//! `check-update` subcommand: compares the running version with the
+//! latest release on crates.io.
+
+use clap::{App, ArgMatches, SubCommand};
+
+#[cfg(feature = "update")]
+const CRATE_URL: &str = "https://crates.io/api/v1/crates/sds011";
+const RELEASES_URL: &str = "https://github.com/Vourhey/nova-sds011-rs/releases";
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("check-update")
+        .about("Checks crates.io for a newer release and prints how to upgrade")
+}
+
+/// Parses `major.minor.patch`, pre-releases are ignored
+#[cfg(feature = "update")]
+fn parse_version(v: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = v.trim().trim_start_matches('v').split('.');
+    let mut next = || parts.next()?.parse::<u64>().ok();
+    let version = (next()?, next()?, next()?);
+    Some(version)
+}
+
+/// Installed with `cargo install`, upgraded the same way
+#[cfg(feature = "update")]
+fn installed_by_cargo(exe: &std::path::Path) -> bool {
+    exe.components().any(|c| c.as_os_str() == ".cargo")
+}
+
+#[cfg(feature = "update")]
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs(30))
+        // crates.io rejects requests without a descriptive user agent
+        .user_agent(&format!(
+            "sds011/{} (https://github.com/Vourhey/nova-sds011-rs)",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .build()
+}
+
+#[cfg(feature = "update")]
+fn latest_version() -> Result<String, String> {
+    let body: serde_json::Value = agent()
+        .get(CRATE_URL)
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_json()
+        .map_err(|e| e.to_string())?;
+    body["crate"]["max_stable_version"]
+        .as_str()
+        .map(|v| v.to_string())
+        .ok_or_else(|| "crates.io: no stable version".to_string())
+}
+
+/// Runs the subcommand and returns the exit code: 0 when up to date, 2 when
+/// an update is available, 1 on errors. Nothing is downloaded, crates.io
+/// can't vouch for a binary
+#[cfg(feature = "update")]
+pub fn run(_m: &ArgMatches) -> i32 {
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = match latest_version() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 1;
+        }
+    };
+
+    match (parse_version(current), parse_version(&latest)) {
+        (Some(c), Some(l)) if l > c => {}
+        (Some(_), Some(_)) => {
+            println!("sds011 {} is up to date", current);
+            return 0;
+        }
+        _ => {
+            eprintln!("error: can't compare {} with {}", current, latest);
+            return 1;
+        }
+    }
+
+    println!("sds011 {} is available, this is {}", latest, current);
+    let exe = std::env::current_exe().unwrap_or_default();
+    if installed_by_cargo(&exe) {
+        println!("Upgrade with: cargo install sds011 --force");
+        return 2;
+    }
+    println!("Download it from {}/tag/v{}", RELEASES_URL, latest);
+    2
+}
+
+#[cfg(not(feature = "update"))]
+pub fn run(_m: &ArgMatches) -> i32 {
+    eprintln!(
+        "error: this build can't check for updates, rebuild with --features update or see {}",
+        RELEASES_URL
+    );
+    1
+}
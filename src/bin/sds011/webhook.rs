@@ -0,0 +1,76 @@
+//! Optional webhook output, see `sds011::sink::webhook`.
+
+use crate::config::Effective;
+use sds011::sink::Sink;
+use serde_json::Value;
+
+/// Splits `Name: value` headers, one per line
+pub fn parse_headers(text: &str) -> Result<Vec<(String, String)>, String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| match line.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                Ok((name.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!("\"{}\": expected Name: value", line.trim())),
+        })
+        .collect()
+}
+
+/// Opens the webhook configured in `settings`, adding the sensor's
+/// `device_id` and `fields` to every measurement
+#[cfg(feature = "webhook")]
+pub fn open(
+    settings: &Effective,
+    url: &str,
+    device_id: Option<u16>,
+    fields: &[(&str, Value)],
+) -> Result<Box<dyn Sink>, String> {
+    use sds011::sink::webhook::Webhook;
+
+    let mut sink = Webhook::new(url);
+    if let Some(headers) = settings.webhook_header.as_ref() {
+        for (name, value) in parse_headers(&headers.value)? {
+            sink = sink.header(&name, &value);
+        }
+    }
+    if let Some(id) = device_id {
+        sink = sink.field("device_id", format!("{:04x}", id));
+    }
+    for (name, value) in fields.iter() {
+        sink = sink.field(name, value.clone());
+    }
+    if let Some(key) = settings.webhook_encrypt.as_ref() {
+        sink = encrypt(sink, &key.value)?;
+    }
+    Ok(Box::new(sink))
+}
+
+#[cfg(all(feature = "webhook", feature = "encryption"))]
+fn encrypt(
+    sink: sds011::sink::webhook::Webhook,
+    public_key: &str,
+) -> Result<sds011::sink::webhook::Webhook, String> {
+    use sds011::encryption::Encryptor;
+
+    let encryptor = Encryptor::from_hex(public_key).map_err(|e| e.to_string())?;
+    Ok(sink.encrypt(encryptor))
+}
+
+#[cfg(all(feature = "webhook", not(feature = "encryption")))]
+fn encrypt(
+    _sink: sds011::sink::webhook::Webhook,
+    _public_key: &str,
+) -> Result<sds011::sink::webhook::Webhook, String> {
+    Err("this build has no encryption support, rebuild with --features encryption".to_string())
+}
+
+#[cfg(not(feature = "webhook"))]
+pub fn open(
+    _settings: &Effective,
+    _url: &str,
+    _device_id: Option<u16>,
+    _fields: &[(&str, Value)],
+) -> Result<Box<dyn Sink>, String> {
+    Err("this build has no webhook support, rebuild with --features webhook".to_string())
+}
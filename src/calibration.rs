@@ -0,0 +1,86 @@
+//! Per-sensor linear calibration.
+//!
+//! Users who co-locate a sensor with a reference instrument can fit
+//! `reference = raw * scale + offset` for each pollutant and have it
+//! applied to every reading.
+
+#[cfg(feature = "serde")]
+use crate::{Error, Result};
+use crate::{Message, MicrogramsPerCubicMeter};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Linear correction of PM values, the identity by default
+///
+/// Stored as TOML, missing keys keep their defaults:
+/// ```toml
+/// pm25_scale = 0.8
+/// pm25_offset = -1.5
+/// ```
+///
+/// # Example
+/// ```
+/// use sds011::calibration::Calibration;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let cal = Calibration { pm25_scale: 0.5, pm10_offset: 2.0, ..Calibration::default() };
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(10.0), pm10: MicrogramsPerCubicMeter(10.0) };
+///
+/// let corrected = cal.apply(&m);
+/// assert_eq!((corrected.pm25.value(), corrected.pm10.value()), (5.0, 12.0));
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct Calibration {
+    pub pm25_offset: f32,
+    pub pm25_scale: f32,
+    pub pm10_offset: f32,
+    pub pm10_scale: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            pm25_offset: 0.0,
+            pm25_scale: 1.0,
+            pm10_offset: 0.0,
+            pm10_scale: 1.0,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn err<E: std::fmt::Display>(path: &str, e: E) -> Error {
+    Error::CalibrationError(format!("{}: {}", path, e))
+}
+
+impl Calibration {
+    /// Applies the correction, negative results are clamped to zero
+    pub fn apply(&self, m: &Message) -> Message {
+        Message {
+            timestamp: m.timestamp,
+            pm25: MicrogramsPerCubicMeter(
+                (m.pm25.value() * self.pm25_scale + self.pm25_offset).max(0.0),
+            ),
+            pm10: MicrogramsPerCubicMeter(
+                (m.pm10.value() * self.pm10_scale + self.pm10_offset).max(0.0),
+            ),
+        }
+    }
+
+    /// Reads a calibration from a TOML file
+    #[cfg(feature = "serde")]
+    pub fn load(path: &str) -> Result<Calibration> {
+        let text = std::fs::read_to_string(path).map_err(|e| err(path, e))?;
+        toml::from_str(&text).map_err(|e| err(path, e))
+    }
+
+    /// Writes the calibration to a TOML file, replacing it atomically
+    #[cfg(feature = "serde")]
+    pub fn save(&self, path: &str) -> Result<()> {
+        let text = toml::to_string(self).map_err(|e| err(path, e))?;
+        crate::durable::write_atomic(path, text.as_bytes())
+    }
+}
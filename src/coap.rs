@@ -0,0 +1,575 @@
+//! CoAP server with the latest reading, for constrained networks.
+//!
+//! 6LoWPAN and Thread nodes can't afford HTTP; CoAP (RFC 7252) is its
+//! counterpart over UDP. Like `server::Server`, the server subscribes to
+//! an `EventBus` and keeps the latest reading:
+//! - `GET /pm`: the latest reading as JSON, formatted like the HTTP
+//!   server's `/reading`, `4.04` before the first one;
+//! - `GET /.well-known/core`: the resources, in CoRE link format.
+//!
+//! `/pm` can be observed (RFC 7641): a `GET` with `Observe: 0` registers
+//! the client, which then gets every new reading as a notification until
+//! it deregisters or answers one with a reset. Notifications are
+//! non-confirmable, except every 20th: an observer that doesn't
+//! acknowledge it by the next reading is gone and dropped.
+
+use crate::events::{Event, EventBus};
+use crate::{schema, Error, Message, Result};
+use serde_json::{Map, Value};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::ServerError(format!("coap: {}", e))
+}
+
+/// Message types
+const CON: u8 = 0;
+const NON: u8 = 1;
+const ACK: u8 = 2;
+const RST: u8 = 3;
+
+/// Codes, `class << 5 | detail`
+const EMPTY: u8 = 0x00;
+const GET: u8 = 0x01;
+const CONTENT: u8 = 0x45;
+const BAD_REQUEST: u8 = 0x80;
+const NOT_FOUND: u8 = 0x84;
+const METHOD_NOT_ALLOWED: u8 = 0x85;
+
+/// Option numbers
+const OBSERVE: u16 = 6;
+const URI_PATH: u16 = 11;
+const CONTENT_FORMAT: u16 = 12;
+
+/// Content formats
+const LINK_FORMAT: u32 = 40;
+const JSON: u32 = 50;
+
+/// Most observers kept, later registrations are served once
+const MAX_OBSERVERS: usize = 64;
+/// Every how many notifications one is confirmable
+const CONFIRM_EVERY: u32 = 20;
+/// Largest datagram read
+const MAX_DATAGRAM: usize = 1152;
+
+/// A request, only the parts the server looks at
+struct Request {
+    kind: u8,
+    code: u8,
+    id: u16,
+    token: Vec<u8>,
+    path: Vec<String>,
+    observe: Option<u32>,
+}
+
+/// Reads an option's extended delta or length
+fn extended(nibble: u8, buf: &[u8], at: &mut usize) -> Option<u16> {
+    match nibble {
+        13 => {
+            let v = *buf.get(*at)? as u16 + 13;
+            *at += 1;
+            Some(v)
+        }
+        14 => {
+            let v = u16::from_be_bytes([*buf.get(*at)?, *buf.get(*at + 1)?]).checked_add(269)?;
+            *at += 2;
+            Some(v)
+        }
+        15 => None,
+        n => Some(n as u16),
+    }
+}
+
+/// Parses a datagram, `None` if it isn't a well-formed CoAP message
+fn parse(buf: &[u8]) -> Option<Request> {
+    if buf.len() < 4 || buf[0] >> 6 != 1 {
+        return None;
+    }
+    let tkl = (buf[0] & 0x0f) as usize;
+    if tkl > 8 || buf.len() < 4 + tkl {
+        return None;
+    }
+    let mut request = Request {
+        kind: (buf[0] >> 4) & 0x03,
+        code: buf[1],
+        id: u16::from_be_bytes([buf[2], buf[3]]),
+        token: buf[4..4 + tkl].to_vec(),
+        path: Vec::new(),
+        observe: None,
+    };
+    let mut at = 4 + tkl;
+    let mut number = 0u16;
+    while at < buf.len() && buf[at] != 0xff {
+        let byte = buf[at];
+        at += 1;
+        number = number.checked_add(extended(byte >> 4, buf, &mut at)?)?;
+        let len = extended(byte & 0x0f, buf, &mut at)? as usize;
+        let value = buf.get(at..at + len)?;
+        at += len;
+        match number {
+            URI_PATH => request
+                .path
+                .push(String::from_utf8_lossy(value).into_owned()),
+            OBSERVE => {
+                request.observe = Some(value.iter().fold(0, |v, b| v << 8 | *b as u32));
+            }
+            _ => {}
+        }
+    }
+    Some(request)
+}
+
+/// Unsigned option value in as few bytes as possible
+fn uint(value: u32) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    bytes[skip..].to_vec()
+}
+
+/// Builds a message, `options` sorted by number
+fn encode(
+    kind: u8,
+    code: u8,
+    id: u16,
+    token: &[u8],
+    options: &[(u16, Vec<u8>)],
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut out = vec![0x40 | kind << 4 | token.len() as u8, code];
+    out.extend_from_slice(&id.to_be_bytes());
+    out.extend_from_slice(token);
+    let nibble = |v: usize| match v {
+        0..=12 => (v as u8, Vec::new()),
+        13..=268 => (13, vec![(v - 13) as u8]),
+        _ => (14, ((v - 269) as u16).to_be_bytes().to_vec()),
+    };
+    let mut last = 0;
+    for (number, value) in options {
+        let (delta, delta_ext) = nibble((number - last) as usize);
+        let (len, len_ext) = nibble(value.len());
+        out.push(delta << 4 | len);
+        out.extend(delta_ext);
+        out.extend(len_ext);
+        out.extend_from_slice(value);
+        last = *number;
+    }
+    if !payload.is_empty() {
+        out.push(0xff);
+        out.extend_from_slice(payload);
+    }
+    out
+}
+
+/// A client observing `/pm`
+struct Observer {
+    addr: SocketAddr,
+    token: Vec<u8>,
+    /// ID of the last notification, a reset with it ends the observation
+    last_id: u16,
+    sent: u32,
+    /// Whether the last notification was confirmable and not acknowledged
+    unacknowledged: bool,
+}
+
+/// What the server knows, updated from events
+struct State {
+    latest: Option<String>,
+    observers: Vec<Observer>,
+    /// Observe sequence number, 24 bits
+    sequence: u32,
+    next_id: u16,
+}
+
+impl State {
+    fn next_id(&mut self) -> u16 {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.next_id
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    match state.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Body of `/pm`
+fn reading_json(port: &str, m: &Message) -> Result<String> {
+    let mut fields = Map::new();
+    fields.insert("port".to_string(), Value::from(port));
+    Ok(crate::sink::with_fields(schema::to_json(m)?, &fields))
+}
+
+/// CoAP server fed by an event bus
+///
+/// # Example
+/// ```
+/// use sds011::coap::CoapServer;
+/// use sds011::events::{Event, EventBus};
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::net::UdpSocket;
+/// use std::time::UNIX_EPOCH;
+///
+/// let bus = EventBus::new();
+/// let server = CoapServer::bind("127.0.0.1:0").unwrap();
+/// let addr = server.local_addr().unwrap();
+/// server.spawn(&bus);
+///
+/// let message = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) };
+/// bus.publish(Event::Measurement { port: "/dev/ttyUSB0".to_string(), message });
+/// # std::thread::sleep(std::time::Duration::from_millis(100));
+///
+/// // Confirmable GET /pm, message ID 0x1234, token 0xab
+/// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// client.send_to(&[0x41, 0x01, 0x12, 0x34, 0xab, 0xb2, b'p', b'm'], addr).unwrap();
+/// let mut buf = [0; 1152];
+/// let n = client.recv(&mut buf).unwrap();
+/// // Acknowledgement, 2.05 Content, JSON
+/// assert_eq!(&buf[..8], &[0x61, 0x45, 0x12, 0x34, 0xab, 0xc1, 50, 0xff]);
+/// assert_eq!(&buf[8..n], &br#"{"schema_version":2,"timestamp":0,"pm25":4.5,"pm10":8.0,"port":"/dev/ttyUSB0"}"#[..]);
+/// ```
+pub struct CoapServer {
+    socket: UdpSocket,
+    state: Arc<Mutex<State>>,
+}
+
+impl CoapServer {
+    /// Listens on `addr`, e.g. `[::]:5683`
+    pub fn bind(addr: &str) -> Result<CoapServer> {
+        let socket = UdpSocket::bind(addr).map_err(|e| err(format!("{}: {}", addr, e)))?;
+        Ok(CoapServer {
+            socket,
+            state: Arc::new(Mutex::new(State {
+                latest: None,
+                observers: Vec::new(),
+                sequence: 0,
+                next_id: 0,
+            })),
+        })
+    }
+
+    /// Address the server listens on, e.g. when bound to port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.local_addr().ok()
+    }
+
+    /// Serves requests in a background thread, with readings published on
+    /// `bus` from now on
+    pub fn spawn(self, bus: &EventBus) -> thread::JoinHandle<()> {
+        let events = bus.subscribe();
+        let state = Arc::clone(&self.state);
+        match self.socket.try_clone() {
+            Ok(socket) => {
+                thread::spawn(move || {
+                    for event in events {
+                        if let Event::Measurement { port, message } = event {
+                            notify(&socket, &state, &port, &message);
+                        }
+                    }
+                });
+            }
+            Err(e) => log::warn!("coap: no notifications: {}", e),
+        }
+
+        thread::spawn(move || {
+            let mut buf = [0; MAX_DATAGRAM];
+            loop {
+                match self.socket.recv_from(&mut buf) {
+                    Ok((n, from)) => self.respond(&buf[..n], from),
+                    Err(e) => log::warn!("coap: {}", e),
+                }
+            }
+        })
+    }
+
+    fn respond(&self, datagram: &[u8], from: SocketAddr) {
+        let request = match parse(datagram) {
+            Some(r) => r,
+            None => return,
+        };
+        let mut state = lock(&self.state);
+        if request.kind == RST || request.kind == ACK {
+            // A reset answers a notification the client no longer wants
+            if request.kind == RST {
+                state
+                    .observers
+                    .retain(|o| !(o.addr == from && o.last_id == request.id));
+            }
+            for o in state.observers.iter_mut() {
+                if o.addr == from && o.last_id == request.id {
+                    o.unacknowledged = false;
+                }
+            }
+            return;
+        }
+        if request.code == EMPTY {
+            // CoAP ping
+            if request.kind == CON {
+                self.send(encode(RST, EMPTY, request.id, &[], &[], &[]), from);
+            }
+            return;
+        }
+        if request.code >> 5 != 0 {
+            // Responses aren't expected here
+            return;
+        }
+
+        let path = request.path.join("/");
+        let mut options = Vec::new();
+        let (code, payload) = match (request.code, path.as_str()) {
+            (GET, "pm") => match state.latest.clone() {
+                Some(json) => {
+                    let observing = &request.token;
+                    state
+                        .observers
+                        .retain(|o| !(o.addr == from && &o.token == observing));
+                    if request.observe == Some(0) && state.observers.len() < MAX_OBSERVERS {
+                        state.observers.push(Observer {
+                            addr: from,
+                            token: request.token.clone(),
+                            last_id: request.id,
+                            sent: 0,
+                            unacknowledged: false,
+                        });
+                        options.push((OBSERVE, uint(state.sequence)));
+                    }
+                    options.push((CONTENT_FORMAT, uint(JSON)));
+                    (CONTENT, json.into_bytes())
+                }
+                None => (NOT_FOUND, b"no reading yet".to_vec()),
+            },
+            (GET, ".well-known/core") => {
+                options.push((CONTENT_FORMAT, uint(LINK_FORMAT)));
+                (CONTENT, b"</pm>;rt=\"pm\";obs;ct=50".to_vec())
+            }
+            (GET, _) => (NOT_FOUND, b"not found".to_vec()),
+            (code, _) if code <= 0x04 => (METHOD_NOT_ALLOWED, b"method not allowed".to_vec()),
+            _ => (BAD_REQUEST, b"bad request".to_vec()),
+        };
+        let (kind, id) = match request.kind {
+            CON => (ACK, request.id),
+            _ => (NON, state.next_id()),
+        };
+        drop(state);
+        self.send(
+            encode(kind, code, id, &request.token, &options, &payload),
+            from,
+        );
+    }
+
+    fn send(&self, datagram: Vec<u8>, to: SocketAddr) {
+        if let Err(e) = self.socket.send_to(&datagram, to) {
+            log::warn!("coap: {}: {}", to, e);
+        }
+    }
+}
+
+/// Keeps the reading and sends it to the observers
+fn notify(socket: &UdpSocket, state: &Mutex<State>, port: &str, m: &Message) {
+    let json = match reading_json(port, m) {
+        Ok(json) => json,
+        Err(e) => {
+            log::warn!("coap: {}", e);
+            return;
+        }
+    };
+    let mut state = lock(state);
+    state.latest = Some(json.clone());
+    if state.observers.is_empty() {
+        return;
+    }
+    state.sequence = (state.sequence + 1) & 0xff_ffff;
+    let options = [
+        (OBSERVE, uint(state.sequence)),
+        (CONTENT_FORMAT, uint(JSON)),
+    ];
+    let mut observers = std::mem::take(&mut state.observers);
+    observers.retain(|o| !o.unacknowledged);
+    for o in observers.iter_mut() {
+        o.last_id = state.next_id();
+        o.sent += 1;
+        let kind = if o.sent % CONFIRM_EVERY == 0 {
+            o.unacknowledged = true;
+            CON
+        } else {
+            NON
+        };
+        let datagram = encode(
+            kind,
+            CONTENT,
+            o.last_id,
+            &o.token,
+            &options,
+            json.as_bytes(),
+        );
+        if let Err(e) = socket.send_to(&datagram, o.addr) {
+            log::warn!("coap: {}: {}", o.addr, e);
+        }
+    }
+    state.observers = observers;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MicrogramsPerCubicMeter;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn round_trips_options() {
+        let token = [1, 2, 3, 4, 5, 6, 7, 8];
+        let options = [
+            (OBSERVE, uint(0)),
+            (URI_PATH, b"pm".to_vec()),
+            (URI_PATH, b"x".repeat(300)),
+            // Delta 289, over 268, and a 20 byte value
+            (300, vec![0; 20]),
+        ];
+        let datagram = encode(CON, GET, 0x1234, &token, &options, b"body");
+        let request = parse(&datagram).unwrap();
+        assert_eq!((request.kind, request.code, request.id), (CON, GET, 0x1234));
+        assert_eq!(request.token, token);
+        assert_eq!(request.observe, Some(0));
+        assert_eq!(request.path, ["pm".to_string(), "x".repeat(300)]);
+
+        // 300 byte path: length nibble 14, 300 - 269 in two bytes
+        let at = 4 + token.len() + 1 + 1 + 2;
+        assert_eq!(&datagram[at..at + 3], &[0x0e, 0x00, 31]);
+        // Option 300: delta nibble 14, 289 - 269; length nibble 13, 20 - 13
+        let at = at + 3 + 300;
+        assert_eq!(&datagram[at..at + 4], &[0xed, 0x00, 20, 7]);
+    }
+
+    #[test]
+    fn encodes_uints_in_few_bytes() {
+        assert_eq!(uint(0), Vec::<u8>::new());
+        assert_eq!(uint(50), [50]);
+        assert_eq!(uint(0x01_0000), [1, 0, 0]);
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let get = [0x41, GET, 0x12, 0x34, 0xab, 0xb2, b'p', b'm'];
+        assert!(parse(&get).is_some());
+        // Too short, wrong version
+        assert!(parse(&get[..3]).is_none());
+        assert!(parse(&[0x81, GET, 0x12, 0x34, 0xab]).is_none());
+        // Token length over 8, token cut short
+        assert!(parse(&[0x49, GET, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9]).is_none());
+        assert!(parse(&get[..4]).is_none());
+        // Option value cut short
+        assert!(parse(&get[..7]).is_none());
+        // Extended delta and length missing their bytes
+        assert!(parse(&[0x40, GET, 0, 0, 0xd0]).is_none());
+        assert!(parse(&[0x40, GET, 0, 0, 0x0e, 0x00]).is_none());
+        // Reserved nibble 15, outside the payload marker
+        assert!(parse(&[0x40, GET, 0, 0, 0xf1, 0]).is_none());
+        assert!(parse(&[0x40, GET, 0, 0, 0x1f, 0]).is_none());
+        // Option numbers past 65535
+        assert!(parse(&[0x40, GET, 0, 0, 0xe0, 0xff, 0xff, 0xe0, 0xff, 0xff]).is_none());
+    }
+
+    /// Server and a client talking to it over loopback
+    fn setup() -> (CoapServer, UdpSocket) {
+        let server = CoapServer::bind("127.0.0.1:0").unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        (server, client)
+    }
+
+    fn reading(server: &CoapServer) {
+        let m = Message {
+            timestamp: UNIX_EPOCH,
+            pm25: MicrogramsPerCubicMeter(4.5),
+            pm10: MicrogramsPerCubicMeter(8.0),
+        };
+        notify(&server.socket, &server.state, "/dev/ttyUSB0", &m);
+    }
+
+    fn receive(client: &UdpSocket) -> Request {
+        let mut buf = [0; MAX_DATAGRAM];
+        let n = client.recv(&mut buf).unwrap();
+        parse(&buf[..n]).unwrap()
+    }
+
+    /// Registers `client` as an observer of `/pm` with token 0xab
+    fn observe(server: &CoapServer, client: &UdpSocket) {
+        let options = [(OBSERVE, uint(0)), (URI_PATH, b"pm".to_vec())];
+        let get = encode(CON, GET, 1, &[0xab], &options, &[]);
+        server.respond(&get, client.local_addr().unwrap());
+        let reply = receive(client);
+        assert_eq!((reply.kind, reply.code, reply.id), (ACK, CONTENT, 1));
+        assert!(reply.observe.is_some());
+    }
+
+    fn observers(server: &CoapServer) -> usize {
+        lock(&server.state).observers.len()
+    }
+
+    #[test]
+    fn reset_ends_the_observation() {
+        let (server, client) = setup();
+        reading(&server);
+        observe(&server, &client);
+        assert_eq!(observers(&server), 1);
+
+        reading(&server);
+        let notification = receive(&client);
+        assert_eq!((notification.kind, notification.code), (NON, CONTENT));
+        assert_eq!(notification.token, [0xab]);
+        assert_eq!(notification.observe, Some(1));
+
+        // A reset with another ID is ignored
+        let from = client.local_addr().unwrap();
+        let other = notification.id.wrapping_add(1);
+        server.respond(&encode(RST, EMPTY, other, &[], &[], &[]), from);
+        assert_eq!(observers(&server), 1);
+        server.respond(&encode(RST, EMPTY, notification.id, &[], &[], &[]), from);
+        assert_eq!(observers(&server), 0);
+    }
+
+    #[test]
+    fn unacknowledged_confirmable_notification_drops_the_observer() {
+        let (server, client) = setup();
+        reading(&server);
+        observe(&server, &client);
+        let from = client.local_addr().unwrap();
+
+        lock(&server.state).observers[0].sent = CONFIRM_EVERY - 1;
+        reading(&server);
+        let confirmable = receive(&client);
+        assert_eq!(confirmable.kind, CON);
+        server.respond(&encode(ACK, EMPTY, confirmable.id, &[], &[], &[]), from);
+
+        // Acknowledged, so the observer gets the next one
+        lock(&server.state).observers[0].sent = CONFIRM_EVERY - 1;
+        reading(&server);
+        assert_eq!(receive(&client).kind, CON);
+        assert_eq!(observers(&server), 1);
+
+        // Not acknowledged by the next reading
+        reading(&server);
+        assert_eq!(observers(&server), 0);
+    }
+
+    #[test]
+    fn get_without_observe_deregisters() {
+        let (server, client) = setup();
+        reading(&server);
+        observe(&server, &client);
+        let options = [(OBSERVE, uint(1)), (URI_PATH, b"pm".to_vec())];
+        let get = encode(NON, GET, 2, &[0xab], &options, &[]);
+        server.respond(&get, client.local_addr().unwrap());
+        let reply = receive(&client);
+        assert_eq!(
+            (reply.kind, reply.code, reply.observe),
+            (NON, CONTENT, None)
+        );
+        assert_eq!(observers(&server), 0);
+    }
+}
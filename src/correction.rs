@@ -0,0 +1,97 @@
+//! Humidity compensation of PM readings.
+//!
+//! Optical sensors like the SDS011 count water-swollen particles as bigger
+//! ones and over-read at high relative humidity. The sensor doesn't measure
+//! humidity, so it has to come from an external sensor, e.g. a BME280 next
+//! to the inlet.
+
+use crate::{Message, MicrogramsPerCubicMeter};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Humidity above this, in percent, is clamped to it because the
+/// hygroscopic growth models diverge close to saturation
+pub const MAX_HUMIDITY: f32 = 95.0;
+
+/// Corrects a PM value in µg/m³ given relative humidity in percent
+///
+/// Any `Fn(f32, f32) -> f32` taking `(pm, humidity)` can be used as well.
+pub trait Compensation {
+    fn compensate(&self, pm: f32, humidity: f32) -> f32;
+}
+
+impl<F: Fn(f32, f32) -> f32> Compensation for F {
+    fn compensate(&self, pm: f32, humidity: f32) -> f32 {
+        self(pm, humidity)
+    }
+}
+
+/// Köhler theory based correction (Crilley et al., 2018), as used by
+/// sensor.community:
+/// `pm / (1 + (kappa / 1.65) / (100 / rh - 1))`
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Kohler {
+    /// Hygroscopicity of the particles, 0.4 for typical urban aerosol
+    pub kappa: f32,
+}
+
+impl Default for Kohler {
+    fn default() -> Self {
+        Kohler { kappa: 0.4 }
+    }
+}
+
+impl Compensation for Kohler {
+    fn compensate(&self, pm: f32, humidity: f32) -> f32 {
+        let rh = humidity.clamp(0.0, MAX_HUMIDITY);
+        if rh == 0.0 {
+            return pm;
+        }
+        let growth = 1.0 + (self.kappa / 1.65) / (100.0 / rh - 1.0);
+        pm / growth
+    }
+}
+
+/// Raw and humidity corrected measurement
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Corrected {
+    pub raw: Message,
+    pub corrected: Message,
+    /// Relative humidity used for the correction, in percent
+    pub humidity: f32,
+}
+
+/// Applies `compensation` to both PM values of `m`
+///
+/// # Example
+/// ```
+/// use sds011::correction::{correct, Kohler};
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(20.0), pm10: MicrogramsPerCubicMeter(30.0) };
+///
+/// let c = correct(&Kohler::default(), &m, 90.0);
+/// assert!(c.corrected.pm25 < c.raw.pm25);
+///
+/// // Any function of (pm, humidity) works too
+/// let c = correct(&|pm: f32, rh: f32| if rh > 80.0 { pm * 0.8 } else { pm }, &m, 90.0);
+/// assert_eq!(c.corrected.pm25.value(), 16.0);
+/// ```
+pub fn correct<C: Compensation + ?Sized>(
+    compensation: &C,
+    m: &Message,
+    humidity: f32,
+) -> Corrected {
+    Corrected {
+        raw: m.clone(),
+        corrected: Message {
+            timestamp: m.timestamp,
+            pm25: MicrogramsPerCubicMeter(compensation.compensate(m.pm25.value(), humidity)),
+            pm10: MicrogramsPerCubicMeter(compensation.compensate(m.pm10.value(), humidity)),
+        },
+        humidity,
+    }
+}
@@ -0,0 +1,162 @@
+//! Directory of sensors by device ID.
+//!
+//! Fleets outgrow spreadsheets mapping sensors to where they hang. A
+//! `Directory` maps device IDs to a `Device` with its location,
+//! calibration and install date, and finds sensors by location. Outputs
+//! add `Device::fields()` to every record, so data stays attributable
+//! when a sensor moves. `FileDirectory` reads a TOML file; other sources,
+//! e.g. an inventory database, implement the trait.
+
+use crate::calibration::Calibration;
+use crate::{durable, Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+fn err<E: std::fmt::Display>(path: &str, e: E) -> Error {
+    Error::DirectoryError(format!("{}: {}", path, e))
+}
+
+/// Metadata of one sensor
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Device {
+    /// Free-form place name, e.g. `Kitchen` or `Roof, Building 4`
+    pub location: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Install date, e.g. `2024-03-01`
+    pub installed: Option<String>,
+    /// Calibration of this sensor
+    pub calibration: Option<Calibration>,
+}
+
+impl Device {
+    /// Set descriptive fields as `(name, value)`, added to output records
+    pub fn fields(&self) -> Vec<(&'static str, Value)> {
+        let mut fields = Vec::new();
+        if let Some(l) = &self.location {
+            fields.push(("location", Value::from(l.as_str())));
+        }
+        if let Some(lat) = self.latitude {
+            fields.push(("latitude", Value::from(lat)));
+        }
+        if let Some(lon) = self.longitude {
+            fields.push(("longitude", Value::from(lon)));
+        }
+        if let Some(i) = &self.installed {
+            fields.push(("installed", Value::from(i.as_str())));
+        }
+        fields
+    }
+}
+
+/// Source of sensor metadata
+pub trait Directory: Send + Sync {
+    /// Metadata of the sensor with `id`
+    fn lookup(&self, id: u16) -> Option<Device>;
+
+    /// Every known sensor, ordered by ID
+    fn devices(&self) -> Vec<(u16, Device)>;
+
+    /// IDs of the sensors at `location`, compared case-insensitively
+    fn find(&self, location: &str) -> Vec<u16> {
+        self.devices()
+            .into_iter()
+            .filter(|(_, d)| matches!(&d.location, Some(l) if l.eq_ignore_ascii_case(location)))
+            .map(|(id, _)| id)
+            .collect()
+    }
+}
+
+/// Directory kept in memory and stored as TOML, keyed by hex device IDs:
+/// ```toml
+/// [devices.a160]
+/// location = "Kitchen"
+/// installed = "2024-03-01"
+///
+/// [devices.a160.calibration]
+/// pm25_scale = 0.8
+/// ```
+///
+/// # Example
+/// ```
+/// use sds011::directory::{Device, Directory, FileDirectory};
+///
+/// let mut directory = FileDirectory::default();
+/// directory.insert(0xa160, Device { location: Some("Kitchen".to_string()), ..Device::default() });
+/// directory.insert(0xb7f2, Device { location: Some("Garden".to_string()), ..Device::default() });
+///
+/// assert_eq!(directory.lookup(0xa160).unwrap().location.as_deref(), Some("Kitchen"));
+/// assert_eq!(directory.find("garden"), vec![0xb7f2]);
+/// assert!(directory.lookup(0x0001).is_none());
+/// ```
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct FileDirectory {
+    devices: BTreeMap<u16, Device>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Stored {
+    #[serde(default)]
+    devices: BTreeMap<String, Device>,
+}
+
+impl FileDirectory {
+    /// Reads a directory from a TOML file
+    pub fn load(path: &str) -> Result<FileDirectory> {
+        let text = std::fs::read_to_string(path).map_err(|e| err(path, e))?;
+        FileDirectory::parse(&text).map_err(|e| err(path, e))
+    }
+
+    /// Parses a directory from TOML
+    pub fn parse(text: &str) -> Result<FileDirectory> {
+        let file: Stored =
+            toml::from_str(text).map_err(|e| Error::DirectoryError(e.to_string()))?;
+        let mut devices = BTreeMap::new();
+        for (key, device) in file.devices {
+            let id = u16::from_str_radix(&key, 16).map_err(|_| {
+                Error::DirectoryError(format!("devices.{}: expected a hex device ID", key))
+            })?;
+            devices.insert(id, device);
+        }
+        Ok(FileDirectory { devices })
+    }
+
+    /// Writes the directory to a TOML file, replacing it atomically
+    pub fn save(&self, path: &str) -> Result<()> {
+        let file = Stored {
+            devices: self
+                .devices
+                .iter()
+                .map(|(id, d)| (format!("{:04x}", id), d.clone()))
+                .collect(),
+        };
+        let text = toml::to_string(&file).map_err(|e| err(path, e))?;
+        durable::write_atomic(path, text.as_bytes())
+    }
+
+    /// Adds or replaces the sensor with `id`
+    pub fn insert(&mut self, id: u16, device: Device) {
+        self.devices.insert(id, device);
+    }
+
+    /// Removes the sensor with `id`
+    pub fn remove(&mut self, id: u16) -> Option<Device> {
+        self.devices.remove(&id)
+    }
+}
+
+impl Directory for FileDirectory {
+    fn lookup(&self, id: u16) -> Option<Device> {
+        self.devices.get(&id).cloned()
+    }
+
+    fn devices(&self) -> Vec<(u16, Device)> {
+        self.devices
+            .iter()
+            .map(|(id, d)| (*id, d.clone()))
+            .collect()
+    }
+}
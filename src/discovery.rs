@@ -0,0 +1,230 @@
+//! Serial port discovery.
+
+use crate::{Error, Result, SDS011};
+use serialport::{SerialPortInfo, SerialPortType};
+
+/// USB-serial bridges commonly shipped with SDS011 kits as `(vid, pid, name)`
+pub const KNOWN_ADAPTERS: &[(u16, u16, &str)] = &[
+    (0x1a86, 0x7523, "CH340"),
+    (0x1a86, 0x5523, "CH341"),
+    (0x10c4, 0xea60, "CP2102"),
+    (0x0403, 0x6001, "FT232R"),
+    (0x0403, 0x6015, "FT231X"),
+];
+
+/// USB metadata of a serial port
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UsbInfo {
+    /// Vendor ID
+    pub vid: u16,
+    /// Product ID
+    pub pid: u16,
+    /// Serial number
+    pub serial_number: Option<String>,
+    /// Manufacturer
+    pub manufacturer: Option<String>,
+    /// Product name
+    pub product: Option<String>,
+    /// Name of the USB-serial bridge if it's one of `KNOWN_ADAPTERS`
+    pub adapter: Option<&'static str>,
+}
+
+/// Describes a serial port found on the system
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PortInfo {
+    /// Path to pass to `SDS011::new()`, for example `/dev/ttyUSB0` or `COM5`
+    pub path: String,
+    /// USB metadata, `None` for non-USB ports or if it can't be determined
+    pub usb: Option<UsbInfo>,
+}
+
+impl From<SerialPortInfo> for PortInfo {
+    fn from(p: SerialPortInfo) -> Self {
+        let usb = match p.port_type {
+            SerialPortType::UsbPort(u) => Some(UsbInfo {
+                vid: u.vid,
+                pid: u.pid,
+                serial_number: u.serial_number,
+                manufacturer: u.manufacturer,
+                product: u.product,
+                adapter: adapter_name(u.vid, u.pid),
+            }),
+            _ => None,
+        };
+
+        PortInfo {
+            path: p.port_name,
+            usb,
+        }
+    }
+}
+
+impl std::fmt::Display for PortInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(u) = &self.usb {
+            write!(f, " [{:04x}:{:04x}]", u.vid, u.pid)?;
+            if let Some(a) = u.adapter {
+                write!(f, " {}", a)?;
+            }
+            if let Some(p) = &u.product {
+                write!(f, " {}", p)?;
+            }
+            if let Some(s) = &u.serial_number {
+                write!(f, " ({})", s)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which ports `discover_ports` returns
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Filter {
+    /// Every serial port found
+    All,
+    /// Only USB ports with a bridge listed in `KNOWN_ADAPTERS`
+    KnownAdapters,
+}
+
+/// Returns the name of a known USB-serial bridge
+pub fn adapter_name(vid: u16, pid: u16) -> Option<&'static str> {
+    KNOWN_ADAPTERS
+        .iter()
+        .find(|(v, p, _)| *v == vid && *p == pid)
+        .map(|(_, _, name)| *name)
+}
+
+/// Lists all serial ports found on the system
+///
+/// # Example
+/// ```no_run
+/// for port in sds011::available_ports().unwrap() {
+///     println!("{}", port);
+/// }
+/// ```
+pub fn available_ports() -> Result<Vec<PortInfo>> {
+    discover_ports(Filter::All)
+}
+
+/// Lists serial ports a sensor may be connected to
+///
+/// # Example
+/// ```no_run
+/// use sds011::discovery::{discover_ports, Filter};
+///
+/// for port in discover_ports(Filter::KnownAdapters).unwrap() {
+///     println!("{}", port.path);
+/// }
+/// ```
+pub fn discover_ports(filter: Filter) -> Result<Vec<PortInfo>> {
+    let ports = match serialport::available_ports() {
+        Ok(ports) => ports,
+        #[cfg(target_os = "linux")]
+        Err(_) => sysfs::available_ports()?,
+        #[cfg(not(target_os = "linux"))]
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(ports
+        .into_iter()
+        .map(PortInfo::from)
+        .filter(|p| match filter {
+            Filter::All => true,
+            Filter::KnownAdapters => p.usb.as_ref().and_then(|u| u.adapter).is_some(),
+        })
+        .collect())
+}
+
+/// Lists serial ports in the order a sensor is looked for: ports with
+/// known USB-serial bridges first, then every other one
+pub fn candidate_ports() -> Result<Vec<PortInfo>> {
+    let mut ports = discover_ports(Filter::KnownAdapters)?;
+    for p in available_ports()? {
+        if !ports.contains(&p) {
+            ports.push(p);
+        }
+    }
+    Ok(ports)
+}
+
+/// Probes `candidate_ports()` for a sensor and returns the first port one
+/// answers on, `Error::DeviceNotFound` if none does
+///
+/// # Example
+/// ```no_run
+/// use sds011::discovery::find_sensor;
+/// use sds011::SDS011;
+///
+/// let port = find_sensor().unwrap();
+/// let mut sensor = SDS011::new(&port.path).unwrap();
+/// ```
+pub fn find_sensor() -> Result<PortInfo> {
+    for p in candidate_ports()? {
+        if let Ok(mut sensor) = SDS011::new(&p.path) {
+            if sensor.device_id().is_ok() {
+                return Ok(p);
+            }
+        }
+    }
+    Err(Error::DeviceNotFound)
+}
+
+/// Port enumeration through sysfs, used when serialport
+/// is built without libudev
+#[cfg(target_os = "linux")]
+mod sysfs {
+    use super::*;
+    use serialport::UsbPortInfo;
+    use std::fs;
+    use std::path::Path;
+
+    pub fn available_ports() -> Result<Vec<SerialPortInfo>> {
+        let mut ports = Vec::new();
+
+        for entry in fs::read_dir("/sys/class/tty")? {
+            let entry = entry?;
+            let device = match fs::canonicalize(entry.path().join("device")) {
+                Ok(d) => d,
+                // Virtual terminals have no device
+                Err(_) => continue,
+            };
+
+            // Legacy 8250 UARTs are always present whether connected or not
+            let driver = fs::read_link(device.join("driver")).ok();
+            if let Some(d) = driver.as_ref().and_then(|d| d.file_name()) {
+                if d == "serial8250" {
+                    continue;
+                }
+            }
+
+            ports.push(SerialPortInfo {
+                port_name: format!("/dev/{}", entry.file_name().to_string_lossy()),
+                port_type: usb_info(&device)
+                    .map(SerialPortType::UsbPort)
+                    .unwrap_or(SerialPortType::Unknown),
+            });
+        }
+
+        ports.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+        Ok(ports)
+    }
+
+    /// Walks up from the tty device to the USB device holding the IDs
+    fn usb_info(device: &Path) -> Option<UsbPortInfo> {
+        let usb = device.ancestors().find(|p| p.join("idVendor").exists())?;
+        let read = |name: &str| {
+            fs::read_to_string(usb.join(name))
+                .ok()
+                .map(|s| s.trim().to_string())
+        };
+
+        Some(UsbPortInfo {
+            vid: u16::from_str_radix(&read("idVendor")?, 16).ok()?,
+            pid: u16::from_str_radix(&read("idProduct")?, 16).ok()?,
+            serial_number: read("serial"),
+            manufacturer: read("manufacturer"),
+            product: read("product"),
+        })
+    }
+}
@@ -0,0 +1,241 @@
+//! Power-loss tolerant file writes.
+//!
+//! Whole files such as configurations and calibrations are replaced with
+//! `write_atomic()`: the new contents are written to a temporary file next
+//! to the target, synced and renamed over it, so after a power cut the file
+//! holds either the old or the new contents, never a mix. Logs are appended
+//! one newline-terminated record at a time with `AppendFile`, which
+//! truncates a torn trailing record left by a power cut when it's opened.
+
+use crate::{Error, Result};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+fn err<E: fmt::Display>(path: &str, e: E) -> Error {
+    Error::StorageError(format!("{}: {}", path, e))
+}
+
+/// When appended records are flushed to the disk with `fsync`
+///
+/// Syncing every record is the safest, but wears SD cards and is slow on
+/// them. Records written since the last sync may be lost on power loss,
+/// though never torn in a way `AppendFile::open()` can't repair.
+///
+/// # Example
+/// ```
+/// use sds011::durable::SyncPolicy;
+/// use std::time::Duration;
+///
+/// assert_eq!("always".parse::<SyncPolicy>().unwrap(), SyncPolicy::Always);
+/// assert_eq!("30".parse::<SyncPolicy>().unwrap(), SyncPolicy::Interval(Duration::from_secs(30)));
+/// assert_eq!(SyncPolicy::Never.to_string(), "never");
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum SyncPolicy {
+    /// Sync after every record
+    #[default]
+    Always,
+    /// Sync when this much time passed since the last sync
+    Interval(Duration),
+    /// Leave it to the operating system
+    Never,
+}
+
+/// Parses `always`, `never` or an interval in seconds
+impl FromStr for SyncPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SyncPolicy> {
+        match s {
+            "always" => Ok(SyncPolicy::Always),
+            "never" => Ok(SyncPolicy::Never),
+            secs => secs
+                .parse::<u64>()
+                .map(|s| SyncPolicy::Interval(Duration::from_secs(s)))
+                .map_err(|_| {
+                    Error::StorageError(format!(
+                        "{}: expected always, never or a number of seconds",
+                        s
+                    ))
+                }),
+        }
+    }
+}
+
+impl fmt::Display for SyncPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncPolicy::Always => write!(f, "always"),
+            SyncPolicy::Interval(d) => write!(f, "{}", d.as_secs()),
+            SyncPolicy::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// Replaces the file at `path` with `contents` atomically
+///
+/// # Example
+/// ```no_run
+/// use sds011::durable;
+///
+/// durable::write_atomic("sds011.toml", b"work_period = 5\n").unwrap();
+/// ```
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    let tmp = match path.file_name() {
+        Some(f) => path.with_file_name(format!(".{}.tmp", f.to_string_lossy())),
+        None => return Err(err(&name, "not a file")),
+    };
+
+    let mut file = File::create(&tmp).map_err(|e| err(&name, e))?;
+    let written = file
+        .write_all(contents)
+        .and_then(|_| file.sync_all())
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(err(&name, e));
+    }
+    sync_dir(path);
+    Ok(())
+}
+
+/// Syncs the directory holding `path`, so a rename in it survives a power
+/// cut. Not every platform can sync directories, failures are ignored.
+fn sync_dir(path: &Path) {
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    if let Ok(d) = File::open(dir) {
+        let _ = d.sync_all();
+    }
+}
+
+/// Truncates the file at `path` after its last newline, dropping a record
+/// torn by a power cut. Returns the number of bytes dropped, a missing
+/// file has none.
+pub fn recover<P: AsRef<Path>>(path: P) -> Result<u64> {
+    let name = path.as_ref().display().to_string();
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(err(&name, e)),
+    };
+    let len = file.metadata().map_err(|e| err(&name, e))?.len();
+
+    // Scan backwards for the last complete record
+    let mut end = len;
+    let mut block = [0u8; 4096];
+    while end > 0 {
+        let start = end.saturating_sub(block.len() as u64);
+        let chunk = &mut block[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))
+            .and_then(|_| file.read_exact(chunk))
+            .map_err(|e| err(&name, e))?;
+        if let Some(i) = chunk.iter().rposition(|b| *b == b'\n') {
+            end = start + i as u64 + 1;
+            break;
+        }
+        end = start;
+    }
+
+    if end < len {
+        file.set_len(end)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| err(&name, e))?;
+    }
+    Ok(len - end)
+}
+
+/// File appended one newline-terminated record at a time
+///
+/// # Example
+/// ```no_run
+/// use sds011::durable::{AppendFile, SyncPolicy};
+/// use std::time::Duration;
+///
+/// let policy = SyncPolicy::Interval(Duration::from_secs(60));
+/// let mut log = AppendFile::open("readings.ndjson", policy).unwrap();
+/// log.append("{\"pm25\":4.0,\"pm10\":8.0}").unwrap();
+/// ```
+pub struct AppendFile {
+    file: File,
+    path: String,
+    policy: SyncPolicy,
+    synced: Instant,
+    dirty: bool,
+}
+
+impl AppendFile {
+    /// Opens `path` for appending, creating it if needed and truncating a
+    /// torn trailing record
+    pub fn open(path: &str, policy: SyncPolicy) -> Result<AppendFile> {
+        let torn = recover(path)?;
+        if torn > 0 {
+            log::warn!(
+                "{}: dropped {} bytes of a record torn by a crash",
+                path,
+                torn
+            );
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| err(path, e))?;
+        Ok(AppendFile {
+            file,
+            path: path.to_string(),
+            policy,
+            synced: Instant::now(),
+            dirty: false,
+        })
+    }
+
+    /// Path of the file
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Appends `record` and a newline with a single write, syncing as the
+    /// policy asks
+    pub fn append(&mut self, record: &str) -> Result<()> {
+        let mut line = String::with_capacity(record.len() + 1);
+        line.push_str(record);
+        line.push('\n');
+        self.file
+            .write_all(line.as_bytes())
+            .map_err(|e| err(&self.path, e))?;
+        self.dirty = true;
+
+        match self.policy {
+            SyncPolicy::Always => self.sync(),
+            SyncPolicy::Interval(d) if self.synced.elapsed() >= d => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flushes appended records to the disk
+    pub fn sync(&mut self) -> Result<()> {
+        if self.dirty {
+            self.file.sync_data().map_err(|e| err(&self.path, e))?;
+            self.dirty = false;
+        }
+        self.synced = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for AppendFile {
+    fn drop(&mut self) {
+        if self.policy != SyncPolicy::Never {
+            let _ = self.sync();
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! In-memory sensor speaking the SDS011 protocol.
+//!
+//! `Emulator` is a `Transport` that answers commands like a real sensor,
+//! for trying the library without hardware, tests and benchmarks.
+
+use crate::transport::Transport;
+use crate::{checksum, Result, CMD_ID, HEAD, TAIL};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+const DATA_ID: u8 = b'\xc0';
+const REPLY_ID: u8 = b'\xc5';
+/// Firmware version reported, year, month and day
+const FIRMWARE: [u8; 3] = [18, 11, 16];
+
+/// Emulated sensor
+///
+/// Like the real sensor, it ignores frames with a wrong checksum or
+/// another device ID and doesn't answer queries while sleeping. Reads
+/// time out when there is nothing to answer.
+///
+/// # Example
+/// ```
+/// use sds011::emulator::Emulator;
+/// use sds011::SDS011;
+///
+/// let mut emulator = Emulator::new(0xa160);
+/// emulator.set_reading(12.3, 45.6);
+///
+/// let mut sensor = SDS011::from_transport(Box::new(emulator)).unwrap();
+/// assert_eq!(sensor.device_id().unwrap(), 0xa160);
+/// assert_eq!(sensor.query().unwrap().pm25.value(), 12.3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Emulator {
+    device_id: u16,
+    pm25: u16,
+    pm10: u16,
+    passive: bool,
+    sleeping: bool,
+    work_period: u8,
+    input: Vec<u8>,
+    output: VecDeque<u8>,
+}
+
+impl Emulator {
+    /// Creates an awake sensor in active report mode reading zeros
+    pub fn new(device_id: u16) -> Emulator {
+        Emulator {
+            device_id,
+            pm25: 0,
+            pm10: 0,
+            passive: false,
+            sleeping: false,
+            work_period: 0,
+            input: Vec::with_capacity(64),
+            output: VecDeque::with_capacity(64),
+        }
+    }
+
+    /// Sets the values returned by queries, in µg/m³
+    pub fn set_reading(&mut self, pm25: f32, pm10: f32) {
+        self.pm25 = (pm25 * 10.0).round() as u16;
+        self.pm10 = (pm10 * 10.0).round() as u16;
+    }
+
+    /// Returns `true` if the sensor was put to sleep
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    /// Work period set by the host, in minutes
+    pub fn work_period(&self) -> u8 {
+        self.work_period
+    }
+
+    fn reply(&mut self, kind: u8, data: [u8; 4]) {
+        let [id1, id2] = self.device_id.to_be_bytes();
+        let payload = [data[0], data[1], data[2], data[3], id1, id2];
+        self.output.extend(&[HEAD, kind]);
+        self.output.extend(&payload);
+        self.output.extend(&[checksum(&payload), TAIL]);
+    }
+
+    fn handle(&mut self, frame: &[u8]) {
+        if frame[18] != TAIL || frame[17] != checksum(&frame[2..17]) {
+            return;
+        }
+        let target = u16::from_be_bytes([frame[15], frame[16]]);
+        if target != 0xffff && target != self.device_id {
+            return;
+        }
+
+        let (command, write, value) = (frame[2], frame[3] == 1, frame[4]);
+        match command {
+            b'\x02' => {
+                if write {
+                    self.passive = value == 1;
+                }
+                let mode = self.passive as u8;
+                self.reply(REPLY_ID, [command, frame[3], mode, 0]);
+            }
+            b'\x04' if !self.sleeping => {
+                let [pm25_lo, pm25_hi] = self.pm25.to_le_bytes();
+                let [pm10_lo, pm10_hi] = self.pm10.to_le_bytes();
+                self.reply(DATA_ID, [pm25_lo, pm25_hi, pm10_lo, pm10_hi]);
+            }
+            b'\x05' => {
+                self.device_id = u16::from_be_bytes([frame[13], frame[14]]);
+                self.reply(REPLY_ID, [command, 0, 0, 0]);
+            }
+            b'\x07' => {
+                let [year, month, day] = FIRMWARE;
+                self.reply(REPLY_ID, [command, year, month, day]);
+            }
+            b'\x06' => {
+                if write {
+                    self.sleeping = value == 0;
+                }
+                let work = !self.sleeping as u8;
+                self.reply(REPLY_ID, [command, frame[3], work, 0]);
+            }
+            b'\x08' => {
+                if write {
+                    self.work_period = value;
+                }
+                let period = self.work_period;
+                self.reply(REPLY_ID, [command, frame[3], period, 0]);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Read for Emulator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.output.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no reply"));
+        }
+
+        let n = buf.len().min(self.output.len());
+        for (b, out) in buf.iter_mut().zip(self.output.drain(..n)) {
+            *b = out;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for Emulator {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.input.extend_from_slice(data);
+
+        loop {
+            match self.input.windows(2).position(|w| w == [HEAD, CMD_ID]) {
+                Some(start) if self.input.len() - start >= 19 => {
+                    let mut frame = [0u8; 19];
+                    frame.copy_from_slice(&self.input[start..start + 19]);
+                    self.input.drain(..start + 19);
+                    self.handle(&frame);
+                }
+                Some(start) => {
+                    // Keep the incomplete frame until the rest arrives
+                    self.input.drain(..start);
+                    break;
+                }
+                None => {
+                    // A trailing HEAD may start the next frame
+                    let keep = (self.input.last() == Some(&HEAD)) as usize;
+                    let len = self.input.len();
+                    self.input.drain(..len - keep);
+                    break;
+                }
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Emulator {
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<()> {
+        Ok(())
+    }
+}
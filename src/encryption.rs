@@ -0,0 +1,98 @@
+//! End-to-end encryption of sink payloads.
+//!
+//! Payloads are sealed with a NaCl sealed box (X25519 + XSalsa20-Poly1305)
+//! to the receiver's public key, so an untrusted broker or webhook relay
+//! only sees ciphertext. Sealed payloads are base64 encoded to stay valid
+//! text for MQTT and HTTP bodies.
+
+use crate::{Error, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crypto_box::aead::OsRng;
+use crypto_box::{PublicKey, SecretKey};
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::EncryptionError(e.to_string())
+}
+
+fn key_from_hex(key: &str) -> Result<[u8; 32]> {
+    let mut bytes = [0u8; 32];
+    hex::decode_to_slice(key.trim(), &mut bytes).map_err(err)?;
+    Ok(bytes)
+}
+
+/// Seals payloads to a receiver's public key
+///
+/// # Example
+/// ```
+/// use sds011::encryption::{Decryptor, Encryptor};
+///
+/// let receiver = Decryptor::generate();
+/// let encryptor = Encryptor::from_hex(&receiver.public_key()).unwrap();
+///
+/// let sealed = encryptor.seal(b"{\"pm25\":4.0}").unwrap();
+/// assert_eq!(receiver.open(&sealed).unwrap(), b"{\"pm25\":4.0}");
+/// ```
+#[derive(Clone)]
+pub struct Encryptor {
+    key: PublicKey,
+}
+
+impl Encryptor {
+    /// Creates an encryptor from a hex encoded public key
+    pub fn from_hex(public_key: &str) -> Result<Encryptor> {
+        Ok(Encryptor {
+            key: PublicKey::from_bytes(key_from_hex(public_key)?),
+        })
+    }
+
+    /// Seals `payload` and returns it base64 encoded
+    pub fn seal(&self, payload: &[u8]) -> Result<String> {
+        let sealed = self.key.seal(&mut OsRng, payload).map_err(err)?;
+        Ok(STANDARD.encode(sealed))
+    }
+}
+
+/// Opens sealed payloads with the receiver's secret key
+pub struct Decryptor {
+    key: SecretKey,
+}
+
+impl Decryptor {
+    /// Creates a new random key pair
+    pub fn generate() -> Decryptor {
+        Decryptor {
+            key: SecretKey::generate(&mut OsRng),
+        }
+    }
+
+    /// Creates a decryptor from a hex encoded secret key
+    pub fn from_hex(secret_key: &str) -> Result<Decryptor> {
+        Ok(Decryptor {
+            key: SecretKey::from_bytes(key_from_hex(secret_key)?),
+        })
+    }
+
+    /// Reads a hex encoded secret key from a file
+    pub fn load(path: &str) -> Result<Decryptor> {
+        let text = std::fs::read_to_string(path).map_err(|e| err(format!("{}: {}", path, e)))?;
+        Decryptor::from_hex(&text)
+    }
+
+    /// Hex encoded secret key, to be stored by the receiver only
+    pub fn secret_key(&self) -> String {
+        hex::encode(self.key.to_bytes())
+    }
+
+    /// Hex encoded public key to configure on the sending side
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key.public_key().as_bytes())
+    }
+
+    /// Opens a base64 encoded sealed payload
+    pub fn open(&self, sealed: &str) -> Result<Vec<u8>> {
+        let bytes = STANDARD.decode(sealed.trim()).map_err(err)?;
+        self.key
+            .unseal(&bytes)
+            .map_err(|_| err("payload can't be decrypted with this key"))
+    }
+}
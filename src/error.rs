@@ -14,8 +14,86 @@ pub enum Error {
     EmptyDataFrame,
     /// Checksum doesn't match.
     BadChecksum,
+    /// Sensor is within its warm-up window, readings are unreliable.
+    WarmingUp,
+    /// Sensor is sleeping.
+    Sleeping,
+    /// The same PM values were read too many times in a row.
+    SuspectStuckSensor,
+    /// No sensor, or none with the requested device ID, was found.
+    DeviceNotFound,
     /// Serial port read errors.
     ReadError(String),
+    /// Export serialization errors.
+    #[from(ignore)]
+    ExportError(String),
+    /// Calibration file errors.
+    #[from(ignore)]
+    CalibrationError(String),
+    /// Encryption key or payload errors.
+    #[from(ignore)]
+    EncryptionError(String),
+    /// Script compilation or runtime errors.
+    #[from(ignore)]
+    ScriptError(String),
+    /// Signing key or signature errors.
+    #[from(ignore)]
+    SignatureError(String),
+    /// Sink connection or publishing errors.
+    #[from(ignore)]
+    SinkError(String),
+    /// WebAssembly plugin loading or runtime errors.
+    #[from(ignore)]
+    PluginError(String),
+    /// Gateway journal or connection errors.
+    #[from(ignore)]
+    GatewayError(String),
+    /// Invalid timestamp format.
+    #[from(ignore)]
+    TimestampFormatError(String),
+    /// Remote command parsing, authentication or connection errors.
+    #[from(ignore)]
+    RemoteError(String),
+    /// Reference feed request or decoding errors.
+    #[from(ignore)]
+    ReferenceError(String),
+    /// File writing or crash recovery errors.
+    #[from(ignore)]
+    StorageError(String),
+    /// Device directory file errors.
+    #[from(ignore)]
+    DirectoryError(String),
+    /// HTTP server errors.
+    #[from(ignore)]
+    ServerError(String),
+    /// Notification delivery errors.
+    #[from(ignore)]
+    NotifyError(String),
+}
+
+impl Error {
+    /// Short name of the kind of a reading error, e.g. `bad_checksum`,
+    /// `other` for errors unrelated to readings
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::Error;
+    ///
+    /// assert_eq!(Error::BadChecksum.kind(), "bad_checksum");
+    /// assert_eq!(Error::ReadError("timed out".to_string()).kind(), "read");
+    /// ```
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::BadChecksum => "bad_checksum",
+            Error::EmptyDataFrame => "empty_frame",
+            Error::ReadError(_) => "read",
+            Error::WarmingUp => "warming_up",
+            Error::Sleeping => "sleeping",
+            Error::SuspectStuckSensor => "stuck",
+            Error::DeviceNotFound => "device_not_found",
+            _ => "other",
+        }
+    }
 }
 
 impl From<SerialError> for Error {
@@ -32,8 +110,6 @@ impl From<std::io::Error> for Error {
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        match self {
-            _ => None,
-        }
+        None
     }
 }
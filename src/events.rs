@@ -0,0 +1,131 @@
+//! In-process event bus.
+//!
+//! Producers publish `Event`s and every subscriber gets its own copy over
+//! a channel, so sinks, servers and alerting don't need to know about each
+//! other.
+
+use crate::Message;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Something that happened in the daemon
+/// Serialize with `schema::to_json()` to tag it with the schema version
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum Event {
+    /// New reading from the sensor on `port`
+    Measurement { port: String, message: Message },
+    /// An alert rule fired
+    Alert { rule: String, message: String },
+    /// A sensor was opened
+    SensorAttached {
+        port: String,
+        device_id: Option<u16>,
+    },
+    /// Talking to a sensor failed
+    SensorError { port: String, error: String },
+    /// The working period of the sensor on `port` was set, 0 is continuous
+    WorkPeriodChanged { port: String, minutes: u8 },
+    /// A watchdog restarted the sensor on `port`, e.g. after stuck readings
+    WatchdogReset { port: String, reason: String },
+    /// Firmware version reported by the sensor on `port`
+    FirmwareInfo { port: String, version: String },
+    /// A sink failed to start or send
+    SinkDegraded { sink: String, error: String },
+    /// The laser of the sensor on `port` worked `hours`, `fraction` of the
+    /// hours it's rated for, past the warning fraction
+    LaserWorn {
+        port: String,
+        hours: f64,
+        fraction: f64,
+    },
+}
+
+impl Event {
+    /// Returns `true` for events about the configuration and state of
+    /// sensors, which sinks may forward for auditing
+    pub fn is_lifecycle(&self) -> bool {
+        matches!(
+            self,
+            Event::SensorAttached { .. }
+                | Event::SensorError { .. }
+                | Event::WorkPeriodChanged { .. }
+                | Event::WatchdogReset { .. }
+                | Event::FirmwareInfo { .. }
+                | Event::LaserWorn { .. }
+        )
+    }
+}
+
+/// Broadcasts events to subscribers, clones share the subscriber list
+///
+/// # Example
+/// ```
+/// use sds011::events::{Event, EventBus};
+///
+/// let bus = EventBus::new();
+/// let events = bus.subscribe();
+///
+/// bus.publish(Event::SensorError {
+///     port: "/dev/ttyUSB0".to_string(),
+///     error: "timed out".to_string(),
+/// });
+/// assert!(matches!(events.recv().unwrap(), Event::SensorError { .. }));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<Event>>>>,
+}
+
+impl EventBus {
+    /// Creates a bus without subscribers
+    pub fn new() -> EventBus {
+        EventBus::default()
+    }
+
+    fn subscribers(&self) -> std::sync::MutexGuard<'_, Vec<Sender<Event>>> {
+        match self.subscribers.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Returns a receiver of every event published from now on
+    /// Dropping the receiver unsubscribes
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (tx, rx) = channel();
+        self.subscribers().push(tx);
+        rx
+    }
+
+    /// Sends `event` to every subscriber, never blocks
+    pub fn publish(&self, event: Event) {
+        self.subscribers()
+            .retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    /// Unsubscribes everyone, their receivers end after the events already
+    /// published, e.g. to let consumer threads finish before exiting
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::events::{Event, EventBus};
+    ///
+    /// let bus = EventBus::new();
+    /// let events = bus.subscribe();
+    /// bus.publish(Event::SinkDegraded { sink: "csv".to_string(), error: "disk full".to_string() });
+    /// bus.close();
+    /// assert_eq!(events.iter().count(), 1);
+    /// ```
+    pub fn close(&self) {
+        self.subscribers().clear();
+    }
+
+    /// Number of subscribers, dropped ones are removed on the next publish
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers().len()
+    }
+}
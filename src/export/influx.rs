@@ -0,0 +1,72 @@
+//! [InfluxDB line protocol](https://docs.influxdata.com/influxdb/v2/reference/syntax/line-protocol/).
+//!
+//! Lines can be piped into Telegraf's `inputs.execd` or posted to the
+//! Influx write API as they are. Timestamps are in nanoseconds, the write
+//! API's default precision.
+
+use crate::Message;
+use std::time::UNIX_EPOCH;
+
+/// Escapes `chars` and backslashes with a backslash
+fn escape(s: &str, chars: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || chars.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes a measurement name
+fn measurement_name(s: &str) -> String {
+    escape(s, &[',', ' '])
+}
+
+/// Escapes a tag key, tag value or field key
+fn key(s: &str) -> String {
+    escape(s, &[',', '=', ' '])
+}
+
+impl Message {
+    /// Line protocol of this measurement with `pm25` and `pm10` fields
+    ///
+    /// Tags are written sorted by key, as Influx recommends, and tags with
+    /// an empty value are left out because Influx rejects them.
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::{Message, MicrogramsPerCubicMeter};
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let m = Message { timestamp: UNIX_EPOCH + Duration::from_secs(1587384000), pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) };
+    /// let line = m.to_line_protocol("air quality", &[("room", "living room"), ("device", "a160")]);
+    /// assert_eq!(line, "air\\ quality,device=a160,room=living\\ room pm25=4.5,pm10=8 1587384000000000000");
+    /// ```
+    pub fn to_line_protocol(&self, measurement: &str, tags: &[(&str, &str)]) -> String {
+        let mut line = measurement_name(measurement);
+
+        let mut tags: Vec<&(&str, &str)> = tags.iter().filter(|(_, v)| !v.is_empty()).collect();
+        tags.sort_by(|a, b| a.0.cmp(b.0));
+        for (k, v) in tags {
+            line.push(',');
+            line.push_str(&key(k));
+            line.push('=');
+            line.push_str(&key(v));
+        }
+
+        let nanos = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        line.push_str(&format!(
+            " pm25={},pm10={} {}",
+            self.pm25.value(),
+            self.pm10.value(),
+            nanos
+        ));
+        line
+    }
+}
@@ -0,0 +1,128 @@
+//! LoRaWAN payloads: [Cayenne LPP](https://docs.mydevices.com/docs/lorawan/cayenne-lpp)
+//! and a compact fixed-size format.
+//!
+//! Both are byte payloads to hand to a LoRaWAN module, e.g. hex encoded
+//! in its `AT+SEND` command. Cayenne LPP is decoded by The Things Network
+//! and ChirpStack out of the box; the compact format is half its size and
+//! needs a custom payload formatter, see [`Message::from_compact`].
+
+use crate::{Error, Message, MicrogramsPerCubicMeter, Result};
+use std::time::SystemTime;
+
+/// Size of a compact payload
+pub const COMPACT_SIZE: usize = 4;
+
+/// Cayenne LPP data type PM values are encoded as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LppType {
+    /// Analog input (type 2): signed, 0.01 resolution, saturating at
+    /// 327.67 µg/m³. Every LPP decoder knows it.
+    AnalogInput,
+    /// Concentration (type 125) of the extended LPP: unsigned whole
+    /// µg/m³, up to the sensor's 999.9. Decoders without the extension,
+    /// like myDevices Cayenne, reject the whole payload.
+    Concentration,
+}
+
+impl LppType {
+    /// Data type identifier
+    pub fn id(self) -> u8 {
+        match self {
+            LppType::AnalogInput => 2,
+            LppType::Concentration => 125,
+        }
+    }
+
+    /// Big-endian value bytes of `value`
+    fn encode(self, value: f32) -> [u8; 2] {
+        match self {
+            LppType::AnalogInput => {
+                let v = (value * 100.0)
+                    .round()
+                    .clamp(i16::MIN as f32, i16::MAX as f32);
+                (v as i16).to_be_bytes()
+            }
+            LppType::Concentration => {
+                let v = value.round().clamp(0.0, u16::MAX as f32);
+                (v as u16).to_be_bytes()
+            }
+        }
+    }
+}
+
+/// Tenths of µg/m³ of `value`, saturating
+fn tenths(value: f32) -> [u8; 2] {
+    let v = (value * 10.0).round().clamp(0.0, u16::MAX as f32);
+    (v as u16).to_be_bytes()
+}
+
+impl Message {
+    /// Cayenne LPP payload of this measurement, PM2.5 on `pm25_channel`
+    /// then PM10 on `pm10_channel`
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::export::lpp::LppType;
+    /// use sds011::{Message, MicrogramsPerCubicMeter};
+    /// use std::time::SystemTime;
+    ///
+    /// let m = Message { timestamp: SystemTime::now(), pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) };
+    /// assert_eq!(m.to_cayenne_lpp(LppType::AnalogInput, 1, 2), [1, 2, 0x01, 0xc2, 2, 2, 0x03, 0x20]);
+    /// assert_eq!(m.to_cayenne_lpp(LppType::Concentration, 1, 2), [1, 125, 0, 5, 2, 125, 0, 8]);
+    /// ```
+    pub fn to_cayenne_lpp(&self, kind: LppType, pm25_channel: u8, pm10_channel: u8) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(8);
+        for (channel, value) in [(pm25_channel, &self.pm25), (pm10_channel, &self.pm10)].iter() {
+            payload.push(*channel);
+            payload.push(kind.id());
+            payload.extend_from_slice(&kind.encode(value.value()));
+        }
+        payload
+    }
+
+    /// Compact payload of this measurement: PM2.5 then PM10 as big-endian
+    /// unsigned 16-bit tenths of µg/m³, the sensor's own resolution
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::{Message, MicrogramsPerCubicMeter};
+    /// use std::time::SystemTime;
+    ///
+    /// let m = Message { timestamp: SystemTime::now(), pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(800.0) };
+    /// assert_eq!(m.to_compact(), [0x00, 0x2d, 0x1f, 0x40]);
+    /// ```
+    pub fn to_compact(&self) -> [u8; COMPACT_SIZE] {
+        let pm25 = tenths(self.pm25.value());
+        let pm10 = tenths(self.pm10.value());
+        [pm25[0], pm25[1], pm10[0], pm10[1]]
+    }
+
+    /// Measurement of a compact `payload`, which carries no time, received
+    /// at `timestamp`
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::Message;
+    /// use std::time::SystemTime;
+    ///
+    /// let m = Message::from_compact(&[0x00, 0x2d, 0x1f, 0x40], SystemTime::now()).unwrap();
+    /// assert_eq!((m.pm25.value(), m.pm10.value()), (4.5, 800.0));
+    /// assert!(Message::from_compact(&[0x00, 0x2d], SystemTime::now()).is_err());
+    /// ```
+    pub fn from_compact(payload: &[u8], timestamp: SystemTime) -> Result<Message> {
+        if payload.len() != COMPACT_SIZE {
+            return Err(Error::ExportError(format!(
+                "compact payload of {} bytes, expected {}",
+                payload.len(),
+                COMPACT_SIZE
+            )));
+        }
+        let value =
+            |hi: u8, lo: u8| MicrogramsPerCubicMeter(u16::from_be_bytes([hi, lo]) as f32 / 10.0);
+        Ok(Message {
+            timestamp,
+            pm25: value(payload[0], payload[1]),
+            pm10: value(payload[2], payload[3]),
+        })
+    }
+}
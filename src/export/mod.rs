@@ -0,0 +1,8 @@
+//! Exporters converting measurements into third-party formats.
+
+pub mod influx;
+pub mod lpp;
+#[cfg(feature = "serde")]
+pub mod openaq;
+#[cfg(feature = "parquet")]
+pub mod parquet;
@@ -0,0 +1,180 @@
+//! [OpenAQ](https://openaq.org) measurement submissions.
+//!
+//! Produces measurement objects in the OpenAQ data format, either as a
+//! JSON array or as the flat CSV used for bulk ingestion.
+
+use crate::privacy::Privacy;
+use crate::time::to_rfc3339;
+use crate::{Error, Message, Result};
+use serde::Serialize;
+
+/// Concentration unit used by OpenAQ
+pub const UNIT: &str = "µg/m³";
+
+/// Station metadata attached to every measurement
+#[derive(Debug, PartialEq, Clone)]
+pub struct Station {
+    /// Unique location name
+    pub location: String,
+    /// Organization submitting the data
+    pub source_name: String,
+    /// `(latitude, longitude)` of the station
+    pub coordinates: Option<(f64, f64)>,
+    /// ISO 3166-1 alpha-2 country code
+    pub country: Option<String>,
+    /// City name
+    pub city: Option<String>,
+    /// Averaging period of a single measurement in seconds
+    pub averaging_period: u64,
+}
+
+impl Station {
+    /// Creates a station with one measurement per second
+    pub fn new(location: &str, source_name: &str) -> Station {
+        Station {
+            location: location.to_string(),
+            source_name: source_name.to_string(),
+            coordinates: None,
+            country: None,
+            city: None,
+            averaging_period: 1,
+        }
+    }
+}
+
+/// Single OpenAQ measurement
+#[derive(Debug, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Measurement {
+    pub location: String,
+    /// `pm25` or `pm10`
+    pub parameter: &'static str,
+    pub value: f32,
+    pub unit: &'static str,
+    pub date: Date,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coordinates: Option<Coordinates>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    pub source_name: String,
+    pub source_type: &'static str,
+    pub mobile: bool,
+    pub averaging_period: AveragingPeriod,
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct Date {
+    pub utc: String,
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct AveragingPeriod {
+    pub value: u64,
+    pub unit: &'static str,
+}
+
+impl Station {
+    /// Returns a copy with coordinates anonymized according to `privacy`
+    pub fn with_privacy(&self, privacy: &Privacy) -> Station {
+        let mut s = self.clone();
+        s.coordinates = self
+            .coordinates
+            .and_then(|(lat, lon)| privacy.location(lat, lon));
+        s
+    }
+
+    /// Converts `m` into PM2.5 and PM10 measurements
+    pub fn measurements(&self, m: &Message) -> Vec<Measurement> {
+        let utc = to_rfc3339(m.timestamp_secs().unwrap_or(0));
+
+        vec![("pm25", m.pm25.value()), ("pm10", m.pm10.value())]
+            .into_iter()
+            .map(|(parameter, value)| Measurement {
+                location: self.location.clone(),
+                parameter,
+                value,
+                unit: UNIT,
+                date: Date { utc: utc.clone() },
+                coordinates: self.coordinates.map(|(latitude, longitude)| Coordinates {
+                    latitude,
+                    longitude,
+                }),
+                country: self.country.clone(),
+                city: self.city.clone(),
+                source_name: self.source_name.clone(),
+                source_type: "research",
+                mobile: false,
+                averaging_period: AveragingPeriod {
+                    value: self.averaging_period,
+                    unit: "seconds",
+                },
+            })
+            .collect()
+    }
+
+    /// Serializes `messages` as a JSON array of measurements
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::export::openaq::Station;
+    /// use sds011::{Message, MicrogramsPerCubicMeter};
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let station = Station::new("Kitchen", "Home");
+    /// let m = Message { timestamp: UNIX_EPOCH + Duration::from_secs(1587384000), pm25: MicrogramsPerCubicMeter(4.0), pm10: MicrogramsPerCubicMeter(8.0) };
+    /// let json = station.to_json(&[m]).unwrap();
+    /// assert!(json.contains("2020-04-20T12:00:00Z"));
+    /// ```
+    pub fn to_json(&self, messages: &[Message]) -> Result<String> {
+        let all: Vec<Measurement> = messages.iter().flat_map(|m| self.measurements(m)).collect();
+        serde_json::to_string(&all).map_err(|e| Error::ExportError(e.to_string()))
+    }
+
+    /// Writes `messages` in the OpenAQ CSV ingest format
+    #[cfg(feature = "csv")]
+    pub fn write_csv<W: std::io::Write>(&self, w: W, messages: &[Message]) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(w);
+        let err = |e: csv::Error| Error::ExportError(e.to_string());
+
+        writer
+            .write_record([
+                "location",
+                "parameter",
+                "value",
+                "unit",
+                "datetime",
+                "latitude",
+                "longitude",
+            ])
+            .map_err(err)?;
+
+        for m in messages.iter().flat_map(|m| self.measurements(m)) {
+            let (lat, lon) = match &m.coordinates {
+                Some(c) => (c.latitude.to_string(), c.longitude.to_string()),
+                None => (String::new(), String::new()),
+            };
+            writer
+                .write_record([
+                    m.location.as_str(),
+                    m.parameter,
+                    m.value.to_string().as_str(),
+                    m.unit,
+                    m.date.utc.as_str(),
+                    lat.as_str(),
+                    lon.as_str(),
+                ])
+                .map_err(err)?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! [Apache Parquet](https://parquet.apache.org) files of measurements.
+//!
+//! Columns are typed, so pandas, polars or DuckDB load months of readings
+//! without parsing a single timestamp:
+//!
+//! ```text
+//! message sds011 {
+//!   REQUIRED INT64 timestamp (TIMESTAMP(MILLIS,true));
+//!   OPTIONAL INT32 device_id (INTEGER(16,false));
+//!   REQUIRED FLOAT pm25;
+//!   REQUIRED FLOAT pm10;
+//! }
+//! ```
+//!
+//! Timestamps are UTC, `device_id` is null when unknown and the PM values
+//! are in µg/m³. Pages are compressed with Snappy.
+
+use crate::{Error, Message, Result};
+use parquet::basic::Compression;
+use parquet::data_type::{FloatType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use std::io::Write;
+use std::sync::Arc;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::ExportError(format!("parquet: {}", e))
+}
+
+const SCHEMA: &str = "
+message sds011 {
+  REQUIRED INT64 timestamp (TIMESTAMP(MILLIS,true));
+  OPTIONAL INT32 device_id (INTEGER(16,false));
+  REQUIRED FLOAT pm25;
+  REQUIRED FLOAT pm10;
+}
+";
+
+/// Rows buffered before they are written as a row group
+const ROW_GROUP_SIZE: usize = 100_000;
+
+/// Writer of a Parquet file of measurements
+///
+/// Rows are buffered and written in row groups of 100000. Nothing is
+/// readable until `finish()` writes the file footer.
+///
+/// # Example
+/// ```
+/// use parquet::file::reader::{FileReader, SerializedFileReader};
+/// use sds011::export::parquet::ParquetWriter;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::fs::File;
+/// use std::time::UNIX_EPOCH;
+///
+/// let path = std::env::temp_dir().join("sds011-example.parquet");
+/// let mut writer = ParquetWriter::new(File::create(&path).unwrap()).unwrap();
+///
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) };
+/// writer.push(&m, Some(0xa160)).unwrap();
+/// writer.push(&m, None).unwrap();
+/// writer.finish().unwrap();
+///
+/// let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+/// assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+/// ```
+pub struct ParquetWriter<W: Write + Send> {
+    writer: SerializedFileWriter<W>,
+    timestamps: Vec<i64>,
+    device_ids: Vec<i32>,
+    /// Definition levels of `device_id`, 0 where it's null
+    defined: Vec<i16>,
+    pm25: Vec<f32>,
+    pm10: Vec<f32>,
+    rows: u64,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    /// Starts a file written to `w`
+    pub fn new(w: W) -> Result<ParquetWriter<W>> {
+        let schema = Arc::new(parse_message_type(SCHEMA).map_err(err)?);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_created_by(format!("sds011 {}", env!("CARGO_PKG_VERSION")))
+            .build();
+        Ok(ParquetWriter {
+            writer: SerializedFileWriter::new(w, schema, Arc::new(properties)).map_err(err)?,
+            timestamps: Vec::new(),
+            device_ids: Vec::new(),
+            defined: Vec::new(),
+            pm25: Vec::new(),
+            pm10: Vec::new(),
+            rows: 0,
+        })
+    }
+
+    /// Adds a row
+    pub fn push(&mut self, m: &Message, device_id: Option<u16>) -> Result<()> {
+        let millis = m
+            .timestamp
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        self.timestamps.push(millis);
+        match device_id {
+            Some(id) => {
+                self.device_ids.push(id as i32);
+                self.defined.push(1);
+            }
+            None => self.defined.push(0),
+        }
+        self.pm25.push(m.pm25.value());
+        self.pm10.push(m.pm10.value());
+        self.rows += 1;
+
+        if self.timestamps.len() >= ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    /// Number of rows added so far
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Writes the buffered rows as a row group
+    fn write_row_group(&mut self) -> Result<()> {
+        if self.timestamps.is_empty() {
+            return Ok(());
+        }
+        let mut group = self.writer.next_row_group().map_err(err)?;
+
+        let mut column = group.next_column().map_err(err)?.ok_or_else(missing)?;
+        column
+            .typed::<Int64Type>()
+            .write_batch(&self.timestamps, None, None)
+            .map_err(err)?;
+        column.close().map_err(err)?;
+
+        let mut column = group.next_column().map_err(err)?.ok_or_else(missing)?;
+        column
+            .typed::<Int32Type>()
+            .write_batch(&self.device_ids, Some(&self.defined), None)
+            .map_err(err)?;
+        column.close().map_err(err)?;
+
+        for values in [&self.pm25, &self.pm10].iter() {
+            let mut column = group.next_column().map_err(err)?.ok_or_else(missing)?;
+            column
+                .typed::<FloatType>()
+                .write_batch(values, None, None)
+                .map_err(err)?;
+            column.close().map_err(err)?;
+        }
+        group.close().map_err(err)?;
+
+        self.timestamps.clear();
+        self.device_ids.clear();
+        self.defined.clear();
+        self.pm25.clear();
+        self.pm10.clear();
+        Ok(())
+    }
+
+    /// Writes the remaining rows and the footer, returning the underlying
+    /// writer
+    pub fn finish(mut self) -> Result<W> {
+        self.write_row_group()?;
+        self.writer.into_inner().map_err(err)
+    }
+}
+
+/// The writer ran out of columns, which means `SCHEMA` and
+/// `write_row_group()` disagree
+fn missing() -> Error {
+    err("schema has fewer columns than written")
+}
@@ -0,0 +1,176 @@
+//! Prometheus exporter.
+//!
+//! The exporter subscribes to an `EventBus`, keeps `metrics::Metrics` for
+//! every sensor attached, labelled with its device ID, and serves them at
+//! `GET /metrics` in the Prometheus text format. The age of the last
+//! reading is computed when scraped, so a stale sensor shows even though
+//! the gauges keep their last values.
+
+use crate::events::{Event, EventBus};
+use crate::metrics::Metrics;
+use crate::{Error, Result};
+use prometheus::{Encoder, Registry, TextEncoder};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response};
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::ServerError(e.to_string())
+}
+
+/// Metrics of the sensors seen so far
+struct State {
+    registry: Registry,
+    /// Metrics by port
+    sensors: BTreeMap<String, Metrics>,
+}
+
+impl State {
+    /// Metrics of the sensor on `port`, created on its first event
+    fn sensor(&mut self, port: &str, device_id: Option<u16>) -> &Metrics {
+        if !self.sensors.contains_key(port) {
+            let metrics = match device_id {
+                Some(id) => Metrics::for_device(id),
+                None => Metrics::new(),
+            };
+            // Only sensors without a device ID clash, the first one wins
+            if let Err(e) = metrics.register(&self.registry) {
+                log::warn!("exporter: {}: {}", port, e);
+            }
+            self.sensors.insert(port.to_string(), metrics);
+        }
+        &self.sensors[port]
+    }
+
+    fn record(&mut self, event: &Event) {
+        match event {
+            Event::SensorAttached { port, device_id } => {
+                self.sensor(port, *device_id);
+            }
+            Event::Measurement { port, message } => self.sensor(port, None).observe(message),
+            // Only the message of the error is published
+            Event::SensorError { port, error } => self
+                .sensor(port, None)
+                .observe_error(&Error::ReadError(error.clone())),
+            _ => {}
+        }
+    }
+
+    /// Metrics in the text format
+    fn scrape(&self) -> Result<Vec<u8>> {
+        for metrics in self.sensors.values() {
+            metrics.refresh();
+        }
+        let mut text = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut text)
+            .map_err(err)?;
+        Ok(text)
+    }
+}
+
+/// Prometheus exporter fed by an event bus
+///
+/// # Example
+/// ```
+/// use sds011::events::{Event, EventBus};
+/// use sds011::exporter::Exporter;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+/// use std::time::SystemTime;
+///
+/// let bus = EventBus::new();
+/// let exporter = Exporter::bind("127.0.0.1:0").unwrap();
+/// let addr = exporter.local_addr().unwrap();
+/// exporter.spawn(&bus);
+///
+/// let port = "/dev/ttyUSB0".to_string();
+/// bus.publish(Event::SensorAttached { port: port.clone(), device_id: Some(0xa160) });
+/// let message = Message { timestamp: SystemTime::now(), pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) };
+/// bus.publish(Event::Measurement { port, message });
+/// # std::thread::sleep(std::time::Duration::from_millis(100));
+///
+/// let mut stream = TcpStream::connect(addr).unwrap();
+/// stream.write_all(b"GET /metrics HTTP/1.0\r\n\r\n").unwrap();
+/// let mut response = String::new();
+/// stream.read_to_string(&mut response).unwrap();
+/// assert!(response.starts_with("HTTP/1.0 200"));
+/// assert!(response.contains("sds011_pm25_ugm3{device_id=\"a160\"} 4.5"));
+/// assert!(response.contains("sds011_reading_age_seconds{device_id=\"a160\"} 0"));
+/// ```
+pub struct Exporter {
+    http: tiny_http::Server,
+    state: Arc<Mutex<State>>,
+}
+
+impl Exporter {
+    /// Listens on `addr`, e.g. `0.0.0.0:9184`
+    pub fn bind(addr: &str) -> Result<Exporter> {
+        let http = tiny_http::Server::http(addr).map_err(|e| err(format!("{}: {}", addr, e)))?;
+        Ok(Exporter {
+            http,
+            state: Arc::new(Mutex::new(State {
+                registry: Registry::new(),
+                sensors: BTreeMap::new(),
+            })),
+        })
+    }
+
+    /// Address the exporter listens on, useful after binding port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Serves scrapes in a background thread, with metrics of events
+    /// published on `bus` from now on
+    pub fn spawn(self, bus: &EventBus) -> thread::JoinHandle<()> {
+        let events = bus.subscribe();
+        let state = Arc::clone(&self.state);
+        thread::spawn(move || {
+            for event in events {
+                lock(&state).record(&event);
+            }
+        });
+
+        thread::spawn(move || {
+            for request in self.http.incoming_requests() {
+                self.respond(request);
+            }
+        })
+    }
+
+    fn respond(&self, request: Request) {
+        let path = request.url().split('?').next().unwrap_or_default();
+        let response = match (request.method(), path) {
+            (Method::Get, "/metrics") => match lock(&self.state).scrape() {
+                Ok(text) => Response::from_data(text).with_header(header(TextEncoder::new().format_type())),
+                Err(e) => Response::from_string(e.to_string()).with_status_code(500),
+            },
+            (Method::Get, "/") => Response::from_string(
+                "<html><body><h1>SDS011 exporter</h1><a href=\"/metrics\">Metrics</a></body></html>",
+            )
+            .with_header(header("text/html; charset=utf-8")),
+            (Method::Get, _) => Response::from_string("not found").with_status_code(404),
+            _ => Response::from_string("method not allowed").with_status_code(405),
+        };
+        if let Err(e) = request.respond(response) {
+            log::warn!("exporter: {}", e);
+        }
+    }
+}
+
+fn header(content_type: &str) -> Header {
+    // The value is a constant ASCII string, so it's valid
+    Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap()
+}
+
+/// Locks the state, a panic while recording doesn't stop scrapes
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    match state.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
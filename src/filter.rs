@@ -0,0 +1,267 @@
+//! Smoothing filters for noisy readings.
+//!
+//! Filters implement `Filter` and can be applied one message at a time or
+//! to a whole iterator with `FilterExt`. `OutlierRejector` drops messages
+//! instead of transforming them, so it has its own `check()`.
+
+use crate::{Message, MicrogramsPerCubicMeter};
+use std::collections::VecDeque;
+
+/// Transforms a stream of measurements one message at a time
+pub trait Filter {
+    /// Feeds a measurement and returns the filtered one
+    fn apply(&mut self, m: &Message) -> Message;
+}
+
+/// Simple moving average over the last `window` measurements
+#[derive(Debug, Clone)]
+pub struct MovingAverage {
+    window: usize,
+    values: VecDeque<(f32, f32)>,
+    sum: (f64, f64),
+}
+
+impl MovingAverage {
+    /// Creates a filter averaging `window` measurements, at least 1
+    pub fn new(window: usize) -> MovingAverage {
+        MovingAverage {
+            window: window.max(1),
+            values: VecDeque::new(),
+            sum: (0.0, 0.0),
+        }
+    }
+}
+
+impl Filter for MovingAverage {
+    fn apply(&mut self, m: &Message) -> Message {
+        if self.values.len() == self.window {
+            if let Some((pm25, pm10)) = self.values.pop_front() {
+                self.sum.0 -= pm25 as f64;
+                self.sum.1 -= pm10 as f64;
+            }
+        }
+        let (pm25, pm10) = (m.pm25.value(), m.pm10.value());
+        self.values.push_back((pm25, pm10));
+        self.sum.0 += pm25 as f64;
+        self.sum.1 += pm10 as f64;
+
+        let n = self.values.len() as f64;
+        Message {
+            timestamp: m.timestamp,
+            pm25: MicrogramsPerCubicMeter((self.sum.0 / n) as f32),
+            pm10: MicrogramsPerCubicMeter((self.sum.1 / n) as f32),
+        }
+    }
+}
+
+/// Exponentially weighted moving average
+/// `alpha` close to 1 follows new readings, close to 0 smooths harder
+#[derive(Debug, Clone)]
+pub struct Ewma {
+    alpha: f32,
+    state: Option<(f32, f32)>,
+}
+
+impl Ewma {
+    /// Creates a filter with smoothing factor `alpha` between 0 and 1
+    pub fn new(alpha: f32) -> Ewma {
+        Ewma {
+            alpha: alpha.clamp(0.0, 1.0),
+            state: None,
+        }
+    }
+}
+
+impl Filter for Ewma {
+    fn apply(&mut self, m: &Message) -> Message {
+        let (pm25, pm10) = match self.state {
+            None => (m.pm25.value(), m.pm10.value()),
+            Some((pm25, pm10)) => (
+                self.alpha * m.pm25.value() + (1.0 - self.alpha) * pm25,
+                self.alpha * m.pm10.value() + (1.0 - self.alpha) * pm10,
+            ),
+        };
+        self.state = Some((pm25, pm10));
+
+        Message {
+            timestamp: m.timestamp,
+            pm25: MicrogramsPerCubicMeter(pm25),
+            pm10: MicrogramsPerCubicMeter(pm10),
+        }
+    }
+}
+
+/// Scaled MADs below this are raised to it, in µg/m³, so a perfectly
+/// steady stream doesn't reject every small change
+const MIN_DEVIATION: f32 = 1.0;
+
+/// Drops physically implausible spikes using a median/MAD criterion
+///
+/// A measurement is rejected when PM2.5 or PM10 is further than
+/// `threshold` scaled median absolute deviations from the median of the
+/// last `window` raw measurements. Rejected measurements still enter the
+/// window, so a real sustained change is accepted once it makes up half
+/// of it.
+#[derive(Debug, Clone)]
+pub struct OutlierRejector {
+    window: usize,
+    threshold: f32,
+    history: VecDeque<(f32, f32)>,
+    rejected: usize,
+}
+
+/// Median of a non-empty slice, sorts it in place
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let n = values.len();
+    // Both indexes are the middle one for odd lengths
+    (values[(n - 1) / 2] + values[n / 2]) / 2.0
+}
+
+/// Whether `value` is an outlier among `history`
+fn is_outlier(history: &[f32], value: f32, threshold: f32) -> bool {
+    let mut values = history.to_vec();
+    let med = median(&mut values);
+    let mut deviations: Vec<f32> = history.iter().map(|v| (v - med).abs()).collect();
+    // 1.4826 makes MAD comparable to the standard deviation for normal data
+    let mad = (median(&mut deviations) * 1.4826).max(MIN_DEVIATION);
+    (value - med).abs() > threshold * mad
+}
+
+impl OutlierRejector {
+    /// Creates a rejector over `window` measurements, at least 3,
+    /// 3.5 is a common `threshold`
+    pub fn new(window: usize, threshold: f32) -> OutlierRejector {
+        OutlierRejector {
+            window: window.max(3),
+            threshold,
+            history: VecDeque::new(),
+            rejected: 0,
+        }
+    }
+
+    /// Returns the measurement if it's plausible, `None` if it's rejected
+    /// Everything is accepted until 3 measurements have been seen
+    pub fn check(&mut self, m: &Message) -> Option<Message> {
+        let outlier = self.history.len() >= 3 && {
+            let (pm25, pm10): (Vec<f32>, Vec<f32>) = self.history.iter().cloned().unzip();
+            is_outlier(&pm25, m.pm25.value(), self.threshold)
+                || is_outlier(&pm10, m.pm10.value(), self.threshold)
+        };
+
+        if self.history.len() == self.window {
+            self.history.pop_front();
+        }
+        self.history.push_back((m.pm25.value(), m.pm10.value()));
+
+        if outlier {
+            self.rejected += 1;
+            None
+        } else {
+            Some(m.clone())
+        }
+    }
+
+    /// Number of rejected measurements so far
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+}
+
+/// Iterator adapter created by `FilterExt::reject_outliers()`
+pub struct Rejecting<I> {
+    iter: I,
+    rejector: OutlierRejector,
+}
+
+impl<I> Rejecting<I> {
+    /// Number of measurements dropped so far
+    pub fn rejected(&self) -> usize {
+        self.rejector.rejected()
+    }
+}
+
+impl<I: Iterator<Item = Message>> Iterator for Rejecting<I> {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        for m in self.iter.by_ref() {
+            if let Some(m) = self.rejector.check(&m) {
+                return Some(m);
+            }
+        }
+        None
+    }
+}
+
+/// Iterator adapter created by `FilterExt::filtered()`
+pub struct Filtered<I, F> {
+    iter: I,
+    filter: F,
+}
+
+impl<I: Iterator<Item = Message>, F: Filter> Iterator for Filtered<I, F> {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        self.iter.next().map(|m| self.filter.apply(&m))
+    }
+}
+
+/// Applies filters to iterators of measurements, adapters can be chained
+///
+/// # Example
+/// ```
+/// use sds011::filter::FilterExt;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let raw = vec![1.0, 3.0, 5.0]
+///     .into_iter()
+///     .map(|pm| Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(pm), pm10: MicrogramsPerCubicMeter(pm) });
+///
+/// let smoothed: Vec<f32> = raw.moving_average(2).map(|m| m.pm25.value()).collect();
+/// assert_eq!(smoothed, vec![1.0, 2.0, 4.0]);
+/// ```
+pub trait FilterExt: Iterator<Item = Message> + Sized {
+    /// Applies any `Filter`
+    fn filtered<F: Filter>(self, filter: F) -> Filtered<Self, F> {
+        Filtered { iter: self, filter }
+    }
+
+    /// Applies a simple moving average
+    fn moving_average(self, window: usize) -> Filtered<Self, MovingAverage> {
+        self.filtered(MovingAverage::new(window))
+    }
+
+    /// Applies an exponentially weighted moving average
+    fn ewma(self, alpha: f32) -> Filtered<Self, Ewma> {
+        self.filtered(Ewma::new(alpha))
+    }
+
+    /// Drops spikes, see `OutlierRejector`
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::filter::FilterExt;
+    /// use sds011::{Message, MicrogramsPerCubicMeter};
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let raw = vec![10.0, 11.0, 10.0, 800.0, 12.0]
+    ///     .into_iter()
+    ///     .map(|pm| Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(pm), pm10: MicrogramsPerCubicMeter(pm) });
+    ///
+    /// let mut cleaned = raw.reject_outliers(5, 3.5);
+    /// let kept: Vec<f32> = cleaned.by_ref().map(|m| m.pm25.value()).collect();
+    /// assert_eq!(kept, vec![10.0, 11.0, 10.0, 12.0]);
+    /// assert_eq!(cleaned.rejected(), 1);
+    /// ```
+    fn reject_outliers(self, window: usize, threshold: f32) -> Rejecting<Self> {
+        Rejecting {
+            iter: self,
+            rejector: OutlierRejector::new(window, threshold),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Message>> FilterExt for I {}
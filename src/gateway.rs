@@ -0,0 +1,611 @@
+//! Store-and-forward gateway collecting measurements from edge stations.
+//!
+//! Edges push records with a `Forwarder` sink over TCP, one
+//! `schema::to_json()` line per record. The gateway appends every record
+//! to a `Journal` before acknowledging it with `ok`, so an acknowledged
+//! record survives a crash. A gateway with a token expects `auth <token>`
+//! as the first line of every connection; lines over 4 KiB and idle
+//! connections are dropped. Edges spool records they couldn't deliver and
+//! send them first once the gateway is reachable again, so they can stay
+//! simple and offline-tolerant. Aggregation and correction happen centrally
+//! in `Rollup`.
+
+use crate::aggregate::{Stats, Tumbling};
+use crate::calibration::Calibration;
+use crate::durable::{self, AppendFile, SyncPolicy};
+use crate::sink::Sink;
+use crate::{schema, Error, Message, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Time an edge waits for the gateway to connect or acknowledge
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Time the gateway keeps a connection without records open
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+/// Longest line accepted from an edge, records are a fraction of it
+const MAX_LINE: usize = 4096;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::GatewayError(e.to_string())
+}
+
+/// Measurement of a named station
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Record {
+    pub station: String,
+    #[serde(flatten)]
+    pub message: Message,
+}
+
+/// Window statistics of a named station
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct StationStats {
+    pub station: String,
+    #[serde(flatten)]
+    pub stats: Stats,
+}
+
+/// Append-only NDJSON file of records
+pub struct Journal {
+    file: AppendFile,
+}
+
+impl Journal {
+    /// Opens `path` for appending, creating it if needed
+    /// Every record is synced to the disk before `append()` returns
+    pub fn open(path: &str) -> Result<Journal> {
+        Journal::open_with(path, SyncPolicy::Always)
+    }
+
+    /// Opens `path` for appending, syncing records as `policy` asks
+    pub fn open_with(path: &str, policy: SyncPolicy) -> Result<Journal> {
+        Ok(Journal {
+            file: AppendFile::open(path, policy)?,
+        })
+    }
+
+    /// Appends a record
+    pub fn append(&mut self, record: &Record) -> Result<()> {
+        self.file.append(&schema::to_json(record)?)
+    }
+
+    /// Reads every record of the journal at `path`, oldest first
+    /// A missing file has no records, a torn last line is skipped
+    pub fn read(path: &str) -> Result<Vec<Record>> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(err(format!("{}: {}", path, e))),
+        };
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(err)?;
+            if let Ok(record) = schema::from_json(&line) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Per-station tumbling windows with optional per-station calibration
+///
+/// # Example
+/// ```
+/// use sds011::gateway::{Record, Rollup};
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let mut rollup = Rollup::new(Duration::from_secs(3600));
+/// let record = |station: &str, t: u64| Record {
+///     station: station.to_string(),
+///     message: Message { timestamp: UNIX_EPOCH + Duration::from_secs(t), pm25: MicrogramsPerCubicMeter(5.0), pm10: MicrogramsPerCubicMeter(9.0) },
+/// };
+///
+/// assert!(rollup.push(&record("kitchen", 0)).is_none());
+/// assert!(rollup.push(&record("garden", 10)).is_none());
+/// let closed = rollup.push(&record("kitchen", 3600)).unwrap();
+/// assert_eq!(closed.station, "kitchen");
+/// assert_eq!(closed.stats.count, 1);
+/// assert_eq!(rollup.flush().len(), 2);
+/// ```
+pub struct Rollup {
+    width: Duration,
+    windows: HashMap<String, Tumbling>,
+    calibrations: HashMap<String, Calibration>,
+}
+
+impl Rollup {
+    /// Aggregates every station into windows of `width`
+    pub fn new(width: Duration) -> Rollup {
+        Rollup {
+            width,
+            windows: HashMap::new(),
+            calibrations: HashMap::new(),
+        }
+    }
+
+    /// Applies `calibration` to records of `station` before aggregating
+    pub fn calibrate(mut self, station: &str, calibration: Calibration) -> Rollup {
+        self.calibrations.insert(station.to_string(), calibration);
+        self
+    }
+
+    /// Corrected measurement of a record
+    pub fn correct(&self, record: &Record) -> Message {
+        match self.calibrations.get(&record.station) {
+            Some(c) => c.apply(&record.message),
+            None => record.message.clone(),
+        }
+    }
+
+    /// Adds a record, returns the station's window it closed, if any
+    pub fn push(&mut self, record: &Record) -> Option<StationStats> {
+        let m = self.correct(record);
+        let width = self.width;
+        let stats = self
+            .windows
+            .entry(record.station.clone())
+            .or_insert_with(|| Tumbling::new(width))
+            .push(&m)?;
+        Some(StationStats {
+            station: record.station.clone(),
+            stats,
+        })
+    }
+
+    /// Closes the open window of every station
+    pub fn flush(&mut self) -> Vec<StationStats> {
+        let mut closed: Vec<StationStats> = self
+            .windows
+            .iter_mut()
+            .filter_map(|(station, w)| {
+                w.flush().map(|stats| StationStats {
+                    station: station.clone(),
+                    stats,
+                })
+            })
+            .collect();
+        closed.sort_by(|a, b| a.station.cmp(&b.station));
+        closed
+    }
+}
+
+/// TCP endpoint receiving records from edges
+pub struct Gateway {
+    listener: TcpListener,
+    journal: Arc<Mutex<Journal>>,
+    token: Option<String>,
+}
+
+impl Gateway {
+    /// Listens on `addr` and journals received records to `journal`
+    pub fn bind<A: ToSocketAddrs>(addr: A, journal: &str) -> Result<Gateway> {
+        Gateway::bind_with(addr, journal, SyncPolicy::Always)
+    }
+
+    /// Like `bind()`, syncing the journal as `policy` asks
+    /// With anything but `SyncPolicy::Always` acknowledged records may be
+    /// lost on power loss
+    pub fn bind_with<A: ToSocketAddrs>(
+        addr: A,
+        journal: &str,
+        policy: SyncPolicy,
+    ) -> Result<Gateway> {
+        Ok(Gateway {
+            listener: TcpListener::bind(addr).map_err(err)?,
+            journal: Arc::new(Mutex::new(Journal::open_with(journal, policy)?)),
+            token: None,
+        })
+    }
+
+    /// Only accepts records from edges authenticated with `token`, see
+    /// `Forwarder::token()`
+    pub fn token(mut self, token: &str) -> Gateway {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Address the gateway listens on, e.g. when bound to port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// Accepts edges forever, calling `f` with every journaled record
+    /// Each edge is served by its own thread, `f` runs on the caller's
+    ///
+    /// # Example
+    /// ```no_run
+    /// use sds011::gateway::{Gateway, Rollup};
+    /// use std::time::Duration;
+    ///
+    /// let mut rollup = Rollup::new(Duration::from_secs(3600));
+    /// Gateway::bind("0.0.0.0:7100", "gateway.ndjson").unwrap().serve(|record| {
+    ///     if let Some(closed) = rollup.push(&record) {
+    ///         println!("{:?}", closed);
+    ///     }
+    /// });
+    /// ```
+    pub fn serve<F: FnMut(Record)>(self, mut f: F) {
+        let (tx, rx) = channel();
+        let Gateway {
+            listener,
+            journal,
+            token,
+        } = self;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let (tx, journal) = (tx.clone(), Arc::clone(&journal));
+                        let token = token.clone();
+                        thread::spawn(move || receive(stream, journal, token, tx));
+                    }
+                    Err(e) => log::warn!("gateway: {}", e),
+                }
+            }
+        });
+        for record in rx {
+            f(record);
+        }
+    }
+}
+
+/// Reads a line of at most `MAX_LINE` bytes without its newline, `None`
+/// at the end of the stream
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    } else if line.len() > MAX_LINE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    } else if line.is_empty() {
+        return Ok(None);
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Journals and acknowledges records of one edge connection
+fn receive(
+    stream: TcpStream,
+    journal: Arc<Mutex<Journal>>,
+    token: Option<String>,
+    tx: Sender<Record>,
+) {
+    let peer = stream
+        .peer_addr()
+        .map(|a| a.to_string())
+        .unwrap_or_default();
+    let mut writer = match stream
+        .set_read_timeout(Some(IDLE_TIMEOUT))
+        .and_then(|_| stream.try_clone())
+    {
+        Ok(w) => w,
+        Err(e) => {
+            log::warn!("gateway: {}: {}", peer, e);
+            return;
+        }
+    };
+
+    let mut reader = BufReader::new(stream);
+    let mut authenticated = token.is_none();
+    loop {
+        let line = match read_line(&mut reader) {
+            Ok(Some(l)) => l,
+            Ok(None) => return,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                log::warn!("gateway: {}: {}", peer, e);
+                let _ = writer.write_all(format!("error {}\n", e).as_bytes());
+                return;
+            }
+            // Idle past the timeout or gone
+            Err(_) => return,
+        };
+        if let Some(sent) = line.strip_prefix("auth ") {
+            authenticated = match &token {
+                Some(token) => crate::remote::same_token(sent, token),
+                None => true,
+            };
+        }
+        if !authenticated {
+            log::warn!("gateway: {}: invalid token", peer);
+            let _ = writer.write_all(b"error invalid token\n");
+            return;
+        }
+        if line.starts_with("auth ") {
+            if writer.write_all(b"ok\n").is_err() {
+                return;
+            }
+            continue;
+        }
+
+        let result = schema::from_json::<Record>(&line).and_then(|record| {
+            let mut journal = match journal.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            journal.append(&record)?;
+            Ok(record)
+        });
+        let ack = match result {
+            Ok(record) => {
+                let _ = tx.send(record);
+                "ok\n".to_string()
+            }
+            Err(e) => {
+                log::warn!("gateway: {}: {}", peer, e);
+                format!("error {}\n", e)
+            }
+        };
+        if writer.write_all(ack.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sink pushing measurements of `station` to a gateway
+///
+/// Records the gateway doesn't acknowledge are appended to a spool file
+/// and delivered, oldest first, before the next measurement. `send()`
+/// succeeds once a record is either acknowledged or spooled.
+///
+/// # Example
+/// ```no_run
+/// use sds011::gateway::Forwarder;
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let mut forwarder = Forwarder::new("gateway.local:7100", "kitchen", "spool.ndjson");
+/// forwarder.send(&sensor.query().unwrap()).unwrap();
+/// ```
+pub struct Forwarder {
+    addr: String,
+    station: String,
+    spool: String,
+    policy: SyncPolicy,
+    journal: Option<Journal>,
+    token: Option<String>,
+    conn: Option<BufReader<TcpStream>>,
+}
+
+impl Forwarder {
+    /// Pushes to the gateway at `addr`, spooling to the file `spool`
+    pub fn new(addr: &str, station: &str, spool: &str) -> Forwarder {
+        Forwarder {
+            addr: addr.to_string(),
+            station: station.to_string(),
+            spool: spool.to_string(),
+            policy: SyncPolicy::Always,
+            journal: None,
+            token: None,
+            conn: None,
+        }
+    }
+
+    /// Authenticates with `token` to a gateway that requires one
+    pub fn token(mut self, token: &str) -> Forwarder {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Syncs the spool as `policy` asks, every record by default
+    pub fn sync_policy(mut self, policy: SyncPolicy) -> Forwarder {
+        self.policy = policy;
+        self
+    }
+
+    fn connect(&mut self) -> Result<&mut BufReader<TcpStream>> {
+        if self.conn.is_none() {
+            let addr = self
+                .addr
+                .to_socket_addrs()
+                .map_err(err)?
+                .next()
+                .ok_or_else(|| err(format!("{}: no address", self.addr)))?;
+            let stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(err)?;
+            stream.set_read_timeout(Some(TIMEOUT)).map_err(err)?;
+            let mut conn = BufReader::new(stream);
+            if let Some(token) = &self.token {
+                let line = format!("auth {}\n", token);
+                conn.get_mut().write_all(line.as_bytes()).map_err(err)?;
+                acknowledged(&mut conn)?;
+            }
+            self.conn = Some(conn);
+        }
+        match self.conn.as_mut() {
+            Some(conn) => Ok(conn),
+            None => Err(err("not connected")),
+        }
+    }
+
+    /// Sends one record and waits for its acknowledgement
+    fn deliver(&mut self, record: &Record) -> Result<()> {
+        let line = schema::to_json(record)? + "\n";
+        let reused = self.conn.is_some();
+        let mut result = self.exchange(&line);
+        if result.is_err() && reused {
+            // The gateway drops idle connections, try a new one
+            result = self.exchange(&line);
+        }
+        result
+    }
+
+    fn exchange(&mut self, line: &str) -> Result<()> {
+        let result = self.connect().and_then(|conn| {
+            conn.get_mut().write_all(line.as_bytes()).map_err(err)?;
+            acknowledged(conn)
+        });
+        if result.is_err() {
+            self.conn = None;
+        }
+        result
+    }
+
+    /// Delivers spooled records, keeping those that fail in the spool
+    fn drain(&mut self) -> Result<()> {
+        let spooled = Journal::read(&self.spool)?;
+        if spooled.is_empty() {
+            return Ok(());
+        }
+        for (i, record) in spooled.iter().enumerate() {
+            if let Err(e) = self.deliver(record) {
+                if i == 0 {
+                    return Err(e);
+                }
+                // Replace the spool with what's left
+                self.journal = None;
+                let mut rest = String::new();
+                for r in spooled[i..].iter() {
+                    rest += &schema::to_json(r)?;
+                    rest.push('\n');
+                }
+                durable::write_atomic(&self.spool, rest.as_bytes())?;
+                return Err(e);
+            }
+        }
+        self.journal = None;
+        std::fs::remove_file(&self.spool).map_err(err)
+    }
+
+    /// Number of records waiting in the spool
+    pub fn spooled(&self) -> usize {
+        Journal::read(&self.spool).map(|r| r.len()).unwrap_or(0)
+    }
+}
+
+/// Reads the gateway's answer to the last line
+fn acknowledged(conn: &mut BufReader<TcpStream>) -> Result<()> {
+    let mut ack = String::new();
+    conn.read_line(&mut ack).map_err(err)?;
+    match ack.trim_end() {
+        "ok" => Ok(()),
+        "" => Err(err("connection closed")),
+        other => Err(err(other.to_string())),
+    }
+}
+
+impl Sink for Forwarder {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        let record = Record {
+            station: self.station.clone(),
+            message: m.clone(),
+        };
+        let delivered = self.drain().and_then(|_| self.deliver(&record));
+        if let Err(e) = delivered {
+            log::warn!("gateway {}: {}, spooling", self.addr, e);
+            if self.journal.is_none() {
+                self.journal = Some(Journal::open_with(&self.spool, self.policy)?);
+            }
+            if let Some(journal) = self.journal.as_mut() {
+                journal.append(&record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MicrogramsPerCubicMeter;
+    use std::time::UNIX_EPOCH;
+
+    /// Gateway on a free port journaling to a fresh file
+    fn gateway(name: &str, token: Option<&str>) -> (SocketAddr, String) {
+        let dir = std::env::temp_dir().join(format!("sds011-gateway-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let journal = dir.join(format!("{}.ndjson", name));
+        let _ = std::fs::remove_file(&journal);
+        let journal = journal.display().to_string();
+        let mut gateway = Gateway::bind("127.0.0.1:0", &journal).unwrap();
+        if let Some(token) = token {
+            gateway = gateway.token(token);
+        }
+        let addr = gateway.local_addr().unwrap();
+        thread::spawn(move || gateway.serve(|_| {}));
+        (addr, journal)
+    }
+
+    /// Sends `data` and returns the gateway's answers until it closes
+    /// The gateway reads all of it, closing with unread data would reset
+    /// the connection and lose the answers
+    fn exchange(addr: SocketAddr, data: &str) -> Vec<String> {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(data.as_bytes()).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        BufReader::new(stream).lines().map(|l| l.unwrap()).collect()
+    }
+
+    fn record() -> String {
+        let record = Record {
+            station: "kitchen".to_string(),
+            message: Message {
+                timestamp: UNIX_EPOCH,
+                pm25: MicrogramsPerCubicMeter(4.5),
+                pm10: MicrogramsPerCubicMeter(8.0),
+            },
+        };
+        schema::to_json(&record).unwrap()
+    }
+
+    #[test]
+    fn read_line_caps_the_length() {
+        let long = "x".repeat(MAX_LINE);
+        let mut exact = io::Cursor::new(format!("{}\nnext\n", long));
+        assert_eq!(read_line(&mut exact).unwrap(), Some(long.clone()));
+        assert_eq!(read_line(&mut exact).unwrap(), Some("next".to_string()));
+        assert_eq!(read_line(&mut exact).unwrap(), None);
+
+        let mut over = io::Cursor::new(format!("{}x\n", long));
+        assert_eq!(
+            read_line(&mut over).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn drops_connections_sending_long_lines() {
+        let (addr, journal) = gateway("long", None);
+        let data = format!("{}\n{}", record(), "x".repeat(MAX_LINE + 1));
+        assert_eq!(exchange(addr, &data), ["ok", "error line too long"]);
+        assert_eq!(Journal::read(&journal).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn requires_the_token() {
+        let (addr, journal) = gateway("token", Some("s3cret"));
+        let authenticated = format!("auth s3cret\n{}\n", record());
+        assert_eq!(exchange(addr, &(record() + "\n")), ["error invalid token"]);
+        assert_eq!(exchange(addr, "auth wrong\n"), ["error invalid token"]);
+        assert_eq!(exchange(addr, &authenticated), ["ok", "ok"]);
+        assert_eq!(Journal::read(&journal).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn forwarder_authenticates() {
+        let (addr, journal) = gateway("forwarder", Some("s3cret"));
+        let spool = format!("{}.spool", journal);
+        let mut forwarder = Forwarder::new(&addr.to_string(), "kitchen", &spool).token("s3cret");
+        let m = Message {
+            timestamp: UNIX_EPOCH,
+            pm25: MicrogramsPerCubicMeter(4.5),
+            pm10: MicrogramsPerCubicMeter(8.0),
+        };
+        forwarder.send(&m).unwrap();
+        assert_eq!(forwarder.spooled(), 0);
+        assert_eq!(Journal::read(&journal).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,209 @@
+//! gRPC service with the latest and upcoming readings.
+//!
+//! Backends in any language can generate a client from
+//! `proto/sds011.proto` instead of parsing the daemon's output. Like
+//! `server::Server`, the service subscribes to an `EventBus`:
+//! - `GetLatest`: the latest reading, of one sensor if the request names
+//!   its port, `NOT_FOUND` before the first one;
+//! - `StreamMeasurements`: every reading from now on, of one sensor or all
+//!   of them. A client too slow to keep up misses readings rather than
+//!   holding up the others.
+
+use crate::events::{Event, EventBus};
+use crate::{Error, Message, Result};
+use proto::sds011_server::{Sds011, Sds011Server};
+use proto::{GetLatestRequest, Measurement, StreamMeasurementsRequest};
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+/// Messages and client and server stubs generated from `proto/sds011.proto`
+#[allow(clippy::all, missing_docs)]
+pub mod proto {
+    tonic::include_proto!("sds011.v1");
+}
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::ServerError(format!("grpc: {}", e))
+}
+
+/// Readings buffered for each stream, a client further behind skips them
+const STREAM_BUFFER: usize = 64;
+
+/// Latest readings, updated from events
+#[derive(Default)]
+struct Latest {
+    last: Option<Measurement>,
+    by_port: HashMap<String, Measurement>,
+}
+
+fn lock(latest: &Mutex<Latest>) -> MutexGuard<'_, Latest> {
+    match latest.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Message of a reading from the sensor on `port`
+fn measurement(port: &str, m: &Message) -> Measurement {
+    Measurement {
+        port: port.to_string(),
+        timestamp: m.timestamp_secs().unwrap_or(0),
+        pm25: m.pm25.value(),
+        pm10: m.pm10.value(),
+    }
+}
+
+/// Implementation of the `Sds011` service
+struct Service {
+    latest: Arc<Mutex<Latest>>,
+    readings: broadcast::Sender<Measurement>,
+}
+
+type MeasurementStream =
+    Pin<Box<dyn Stream<Item = std::result::Result<Measurement, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Sds011 for Service {
+    async fn get_latest(
+        &self,
+        request: Request<GetLatestRequest>,
+    ) -> std::result::Result<Response<Measurement>, Status> {
+        let port = request.into_inner().port;
+        let latest = lock(&self.latest);
+        let found = if port.is_empty() {
+            latest.last.clone()
+        } else {
+            latest.by_port.get(&port).cloned()
+        };
+        match found {
+            Some(m) => Ok(Response::new(m)),
+            None if port.is_empty() => Err(Status::not_found("no reading yet")),
+            None => Err(Status::not_found(format!("no reading from {} yet", port))),
+        }
+    }
+
+    type StreamMeasurementsStream = MeasurementStream;
+
+    async fn stream_measurements(
+        &self,
+        request: Request<StreamMeasurementsRequest>,
+    ) -> std::result::Result<Response<MeasurementStream>, Status> {
+        let port = request.into_inner().port;
+        let stream = BroadcastStream::new(self.readings.subscribe()).filter_map(move |r| match r {
+            Ok(m) if port.is_empty() || m.port == port => Some(Ok(m)),
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(n)) => {
+                log::warn!("grpc: a slow client skipped {} readings", n);
+                None
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// gRPC server fed by an event bus
+///
+/// # Example
+/// ```
+/// use sds011::events::{Event, EventBus};
+/// use sds011::grpc::proto::sds011_client::Sds011Client;
+/// use sds011::grpc::proto::GetLatestRequest;
+/// use sds011::grpc::GrpcServer;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+/// use tonic::transport::Channel;
+///
+/// let bus = EventBus::new();
+/// let server = GrpcServer::bind("127.0.0.1:0").unwrap();
+/// let addr = server.local_addr().unwrap();
+/// server.spawn(&bus);
+///
+/// let message = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) };
+/// bus.publish(Event::Measurement { port: "/dev/ttyUSB0".to_string(), message });
+/// # std::thread::sleep(std::time::Duration::from_millis(100));
+///
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// let latest = runtime.block_on(async {
+///     let channel = Channel::from_shared(format!("http://{}", addr)).unwrap().connect().await.unwrap();
+///     let mut client = Sds011Client::new(channel);
+///     client.get_latest(GetLatestRequest::default()).await.unwrap().into_inner()
+/// });
+/// assert_eq!((latest.port.as_str(), latest.pm25, latest.pm10), ("/dev/ttyUSB0", 4.5, 8.0));
+/// ```
+pub struct GrpcServer {
+    listener: TcpListener,
+    latest: Arc<Mutex<Latest>>,
+    readings: broadcast::Sender<Measurement>,
+}
+
+impl GrpcServer {
+    /// Listens on `addr`, e.g. `[::]:50051`
+    pub fn bind(addr: &str) -> Result<GrpcServer> {
+        let listener = TcpListener::bind(addr).map_err(|e| err(format!("{}: {}", addr, e)))?;
+        listener.set_nonblocking(true).map_err(err)?;
+        Ok(GrpcServer {
+            listener,
+            latest: Arc::new(Mutex::new(Latest::default())),
+            readings: broadcast::channel(STREAM_BUFFER).0,
+        })
+    }
+
+    /// Address the server listens on, e.g. when bound to port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// Serves requests in a background thread, with readings published on
+    /// `bus` from now on
+    pub fn spawn(self, bus: &EventBus) -> thread::JoinHandle<()> {
+        let events = bus.subscribe();
+        let latest = Arc::clone(&self.latest);
+        let readings = self.readings.clone();
+        thread::spawn(move || {
+            for event in events {
+                if let Event::Measurement { port, message } = event {
+                    let m = measurement(&port, &message);
+                    let mut latest = lock(&latest);
+                    latest.last = Some(m.clone());
+                    latest.by_port.insert(port, m.clone());
+                    drop(latest);
+                    // Fails only when no client is streaming
+                    let _ = readings.send(m);
+                }
+            }
+        });
+
+        thread::spawn(move || {
+            if let Err(e) = self.serve() {
+                log::error!("{}", e);
+            }
+        })
+    }
+
+    fn serve(self) -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(err)?;
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::from_std(self.listener).map_err(err)?;
+            let service = Service {
+                latest: self.latest,
+                readings: self.readings,
+            };
+            tonic::transport::Server::builder()
+                .add_service(Sds011Server::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+                .map_err(err)
+        })
+    }
+}
@@ -0,0 +1,233 @@
+//! In-memory history of recent measurements.
+
+use crate::aggregate::{Stats, Summary, Tumbling};
+use crate::aqi::Pollutant;
+use crate::trend::{self, Trend};
+use crate::Message;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::vec_deque::{self, VecDeque};
+use std::time::Duration;
+
+/// Statistics of an hour or a day
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Report {
+    /// Period start in UNIX seconds, inclusive
+    pub start: u64,
+    /// Period end in UNIX seconds, exclusive
+    pub end: u64,
+    /// Number of measurements
+    pub count: usize,
+    /// Share of expected measurements present, 0 to 100
+    pub completeness: f32,
+    pub pm25: Summary,
+    pub pm10: Summary,
+}
+
+/// Hourly and daily reports, oldest first
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Summaries {
+    pub hourly: Vec<Report>,
+    pub daily: Vec<Report>,
+}
+
+impl Report {
+    fn new(stats: Stats, interval: u64) -> Report {
+        let expected = ((stats.end - stats.start) / interval).max(1);
+        Report {
+            start: stats.start,
+            end: stats.end,
+            count: stats.count,
+            completeness: (stats.count as f32 / expected as f32 * 100.0).min(100.0),
+            pm25: stats.pm25,
+            pm10: stats.pm10,
+        }
+    }
+}
+
+/// Ring buffer of the last measurements, oldest first
+///
+/// Keeps at most `capacity` measurements and, optionally, only those
+/// within `max_age` of the latest one. Ages are based on measurement
+/// timestamps, so messages must be pushed in time order.
+///
+/// # Example
+/// ```
+/// use sds011::history::History;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let mut history = History::new(1000).max_age(Duration::from_secs(3600));
+/// for t in (0..7200).step_by(600) {
+///     history.push(&Message { timestamp: UNIX_EPOCH + Duration::from_secs(t), pm25: MicrogramsPerCubicMeter(1.0), pm10: MicrogramsPerCubicMeter(2.0) });
+/// }
+///
+/// assert_eq!(history.len(), 7);
+/// assert_eq!(history.since(6000).count(), 2);
+/// assert_eq!(history.last(1).next().unwrap().timestamp_secs(), Some(6600));
+/// ```
+#[derive(Debug, Clone)]
+pub struct History {
+    capacity: usize,
+    max_age: Option<u64>,
+    buf: VecDeque<Message>,
+}
+
+impl History {
+    /// Creates a history of at most `capacity` measurements, at least 1
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity: capacity.max(1),
+            max_age: None,
+            buf: VecDeque::new(),
+        }
+    }
+
+    /// Also drops measurements older than `max_age` before the latest one,
+    /// rounded to whole seconds
+    pub fn max_age(mut self, max_age: Duration) -> History {
+        self.max_age = Some(max_age.as_secs());
+        self
+    }
+
+    /// Adds a measurement, evicting the oldest ones if needed
+    pub fn push(&mut self, m: &Message) {
+        if self.buf.len() == self.capacity {
+            self.buf.pop_front();
+        }
+        self.buf.push_back(m.clone());
+
+        if let (Some(max_age), Some(latest)) = (self.max_age, m.timestamp_secs()) {
+            let oldest = latest.saturating_sub(max_age);
+            while let Some(front) = self.buf.front() {
+                match front.timestamp_secs() {
+                    Some(t) if t >= oldest => break,
+                    _ => {
+                        self.buf.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    /// All measurements, oldest first
+    pub fn iter(&self) -> vec_deque::Iter<'_, Message> {
+        self.buf.iter()
+    }
+
+    /// Measurements taken at or after `timestamp` UNIX seconds, oldest first
+    pub fn since(&self, timestamp: u64) -> impl Iterator<Item = &Message> {
+        self.buf
+            .iter()
+            .filter(move |m| matches!(m.timestamp_secs(), Some(t) if t >= timestamp))
+    }
+
+    /// The last `n` measurements, oldest first
+    pub fn last(&self, n: usize) -> vec_deque::Iter<'_, Message> {
+        self.buf.range(self.buf.len().saturating_sub(n)..)
+    }
+
+    /// The latest measurement
+    pub fn latest(&self) -> Option<&Message> {
+        self.buf.back()
+    }
+
+    /// Number of measurements
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns `true` if there are no measurements
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Per-hour and per-day reports of the measurements, days are UTC
+    ///
+    /// `interval` is how often measurements are taken and gives the
+    /// expected count for the completeness. Periods without measurements
+    /// are left out, the last ones may be incomplete.
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::history::History;
+    /// use sds011::{Message, MicrogramsPerCubicMeter};
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let mut history = History::new(1000);
+    /// for t in (0..5400).step_by(300) {
+    ///     history.push(&Message { timestamp: UNIX_EPOCH + Duration::from_secs(t), pm25: MicrogramsPerCubicMeter(1.0), pm10: MicrogramsPerCubicMeter(2.0) });
+    /// }
+    ///
+    /// let summaries = history.summaries(Duration::from_secs(300));
+    /// assert_eq!(summaries.hourly.len(), 2);
+    /// assert_eq!(summaries.hourly[0].completeness, 100.0);
+    /// assert_eq!(summaries.hourly[1].completeness, 50.0);
+    /// assert_eq!(summaries.daily[0].count, 18);
+    /// ```
+    pub fn summaries(&self, interval: Duration) -> Summaries {
+        let interval = interval.as_secs().max(1);
+        let mut hours = Tumbling::new(Duration::from_secs(3600));
+        let mut days = Tumbling::new(Duration::from_secs(86400));
+        let mut summaries = Summaries::default();
+
+        for m in self.buf.iter() {
+            if let Some(stats) = hours.push(m) {
+                summaries.hourly.push(Report::new(stats, interval));
+            }
+            if let Some(stats) = days.push(m) {
+                summaries.daily.push(Report::new(stats, interval));
+            }
+        }
+        if let Some(stats) = hours.flush() {
+            summaries.hourly.push(Report::new(stats, interval));
+        }
+        if let Some(stats) = days.flush() {
+            summaries.daily.push(Report::new(stats, interval));
+        }
+        summaries
+    }
+
+    /// Trend of `pollutant` over the `window` ending at the latest
+    /// measurement, see `trend::trend()`
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::aqi::Pollutant;
+    /// use sds011::history::History;
+    /// use sds011::trend::Direction;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// # use sds011::{Message, MicrogramsPerCubicMeter};
+    /// # let mut history = History::new(100);
+    /// # for i in 0..10 {
+    /// #     history.push(&Message { timestamp: UNIX_EPOCH + Duration::from_secs(i * 60), pm25: MicrogramsPerCubicMeter(10.0 + i as f32), pm10: MicrogramsPerCubicMeter(20.0) });
+    /// # }
+    ///
+    /// // Close the windows when PM2.5 rises by more than 10 µg/m³ per hour
+    /// let t = history.trend(Duration::from_secs(30 * 60), Pollutant::Pm25, 10.0);
+    /// if t.map(|t| t.direction) == Some(Direction::Rising) {
+    ///     println!("closing the windows");
+    /// }
+    /// ```
+    pub fn trend(&self, window: Duration, pollutant: Pollutant, threshold: f32) -> Option<Trend> {
+        let latest = self.latest()?.timestamp_secs()?;
+        let start = latest.saturating_sub(window.as_secs());
+        trend::trend(self.since(start), pollutant, threshold)
+    }
+
+    /// Drops all measurements
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+}
+
+impl<'a> IntoIterator for &'a History {
+    type Item = &'a Message;
+    type IntoIter = vec_deque::Iter<'a, Message>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
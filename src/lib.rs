@@ -1,78 +1,296 @@
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use serialport::{DataBits, FlowControl, Parity, SerialPort, SerialPortSettings, StopBits};
-use std::iter::FromIterator;
-use std::mem::transmute;
-use std::time::{Duration, SystemTime};
-
+use std::net::ToSocketAddrs;
+use std::time::{Duration, Instant, SystemTime};
+
+pub mod aggregate;
+pub mod alerts;
+pub mod aqi;
+pub mod baseline;
+pub mod calibration;
+#[cfg(feature = "coap")]
+pub mod coap;
+pub mod correction;
+#[cfg(feature = "serde")]
+pub mod directory;
+pub mod discovery;
+pub mod durable;
+pub mod emulator;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 mod error;
+pub mod events;
+pub mod export;
+#[cfg(all(feature = "metrics", feature = "http"))]
+pub mod exporter;
+pub mod filter;
+#[cfg(feature = "serde")]
+pub mod gateway;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+#[cfg(feature = "serde")]
+pub mod lifetime;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod notify;
+pub mod observer;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod privacy;
+pub mod quality;
+#[cfg(feature = "reference")]
+pub mod reference;
+#[cfg(feature = "serde")]
+pub mod remote;
+pub mod sampler;
+#[cfg(feature = "serde")]
+pub mod schema;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod sds198;
+pub mod sensor;
+#[cfg(feature = "http")]
+pub mod server;
+pub mod shared;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(feature = "serde")]
+pub mod sink;
+pub mod stats;
+#[cfg(feature = "sqlite")]
+pub mod store;
+mod time;
+pub mod timestamp;
+pub mod transport;
+pub mod trend;
+pub mod units;
+pub mod who;
+
+pub use discovery::{available_ports, PortInfo};
 pub use error::*;
+pub use sensor::ParticulateSensor;
+pub use stats::Stats;
+pub use transport::Transport;
+pub use units::MicrogramsPerCubicMeter;
+
+/// Default read timeout
+const TIMEOUT: Duration = transport::DEFAULT_TIMEOUT;
+/// Default warm-up window after power-on or wake
+const WARM_UP: Duration = Duration::from_secs(30);
 
-const HEAD: u8 = b'\xaa';
-const TAIL: u8 = b'\xab';
-const CMD_ID: u8 = b'\xb4';
+pub(crate) const HEAD: u8 = b'\xaa';
+pub(crate) const TAIL: u8 = b'\xab';
+pub(crate) const CMD_ID: u8 = b'\xb4';
 
 const READ: u8 = b'\x00';
 const WRITE: u8 = b'\x01';
 
+const REPLY_ID: u8 = b'\xc5';
+// Readings, the reply to a query
+const DATA_ID: u8 = b'\xc0';
+
 const REPORT_MODE_CMD: u8 = b'\x02';
 const ACTIVE: u8 = b'\x00';
 const PASSIVE: u8 = b'\x01';
 
 const QUERY_CMD: u8 = b'\x04';
 
+const DEVICE_ID_CMD: u8 = b'\x05';
+
 // The sleep command ID
-// TODO
-//const SLEEP_CMD: u8 = b'\x06';
+const SLEEP_CMD: u8 = b'\x06';
 // Sleep and work byte
-// TODO
-// const SLEEP: u8 = b'\x00';
-// const WORK: u8= b'\x01';
+const SLEEP: u8 = b'\x00';
+const WORK: u8 = b'\x01';
+
+const FIRMWARE_CMD: u8 = b'\x07';
 
 // The work period command ID
 const WORK_PERIOD_CMD: u8 = b'\x08';
 
+/// Frames the sensor may send before the reply expected, e.g. readings in
+/// active mode
+const MAX_SKIPPED: usize = 5;
+/// Bytes skipped looking for the start of a frame before giving up
+const MAX_GARBAGE: usize = 64;
+
 /// Struct holds a link to a sensor and provides functions to interact with it
 ///
 /// Example:
-/// ```
+/// ```no_run
 /// use sds011::{SDS011};
 /// use std::thread::sleep;
 /// use std::time::{Duration};
 ///
-/// match SDS011::new(port) {
+/// let work_period = 5;
+///
+/// match SDS011::new("/dev/ttyUSB0") {
 ///     Ok(mut sensor) => {
 ///         sensor.set_work_period(work_period).unwrap();
 ///
 ///         loop {
-///             if let Some(m) = sensor.query() {
+///             if let Ok(m) = sensor.query() {
 ///                 println!("{:?}", m);
 ///             }
 ///
-///             sleep(Duration::from_secs(5u64 * 60));
+///             sleep(Duration::from_secs(work_period as u64 * 60));
 ///         }
 ///     },
-///     Err(e) => println!("{:?}", e.description),
+///     Err(e) => println!("{:?}", e),
 /// };
 /// ```
 pub struct SDS011 {
     /// Link to a sensor, must be open via new()
-    port: Box<dyn SerialPort>,
+    port: Box<dyn Transport>,
+    /// When the sensor was last woken up, `None` while sleeping
+    awake_since: Option<Instant>,
+    /// Readings within this time after waking are unreliable
+    warm_up: Duration,
+    /// Optional detector of repeated identical readings
+    stuck: Option<quality::StuckDetector>,
+    /// Optional correction applied to readings
+    calibration: Option<calibration::Calibration>,
+    stats: Stats,
+    /// Whether dropping puts the sensor to sleep
+    sleep_on_drop: bool,
+    /// Optional tracker of the laser's working hours
+    #[cfg(feature = "serde")]
+    hours: Option<lifetime::OperatingHours>,
+    model: sensor::Model,
+    /// Read timeout of commands without their own, see `with_timeout()`
+    timeout: Duration,
+}
+
+impl Drop for SDS011 {
+    fn drop(&mut self) {
+        if self.sleep_on_drop && self.awake_since.is_some() {
+            if let Err(e) = self.sleep() {
+                log::warn!("can't put the sensor to sleep: {}", e);
+            }
+        }
+    }
 }
 
 /// Represents a single measurement
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Message {
-    /// A timestamp in UNIX format
-    pub timestamp: String,
+    /// When the measurement was taken, serialized as UNIX seconds
+    #[cfg_attr(feature = "serde", serde(with = "time::unix_secs"))]
+    pub timestamp: SystemTime,
     /// PM2.5 particles
-    pub pm25: f32,
+    pub pm25: MicrogramsPerCubicMeter,
     /// PM10 particles
-    pub pm10: f32,
+    pub pm10: MicrogramsPerCubicMeter,
+}
+
+impl Message {
+    /// Timestamp as UNIX seconds, `None` if it's before 1970
+    pub fn timestamp_secs(&self) -> Option<u64> {
+        time::to_unix(self.timestamp)
+    }
+
+    /// Timestamp as a string of UNIX seconds, the format `timestamp` had
+    /// before it was a `SystemTime`
+    pub fn timestamp_string(&self) -> String {
+        self.timestamp_secs().unwrap_or(0).to_string()
+    }
+}
+
+impl std::fmt::Debug for Message {
+    /// Shows the timestamp as UNIX seconds, like it was printed before it
+    /// was a `SystemTime`
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Message")
+            .field("timestamp", &self.timestamp_string())
+            .field("pm25", &self.pm25.value())
+            .field("pm10", &self.pm10.value())
+            .finish()
+    }
 }
 
 impl std::fmt::Display for Message {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "[{}] PM10={} PM25={}", self.timestamp, self.pm10, self.pm25)
+        write!(
+            f,
+            "[{}] PM10={} PM25={}",
+            self.timestamp_string(),
+            self.pm10.value(),
+            self.pm25.value()
+        )
+    }
+}
+
+/// How the sensor reports readings
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ReportMode {
+    /// The sensor sends readings on its own, every second or every work
+    /// period
+    Active,
+    /// The sensor only answers queries, the mode `query()` needs
+    Query,
+}
+
+/// Firmware version, the date the firmware was built
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Firmware {
+    /// Year within the century
+    pub year: u8,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl std::fmt::Display for Firmware {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:02}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Sum of `bytes` modulo 256
+pub(crate) fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |acc, b| acc.wrapping_add(*b))
+}
+
+/// Bytes of a frame in hex, space-separated, for logs
+fn hexdump(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Builds a command frame addressed to all sensors
+/// `data` is zero padded to the 12 data bytes of the frame
+fn command_frame(command: u8, data: &[u8]) -> [u8; 19] {
+    let mut frame = [0u8; 19];
+    frame[0] = HEAD;
+    frame[1] = CMD_ID;
+    frame[2] = command;
+    frame[3..3 + data.len()].copy_from_slice(data);
+    // Device ID FFFF addresses any sensor
+    frame[15] = b'\xff';
+    frame[16] = b'\xff';
+    frame[17] = checksum(&frame[2..17]);
+    frame[18] = TAIL;
+    frame
+}
+
+/// Decodes PM values of a data reply frame, timestamped now
+pub(crate) fn decode_data(raw: &[u8; 10]) -> Message {
+    let pm25_ar = [raw[2], raw[3]];
+    let pm10_ar = [raw[4], raw[5]];
+    let pm25 = u16::from_le_bytes(pm25_ar);
+    let pm10 = u16::from_le_bytes(pm10_ar);
+
+    Message {
+        timestamp: time::now(),
+        pm25: MicrogramsPerCubicMeter(pm25 as f32 / 10.0),
+        pm10: MicrogramsPerCubicMeter(pm10 as f32 / 10.0),
     }
 }
 
@@ -81,83 +299,457 @@ impl SDS011 {
     /// `port` is required, for example `/dev/ttyUSB0`
     ///
     /// # Example
-    /// ```
+    /// ```no_run
+    /// # use sds011::SDS011;
     /// let mut sensor = SDS011::new("/dev/ttyUSB0").unwrap();
     /// ```
     pub fn new(port: &str) -> Result<SDS011> {
-        let s = SerialPortSettings {
-            baud_rate: 9600,
-            data_bits: DataBits::Eight,
-            flow_control: FlowControl::None,
-            parity: Parity::None,
-            stop_bits: StopBits::One,
-            timeout: Duration::from_secs(2),
+        SDS011::from_transport(Box::new(transport::serial(port, TIMEOUT)?))
+    }
+
+    /// Opens a serial port or, for `tcp://host:port` and `rfc2217://host:port`,
+    /// a network connection to a sensor
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use sds011::SDS011;
+    /// let mut local = SDS011::open("/dev/ttyUSB0").unwrap();
+    /// let mut remote = SDS011::open("tcp://192.168.1.50:2000").unwrap();
+    /// ```
+    pub fn open(target: &str) -> Result<SDS011> {
+        SDS011::from_transport(transport::open(target, TIMEOUT)?)
+    }
+
+    /// Opens `port` like `open()`, wakes the sensor, runs `f` and puts the
+    /// sensor back to sleep, even if `f` fails or panics
+    ///
+    /// Readings are unreliable right after waking, `f` may wait for
+    /// `warm_up_remaining()` or use `query_stable()`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use sds011::SDS011;
+    /// use std::thread::sleep;
+    ///
+    /// let reading = SDS011::with("/dev/ttyUSB0", |sensor| {
+    ///     sleep(sensor.warm_up_remaining().unwrap_or_default());
+    ///     sensor.query()
+    /// })
+    /// .unwrap();
+    /// println!("{:?}", reading);
+    /// ```
+    pub fn with<T>(port: &str, f: impl FnOnce(&mut SDS011) -> Result<T>) -> Result<T> {
+        let mut sensor = SDS011::open(port)?;
+        // Covers a failed wake-up and a panic in `f` too
+        sensor.set_sleep_on_drop(true);
+        sensor.wake()?;
+        let result = f(&mut sensor);
+        let closed = sensor.close();
+        let value = result?;
+        closed?;
+        Ok(value)
+    }
+
+    /// Connects to a sensor exposed over the network as a raw TCP stream,
+    /// e.g. by ser2net in `raw` mode or an ESP-Link bridge
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use sds011::SDS011;
+    /// let mut sensor = SDS011::open_tcp("192.168.1.50:2000").unwrap();
+    /// ```
+    pub fn open_tcp<A: ToSocketAddrs>(addr: A) -> Result<SDS011> {
+        SDS011::from_transport(Box::new(transport::tcp(addr, TIMEOUT)?))
+    }
+
+    /// Connects to a sensor exposed over the network with RFC 2217
+    /// (Telnet COM port control), e.g. by ser2net in `telnet` mode
+    pub fn open_rfc2217<A: ToSocketAddrs>(addr: A) -> Result<SDS011> {
+        SDS011::from_transport(Box::new(transport::Rfc2217::connect(addr, TIMEOUT)?))
+    }
+
+    /// Creates new instance of SDS011 on top of an already open transport
+    ///
+    /// Its read timeout is taken to be the default one, calls with their
+    /// own timeout restore it afterwards; a transport opened with another
+    /// one needs `set_timeout()`.
+    pub fn from_transport(port: Box<dyn Transport>) -> Result<SDS011> {
+        let mut s = SDS011 {
+            port,
+            awake_since: Some(Instant::now()),
+            warm_up: WARM_UP,
+            stuck: None,
+            calibration: None,
+            stats: Stats::default(),
+            sleep_on_drop: false,
+            #[cfg(feature = "serde")]
+            hours: None,
+            model: sensor::Model::default(),
+            timeout: TIMEOUT,
         };
+        s.set_report_mode()?;
+        Ok(s)
+    }
 
-        let opened = serialport::open_with_settings(port, &s);
-        match opened {
-            Ok(o) => {
-                let mut s = SDS011 { port: o };
-                s.set_report_mode()?;
-                Ok(s)
+    /// Scans serial ports for a sensor with device ID `id` and connects to it
+    ///
+    /// Ports with known USB-serial bridges are probed first, so multi-sensor
+    /// rigs keep working when USB enumeration order changes across reboots.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use sds011::SDS011;
+    /// let mut sensor = SDS011::open_by_device_id(0xa160).unwrap();
+    /// ```
+    pub fn open_by_device_id(id: u16) -> Result<SDS011> {
+        for p in discovery::candidate_ports()?.iter() {
+            log::debug!("looking for {:04x} on {}", id, p.path);
+            if let Ok(mut sensor) = SDS011::new(&p.path) {
+                if let Ok(found) = sensor.device_id() {
+                    if found == id {
+                        return Ok(sensor);
+                    }
+                }
             }
-            Err(e) => Err(e.into()),
         }
+
+        Err(Error::DeviceNotFound)
     }
 
-    /// Sets report mode
-    /// TODO at the moment sets WRITE and PASSIVE mode only
+    /// Returns the sensor's device ID
+    /// ID bytes are combined big-endian, e.g. bytes `A1 60` give `0xa160`
+    pub fn device_id(&mut self) -> Result<u16> {
+        self.traced("device_id", |s| {
+            s.execute(&command_frame(REPORT_MODE_CMD, &[READ]))?;
+
+            let raw = s.get_command_reply(REPORT_MODE_CMD)?;
+            Ok(u16::from_be_bytes([raw[6], raw[7]]))
+        })
+    }
+
+    /// Sets query report mode, see `set_mode()`
     pub fn set_report_mode(&mut self) -> Result<()> {
-        let read = false;
-        let active = false;
+        self.set_mode(ReportMode::Query)
+    }
 
-        let mut cmd = self.cmd_begin();
+    /// Switches how the sensor reports readings, kept across power cycles
+    /// `query()` needs `ReportMode::Query`, which opening the sensor sets;
+    /// in active mode it streams readings on its own, e.g. to a logger or
+    /// an `observer::Observer`
+    pub fn set_mode(&mut self, mode: ReportMode) -> Result<()> {
+        let report = match mode {
+            ReportMode::Active => ACTIVE,
+            ReportMode::Query => PASSIVE,
+        };
+        self.traced("set_mode", |s| {
+            s.execute(&command_frame(REPORT_MODE_CMD, &[WRITE, report]))?;
+            s.get_command_reply(REPORT_MODE_CMD)?;
+            Ok(())
+        })
+    }
 
-        cmd.push(REPORT_MODE_CMD);
-        cmd.push(if read { READ } else { WRITE });
-        cmd.push(if active { ACTIVE } else { PASSIVE });
-        cmd.append(vec![b'\x00'; 10].as_mut());
+    /// Reads the report mode
+    pub fn mode(&mut self) -> Result<ReportMode> {
+        self.traced("mode", |s| {
+            s.execute(&command_frame(REPORT_MODE_CMD, &[READ]))?;
+            let raw = s.get_command_reply(REPORT_MODE_CMD)?;
+            Ok(if raw[4] == ACTIVE {
+                ReportMode::Active
+            } else {
+                ReportMode::Query
+            })
+        })
+    }
 
-        self.finish_cmd(&mut cmd);
-        self.execute(&cmd)?;
-        self.get_reply()?;
+    /// Changes the sensor's device ID, kept across power cycles
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::emulator::Emulator;
+    /// use sds011::SDS011;
+    ///
+    /// let mut sensor = SDS011::from_transport(Box::new(Emulator::new(0xa160))).unwrap();
+    /// sensor.set_device_id(0x0102).unwrap();
+    /// assert_eq!(sensor.device_id().unwrap(), 0x0102);
+    /// ```
+    pub fn set_device_id(&mut self, id: u16) -> Result<()> {
+        // The new ID takes the last two data bytes; the frame is addressed
+        // to the current ID like the datasheet does, not to all sensors
+        let current = self.device_id()?;
+        let mut data = [0u8; 12];
+        data[10..].copy_from_slice(&id.to_be_bytes());
+        let mut frame = command_frame(DEVICE_ID_CMD, &data);
+        frame[15..17].copy_from_slice(&current.to_be_bytes());
+        frame[17] = checksum(&frame[2..17]);
+        self.traced("set_device_id", |s| {
+            s.execute(&frame)?;
+            s.get_command_reply(DEVICE_ID_CMD)?;
+            Ok(())
+        })
+    }
+
+    /// Reads the firmware version
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::emulator::Emulator;
+    /// use sds011::SDS011;
+    ///
+    /// let mut sensor = SDS011::from_transport(Box::new(Emulator::new(0xa160))).unwrap();
+    /// assert_eq!(sensor.firmware_version().unwrap().to_string(), "18-11-16");
+    /// ```
+    pub fn firmware_version(&mut self) -> Result<Firmware> {
+        self.traced("firmware_version", |s| {
+            s.execute(&command_frame(FIRMWARE_CMD, &[]))?;
+            let raw = s.get_command_reply(FIRMWARE_CMD)?;
+            Ok(Firmware {
+                year: raw[3],
+                month: raw[4],
+                day: raw[5],
+            })
+        })
+    }
+
+    /// Puts the sensor to sleep, stopping the laser and the fan
+    pub fn sleep(&mut self) -> Result<()> {
+        self.set_sleep(true)
+    }
+
+    /// Wakes the sensor up
+    /// Readings are unreliable for about 30 seconds after waking, see `query_stable()`
+    pub fn wake(&mut self) -> Result<()> {
+        self.set_sleep(false)
+    }
+
+    /// Puts the sensor to sleep and closes the port, so the laser and the
+    /// fan don't keep running after the program is done with the sensor
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::emulator::Emulator;
+    /// use sds011::SDS011;
+    ///
+    /// let mut sensor = SDS011::from_transport(Box::new(Emulator::new(0xa160))).unwrap();
+    /// let reading = sensor.query().unwrap();
+    /// sensor.close().unwrap();
+    /// ```
+    pub fn close(mut self) -> Result<()> {
+        // Already done here, whatever the result
+        self.sleep_on_drop = false;
+        self.sleep()
+    }
+
+    /// Makes dropping the sensor put it to sleep if it's awake, like
+    /// `close()` but errors are only logged; off by default, as the sensor
+    /// may be shared with a program that expects it to keep running
+    pub fn set_sleep_on_drop(&mut self, sleep_on_drop: bool) {
+        self.sleep_on_drop = sleep_on_drop;
+    }
+
+    /// Sets the warm-up window, 30 seconds by default
+    pub fn set_warm_up(&mut self, warm_up: Duration) {
+        self.warm_up = warm_up;
+    }
+
+    /// Time left until readings are reliable, zero once the sensor is stable
+    /// or `None` while it's sleeping
+    pub fn warm_up_remaining(&self) -> Option<Duration> {
+        self.awake_since
+            .map(|t| self.warm_up.checked_sub(t.elapsed()).unwrap_or_default())
+    }
+
+    /// Returns `true` if the sensor is awake and past its warm-up window
+    pub fn is_stable(&self) -> bool {
+        self.warm_up_remaining() == Some(Duration::from_secs(0))
+    }
+
+    fn set_sleep(&mut self, sleep: bool) -> Result<()> {
+        let state = if sleep { SLEEP } else { WORK };
+        self.traced(if sleep { "sleep" } else { "wake" }, |s| {
+            s.execute(&command_frame(SLEEP_CMD, &[WRITE, state]))?;
+            s.get_command_reply(SLEEP_CMD)
+        })?;
+
+        self.awake_since = if sleep { None } else { Some(Instant::now()) };
+        #[cfg(feature = "serde")]
+        if let Some(hours) = self.hours.as_mut() {
+            hours.set_awake(!sleep);
+        }
         Ok(())
     }
 
+    /// Like `query()`, but discards readings taken within the warm-up window
+    /// Returns `Error::WarmingUp` until the sensor is stable and
+    /// `Error::Sleeping` if it's asleep
+    pub fn query_stable(&mut self) -> Result<Message> {
+        match self.warm_up_remaining() {
+            None => Err(Error::Sleeping),
+            Some(left) if left > Duration::from_secs(0) => Err(Error::WarmingUp),
+            Some(_) => self.query(),
+        }
+    }
+
     /// Reads data from the sensor and returns as `Message`
+    /// Readings are calibrated if a calibration is set
     pub fn query(&mut self) -> Result<Message> {
-        let mut cmd = self.cmd_begin();
+        self.stats.queries += 1;
+        let raw = self.traced("query", |s| {
+            s.execute(&command_frame(QUERY_CMD, &[]))?;
+            s.get_data_reply()
+        })?;
+        #[cfg(feature = "serde")]
+        if let Some(hours) = self.hours.as_mut() {
+            hours.checkpoint();
+        }
+        let m = decode_data(&raw);
+
+        if let Some(detector) = self.stuck.as_mut() {
+            if detector.check(&m) {
+                log::warn!(
+                    "same reading {:?} repeated, the sensor may be stuck",
+                    (m.pm25.value(), m.pm10.value())
+                );
+                return Err(Error::SuspectStuckSensor);
+            }
+        }
+        match &self.calibration {
+            Some(c) => Ok(c.apply(&m)),
+            None => Ok(m),
+        }
+    }
 
-        cmd.push(QUERY_CMD);
-        cmd.append(vec![b'\x00'; 12].as_mut());
+    /// Reads data from the sensor like `query()`, waiting at most `timeout`
+    /// for the reply instead of the read timeout, e.g. a short one to check
+    /// that the sensor is alive or a long one for the first reading after
+    /// waking it up
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::emulator::Emulator;
+    /// use sds011::SDS011;
+    /// use std::time::Duration;
+    ///
+    /// let mut sensor = SDS011::from_transport(Box::new(Emulator::new(0xa160))).unwrap();
+    /// let alive = sensor.query_timeout(Duration::from_millis(200)).is_ok();
+    /// assert!(alive);
+    /// ```
+    pub fn query_timeout(&mut self, timeout: Duration) -> Result<Message> {
+        self.with_timeout(timeout, |s| s.query())
+    }
 
-        self.finish_cmd(&mut cmd);
-        self.execute(&cmd)?;
+    /// Runs `f` with the read timeout set to `timeout`, restoring it
+    /// afterwards, the equivalent of `query_timeout()` for other commands
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use sds011::SDS011;
+    /// use std::time::Duration;
+    ///
+    /// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+    /// let id = sensor
+    ///     .with_timeout(Duration::from_millis(200), |s| s.device_id())
+    ///     .unwrap();
+    /// ```
+    pub fn with_timeout<T>(
+        &mut self,
+        timeout: Duration,
+        f: impl FnOnce(&mut SDS011) -> Result<T>,
+    ) -> Result<T> {
+        self.port.set_timeout(timeout)?;
+        let result = f(self);
+        let restored = self.port.set_timeout(self.timeout);
+        let value = result?;
+        restored?;
+        Ok(value)
+    }
 
-        let raw = self.get_reply()?;
+    /// Sets the read timeout of every command, `transport::DEFAULT_TIMEOUT`
+    /// by default
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.port.set_timeout(timeout)?;
+        self.timeout = timeout;
+        Ok(())
+    }
 
-        let pm25_ar = [raw[2], raw[3]];
-        let pm10_ar = [raw[4], raw[5]];
-        let pm25: u16 = unsafe { transmute::<[u8; 2], u16>(pm25_ar) }.to_le();
-        let pm10: u16 = unsafe { transmute::<[u8; 2], u16>(pm10_ar) }.to_le();
+    /// Applies `calibration` to readings returned by `query()`,
+    /// `None` returns raw readings
+    pub fn set_calibration(&mut self, calibration: Option<calibration::Calibration>) {
+        self.calibration = calibration;
+    }
 
-        Ok(Message {
-            timestamp: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-                .to_string(),
-            pm25: pm25 as f32 / 10.0,
-            pm10: pm10 as f32 / 10.0,
-        })
+    /// Calibration applied to readings, if any
+    pub fn calibration(&self) -> Option<&calibration::Calibration> {
+        self.calibration.as_ref()
+    }
+
+    /// Makes `query()` fail with `Error::SuspectStuckSensor` after `threshold`
+    /// consecutive identical readings, `None` disables the check
+    pub fn set_stuck_threshold(&mut self, threshold: Option<usize>) {
+        self.stuck = threshold.map(quality::StuckDetector::new);
+    }
+
+    /// Sets the model of the sensor, an SDS011 by default; the SDS018 and
+    /// the SDS021 speak the same protocol
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::emulator::Emulator;
+    /// use sds011::sensor::{Model, ParticulateSensor};
+    /// use sds011::SDS011;
+    ///
+    /// let mut sensor = SDS011::from_transport(Box::new(Emulator::new(0xa160))).unwrap();
+    /// sensor.set_model(Model::Sds021);
+    /// assert_eq!(sensor.identity().unwrap().model, "SDS021");
+    /// ```
+    pub fn set_model(&mut self, model: sensor::Model) {
+        self.model = model;
+    }
+
+    /// Model of the sensor
+    pub fn model(&self) -> sensor::Model {
+        self.model
+    }
+
+    /// Tracks the laser's working hours with `hours`, from the sensor's
+    /// current state on; `None` stops tracking, saving them
+    #[cfg(feature = "serde")]
+    pub fn set_operating_hours(&mut self, hours: Option<lifetime::OperatingHours>) {
+        self.hours = hours;
+        if let Some(hours) = self.hours.as_mut() {
+            hours.set_awake(self.awake_since.is_some());
+        }
+    }
+
+    /// Tracker of the laser's working hours, if any
+    #[cfg(feature = "serde")]
+    pub fn operating_hours(&self) -> Option<&lifetime::OperatingHours> {
+        self.hours.as_ref()
+    }
+
+    /// Counters of the traffic with the sensor since it was opened
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::emulator::Emulator;
+    /// use sds011::SDS011;
+    ///
+    /// let mut sensor = SDS011::from_transport(Box::new(Emulator::new(0xa160))).unwrap();
+    /// sensor.query().unwrap();
+    /// let stats = sensor.stats();
+    /// assert_eq!(stats.queries, 1);
+    /// assert_eq!(stats.checksum_failures, 0);
+    /// // Opening sets the report mode, a 19-byte command and a 10-byte reply
+    /// assert_eq!((stats.bytes_written, stats.bytes_read), (38, 20));
+    /// ```
+    pub fn stats(&self) -> Stats {
+        self.stats
     }
 
     /// Returns command header and command ID bytes
+    /// Commands are built without allocating internally, this is kept for
+    /// callers assembling their own frames
     pub fn cmd_begin(&self) -> Vec<u8> {
-        let mut vec = Vec::new();
-        vec.push(HEAD);
-        vec.push(CMD_ID);
-        vec
+        vec![HEAD, CMD_ID]
     }
 
     /// Sets working period
@@ -167,61 +759,237 @@ impl SDS011 {
             return Err(Error::TooLongWorkTime);
         }
         let read = false;
-        let mut cmd = self.cmd_begin();
+        let mode = if read { READ } else { WRITE };
+        self.traced("set_work_period", |s| {
+            s.execute(&command_frame(WORK_PERIOD_CMD, &[mode, work_time]))?;
+            s.get_command_reply(WORK_PERIOD_CMD)?;
+            Ok(())
+        })?;
+        #[cfg(feature = "serde")]
+        if let Some(hours) = self.hours.as_mut() {
+            hours.set_work_period(work_time);
+        }
+        Ok(())
+    }
+
+    /// Reads the working period in minutes, 0 is continuous
+    pub fn work_period(&mut self) -> Result<u8> {
+        self.traced("work_period", |s| {
+            s.execute(&command_frame(WORK_PERIOD_CMD, &[READ]))?;
+            Ok(s.get_command_reply(WORK_PERIOD_CMD)?[4])
+        })
+    }
 
-        cmd.push(WORK_PERIOD_CMD);
-        cmd.push(if read { READ } else { WRITE });
-        cmd.push(work_time);
-        cmd.append(vec![b'\x00'; 10].as_mut());
+    /// Runs `command`, logging when it starts and how long it took
+    fn traced<T>(
+        &mut self,
+        name: &str,
+        command: impl FnOnce(&mut SDS011) -> Result<T>,
+    ) -> Result<T> {
+        log::debug!("{}", name);
+        let started = Instant::now();
+        let result = command(self);
+        match &result {
+            Ok(_) => log::debug!("{} done in {:?}", name, started.elapsed()),
+            Err(e) => log::debug!("{} failed after {:?}: {}", name, started.elapsed(), e),
+        }
+        result
+    }
 
-        self.finish_cmd(&mut cmd);
-        self.execute(&cmd)?;
-        self.get_reply()?;
+    fn execute(&mut self, cmd_bytes: &[u8]) -> Result<()> {
+        log::trace!("> {}", hexdump(cmd_bytes));
+        self.port.write_all(cmd_bytes)?;
+        self.stats.bytes_written += cmd_bytes.len() as u64;
         Ok(())
     }
 
-    fn finish_cmd(&self, cmd: &mut Vec<u8>) {
-        let id1 = b'\xff';
-        let id2 = b'\xff';
+    /// Reads the reply to `command`, skipping readings the sensor sends
+    /// on its own in active mode
+    fn get_command_reply(&mut self, command: u8) -> Result<[u8; 10]> {
+        self.get_reply_where(&format!("command {:02x}", command), |raw| {
+            raw[1] == REPLY_ID && raw[2] == command
+        })
+    }
 
-        cmd.push(id1);
-        cmd.push(id2);
+    /// Reads the reply to a query, skipping late replies to other commands
+    fn get_data_reply(&mut self) -> Result<[u8; 10]> {
+        self.get_reply_where("the query", |raw| raw[1] == DATA_ID)
+    }
 
-        let ch = Vec::from_iter(cmd[2..].iter().cloned());
-        let mut checksum: u32 = 0;
-        for i in ch {
-            checksum += i as u32;
+    /// Reads frames until one is `expected`, the reply to `what`
+    fn get_reply_where(
+        &mut self,
+        what: &str,
+        expected: impl Fn(&[u8; 10]) -> bool,
+    ) -> Result<[u8; 10]> {
+        for _ in 0..MAX_SKIPPED {
+            let raw = self.get_reply()?;
+            if expected(&raw) {
+                return Ok(raw);
+            }
+            self.stats.retries += 1;
+            log::debug!("skipped a frame waiting for the reply to {}", what);
         }
-        checksum = checksum % 256;
-
-        cmd.push(checksum as u8);
-        cmd.push(TAIL);
+        log::warn!("no reply to {} within {} frames", what, MAX_SKIPPED);
+        Err(Error::ReadError(format!("no reply to {}", what)))
     }
 
-    fn execute(&mut self, cmd_bytes: &Vec<u8>) -> Result<()> {
-        self.port.write_all(cmd_bytes)?;
-        Ok(())
+    /// Fills `buf` from the port, counting the bytes and timeouts
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self.port.read_exact(buf) {
+            Ok(()) => {
+                self.stats.bytes_read += buf.len() as u64;
+                Ok(())
+            }
+            Err(e) => {
+                // Serial ports time out, sockets would block
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+                ) {
+                    self.stats.timeouts += 1;
+                }
+                log::warn!("reading a reply failed: {}", e);
+                Err(e.into())
+            }
+        }
     }
 
     fn get_reply(&mut self) -> Result<[u8; 10]> {
         let mut buf = [0u8; 10];
-        self.port.read_exact(buf.as_mut())?;
+        self.read_exact(&mut buf)?;
+        // Bytes lost on the line or garbage after opening the port
+        // misalign frames, they start again at the next head byte
+        let mut skipped = 0;
+        while buf[0] != HEAD {
+            let shift = buf[1..]
+                .iter()
+                .position(|b| *b == HEAD)
+                .map_or(buf.len(), |p| p + 1);
+            skipped += shift;
+            if skipped > MAX_GARBAGE {
+                log::warn!("no frame start in {} bytes", skipped);
+                return Err(Error::ReadError(format!(
+                    "no frame start in {} bytes",
+                    skipped
+                )));
+            }
+            buf.copy_within(shift.., 0);
+            let len = buf.len();
+            self.read_exact(&mut buf[len - shift..])?;
+        }
+        if skipped > 0 {
+            self.stats.resyncs += 1;
+            log::warn!("skipped {} bytes to the start of a frame", skipped);
+        }
+        log::trace!("< {}", hexdump(&buf));
 
         let data = &buf[2..8];
-        if data.len() == 0 {
+        if data.is_empty() {
             return Err(Error::EmptyDataFrame);
         }
 
-        let mut checksum: u32 = 0;
-        for i in data.iter() {
-            checksum += *i as u32;
-        }
-        checksum = checksum & 255;
-
-        if checksum as u8 != buf[8] {
+        if checksum(data) != buf[8] {
+            self.stats.checksum_failures += 1;
+            log::warn!(
+                "bad checksum {:02x}, expected {:02x}, in {}",
+                buf[8],
+                checksum(data),
+                hexdump(&buf)
+            );
             return Err(Error::BadChecksum);
         }
 
         Ok(buf)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read, Write};
+
+    /// Sends canned frames whatever is written
+    struct Scripted(Cursor<Vec<u8>>);
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Scripted {
+        fn set_timeout(&mut self, _timeout: Duration) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(id: u8, data: [u8; 6]) -> Vec<u8> {
+        let mut frame = vec![HEAD, id];
+        frame.extend_from_slice(&data);
+        frame.push(checksum(&data));
+        frame.push(TAIL);
+        frame
+    }
+
+    /// Reply to setting query mode, which opening the sensor does
+    fn opened() -> Vec<u8> {
+        frame(REPLY_ID, [REPORT_MODE_CMD, WRITE, PASSIVE, 0, 0xa1, 0x60])
+    }
+
+    fn reading() -> Vec<u8> {
+        frame(DATA_ID, [45, 0, 80, 0, 0xa1, 0x60])
+    }
+
+    fn sensor(frames: &[Vec<u8>]) -> SDS011 {
+        let script = Scripted(Cursor::new(frames.concat()));
+        SDS011::from_transport(Box::new(script)).unwrap()
+    }
+
+    #[test]
+    fn query_skips_command_replies() {
+        let late = frame(REPLY_ID, [SLEEP_CMD, WRITE, WORK, 0, 0xa1, 0x60]);
+        let mut sensor = sensor(&[opened(), late, reading()]);
+        let m = sensor.query().unwrap();
+        assert_eq!((m.pm25.value(), m.pm10.value()), (4.5, 8.0));
+        assert_eq!(sensor.stats().retries, 1);
+    }
+
+    #[test]
+    fn query_requires_a_data_frame() {
+        let reply = frame(REPLY_ID, [QUERY_CMD, 0, 0, 0, 0xa1, 0x60]);
+        let mut frames = vec![opened()];
+        frames.extend(vec![reply; MAX_SKIPPED]);
+        assert!(sensor(&frames).query().is_err());
+    }
+
+    #[test]
+    fn commands_skip_readings() {
+        let id = frame(REPLY_ID, [REPORT_MODE_CMD, READ, PASSIVE, 0, 0xa1, 0x60]);
+        let asleep = frame(REPLY_ID, [SLEEP_CMD, WRITE, SLEEP, 0, 0xa1, 0x60]);
+        let period = frame(REPLY_ID, [WORK_PERIOD_CMD, WRITE, 5, 0, 0xa1, 0x60]);
+        let mut sensor = sensor(&[
+            opened(),
+            reading(),
+            id,
+            reading(),
+            asleep,
+            reading(),
+            period,
+        ]);
+        assert_eq!(sensor.device_id().unwrap(), 0xa160);
+        sensor.sleep().unwrap();
+        sensor.set_work_period(5).unwrap();
+        assert_eq!(sensor.stats().retries, 3);
+    }
+}
@@ -0,0 +1,176 @@
+//! Laser operating hours, persisted across restarts.
+//!
+//! The SDS011's laser is rated for about 8000 hours of work. `OperatingHours`
+//! accumulates the time the sensor was awake, weighted by its work period:
+//! with a period of N minutes it only works 30 seconds of every N minutes.
+//! Attached with `SDS011::set_operating_hours()`, it follows `wake()`,
+//! `sleep()` and `set_work_period()` and is saved to a small JSON file.
+
+use crate::durable;
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Working hours the laser is rated for
+pub const RATED_HOURS: f64 = 8000.0;
+/// Fraction of the rated hours `worn()` reports from by default
+pub const DEFAULT_WARNING: f64 = 0.9;
+/// Seconds the sensor works every work period
+const WORK_SECS: f64 = 30.0;
+/// Longest time between two saves while the sensor is in use
+const SAVE_EVERY: Duration = Duration::from_secs(600);
+
+/// What the state file holds
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    hours: f64,
+}
+
+/// Hours the laser worked
+///
+/// # Example
+/// ```
+/// use sds011::emulator::Emulator;
+/// use sds011::lifetime::OperatingHours;
+/// use sds011::SDS011;
+///
+/// let path = std::env::temp_dir().join("sds011-hours-doctest.json");
+/// let hours = OperatingHours::open(&path).unwrap().warning(0.75);
+///
+/// let mut sensor = SDS011::from_transport(Box::new(Emulator::new(0xa160))).unwrap();
+/// sensor.set_operating_hours(Some(hours));
+/// sensor.set_work_period(5).unwrap();
+/// sensor.sleep().unwrap();
+///
+/// let hours = sensor.operating_hours().unwrap();
+/// assert!(!hours.worn());
+/// println!("{:.1} h, {:.1}% of the rated lifetime", hours.hours(), hours.fraction() * 100.0);
+/// # std::fs::remove_file(&path).ok();
+/// ```
+#[derive(Debug)]
+pub struct OperatingHours {
+    path: PathBuf,
+    /// Hours up to `since`, or to the last sleep
+    hours: f64,
+    /// Start of the current stretch the sensor is awake, `None` while
+    /// sleeping
+    since: Option<Instant>,
+    /// Fraction of the time awake the laser is on
+    duty: f64,
+    rated: f64,
+    warning: f64,
+    saved: Instant,
+}
+
+impl OperatingHours {
+    /// Loads the hours saved in `path`, 0 if it doesn't exist yet
+    ///
+    /// Until its work period is set, e.g. by `SDS011::set_work_period()`,
+    /// the sensor counts as working continuously, which overestimates the
+    /// hours of a sensor with a work period.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<OperatingHours> {
+        let path = path.as_ref().to_path_buf();
+        let state = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str::<State>(&text)
+                .map_err(|e| Error::StorageError(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::default(),
+            Err(e) => return Err(Error::StorageError(format!("{}: {}", path.display(), e))),
+        };
+        Ok(OperatingHours {
+            path,
+            hours: state.hours.max(0.0),
+            since: None,
+            duty: 1.0,
+            rated: RATED_HOURS,
+            warning: DEFAULT_WARNING,
+            saved: Instant::now(),
+        })
+    }
+
+    /// Sets the hours the laser is rated for, `RATED_HOURS` by default
+    pub fn rated(mut self, hours: f64) -> OperatingHours {
+        self.rated = hours;
+        self
+    }
+
+    /// Sets the fraction of the rated hours from which `worn()` returns
+    /// `true`, `DEFAULT_WARNING` by default
+    pub fn warning(mut self, fraction: f64) -> OperatingHours {
+        self.warning = fraction;
+        self
+    }
+
+    /// Hours the laser worked, including the current stretch
+    pub fn hours(&self) -> f64 {
+        self.hours + self.running()
+    }
+
+    /// Fraction of the rated hours used
+    pub fn fraction(&self) -> f64 {
+        self.hours() / self.rated
+    }
+
+    /// Returns `true` once the warning fraction of the rated hours is used,
+    /// time to order a spare sensor
+    pub fn worn(&self) -> bool {
+        self.fraction() >= self.warning
+    }
+
+    /// Saves the hours to the state file
+    pub fn save(&mut self) -> Result<()> {
+        self.fold();
+        self.saved = Instant::now();
+        let text = serde_json::to_string(&State { hours: self.hours })
+            .map_err(|e| Error::StorageError(e.to_string()))?;
+        durable::write_atomic(&self.path, text.as_bytes())
+    }
+
+    /// Hours of the current stretch
+    fn running(&self) -> f64 {
+        self.since
+            .map_or(0.0, |t| t.elapsed().as_secs_f64() / 3600.0 * self.duty)
+    }
+
+    /// Adds the current stretch to `hours`, starting a new one
+    fn fold(&mut self) {
+        self.hours += self.running();
+        if self.since.is_some() {
+            self.since = Some(Instant::now());
+        }
+    }
+
+    /// Records the sensor waking up or going to sleep
+    pub(crate) fn set_awake(&mut self, awake: bool) {
+        self.fold();
+        self.since = if awake { Some(Instant::now()) } else { None };
+        self.checkpoint();
+    }
+
+    /// Records a new work period, 0 is continuous
+    pub(crate) fn set_work_period(&mut self, minutes: u8) {
+        self.fold();
+        self.duty = match minutes {
+            0 => 1.0,
+            n => WORK_SECS / (n as f64 * 60.0),
+        };
+    }
+
+    /// Saves the hours if the last save is old enough, so a crash loses
+    /// little
+    pub(crate) fn checkpoint(&mut self) {
+        if self.saved.elapsed() >= SAVE_EVERY || self.since.is_none() {
+            if let Err(e) = self.save() {
+                log::warn!("operating hours: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for OperatingHours {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            log::warn!("operating hours: {}", e);
+        }
+    }
+}
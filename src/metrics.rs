@@ -0,0 +1,219 @@
+//! Prometheus metrics of a sensor.
+//!
+//! `Metrics` registers gauges for the last PM2.5 and PM10 readings, their
+//! baseline, time and age, and counters of readings and errors by kind, with a
+//! `prometheus::Registry`. Hand it to `DutyCycleSampler::metrics()` to
+//! update them on every reading, or call `observe()` from your own loop,
+//! then expose the registry to a scraper with `prometheus::TextEncoder`,
+//! calling `refresh()` first. Built with `http` too, `exporter` serves
+//! them.
+
+use crate::baseline::Baseline;
+use crate::{Error, Message, Result};
+use prometheus::{Gauge, IntCounter, IntCounterVec, Opts, Registry};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Readings the baseline covers, a day at one a minute
+const BASELINE_WINDOW: usize = 1440;
+/// Percentile of the readings the baseline is
+const BASELINE_PERCENTILE: f32 = 10.0;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::ExportError(format!("metrics: {}", e))
+}
+
+/// Gauges and counters of one sensor
+///
+/// Metrics of several sensors can share a registry when each is created
+/// with `for_device()`, which labels them with the device ID.
+///
+/// # Example
+/// ```
+/// use prometheus::{Encoder, Registry, TextEncoder};
+/// use sds011::metrics::Metrics;
+/// use sds011::{Error, Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let registry = Registry::new();
+/// let metrics = Metrics::for_device(0xa160);
+/// metrics.register(&registry).unwrap();
+///
+/// metrics.observe(&Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) });
+/// metrics.observe_error(&Error::BadChecksum);
+///
+/// let mut text = Vec::new();
+/// TextEncoder::new().encode(&registry.gather(), &mut text).unwrap();
+/// let text = String::from_utf8(text).unwrap();
+/// assert!(text.contains("sds011_pm25_ugm3{device_id=\"a160\"} 4.5"));
+/// assert!(text.contains("sds011_baseline_pm25_ugm3{device_id=\"a160\"} 4.5"));
+/// assert!(text.contains("sds011_errors_total{device_id=\"a160\",kind=\"bad_checksum\"} 1"));
+/// ```
+#[derive(Clone)]
+pub struct Metrics {
+    pm25: Gauge,
+    pm10: Gauge,
+    baseline_pm25: Gauge,
+    baseline_pm10: Gauge,
+    /// Shared by clones, like the gauges
+    baseline: Arc<Mutex<Baseline>>,
+    last_read: Gauge,
+    age: Gauge,
+    readings: IntCounter,
+    errors: IntCounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    /// Creates unlabelled metrics for a single sensor
+    pub fn new() -> Metrics {
+        Metrics::with_labels(None)
+    }
+
+    /// Creates metrics labelled `device_id` with `id` in hex
+    pub fn for_device(id: u16) -> Metrics {
+        Metrics::with_labels(Some(format!("{:04x}", id)))
+    }
+
+    fn with_labels(device_id: Option<String>) -> Metrics {
+        let opts = |name: &str, help: &str| {
+            let opts = Opts::new(name, help).namespace("sds011");
+            match &device_id {
+                Some(id) => opts.const_label("device_id", id),
+                None => opts,
+            }
+        };
+        // Names and help texts are constant and valid, so creation can't fail
+        let metrics = Metrics {
+            pm25: Gauge::with_opts(opts("pm25_ugm3", "Last PM2.5 reading in µg/m³")).unwrap(),
+            pm10: Gauge::with_opts(opts("pm10_ugm3", "Last PM10 reading in µg/m³")).unwrap(),
+            baseline_pm25: Gauge::with_opts(opts(
+                "baseline_pm25_ugm3",
+                "Background PM2.5 in µg/m³, 10th percentile of the last 1440 readings",
+            ))
+            .unwrap(),
+            baseline_pm10: Gauge::with_opts(opts(
+                "baseline_pm10_ugm3",
+                "Background PM10 in µg/m³, 10th percentile of the last 1440 readings",
+            ))
+            .unwrap(),
+            baseline: Arc::new(Mutex::new(Baseline::new(
+                BASELINE_WINDOW,
+                BASELINE_PERCENTILE,
+            ))),
+            last_read: Gauge::with_opts(opts(
+                "last_read_timestamp_seconds",
+                "UNIX time of the last reading",
+            ))
+            .unwrap(),
+            age: Gauge::with_opts(opts(
+                "reading_age_seconds",
+                "Seconds since the last reading when scraped, NaN before the first one",
+            ))
+            .unwrap(),
+            readings: IntCounter::with_opts(opts("readings_total", "Readings taken")).unwrap(),
+            errors: IntCounterVec::new(opts("errors_total", "Failed readings by kind"), &["kind"])
+                .unwrap(),
+        };
+        metrics.age.set(f64::NAN);
+        metrics.baseline_pm25.set(f64::NAN);
+        metrics.baseline_pm10.set(f64::NAN);
+        metrics
+    }
+
+    /// Registers the metrics with `registry`, e.g.
+    /// `prometheus::default_registry()`
+    ///
+    /// Fails if metrics with the same name and labels are already
+    /// registered.
+    pub fn register(&self, registry: &Registry) -> Result<()> {
+        registry
+            .register(Box::new(self.pm25.clone()))
+            .map_err(err)?;
+        registry
+            .register(Box::new(self.pm10.clone()))
+            .map_err(err)?;
+        registry
+            .register(Box::new(self.baseline_pm25.clone()))
+            .map_err(err)?;
+        registry
+            .register(Box::new(self.baseline_pm10.clone()))
+            .map_err(err)?;
+        registry
+            .register(Box::new(self.last_read.clone()))
+            .map_err(err)?;
+        registry.register(Box::new(self.age.clone())).map_err(err)?;
+        registry
+            .register(Box::new(self.readings.clone()))
+            .map_err(err)?;
+        registry
+            .register(Box::new(self.errors.clone()))
+            .map_err(err)
+    }
+
+    /// Updates the gauges and the baseline with a reading
+    pub fn observe(&self, m: &Message) {
+        self.pm25.set(m.pm25.value() as f64);
+        self.pm10.set(m.pm10.value() as f64);
+        let baseline = match self.baseline.lock() {
+            Ok(mut b) => b.push(m),
+            Err(poisoned) => poisoned.into_inner().push(m),
+        };
+        self.baseline_pm25.set(baseline.pm25.value() as f64);
+        self.baseline_pm10.set(baseline.pm10.value() as f64);
+        if let Ok(d) = m.timestamp.duration_since(UNIX_EPOCH) {
+            self.last_read.set(d.as_secs_f64());
+        }
+        self.readings.inc();
+    }
+
+    /// Updates the age of the last reading, call it before gathering
+    pub fn refresh(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        match self.readings.get() {
+            0 => self.age.set(f64::NAN),
+            _ => self.age.set((now - self.last_read.get()).max(0.0)),
+        }
+    }
+
+    /// Counts a failed reading
+    pub fn observe_error(&self, e: &Error) {
+        self.errors.with_label_values(&[e.kind()]).inc();
+    }
+
+    /// Updates the metrics with the outcome of a query
+    pub fn record(&self, result: &Result<Message>) {
+        match result {
+            Ok(m) => self.observe(m),
+            Err(e) => self.observe_error(e),
+        }
+    }
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics")
+            .field("pm25", &self.pm25.get())
+            .field("pm10", &self.pm10.get())
+            .field("readings", &self.readings.get())
+            .finish()
+    }
+}
+
+/// Metrics are equal when they update the same time series
+impl PartialEq for Metrics {
+    fn eq(&self, other: &Metrics) -> bool {
+        use prometheus::core::Collector;
+
+        let ids = |m: &Metrics| -> Vec<u64> { m.pm25.desc().iter().map(|d| d.id).collect() };
+        ids(self) == ids(other)
+    }
+}
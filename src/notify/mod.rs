@@ -0,0 +1,79 @@
+//! Phone notifications of alerts, see `alerts`.
+//!
+//! A `Notifier` delivers an alert somewhere a person will see it;
+//! `Alerts::notify()` hands it every alert. Notifiers send the rule name
+//! as the title and `Alert::text()` as the message, and tell entering an
+//! alert from clearing it.
+
+#[cfg(feature = "pushover")]
+pub mod pushover;
+#[cfg(feature = "telegram")]
+pub mod telegram;
+
+use crate::alerts::{Alert, Alerts, Transition};
+use crate::Result;
+
+/// Delivers alerts
+pub trait Notifier: Send {
+    /// Delivers an alert
+    fn notify(&mut self, alert: &Alert) -> Result<()>;
+}
+
+impl Alerts {
+    /// Hands every alert to `notifier`
+    /// Delivery blocks `check()`; failures are printed as warnings, the
+    /// alert isn't delivered again
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::alerts::{Alert, Alerts, Rule};
+    /// use sds011::aqi::Pollutant;
+    /// use sds011::notify::Notifier;
+    /// use sds011::{Message, MicrogramsPerCubicMeter, Result};
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// struct Print;
+    ///
+    /// impl Notifier for Print {
+    ///     fn notify(&mut self, alert: &Alert) -> Result<()> {
+    ///         println!("{}", alert.text());
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let mut alerts = Alerts::new();
+    /// alerts.add(Rule::above("unhealthy", Pollutant::Pm25, 35.0));
+    /// alerts.notify(Print);
+    /// alerts.check(&Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(40.0), pm10: MicrogramsPerCubicMeter(60.0) });
+    /// ```
+    pub fn notify<N: Notifier + 'static>(&mut self, mut notifier: N) {
+        self.on_alert(move |alert| {
+            if let Err(e) = notifier.notify(alert) {
+                log::warn!("alert {} not delivered: {}", alert.rule, e);
+            }
+        });
+    }
+}
+
+/// Title of an alert's notification, the rule name
+pub fn title(alert: &Alert) -> String {
+    match alert.transition {
+        Transition::Enter => alert.rule.clone(),
+        Transition::Exit => format!("{} cleared", alert.rule),
+    }
+}
+
+/// Describes a failed request without its URL, which may hold a token
+#[cfg(any(feature = "telegram", feature = "pushover"))]
+fn describe(e: ureq::Error) -> String {
+    match e {
+        ureq::Error::Status(code, response) => {
+            let body = response.into_string().unwrap_or_default();
+            format!("HTTP status {}: {}", code, body.trim())
+        }
+        ureq::Error::Transport(t) => match t.message() {
+            Some(message) => format!("{}: {}", t.kind(), message),
+            None => t.kind().to_string(),
+        },
+    }
+}
@@ -0,0 +1,105 @@
+//! Pushover notifications.
+//!
+//! Alerts are pushed with an application's API token to a user or group
+//! key. Entering alerts use the configured priority, normal by default;
+//! cleared ones are sent quietly.
+
+use super::{describe, title, Notifier};
+use crate::alerts::{Alert, Transition};
+use crate::{Error, Result};
+use std::time::Duration;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::NotifyError(format!("Pushover: {}", e))
+}
+
+/// Messages endpoint of the API
+const URL: &str = "https://api.pushover.net/1/messages.json";
+/// Priority of cleared alerts: no sound or vibration
+const QUIET: i8 = -1;
+/// Timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Notifier pushing alerts with Pushover
+///
+/// # Example
+/// ```no_run
+/// use sds011::alerts::{Alerts, Rule};
+/// use sds011::aqi::Pollutant;
+/// use sds011::notify::pushover::Pushover;
+///
+/// let mut alerts = Alerts::new();
+/// alerts.add(Rule::above("unhealthy", Pollutant::Pm25, 35.0));
+/// alerts.notify(Pushover::new("azGDORePK8gMaC0QOYAMyEEuzJnyUi", "uQiRzpo4DXghDmr9QzzfQu27cmVRsG").priority(1));
+/// ```
+pub struct Pushover {
+    token: String,
+    user: String,
+    priority: i8,
+    device: Option<String>,
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl Pushover {
+    /// Pushes with the application `token` to the user or group `user`
+    pub fn new(token: &str, user: &str) -> Pushover {
+        Pushover {
+            token: token.to_string(),
+            user: user.to_string(),
+            priority: 0,
+            device: None,
+            url: URL.to_string(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Pushes entering alerts with `priority`, -2 to 1; 2, which needs
+    /// acknowledging, isn't supported
+    pub fn priority(mut self, priority: i8) -> Pushover {
+        self.priority = priority.clamp(-2, 1);
+        self
+    }
+
+    /// Pushes to the user's `device` only
+    pub fn device(mut self, device: &str) -> Pushover {
+        self.device = Some(device.to_string());
+        self
+    }
+
+    /// Posts to `url` instead of the public API
+    pub fn url(mut self, url: &str) -> Pushover {
+        self.url = url.to_string();
+        self
+    }
+
+    /// Pushes a message
+    pub fn send(&self, title: &str, message: &str, priority: i8) -> Result<()> {
+        let priority = priority.to_string();
+        let mut form = vec![
+            ("token", self.token.as_str()),
+            ("user", self.user.as_str()),
+            ("title", title),
+            ("message", message),
+            ("priority", priority.as_str()),
+        ];
+        if let Some(device) = &self.device {
+            form.push(("device", device));
+        }
+        self.agent
+            .post(&self.url)
+            .send_form(&form)
+            .map_err(|e| err(describe(e)))?;
+        Ok(())
+    }
+}
+
+impl Notifier for Pushover {
+    fn notify(&mut self, alert: &Alert) -> Result<()> {
+        let priority = match alert.transition {
+            Transition::Enter => self.priority,
+            Transition::Exit => QUIET.min(self.priority),
+        };
+        self.send(&title(alert), &alert.text(), priority)
+    }
+}
@@ -0,0 +1,87 @@
+//! Telegram bot notifications.
+//!
+//! Alerts are sent as messages from a bot, created with @BotFather, to a
+//! chat: a user who started a conversation with the bot, a group it was
+//! added to or a channel it posts to. Cleared alerts are sent silently.
+
+use super::{describe, title, Notifier};
+use crate::alerts::{Alert, Transition};
+use crate::{Error, Result};
+use serde::Serialize;
+use std::time::Duration;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::NotifyError(format!("Telegram: {}", e))
+}
+
+/// Base URL of the Bot API
+const URL: &str = "https://api.telegram.org";
+/// Timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Serialize)]
+struct SendMessage<'a> {
+    chat_id: &'a str,
+    text: String,
+    disable_notification: bool,
+}
+
+/// Notifier sending alerts from a Telegram bot
+///
+/// # Example
+/// ```no_run
+/// use sds011::alerts::{Alerts, Rule};
+/// use sds011::aqi::Pollutant;
+/// use sds011::notify::telegram::Telegram;
+///
+/// let mut alerts = Alerts::new();
+/// alerts.add(Rule::above("unhealthy", Pollutant::Pm25, 35.0));
+/// alerts.notify(Telegram::new("123456:ABC-DEF1234ghIkl-zyx57W2v1u123ew11", "987654321"));
+/// ```
+pub struct Telegram {
+    token: String,
+    chat_id: String,
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl Telegram {
+    /// Sends as the bot with `token` to the chat `chat_id`, a number or
+    /// `@channelname`
+    pub fn new(token: &str, chat_id: &str) -> Telegram {
+        Telegram {
+            token: token.to_string(),
+            chat_id: chat_id.to_string(),
+            url: URL.to_string(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Uses the Bot API at `url` instead of the public one, e.g. a local
+    /// Bot API server
+    pub fn url(mut self, url: &str) -> Telegram {
+        self.url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sends a text message to the chat
+    pub fn send(&self, text: &str, silent: bool) -> Result<()> {
+        let body = SendMessage {
+            chat_id: &self.chat_id,
+            text: text.to_string(),
+            disable_notification: silent,
+        };
+        self.agent
+            .post(&format!("{}/bot{}/sendMessage", self.url, self.token))
+            .send_json(&body)
+            .map_err(|e| err(describe(e)))?;
+        Ok(())
+    }
+}
+
+impl Notifier for Telegram {
+    fn notify(&mut self, alert: &Alert) -> Result<()> {
+        let text = format!("{}: {}", title(alert), alert.text());
+        self.send(&text, alert.transition == Transition::Exit)
+    }
+}
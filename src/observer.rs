@@ -0,0 +1,162 @@
+//! Listen-only decoding of traffic on a port driven by someone else.
+//!
+//! `Observer` never writes to the port. It's meant for a second process
+//! watching a sensor that another program, or the other end of a Y-cable,
+//! is driving, e.g. for debugging or mirroring readings to extra sinks.
+
+use crate::transport::{self, Transport};
+use crate::{checksum, decode_data, Message, Result, CMD_ID, HEAD, TAIL};
+use std::time::Duration;
+
+/// Data reply command ID
+const DATA_ID: u8 = b'\xc0';
+/// Command reply ID
+const REPLY_ID: u8 = b'\xc5';
+
+/// Frame seen on the wire
+#[derive(Debug, PartialEq, Clone)]
+pub enum Frame {
+    /// Measurement reported by the sensor
+    Data { message: Message, device_id: u16 },
+    /// Sensor's reply to a command
+    Reply {
+        command: u8,
+        data: [u8; 3],
+        device_id: u16,
+    },
+    /// Command sent by the host, `device_id` is `0xffff` for all sensors
+    Command {
+        command: u8,
+        data: [u8; 12],
+        device_id: u16,
+    },
+}
+
+/// Passive frame decoder
+///
+/// # Example
+/// ```no_run
+/// use sds011::observer::Observer;
+///
+/// let mut observer = Observer::open("/dev/ttyUSB0").unwrap();
+/// loop {
+///     if let Ok(frame) = observer.next_frame() {
+///         println!("{:?}", frame);
+///     }
+/// }
+/// ```
+pub struct Observer {
+    port: Box<dyn Transport>,
+    /// Read-ahead buffer, so a serial port isn't read a byte per syscall
+    buf: [u8; 64],
+    pos: usize,
+    len: usize,
+}
+
+impl Observer {
+    /// Opens `target` like `SDS011::open()` without sending anything
+    pub fn open(target: &str) -> Result<Observer> {
+        Ok(Observer::from_transport(transport::open(
+            target,
+            Duration::from_secs(2),
+        )?))
+    }
+
+    /// Observes an already open transport
+    pub fn from_transport(port: Box<dyn Transport>) -> Observer {
+        Observer {
+            port,
+            buf: [0u8; 64],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Blocks until the next valid frame, skipping garbage and bad frames
+    /// Returns an error if the transport fails or times out
+    pub fn next_frame(&mut self) -> Result<Frame> {
+        loop {
+            if self.read_byte()? != HEAD {
+                continue;
+            }
+
+            let kind = self.read_byte()?;
+            let len = match kind {
+                DATA_ID | REPLY_ID => 10,
+                CMD_ID => 19,
+                _ => continue,
+            };
+
+            let mut frame = [0u8; 19];
+            frame[0] = HEAD;
+            frame[1] = kind;
+            for b in frame[2..len].iter_mut() {
+                *b = self.read_byte()?;
+            }
+
+            if let Some(frame) = decode(&frame[..len]) {
+                return Ok(frame);
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8> {
+        while self.pos == self.len {
+            self.len = self.port.read(&mut self.buf)?;
+            self.pos = 0;
+            if self.len == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+        self.pos += 1;
+        Ok(self.buf[self.pos - 1])
+    }
+}
+
+/// Decodes a complete frame, `None` if the tail or checksum is wrong
+fn decode(buf: &[u8]) -> Option<Frame> {
+    let len = buf.len();
+    if buf[len - 1] != TAIL {
+        return None;
+    }
+
+    if checksum(&buf[2..len - 2]) != buf[len - 2] {
+        return None;
+    }
+
+    let device_id = u16::from_be_bytes([buf[len - 4], buf[len - 3]]);
+    match buf[1] {
+        DATA_ID => {
+            let mut raw = [0u8; 10];
+            raw.copy_from_slice(buf);
+            Some(Frame::Data {
+                message: decode_data(&raw),
+                device_id,
+            })
+        }
+        REPLY_ID => Some(Frame::Reply {
+            command: buf[2],
+            data: [buf[3], buf[4], buf[5]],
+            device_id,
+        }),
+        _ => {
+            let mut data = [0u8; 12];
+            data.copy_from_slice(&buf[3..15]);
+            Some(Frame::Command {
+                command: buf[2],
+                data,
+                device_id,
+            })
+        }
+    }
+}
+
+impl Frame {
+    /// Returns the measurement if this is a data frame
+    pub fn message(&self) -> Option<&Message> {
+        match self {
+            Frame::Data { message, .. } => Some(message),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! WebAssembly plugins implementing sinks and humidity compensation.
+//!
+//! Plugins are core WebAssembly modules run by wasmtime, so third-party
+//! integrations can be built and distributed independently of this crate.
+//!
+//! # ABI version 1
+//!
+//! Every plugin exports `sds011_abi_version() -> i32` returning `1`.
+//!
+//! Sink plugins export `memory`, `sds011_alloc(len: i32) -> i32` returning
+//! a buffer of `len` bytes, and `sds011_send(ptr: i32, len: i32) -> i32`
+//! receiving a measurement as JSON
+//! (`{"schema_version":2,"timestamp":..,"pm25":..,"pm10":..}`, see
+//! `schema`) and returning `0` on success.
+//!
+//! Compensation plugins export `sds011_compensate(pm: f32, humidity: f32) -> f32`,
+//! see `correction::Compensation`.
+//!
+//! The host provides `sds011.log(ptr: i32, len: i32)` logging a UTF-8
+//! message at the info level. Calls are limited in fuel, so a looping plugin fails
+//! instead of hanging the daemon.
+
+use crate::correction::Compensation;
+use crate::sink::Sink;
+use crate::{Error, Message, Result};
+use std::sync::Mutex;
+use wasmtime::{Caller, Config, Engine, Instance, Linker, Module, Store, TypedFunc};
+
+/// ABI version implemented by the host
+pub const ABI_VERSION: i32 = 1;
+
+/// Fuel given to every call, roughly the number of wasm instructions
+const FUEL: u64 = 10_000_000;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::PluginError(e.to_string())
+}
+
+/// Instantiated module with its store
+struct Plugin {
+    store: Store<()>,
+    instance: Instance,
+}
+
+impl Plugin {
+    /// Compiles a binary or text module, links host functions and checks
+    /// the ABI version
+    fn new(bytes: &[u8]) -> Result<Plugin> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(err)?;
+        let module = Module::new(&engine, bytes).map_err(err)?;
+
+        let mut linker = Linker::new(&engine);
+        linker
+            .func_wrap(
+                "sds011",
+                "log",
+                |mut caller: Caller<'_, ()>, ptr: i32, len: i32| {
+                    let memory = caller.get_export("memory").and_then(|e| e.into_memory());
+                    if let Some(memory) = memory {
+                        let data = memory.data(&caller);
+                        let (start, end) = (ptr as usize, ptr as usize + len as usize);
+                        if let Some(bytes) = data.get(start..end) {
+                            log::info!("plugin: {}", String::from_utf8_lossy(bytes));
+                        }
+                    }
+                },
+            )
+            .map_err(err)?;
+
+        let mut store = Store::new(&engine, ());
+        store.set_fuel(FUEL).map_err(err)?;
+        let instance = linker.instantiate(&mut store, &module).map_err(err)?;
+
+        let mut plugin = Plugin { store, instance };
+        let version: TypedFunc<(), i32> = plugin.func("sds011_abi_version")?;
+        let found = plugin.call(&version, ())?;
+        if found != ABI_VERSION {
+            return Err(err(format!(
+                "plugin ABI version {}, expected {}",
+                found, ABI_VERSION
+            )));
+        }
+        Ok(plugin)
+    }
+
+    fn load(path: &str) -> Result<Plugin> {
+        let bytes = std::fs::read(path).map_err(|e| err(format!("{}: {}", path, e)))?;
+        Plugin::new(&bytes).map_err(|e| err(format!("{}: {}", path, e)))
+    }
+
+    fn func<P, R>(&mut self, name: &str) -> Result<TypedFunc<P, R>>
+    where
+        P: wasmtime::WasmParams,
+        R: wasmtime::WasmResults,
+    {
+        self.instance
+            .get_typed_func(&mut self.store, name)
+            .map_err(|e| err(format!("{}: {}", name, e)))
+    }
+
+    fn call<P, R>(&mut self, f: &TypedFunc<P, R>, params: P) -> Result<R>
+    where
+        P: wasmtime::WasmParams,
+        R: wasmtime::WasmResults,
+    {
+        self.store.set_fuel(FUEL).map_err(err)?;
+        f.call(&mut self.store, params).map_err(err)
+    }
+}
+
+/// Sink implemented by a plugin
+///
+/// # Example
+/// ```
+/// use sds011::plugin::PluginSink;
+/// use sds011::sink::Sink;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// // Text format for brevity, plugins are normally compiled to .wasm
+/// let mut sink = PluginSink::from_bytes(br#"(module
+///     (import "sds011" "log" (func $log (param i32 i32)))
+///     (memory (export "memory") 1)
+///     (func (export "sds011_abi_version") (result i32) i32.const 1)
+///     (func (export "sds011_alloc") (param i32) (result i32) i32.const 1024)
+///     (func (export "sds011_send") (param i32 i32) (result i32)
+///         local.get 0 local.get 1 call $log
+///         i32.const 0))"#).unwrap();
+///
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.0), pm10: MicrogramsPerCubicMeter(8.0) };
+/// sink.send(&m).unwrap();
+/// ```
+pub struct PluginSink {
+    plugin: Plugin,
+    alloc: TypedFunc<i32, i32>,
+    send: TypedFunc<(i32, i32), i32>,
+}
+
+impl PluginSink {
+    /// Loads a `.wasm` file
+    pub fn load(path: &str) -> Result<PluginSink> {
+        PluginSink::from_plugin(Plugin::load(path)?)
+    }
+
+    /// Loads a module from memory, in binary or text format
+    pub fn from_bytes(bytes: &[u8]) -> Result<PluginSink> {
+        PluginSink::from_plugin(Plugin::new(bytes)?)
+    }
+
+    fn from_plugin(mut plugin: Plugin) -> Result<PluginSink> {
+        Ok(PluginSink {
+            alloc: plugin.func("sds011_alloc")?,
+            send: plugin.func("sds011_send")?,
+            plugin,
+        })
+    }
+}
+
+impl Sink for PluginSink {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        let json = crate::schema::to_json(m)?.into_bytes();
+        let ptr = self.plugin.call(&self.alloc, json.len() as i32)?;
+
+        let memory = self
+            .plugin
+            .instance
+            .get_memory(&mut self.plugin.store, "memory")
+            .ok_or_else(|| err("plugin exports no memory"))?;
+        memory
+            .write(&mut self.plugin.store, ptr as usize, &json)
+            .map_err(err)?;
+
+        match self.plugin.call(&self.send, (ptr, json.len() as i32))? {
+            0 => Ok(()),
+            code => Err(Error::SinkError(format!("plugin returned {}", code))),
+        }
+    }
+}
+
+/// Humidity compensation implemented by a plugin
+///
+/// A failing plugin leaves values uncorrected and logs the error.
+pub struct PluginCompensation {
+    plugin: Mutex<Plugin>,
+    compensate: TypedFunc<(f32, f32), f32>,
+}
+
+impl PluginCompensation {
+    /// Loads a `.wasm` file
+    pub fn load(path: &str) -> Result<PluginCompensation> {
+        PluginCompensation::from_plugin(Plugin::load(path)?)
+    }
+
+    /// Loads a module from memory, in binary or text format
+    pub fn from_bytes(bytes: &[u8]) -> Result<PluginCompensation> {
+        PluginCompensation::from_plugin(Plugin::new(bytes)?)
+    }
+
+    fn from_plugin(mut plugin: Plugin) -> Result<PluginCompensation> {
+        Ok(PluginCompensation {
+            compensate: plugin.func("sds011_compensate")?,
+            plugin: Mutex::new(plugin),
+        })
+    }
+}
+
+impl Compensation for PluginCompensation {
+    fn compensate(&self, pm: f32, humidity: f32) -> f32 {
+        let mut plugin = match self.plugin.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match plugin.call(&self.compensate, (pm, humidity)) {
+            Ok(corrected) => corrected,
+            Err(e) => {
+                log::warn!("compensation plugin: {}", e);
+                pm
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Anonymization of device IDs and locations for public uploads.
+//!
+//! Each sink publishing to a public network can carry its own `Privacy`
+//! settings, so home users can share data without revealing which device
+//! it came from or exactly where they live.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// How a device ID is published
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "mode", rename_all = "snake_case"))]
+pub enum IdPolicy {
+    /// As is, e.g. `a160`
+    Plain,
+    /// Salted SHA-256, stable for the same salt but not reversible
+    Hashed { salt: String },
+    /// Not published at all
+    Omit,
+}
+
+/// How a location is published
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "mode", rename_all = "snake_case"))]
+pub enum LocationPolicy {
+    /// As is
+    Exact,
+    /// Snapped to the center of a grid cell of `cell` degrees,
+    /// 0.01° is roughly a kilometer
+    Grid { cell: f64 },
+    /// Not published at all
+    Omit,
+}
+
+/// Privacy settings of a sink
+///
+/// # Example
+/// ```
+/// use sds011::privacy::{IdPolicy, LocationPolicy, Privacy};
+///
+/// let privacy = Privacy {
+///     id: IdPolicy::Hashed { salt: "my secret".to_string() },
+///     location: LocationPolicy::Grid { cell: 0.01 },
+/// };
+/// assert_ne!(privacy.device_id(0xa160).unwrap(), "a160");
+/// assert_eq!(privacy.location(55.7512, 37.6184), Some((55.755, 37.615)));
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Privacy {
+    pub id: IdPolicy,
+    pub location: LocationPolicy,
+}
+
+impl Default for Privacy {
+    /// Publishes everything as is
+    fn default() -> Self {
+        Privacy {
+            id: IdPolicy::Plain,
+            location: LocationPolicy::Exact,
+        }
+    }
+}
+
+impl Privacy {
+    /// Device ID as it should be published
+    pub fn device_id(&self, id: u16) -> Option<String> {
+        match &self.id {
+            IdPolicy::Plain => Some(format!("{:04x}", id)),
+            IdPolicy::Hashed { salt } => {
+                let mut hasher = Sha256::new();
+                hasher.update(salt.as_bytes());
+                hasher.update(id.to_be_bytes());
+                let digest = hasher.finalize();
+                // 64 bits are plenty to tell devices apart
+                Some(digest[..8].iter().map(|b| format!("{:02x}", b)).collect())
+            }
+            IdPolicy::Omit => None,
+        }
+    }
+
+    /// `(latitude, longitude)` as it should be published
+    pub fn location(&self, latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+        match &self.location {
+            LocationPolicy::Exact => Some((latitude, longitude)),
+            LocationPolicy::Grid { cell } if *cell > 0.0 => {
+                let snap = |v: f64| {
+                    let snapped = ((v / cell).floor() + 0.5) * cell;
+                    // Avoid printing float noise like 55.755000000000004
+                    (snapped * 1e6).round() / 1e6
+                };
+                Some((snap(latitude), snap(longitude)))
+            }
+            LocationPolicy::Grid { .. } => Some((latitude, longitude)),
+            LocationPolicy::Omit => None,
+        }
+    }
+}
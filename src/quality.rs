@@ -0,0 +1,66 @@
+//! Data quality checks.
+
+use crate::Message;
+
+/// Flags a sensor repeating the exact same PM values
+///
+/// Dying sensors often report identical readings for hours, which is
+/// practically impossible for a working one.
+///
+/// # Example
+/// ```
+/// use sds011::quality::StuckDetector;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let mut detector = StuckDetector::new(3);
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.0), pm10: MicrogramsPerCubicMeter(8.0) };
+/// assert!(!detector.check(&m));
+/// assert!(!detector.check(&m));
+/// assert!(detector.check(&m));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct StuckDetector {
+    threshold: usize,
+    last: Option<(f32, f32)>,
+    repeats: usize,
+}
+
+impl StuckDetector {
+    /// Creates a detector flagging `threshold` consecutive identical readings
+    pub fn new(threshold: usize) -> StuckDetector {
+        StuckDetector {
+            threshold: threshold.max(2),
+            last: None,
+            repeats: 0,
+        }
+    }
+
+    /// Records a reading and returns `true` if the sensor looks stuck
+    pub fn check(&mut self, m: &Message) -> bool {
+        let values = (m.pm25.value(), m.pm10.value());
+        if self.last == Some(values) {
+            self.repeats += 1;
+        } else {
+            self.last = Some(values);
+            self.repeats = 1;
+        }
+        self.is_stuck()
+    }
+
+    /// Returns `true` if the last `threshold` readings were identical
+    pub fn is_stuck(&self) -> bool {
+        self.repeats >= self.threshold
+    }
+
+    /// Number of consecutive identical readings so far
+    pub fn repeats(&self) -> usize {
+        self.repeats
+    }
+
+    /// Forgets the history, e.g. after the sensor was replaced
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.repeats = 0;
+    }
+}
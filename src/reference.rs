@@ -0,0 +1,178 @@
+//! Outdoor reference feed and indoor/outdoor comparison.
+//!
+//! Fetches the latest reading of a nearby public station from the
+//! [sensor.community](https://sensor.community) API and compares it with
+//! indoor measurements.
+
+use crate::{Error, Message, MicrogramsPerCubicMeter, Result};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+
+const SENSOR_COMMUNITY_URL: &str = "https://data.sensor.community/airrohr/v1/sensor";
+
+#[derive(Deserialize)]
+struct Reading {
+    timestamp: String,
+    sensordatavalues: Vec<DataValue>,
+}
+
+#[derive(Deserialize)]
+struct DataValue {
+    value_type: String,
+    value: String,
+}
+
+/// Fetches the latest reading of the sensor.community sensor `sensor_id`
+///
+/// # Example
+/// ```no_run
+/// let outdoor = sds011::reference::fetch_sensor_community(12345).unwrap();
+/// println!("{}", outdoor);
+/// ```
+pub fn fetch_sensor_community(sensor_id: u32) -> Result<Message> {
+    let url = format!("{}/{}/", SENSOR_COMMUNITY_URL, sensor_id);
+    let readings: Vec<Reading> = ureq::get(&url)
+        .call()
+        .map_err(|e| Error::ReferenceError(e.to_string()))?
+        .into_json()
+        .map_err(|e| Error::ReferenceError(e.to_string()))?;
+
+    let latest = readings
+        .iter()
+        .max_by(|a, b| a.timestamp.cmp(&b.timestamp))
+        .ok_or_else(|| Error::ReferenceError("no readings".to_string()))?;
+
+    // sensor.community names PM10 `P1` and PM2.5 `P2`
+    let value = |name: &str| -> Result<f32> {
+        latest
+            .sensordatavalues
+            .iter()
+            .find(|v| v.value_type == name)
+            .and_then(|v| v.value.parse::<f32>().ok())
+            .ok_or_else(|| Error::ReferenceError(format!("missing {} value", name)))
+    };
+
+    let secs = parse_timestamp(&latest.timestamp)?.max(0) as u64;
+    let timestamp = crate::time::from_unix(secs)
+        .ok_or_else(|| Error::ReferenceError(format!("timestamp {} out of range", secs)))?;
+    Ok(Message {
+        timestamp,
+        pm25: MicrogramsPerCubicMeter(value("P2")?),
+        pm10: MicrogramsPerCubicMeter(value("P1")?),
+    })
+}
+
+/// Converts `YYYY-MM-DD hh:mm:ss` in UTC to UNIX seconds
+fn parse_timestamp(s: &str) -> Result<i64> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .map(|t| t.and_utc().timestamp())
+        .map_err(|_| Error::ReferenceError(format!("bad timestamp {}", s)))
+}
+
+/// Indoor to outdoor ratio of a single pair of measurements
+/// Returns `(pm25, pm10)`, `None` for a pollutant whose outdoor value is 0
+pub fn ratio(indoor: &Message, outdoor: &Message) -> (Option<f32>, Option<f32>) {
+    let r = |i: MicrogramsPerCubicMeter, o: MicrogramsPerCubicMeter| {
+        if o.value() > 0.0 {
+            Some(i / o)
+        } else {
+            None
+        }
+    };
+    (r(indoor.pm25, outdoor.pm25), r(indoor.pm10, outdoor.pm10))
+}
+
+/// Infiltration estimate of a single pollutant
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Infiltration {
+    /// Fraction of outdoor particles found indoors (regression slope)
+    pub factor: f32,
+    /// Concentration generated by indoor sources (regression intercept)
+    pub indoor_sources: f32,
+    /// Pearson correlation between indoor and outdoor series
+    pub correlation: f32,
+}
+
+/// Indoor/outdoor comparison over a series of paired measurements
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Comparison {
+    /// Mean indoor to outdoor ratio of PM2.5
+    pub ratio_pm25: Option<f32>,
+    /// Mean indoor to outdoor ratio of PM10
+    pub ratio_pm10: Option<f32>,
+    /// PM2.5 infiltration estimate
+    pub pm25: Option<Infiltration>,
+    /// PM10 infiltration estimate
+    pub pm10: Option<Infiltration>,
+}
+
+/// Compares paired `(indoor, outdoor)` measurements
+///
+/// Infiltration is estimated with a least squares fit of
+/// `indoor = factor * outdoor + indoor_sources`, which needs at least
+/// two pairs with different outdoor values.
+pub fn compare(pairs: &[(Message, Message)]) -> Comparison {
+    let pm25: Vec<(f32, f32)> = pairs
+        .iter()
+        .map(|(i, o)| (i.pm25.value(), o.pm25.value()))
+        .collect();
+    let pm10: Vec<(f32, f32)> = pairs
+        .iter()
+        .map(|(i, o)| (i.pm10.value(), o.pm10.value()))
+        .collect();
+
+    Comparison {
+        ratio_pm25: mean_ratio(&pm25),
+        ratio_pm10: mean_ratio(&pm10),
+        pm25: infiltration(&pm25),
+        pm10: infiltration(&pm10),
+    }
+}
+
+fn mean_ratio(pairs: &[(f32, f32)]) -> Option<f32> {
+    let ratios: Vec<f32> = pairs
+        .iter()
+        .filter(|(_, o)| *o > 0.0)
+        .map(|(i, o)| i / o)
+        .collect();
+    if ratios.is_empty() {
+        return None;
+    }
+    Some(ratios.iter().sum::<f32>() / ratios.len() as f32)
+}
+
+fn infiltration(pairs: &[(f32, f32)]) -> Option<Infiltration> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_i = pairs.iter().map(|p| p.0 as f64).sum::<f64>() / n;
+    let mean_o = pairs.iter().map(|p| p.1 as f64).sum::<f64>() / n;
+
+    let mut cov = 0.0;
+    let mut var_i = 0.0;
+    let mut var_o = 0.0;
+    for (i, o) in pairs.iter() {
+        let di = *i as f64 - mean_i;
+        let d_o = *o as f64 - mean_o;
+        cov += di * d_o;
+        var_i += di * di;
+        var_o += d_o * d_o;
+    }
+    if var_o == 0.0 {
+        return None;
+    }
+
+    let factor = cov / var_o;
+    let correlation = if var_i > 0.0 {
+        cov / (var_i * var_o).sqrt()
+    } else {
+        0.0
+    };
+
+    Some(Infiltration {
+        factor: factor as f32,
+        indoor_sources: (mean_i - factor * mean_o) as f32,
+        correlation: correlation as f32,
+    })
+}
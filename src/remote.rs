@@ -0,0 +1,424 @@
+//! Remote management commands.
+//!
+//! Stations behind NAT can be managed without opening inbound ports: the
+//! daemon subscribes to a command topic on an MQTT broker and executes
+//! commands carrying the station's token. Commands are JSON objects with
+//! the UNIX time they were sent at and a nonce, unique per command, e.g. a
+//! UUID:
+//!
+//! ```text
+//! {"token": "...", "timestamp": 1700000000, "nonce": "...", "command": "set_work_period", "minutes": 5}
+//! {"token": "...", "timestamp": 1700000000, "nonce": "...", "command": "sleep"}
+//! {"token": "...", "timestamp": 1700000000, "nonce": "...", "command": "wake"}
+//! {"token": "...", "timestamp": 1700000000, "nonce": "...", "command": "measure"}
+//! {"token": "...", "timestamp": 1700000000, "nonce": "...", "command": "reload_config"}
+//! ```
+//!
+//! Commands sent more than `MAX_AGE` ago, or ahead of the station's
+//! clock, are rejected, and so are nonces seen in that time, so a
+//! recorded command can't be replayed.
+//!
+//! Every command is answered on `<topic>/reply` with a `Reply`.
+
+use crate::{Error, Message, Result, SDS011};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Difference between the time a command was sent at and the station's
+/// clock it's accepted with
+pub const MAX_AGE: Duration = Duration::from_secs(120);
+/// Longest nonce accepted
+const MAX_NONCE: usize = 64;
+/// Nonces remembered at most, more commands within `MAX_AGE` are rejected
+const MAX_SEEN: usize = 1024;
+
+/// Command sent to a station
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// Sets the working period, 0 is continuous
+    SetWorkPeriod {
+        minutes: u8,
+    },
+    Sleep,
+    Wake,
+    /// Queries the sensor now, the reading is sent with the reply
+    Measure,
+    /// Reloads the configuration, handled by the daemon
+    ReloadConfig,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    token: String,
+    /// UNIX seconds the command was sent at
+    timestamp: u64,
+    nonce: String,
+    #[serde(flatten)]
+    command: Command,
+}
+
+/// Outcome of a command
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Reply {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Reading taken by `Command::Measure`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<Message>,
+}
+
+impl Reply {
+    /// Reply to a command that returned `result`
+    pub fn of(result: &Result<Option<Message>>) -> Reply {
+        match result {
+            Ok(message) => Reply {
+                ok: true,
+                error: None,
+                message: message.clone(),
+            },
+            Err(e) => Reply {
+                ok: false,
+                error: Some(e.to_string()),
+                message: None,
+            },
+        }
+    }
+}
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::RemoteError(e.to_string())
+}
+
+/// Compares tokens in time independent of where they differ
+pub(crate) fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// Checks the token, the timestamp and the nonce of command payloads
+///
+/// # Example
+/// ```
+/// use sds011::remote::{Command, Verifier};
+/// use std::time::{SystemTime, UNIX_EPOCH};
+///
+/// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+/// let payload = format!(
+///     r#"{{"token": "s3cret", "timestamp": {}, "nonce": "4f1c", "command": "set_work_period", "minutes": 5}}"#,
+///     now
+/// );
+///
+/// let mut verifier = Verifier::new("s3cret");
+/// assert_eq!(verifier.parse(payload.as_bytes()).unwrap(), Command::SetWorkPeriod { minutes: 5 });
+/// // Replayed
+/// assert!(verifier.parse(payload.as_bytes()).is_err());
+/// assert!(Verifier::new("other").parse(payload.as_bytes()).is_err());
+/// ```
+pub struct Verifier {
+    token: String,
+    /// Nonces of the commands accepted within `MAX_AGE`, with their
+    /// timestamps
+    seen: HashMap<String, u64>,
+}
+
+impl Verifier {
+    /// Accepts commands carrying `token`, none if it's empty
+    pub fn new(token: &str) -> Verifier {
+        Verifier {
+            token: token.to_string(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Parses a command payload and checks it
+    pub fn parse(&mut self, payload: &[u8]) -> Result<Command> {
+        self.parse_at(payload, SystemTime::now())
+    }
+
+    /// Parses a command payload, checking it's fresh at `now`
+    pub fn parse_at(&mut self, payload: &[u8], now: SystemTime) -> Result<Command> {
+        let request: Request = serde_json::from_slice(payload).map_err(err)?;
+        if self.token.is_empty() || !same_token(&request.token, &self.token) {
+            return Err(err("invalid token"));
+        }
+
+        let now = now.duration_since(UNIX_EPOCH).map_err(err)?.as_secs();
+        let max_age = MAX_AGE.as_secs();
+        if request.timestamp.abs_diff(now) > max_age {
+            return Err(err(format!(
+                "stale command, sent at {} but it's {}",
+                request.timestamp, now
+            )));
+        }
+        if request.nonce.is_empty() || request.nonce.len() > MAX_NONCE {
+            return Err(err(format!("expected a nonce of 1 to {} bytes", MAX_NONCE)));
+        }
+        self.seen
+            .retain(|_, timestamp| timestamp.saturating_add(max_age) >= now);
+        if self.seen.contains_key(&request.nonce) {
+            return Err(err("reused nonce"));
+        }
+        if self.seen.len() >= MAX_SEEN {
+            return Err(err("too many commands"));
+        }
+        self.seen.insert(request.nonce, request.timestamp);
+        Ok(request.command)
+    }
+}
+
+/// Executes a sensor command
+/// Returns the reading of `Command::Measure`, `Command::ReloadConfig`
+/// is left to the caller and does nothing here
+pub fn execute(sensor: &mut SDS011, command: &Command) -> Result<Option<Message>> {
+    match command {
+        Command::SetWorkPeriod { minutes } => sensor.set_work_period(*minutes).map(|_| None),
+        Command::Sleep => sensor.sleep().map(|_| None),
+        Command::Wake => sensor.wake().map(|_| None),
+        Command::Measure => sensor.query().map(Some),
+        Command::ReloadConfig => Ok(None),
+    }
+}
+
+#[cfg(feature = "mqtt")]
+pub use mqtt::MqttCommands;
+
+#[cfg(feature = "mqtt")]
+mod mqtt {
+    use super::{err, Command, Reply, Verifier};
+    use crate::sink::mqtt::Tls;
+    use crate::Result;
+    use rumqttc::{Client, Event, MqttOptions, Packet, QoS, TlsConfiguration, Transport};
+    use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Delay before reconnecting after a connection error
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+    /// Command topic subscription on an MQTT broker
+    ///
+    /// Commands with a wrong token, stale or replayed are logged and
+    /// dropped, see `Verifier`. The connection is kept up in a background
+    /// thread and re-subscribes on reconnect.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use sds011::remote::{self, MqttCommands};
+    /// use sds011::SDS011;
+    /// use std::time::Duration;
+    ///
+    /// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+    /// let commands = MqttCommands::connect("broker.local", 1883, "stations/kitchen/cmd", "s3cret").unwrap();
+    /// loop {
+    ///     if let Some(command) = commands.recv_timeout(Duration::from_secs(60)) {
+    ///         let result = remote::execute(&mut sensor, &command);
+    ///         commands.reply(&result);
+    ///     }
+    /// }
+    /// ```
+    pub struct MqttCommands {
+        client: Client,
+        topic: String,
+        commands: Receiver<Command>,
+    }
+
+    impl MqttCommands {
+        /// Connects to the broker at `host:port` and subscribes to `topic`
+        /// `token` must be non-empty
+        pub fn connect(host: &str, port: u16, topic: &str, token: &str) -> Result<MqttCommands> {
+            MqttCommands::start(host, port, topic, token, None)
+        }
+
+        /// Connects like `connect()` over TLS, checking the broker against
+        /// the CAs of `tls`
+        pub fn connect_tls(
+            host: &str,
+            port: u16,
+            topic: &str,
+            token: &str,
+            tls: Tls,
+        ) -> Result<MqttCommands> {
+            MqttCommands::start(host, port, topic, token, Some(tls))
+        }
+
+        fn start(
+            host: &str,
+            port: u16,
+            topic: &str,
+            token: &str,
+            tls: Option<Tls>,
+        ) -> Result<MqttCommands> {
+            if token.is_empty() {
+                return Err(err("empty token"));
+            }
+            let id = format!("sds011-{}", std::process::id());
+            let mut options = MqttOptions::new(id, host, port);
+            options.set_keep_alive(Duration::from_secs(30));
+            if let Some(tls) = tls {
+                options.set_transport(Transport::tls_with_config(TlsConfiguration::Simple {
+                    ca: tls.ca()?,
+                    alpn: None,
+                    client_auth: None,
+                }));
+            }
+            let (client, mut connection) = Client::new(options, 16);
+
+            let (tx, commands) = channel();
+            let subscriber = client.clone();
+            let sub_topic = topic.to_string();
+            let mut verifier = Verifier::new(token);
+            thread::spawn(move || {
+                for event in connection.iter() {
+                    match event {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            if let Err(e) =
+                                subscriber.subscribe(sub_topic.as_str(), QoS::AtLeastOnce)
+                            {
+                                log::warn!("remote: subscribe: {}", e);
+                            }
+                        }
+                        Ok(Event::Incoming(Packet::Publish(p))) if p.topic == sub_topic => {
+                            match verifier.parse(&p.payload) {
+                                Ok(command) => {
+                                    if tx.send(command).is_err() {
+                                        return;
+                                    }
+                                }
+                                Err(e) => log::warn!("remote: rejected command: {}", e),
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            log::warn!("remote: {}", e);
+                            thread::sleep(RECONNECT_DELAY);
+                        }
+                    }
+                }
+            });
+
+            Ok(MqttCommands {
+                client,
+                topic: topic.to_string(),
+                commands,
+            })
+        }
+
+        /// Waits up to `timeout` for a command
+        pub fn recv_timeout(&self, timeout: Duration) -> Option<Command> {
+            match self.commands.recv_timeout(timeout) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => {
+                    // Nothing more will come, behave like a plain sleep
+                    thread::sleep(timeout);
+                    None
+                }
+            }
+        }
+
+        /// Publishes the outcome of a command on `<topic>/reply`
+        pub fn reply(&self, result: &Result<Option<crate::Message>>) {
+            let payload = match crate::schema::to_json(&Reply::of(result)) {
+                Ok(p) => p,
+                Err(e) => {
+                    log::warn!("remote: {}", e);
+                    return;
+                }
+            };
+            let topic = format!("{}/reply", self.topic);
+            if let Err(e) = self.client.publish(topic, QoS::AtLeastOnce, false, payload) {
+                log::warn!("remote: reply: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn payload(timestamp: u64, nonce: &str) -> Vec<u8> {
+        format!(
+            r#"{{"token": "s3cret", "timestamp": {}, "nonce": "{}", "command": "sleep"}}"#,
+            timestamp, nonce
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn rejects_stale_and_future_commands() {
+        let mut verifier = Verifier::new("s3cret");
+        let max_age = MAX_AGE.as_secs();
+        assert!(verifier
+            .parse_at(&payload(NOW - max_age - 1, "a"), at(NOW))
+            .is_err());
+        assert!(verifier
+            .parse_at(&payload(NOW + max_age + 1, "b"), at(NOW))
+            .is_err());
+        assert_eq!(
+            verifier
+                .parse_at(&payload(NOW - max_age, "c"), at(NOW))
+                .unwrap(),
+            Command::Sleep
+        );
+        assert_eq!(
+            verifier
+                .parse_at(&payload(NOW + max_age, "d"), at(NOW))
+                .unwrap(),
+            Command::Sleep
+        );
+    }
+
+    #[test]
+    fn requires_timestamp_and_nonce() {
+        let mut verifier = Verifier::new("s3cret");
+        let without_timestamp = br#"{"token": "s3cret", "nonce": "a", "command": "sleep"}"#;
+        let without_nonce = format!(
+            r#"{{"token": "s3cret", "timestamp": {}, "command": "sleep"}}"#,
+            NOW
+        );
+        assert!(verifier.parse_at(without_timestamp, at(NOW)).is_err());
+        assert!(verifier
+            .parse_at(without_nonce.as_bytes(), at(NOW))
+            .is_err());
+        assert!(verifier.parse_at(&payload(NOW, ""), at(NOW)).is_err());
+        let long = "n".repeat(MAX_NONCE + 1);
+        assert!(verifier.parse_at(&payload(NOW, &long), at(NOW)).is_err());
+    }
+
+    #[test]
+    fn rejects_reused_nonces_while_fresh() {
+        let mut verifier = Verifier::new("s3cret");
+        assert!(verifier.parse_at(&payload(NOW, "a"), at(NOW)).is_ok());
+        assert!(verifier
+            .parse_at(&payload(NOW + 10, "a"), at(NOW + 10))
+            .is_err());
+        assert!(verifier
+            .parse_at(&payload(NOW + 10, "b"), at(NOW + 10))
+            .is_ok());
+        // Forgotten once a command with it would be stale anyway
+        let later = NOW + MAX_AGE.as_secs() + 1;
+        assert!(verifier.parse_at(&payload(later, "a"), at(later)).is_ok());
+        assert_eq!(verifier.seen.len(), 2);
+    }
+
+    #[test]
+    fn rejects_everything_without_a_token() {
+        let mut verifier = Verifier::new("");
+        let payload =
+            br#"{"token": "", "timestamp": 1700000000, "nonce": "a", "command": "sleep"}"#;
+        assert!(verifier.parse_at(payload, at(NOW)).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! High-level sampling strategies.
+
+use crate::history::History;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::{Error, Message, MicrogramsPerCubicMeter, Result, SDS011};
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Wakes the sensor, waits for it to warm up, averages several readings
+/// and puts it back to sleep
+///
+/// This is the recommended usage pattern for laser longevity. The sensor
+/// must be in continuous mode (work period 0) so that a new reading is
+/// available every second while it's awake.
+///
+/// # Example
+/// ```no_run
+/// use sds011::sampler::DutyCycleSampler;
+/// use sds011::SDS011;
+/// use std::time::Duration;
+///
+/// let mut sensor = SDS011::new("/dev/ttyUSB0").unwrap();
+/// sensor.set_work_period(0).unwrap();
+///
+/// let sampler = DutyCycleSampler::new().samples(10);
+/// loop {
+///     println!("{}", sampler.sample(&mut sensor).unwrap());
+///     std::thread::sleep(Duration::from_secs(5 * 60));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DutyCycleSampler {
+    warm_up: Duration,
+    samples: usize,
+    interval: Duration,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl Default for DutyCycleSampler {
+    fn default() -> Self {
+        DutyCycleSampler::new()
+    }
+}
+
+impl DutyCycleSampler {
+    /// Creates a sampler with 30 seconds warm-up and 5 readings one second apart
+    pub fn new() -> DutyCycleSampler {
+        DutyCycleSampler {
+            warm_up: Duration::from_secs(30),
+            samples: 5,
+            interval: Duration::from_secs(1),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Sets how long to wait after waking before reading
+    pub fn warm_up(mut self, warm_up: Duration) -> Self {
+        self.warm_up = warm_up;
+        self
+    }
+
+    /// Sets the number of readings to average, at least 1
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Sets the pause between readings
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Updates `metrics` with every reading and failure
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Runs one wake → warm up → average → sleep cycle
+    /// The sensor is put back to sleep even if reading fails
+    pub fn sample(&self, sensor: &mut SDS011) -> Result<Message> {
+        sensor.wake()?;
+        sleep(self.warm_up);
+
+        let result = self.average(sensor);
+        let slept = sensor.sleep();
+
+        let m = result?;
+        slept?;
+        Ok(m)
+    }
+
+    /// Like `sample()`, and adds the averaged reading to `history`
+    pub fn sample_into(&self, sensor: &mut SDS011, history: &mut History) -> Result<Message> {
+        let m = self.sample(sensor)?;
+        history.push(&m);
+        Ok(m)
+    }
+
+    fn average(&self, sensor: &mut SDS011) -> Result<Message> {
+        let mut readings = Vec::with_capacity(self.samples);
+        let mut last_error = None;
+
+        for i in 0..self.samples {
+            if i > 0 {
+                sleep(self.interval);
+            }
+            let result = sensor.query();
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &self.metrics {
+                metrics.record(&result);
+            }
+            match result {
+                Ok(m) => readings.push(m),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        let last = match readings.last() {
+            Some(m) => m.clone(),
+            None => return Err(last_error.unwrap_or(Error::EmptyDataFrame)),
+        };
+        let n = readings.len() as f32;
+
+        Ok(Message {
+            timestamp: last.timestamp,
+            pm25: readings
+                .iter()
+                .map(|m| m.pm25)
+                .sum::<MicrogramsPerCubicMeter>()
+                / n,
+            pm10: readings
+                .iter()
+                .map(|m| m.pm10)
+                .sum::<MicrogramsPerCubicMeter>()
+                / n,
+        })
+    }
+}
@@ -0,0 +1,130 @@
+//! Versioned JSON schema of records emitted by this crate.
+//!
+//! Every JSON or NDJSON record the crate produces, e.g. measurements,
+//! events and summaries, carries a top-level `schema_version`. Third-party
+//! formats like OpenAQ follow their own schema and don't.
+//!
+//! Compatibility policy:
+//! - fields may be added within a version, consumers must ignore unknown
+//!   fields;
+//! - removing, renaming or changing the type of a field bumps the version;
+//! - `upgrade()` converts records of every older version to the current
+//!   one, so readers of old files and old daemons keep working.
+//!
+//! Versions:
+//! - 0: records without `schema_version`, before it was introduced;
+//! - 1: same fields as 0 plus `schema_version`;
+//! - 2: timestamps are numbers of UNIX seconds instead of strings.
+
+use crate::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Current schema version
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// Record with its schema version, flattened into one JSON object
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Versioned<T> {
+    pub schema_version: u64,
+    #[serde(flatten)]
+    pub record: T,
+}
+
+impl<T> Versioned<T> {
+    /// Tags `record` with the current version
+    pub fn new(record: T) -> Versioned<T> {
+        Versioned {
+            schema_version: SCHEMA_VERSION,
+            record,
+        }
+    }
+}
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::ExportError(e.to_string())
+}
+
+/// Serializes `record` as one line of JSON with the current `schema_version`
+///
+/// # Example
+/// ```
+/// use sds011::schema;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.0), pm10: MicrogramsPerCubicMeter(8.0) };
+/// let json = schema::to_json(&m).unwrap();
+/// assert!(json.starts_with(r#"{"schema_version":2,"timestamp":0,"#));
+///
+/// let back: Message = schema::from_json(&json).unwrap();
+/// assert_eq!(back, m);
+///
+/// // Version 0 records had string timestamps
+/// let old: Message = schema::from_json(r#"{"timestamp":"0","pm25":4.0,"pm10":8.0}"#).unwrap();
+/// assert_eq!(old, m);
+/// ```
+pub fn to_json<T: Serialize>(record: &T) -> Result<String> {
+    serde_json::to_string(&Versioned::new(record)).map_err(err)
+}
+
+/// Parses a record of any supported version, upgrading it first
+pub fn from_json<T: DeserializeOwned>(text: &str) -> Result<T> {
+    let value = serde_json::from_str(text).map_err(err)?;
+    let mut value = upgrade(value)?;
+    if let Value::Object(map) = &mut value {
+        map.remove("schema_version");
+    }
+    serde_json::from_value(value).map_err(err)
+}
+
+/// Converts a record of an older schema version to the current one
+/// Returns an error for versions newer than this crate knows
+pub fn upgrade(mut value: Value) -> Result<Value> {
+    let map = value
+        .as_object()
+        .ok_or_else(|| err("record is not a JSON object"))?;
+    let version = match map.get("schema_version") {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| err("schema_version is not a number"))?,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(err(format!(
+            "schema_version {} is newer than supported {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+
+    // 0 -> 1: only the version field was added
+    if version < 2 {
+        numeric_timestamps(&mut value);
+    }
+    if let Value::Object(map) = &mut value {
+        map.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+    }
+    Ok(value)
+}
+
+/// 1 -> 2: converts `timestamp` strings of UNIX seconds to numbers,
+/// including those of nested records like events' measurements
+fn numeric_timestamps(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                match v {
+                    Value::String(s) if key == "timestamp" => {
+                        if let Ok(secs) = s.parse::<u64>() {
+                            *v = secs.into();
+                        }
+                    }
+                    _ => numeric_timestamps(v),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(numeric_timestamps),
+        _ => {}
+    }
+}
@@ -0,0 +1,162 @@
+//! User scripts transforming measurements and raising alerts.
+//!
+//! Scripts are written in [Rhai](https://rhai.rs) and may define two
+//! functions, both taking a measurement as a map with `timestamp` (UNIX
+//! seconds), `pm25` and `pm10` keys:
+//!
+//! - `transform(m)` returns the, possibly modified, map or `()` to drop
+//!   the measurement;
+//! - `alert(m)` returns an alert text or `()`, it sees the transformed
+//!   measurement.
+//!
+//! ```text
+//! fn transform(m) {
+//!     if m.pm10 > 999.0 { return (); }
+//!     m.pm25 = m.pm25 * 0.9;
+//!     m
+//! }
+//!
+//! fn alert(m) {
+//!     if m.pm25 > 35.0 { `PM2.5 is ${m.pm25}` }
+//! }
+//! ```
+
+use crate::{Error, Message, MicrogramsPerCubicMeter, Result};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+/// Upper bound of operations per call, so a runaway script can't hang
+/// the daemon
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Result of running a script on a measurement
+#[derive(Debug, PartialEq, Clone)]
+pub struct Outcome {
+    /// Transformed measurement, `None` if the script dropped it
+    pub message: Option<Message>,
+    /// Alert raised by the script
+    pub alert: Option<String>,
+}
+
+/// Compiled script
+///
+/// # Example
+/// ```
+/// use sds011::script::Script;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let script = Script::compile(r#"
+///     fn transform(m) { m.pm25 = m.pm25 * 2.0; m }
+///     fn alert(m) { if m.pm25 > 10.0 { "high" } }
+/// "#).unwrap();
+///
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(6.0), pm10: MicrogramsPerCubicMeter(8.0) };
+/// let outcome = script.run(&m).unwrap();
+/// assert_eq!(outcome.message.unwrap().pm25.value(), 12.0);
+/// assert_eq!(outcome.alert.as_deref(), Some("high"));
+/// ```
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    transform: bool,
+    alert: bool,
+}
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::ScriptError(e.to_string())
+}
+
+fn to_map(m: &Message) -> Map {
+    let mut map = Map::new();
+    let secs = m.timestamp_secs().unwrap_or(0) as rhai::INT;
+    map.insert("timestamp".into(), Dynamic::from_int(secs));
+    map.insert("pm25".into(), Dynamic::from_float(m.pm25.value() as f64));
+    map.insert("pm10".into(), Dynamic::from_float(m.pm10.value() as f64));
+    map
+}
+
+fn number(map: &Map, key: &str) -> Result<f32> {
+    let value = map
+        .get(key)
+        .ok_or_else(|| err(format!("transform() result has no `{}`", key)))?;
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|i| i as f64))
+        .map(|v| v as f32)
+        .map_err(|t| err(format!("`{}` must be a number, got {}", key, t)))
+}
+
+fn from_map(map: &Map) -> Result<Message> {
+    let timestamp = match map.get("timestamp").map(Dynamic::as_int) {
+        Some(Ok(secs)) if secs >= 0 => crate::time::from_unix(secs as u64)
+            .ok_or_else(|| err(format!("`timestamp` {} is out of range", secs)))?,
+        Some(_) => return Err(err("`timestamp` must be UNIX seconds")),
+        None => return Err(err("transform() result has no `timestamp`")),
+    };
+    Ok(Message {
+        timestamp,
+        pm25: MicrogramsPerCubicMeter(number(map, "pm25")?),
+        pm10: MicrogramsPerCubicMeter(number(map, "pm10")?),
+    })
+}
+
+impl Script {
+    /// Compiles a script
+    pub fn compile(source: &str) -> Result<Script> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine.compile(source).map_err(err)?;
+
+        let defines = |name: &str| ast.iter_functions().any(|f| f.name == name);
+        let (transform, alert) = (defines("transform"), defines("alert"));
+        Ok(Script {
+            engine,
+            ast,
+            transform,
+            alert,
+        })
+    }
+
+    /// Reads and compiles a script file
+    pub fn load(path: &str) -> Result<Script> {
+        let source = std::fs::read_to_string(path).map_err(|e| err(format!("{}: {}", path, e)))?;
+        Script::compile(&source).map_err(|e| err(format!("{}: {}", path, e)))
+    }
+
+    fn call(&self, name: &str, m: &Message) -> Result<Dynamic> {
+        self.engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, (to_map(m),))
+            .map_err(err)
+    }
+
+    /// Runs `transform()` and then `alert()` on a measurement
+    pub fn run(&self, m: &Message) -> Result<Outcome> {
+        let message = if self.transform {
+            let result = self.call("transform", m)?;
+            if result.is_unit() {
+                None
+            } else {
+                let map = result
+                    .try_cast::<Map>()
+                    .ok_or_else(|| err("transform() must return a map or ()"))?;
+                Some(from_map(&map)?)
+            }
+        } else {
+            Some(m.clone())
+        };
+
+        let alert = match (&message, self.alert) {
+            (Some(m), true) => {
+                let result = self.call("alert", m)?;
+                if result.is_unit() {
+                    None
+                } else {
+                    Some(result.to_string())
+                }
+            }
+            _ => None,
+        };
+
+        Ok(Outcome { message, alert })
+    }
+}
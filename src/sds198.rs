@@ -0,0 +1,155 @@
+//! Driver for the SDS198, Nova Fitness' PM100 sensor.
+//!
+//! It takes the SDS011's commands over the same transports, but its
+//! readings are a single PM100 value in whole µg/m³, sent in frames with
+//! their own ID. Its readings aren't a `Message`, so it doesn't implement
+//! `sensor::ParticulateSensor`.
+
+use crate::transport::{self, Transport};
+use crate::units::MicrogramsPerCubicMeter;
+use crate::{
+    checksum, command_frame, hexdump, time, Error, Firmware, Result, FIRMWARE_CMD, HEAD, PASSIVE,
+    QUERY_CMD, READ, REPLY_ID, REPORT_MODE_CMD, SLEEP, SLEEP_CMD, WORK, WORK_PERIOD_CMD, WRITE,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// ID of the SDS198's data frames, the SDS011's are `c0`
+const DATA_ID: u8 = b'\xcf';
+/// Frames read waiting for a command's reply
+const MAX_SKIPPED: usize = 5;
+
+/// A PM100 reading
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Pm100Reading {
+    /// When the measurement was taken, serialized as UNIX seconds
+    #[cfg_attr(feature = "serde", serde(with = "time::unix_secs"))]
+    pub timestamp: SystemTime,
+    /// Particles up to 100 µm
+    pub pm100: MicrogramsPerCubicMeter,
+}
+
+/// An SDS198 on a serial port or the network
+///
+/// # Example
+/// ```no_run
+/// use sds011::sds198::SDS198;
+///
+/// let mut sensor = SDS198::open("/dev/ttyUSB0").unwrap();
+/// let reading = sensor.query().unwrap();
+/// println!("PM100 {} µg/m³", reading.pm100.value());
+/// sensor.sleep().unwrap();
+/// ```
+pub struct SDS198 {
+    port: Box<dyn Transport>,
+}
+
+impl SDS198 {
+    /// Opens a serial port or, for `tcp://host:port` and `rfc2217://host:port`,
+    /// a network connection to a sensor
+    pub fn open(target: &str) -> Result<SDS198> {
+        SDS198::from_transport(transport::open(target, transport::DEFAULT_TIMEOUT)?)
+    }
+
+    /// Drives the sensor on an already open transport, switching it to
+    /// query mode
+    pub fn from_transport(port: Box<dyn Transport>) -> Result<SDS198> {
+        let mut s = SDS198 { port };
+        s.execute(&command_frame(REPORT_MODE_CMD, &[WRITE, PASSIVE]))?;
+        s.get_command_reply(REPORT_MODE_CMD)?;
+        Ok(s)
+    }
+
+    /// Reads the PM100 concentration
+    pub fn query(&mut self) -> Result<Pm100Reading> {
+        self.execute(&command_frame(QUERY_CMD, &[]))?;
+        for _ in 0..MAX_SKIPPED {
+            let raw = self.get_reply()?;
+            if raw[1] == DATA_ID {
+                return Ok(Pm100Reading {
+                    timestamp: time::now(),
+                    pm100: MicrogramsPerCubicMeter(u16::from_le_bytes([raw[4], raw[5]]) as f32),
+                });
+            }
+        }
+        Err(Error::ReadError("no reading".to_string()))
+    }
+
+    /// Returns the sensor's device ID
+    pub fn device_id(&mut self) -> Result<u16> {
+        self.execute(&command_frame(REPORT_MODE_CMD, &[READ]))?;
+        let raw = self.get_command_reply(REPORT_MODE_CMD)?;
+        Ok(u16::from_be_bytes([raw[6], raw[7]]))
+    }
+
+    /// Reads the firmware version
+    pub fn firmware_version(&mut self) -> Result<Firmware> {
+        self.execute(&command_frame(FIRMWARE_CMD, &[]))?;
+        let raw = self.get_command_reply(FIRMWARE_CMD)?;
+        Ok(Firmware {
+            year: raw[3],
+            month: raw[4],
+            day: raw[5],
+        })
+    }
+
+    /// Puts the sensor to sleep, stopping the laser and the fan
+    pub fn sleep(&mut self) -> Result<()> {
+        self.execute(&command_frame(SLEEP_CMD, &[WRITE, SLEEP]))?;
+        self.get_command_reply(SLEEP_CMD)?;
+        Ok(())
+    }
+
+    /// Wakes the sensor up
+    pub fn wake(&mut self) -> Result<()> {
+        self.execute(&command_frame(SLEEP_CMD, &[WRITE, WORK]))?;
+        self.get_command_reply(SLEEP_CMD)?;
+        Ok(())
+    }
+
+    /// Sets the work period, 0 to 30 minutes, 0 is continuous
+    pub fn set_work_period(&mut self, minutes: u8) -> Result<()> {
+        if minutes > 30 {
+            return Err(Error::TooLongWorkTime);
+        }
+        self.execute(&command_frame(WORK_PERIOD_CMD, &[WRITE, minutes]))?;
+        self.get_command_reply(WORK_PERIOD_CMD)?;
+        Ok(())
+    }
+
+    fn execute(&mut self, frame: &[u8]) -> Result<()> {
+        log::trace!("> {}", hexdump(frame));
+        self.port.write_all(frame)?;
+        Ok(())
+    }
+
+    /// Reads the reply to `command`, skipping readings sent in active mode
+    fn get_command_reply(&mut self, command: u8) -> Result<[u8; 10]> {
+        for _ in 0..MAX_SKIPPED {
+            let raw = self.get_reply()?;
+            if raw[1] == REPLY_ID && raw[2] == command {
+                return Ok(raw);
+            }
+        }
+        Err(Error::ReadError(format!(
+            "no reply to command {:02x}",
+            command
+        )))
+    }
+
+    fn get_reply(&mut self) -> Result<[u8; 10]> {
+        let mut buf = [0u8; 10];
+        self.port.read_exact(&mut buf)?;
+        log::trace!("< {}", hexdump(&buf));
+        if buf[0] != HEAD {
+            return Err(Error::ReadError("misaligned frame".to_string()));
+        }
+        if checksum(&buf[2..8]) != buf[8] {
+            log::warn!("bad checksum in {}", hexdump(&buf));
+            return Err(Error::BadChecksum);
+        }
+        Ok(buf)
+    }
+}
@@ -0,0 +1,157 @@
+//! What applications need from a dust sensor, independent of the model.
+//!
+//! Code written against `ParticulateSensor` works with an `SDS011`, a
+//! `shared::SharedSensor`, other dust sensors or a mock in tests.
+
+use crate::shared::SharedSensor;
+use crate::{Message, Result, SDS011};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Nova Fitness sensors speaking the SDS011's protocol
+///
+/// The SDS018 and the smaller SDS021 use the same frames and commands;
+/// `SDS011` drives all three, the model only names the sensor, e.g. in
+/// `identity()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Model {
+    #[default]
+    Sds011,
+    Sds018,
+    Sds021,
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Model::Sds011 => "SDS011",
+            Model::Sds018 => "SDS018",
+            Model::Sds021 => "SDS021",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Which sensor is on the other end
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Identity {
+    /// Model name, e.g. `SDS011`
+    pub model: String,
+    /// Device ID or serial number, e.g. `a160`
+    pub id: String,
+    /// Firmware version, if the sensor reports one
+    pub firmware: Option<String>,
+}
+
+/// A sensor measuring PM2.5 and PM10
+///
+/// # Example
+/// ```
+/// use sds011::emulator::Emulator;
+/// use sds011::sensor::ParticulateSensor;
+/// use sds011::SDS011;
+///
+/// /// Takes a reading and lets the sensor rest, whatever the sensor
+/// fn sample(sensor: &mut dyn ParticulateSensor) -> sds011::Result<f32> {
+///     sensor.wake()?;
+///     let reading = sensor.query()?;
+///     sensor.sleep()?;
+///     Ok(reading.pm25.value())
+/// }
+///
+/// let mut emulator = Emulator::new(0xa160);
+/// emulator.set_reading(12.3, 45.6);
+/// let mut sensor = SDS011::from_transport(Box::new(emulator)).unwrap();
+/// assert_eq!(sample(&mut sensor).unwrap(), 12.3);
+/// assert_eq!(sensor.identity().unwrap().id, "a160");
+/// ```
+pub trait ParticulateSensor {
+    /// Takes a reading
+    fn query(&mut self) -> Result<Message>;
+
+    /// Stops the fan and the laser, or whatever wears out
+    fn sleep(&mut self) -> Result<()>;
+
+    /// Starts measuring again
+    fn wake(&mut self) -> Result<()>;
+
+    /// Makes the sensor measure every `minutes`, 0 is continuous
+    fn set_work_period(&mut self, minutes: u8) -> Result<()>;
+
+    /// Model, ID and firmware of the sensor
+    fn identity(&mut self) -> Result<Identity>;
+}
+
+impl ParticulateSensor for SDS011 {
+    fn query(&mut self) -> Result<Message> {
+        SDS011::query(self)
+    }
+
+    fn sleep(&mut self) -> Result<()> {
+        SDS011::sleep(self)
+    }
+
+    fn wake(&mut self) -> Result<()> {
+        SDS011::wake(self)
+    }
+
+    fn set_work_period(&mut self, minutes: u8) -> Result<()> {
+        SDS011::set_work_period(self, minutes)
+    }
+
+    fn identity(&mut self) -> Result<Identity> {
+        Ok(Identity {
+            model: self.model().to_string(),
+            id: format!("{:04x}", self.device_id()?),
+            firmware: Some(self.firmware_version()?.to_string()),
+        })
+    }
+}
+
+impl ParticulateSensor for SharedSensor {
+    fn query(&mut self) -> Result<Message> {
+        SharedSensor::query(self)
+    }
+
+    fn sleep(&mut self) -> Result<()> {
+        SharedSensor::sleep(self)
+    }
+
+    fn wake(&mut self) -> Result<()> {
+        SharedSensor::wake(self)
+    }
+
+    fn set_work_period(&mut self, minutes: u8) -> Result<()> {
+        SharedSensor::set_work_period(self, minutes)
+    }
+
+    fn identity(&mut self) -> Result<Identity> {
+        self.exclusive(|sensor| sensor.identity())
+    }
+}
+
+impl<S: ParticulateSensor + ?Sized> ParticulateSensor for Box<S> {
+    fn query(&mut self) -> Result<Message> {
+        (**self).query()
+    }
+
+    fn sleep(&mut self) -> Result<()> {
+        (**self).sleep()
+    }
+
+    fn wake(&mut self) -> Result<()> {
+        (**self).wake()
+    }
+
+    fn set_work_period(&mut self, minutes: u8) -> Result<()> {
+        (**self).set_work_period(minutes)
+    }
+
+    fn identity(&mut self) -> Result<Identity> {
+        (**self).identity()
+    }
+}
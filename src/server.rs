@@ -0,0 +1,511 @@
+//! Embedded HTTP server with the latest readings.
+//!
+//! The server subscribes to an `EventBus` and keeps the latest reading, a
+//! `History` and counters, so dashboards and scripts can poll the sensor
+//! over the LAN without talking to the serial port. Every response is JSON
+//! with a `schema_version`:
+//! - `GET /reading`: the latest reading, with the port it came from;
+//! - `GET /history?since=<UNIX seconds>&limit=<n>`: readings, oldest first;
+//! - `GET /status`: uptime, counters, sensors and sink health;
+//! - `GET /healthz`: `200` if every sink is healthy, `503` otherwise;
+//! - `GET /devices?location=<name>`, `GET /devices/<hex ID>`: the device
+//!   directory, if one is set;
+//! - `GET /ws`: a WebSocket pushing every new reading, formatted like
+//!   `/reading`, as a text frame, starting with the latest one.
+
+use crate::directory::{Device, Directory};
+use crate::events::{Event, EventBus};
+use crate::history::History;
+use crate::schema::{self, Versioned};
+use crate::sink::{breaker, Health, SinkSet};
+use crate::{Error, Message, Result};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+use tiny_http::{Header, Method, Request, Response};
+use tungstenite::protocol::{Role, WebSocket};
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::ServerError(e.to_string())
+}
+
+/// Readings kept for `/history` by default, a day of one per minute
+const HISTORY: usize = 1440;
+
+/// What the server knows, updated from events
+struct State {
+    latest: Option<(String, Message)>,
+    history: History,
+    readings: u64,
+    errors: u64,
+    last_error: Option<String>,
+    /// Device IDs by port
+    sensors: BTreeMap<String, Option<u16>>,
+    /// Writers of connected WebSocket clients
+    clients: Vec<Sender<String>>,
+}
+
+impl State {
+    fn record(&mut self, event: &Event) {
+        match event {
+            Event::Measurement { port, message } => {
+                self.history.push(message);
+                self.latest = Some((port.clone(), message.clone()));
+                self.readings += 1;
+                self.sensors.entry(port.clone()).or_insert(None);
+                if !self.clients.is_empty() {
+                    if let Ok(json) = reading_json(port, message) {
+                        // Clients that went away are dropped here
+                        self.clients.retain(|c| c.send(json.clone()).is_ok());
+                    }
+                }
+            }
+            Event::SensorError { port, error } => {
+                self.errors += 1;
+                self.last_error = Some(format!("{}: {}", port, error));
+            }
+            Event::SensorAttached { port, device_id } => {
+                self.sensors.insert(port.clone(), *device_id);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// HTTP server fed by an event bus
+///
+/// # Example
+/// ```
+/// use sds011::events::{Event, EventBus};
+/// use sds011::server::Server;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+/// use std::time::UNIX_EPOCH;
+///
+/// let bus = EventBus::new();
+/// let server = Server::bind("127.0.0.1:0").unwrap();
+/// let addr = server.local_addr().unwrap();
+/// server.spawn(&bus);
+///
+/// let message = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) };
+/// bus.publish(Event::Measurement { port: "/dev/ttyUSB0".to_string(), message });
+/// # std::thread::sleep(std::time::Duration::from_millis(100));
+///
+/// let mut stream = TcpStream::connect(addr).unwrap();
+/// stream.write_all(b"GET /reading HTTP/1.0\r\n\r\n").unwrap();
+/// let mut response = String::new();
+/// stream.read_to_string(&mut response).unwrap();
+/// assert!(response.starts_with("HTTP/1.0 200"));
+/// assert!(response.ends_with(r#"{"schema_version":2,"timestamp":0,"pm25":4.5,"pm10":8.0,"port":"/dev/ttyUSB0"}"#));
+/// ```
+pub struct Server {
+    http: tiny_http::Server,
+    state: Arc<Mutex<State>>,
+    directory: Option<Arc<dyn Directory>>,
+    sinks: Vec<Arc<SinkSet>>,
+    started: Instant,
+}
+
+impl Server {
+    /// Listens on `addr`, e.g. `0.0.0.0:8080`
+    pub fn bind(addr: &str) -> Result<Server> {
+        let http = tiny_http::Server::http(addr).map_err(|e| err(format!("{}: {}", addr, e)))?;
+        Ok(Server {
+            http,
+            state: Arc::new(Mutex::new(State {
+                latest: None,
+                history: History::new(HISTORY),
+                readings: 0,
+                errors: 0,
+                last_error: None,
+                sensors: BTreeMap::new(),
+                clients: Vec::new(),
+            })),
+            directory: None,
+            sinks: Vec::new(),
+            started: Instant::now(),
+        })
+    }
+
+    /// Keeps readings for `/history` in `history`, the last 1440 by default
+    pub fn history(self, history: History) -> Server {
+        lock(&self.state).history = history;
+        self
+    }
+
+    /// Serves `directory` on `/devices`
+    pub fn directory(mut self, directory: Arc<dyn Directory>) -> Server {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Reports the health of `sinks` on `/status` and `/healthz`
+    /// Called again, e.g. with the sinks of another sensor, adds to them
+    pub fn sinks(mut self, sinks: Arc<SinkSet>) -> Server {
+        self.sinks.push(sinks);
+        self
+    }
+
+    /// Address the server listens on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Serves requests in a background thread, with data from events
+    /// published on `bus` from now on
+    pub fn spawn(self, bus: &EventBus) -> thread::JoinHandle<()> {
+        let events = bus.subscribe();
+        let state = Arc::clone(&self.state);
+        thread::spawn(move || {
+            for event in events {
+                lock(&state).record(&event);
+            }
+        });
+
+        thread::spawn(move || {
+            for request in self.http.incoming_requests() {
+                self.respond(request);
+            }
+        })
+    }
+
+    fn respond(&self, request: Request) {
+        let (path, query) = match request.url().split_once('?') {
+            Some((path, query)) => (path.to_string(), parse_query(query)),
+            None => (request.url().to_string(), BTreeMap::new()),
+        };
+        if path == "/ws" && *request.method() == Method::Get {
+            return self.websocket(request);
+        }
+        let (code, body) = if *request.method() != Method::Get {
+            (405, error("method not allowed"))
+        } else {
+            self.route(&path, &query)
+        };
+
+        let response = Response::from_string(body)
+            .with_status_code(code)
+            .with_header(json_header());
+        if let Err(e) = request.respond(response) {
+            log::warn!("server: {}", e);
+        }
+    }
+
+    fn route(&self, path: &str, query: &BTreeMap<String, String>) -> (u16, String) {
+        match path.trim_end_matches('/') {
+            "/reading" => self.reading(),
+            "/history" => self.history_page(query),
+            "/status" => self.status(),
+            "/healthz" => self.healthz(),
+            "/devices" => self.devices(query.get("location")),
+            p => match p.strip_prefix("/devices/") {
+                Some(id) => self.device(id),
+                None => (404, error("not found")),
+            },
+        }
+    }
+
+    /// Upgrades `request` to a WebSocket and streams readings to it from a
+    /// thread of its own
+    fn websocket(&self, request: Request) {
+        let header = |name: &'static str| {
+            request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv(name))
+                .map(|h| h.value.as_str().to_string())
+        };
+        let upgrade = header("Upgrade").unwrap_or_default();
+        let key = match header("Sec-WebSocket-Key") {
+            Some(key) if upgrade.eq_ignore_ascii_case("websocket") => key,
+            _ => {
+                let response = Response::from_string(error("expected a WebSocket upgrade"))
+                    .with_status_code(400)
+                    .with_header(json_header());
+                if let Err(e) = request.respond(response) {
+                    log::warn!("server: {}", e);
+                }
+                return;
+            }
+        };
+
+        let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+        // A base64 digest is valid ASCII, so this can't fail
+        let accept = Header::from_bytes(&b"Sec-WebSocket-Accept"[..], accept.as_bytes()).unwrap();
+        let stream = request.upgrade("websocket", Response::empty(101).with_header(accept));
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+        let (tx, frames) = channel();
+        {
+            let mut state = lock(&self.state);
+            if let Some((port, m)) = &state.latest {
+                if let Ok(json) = reading_json(port, m) {
+                    let _ = tx.send(json);
+                }
+            }
+            state.clients.push(tx);
+        }
+        thread::spawn(move || {
+            for json in frames {
+                if socket.send(tungstenite::Message::text(json)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    fn reading(&self) -> (u16, String) {
+        match &lock(&self.state).latest {
+            Some((port, m)) => match reading_json(port, m) {
+                Ok(json) => (200, json),
+                Err(e) => (500, error(&e.to_string())),
+            },
+            None => (404, error("no reading yet")),
+        }
+    }
+
+    fn history_page(&self, query: &BTreeMap<String, String>) -> (u16, String) {
+        let number = |key: &str| query.get(key).map(|v| v.parse::<u64>());
+        let since = match number("since") {
+            Some(Ok(t)) => t,
+            Some(Err(_)) => return (400, error("since: expected UNIX seconds")),
+            None => 0,
+        };
+        let limit = match number("limit") {
+            Some(Ok(n)) => n as usize,
+            Some(Err(_)) => return (400, error("limit: expected a number")),
+            None => usize::MAX,
+        };
+
+        let state = lock(&self.state);
+        let readings: Vec<&Message> = state.history.since(since).collect();
+        let skip = readings.len().saturating_sub(limit);
+        (
+            200,
+            versioned(Readings {
+                readings: &readings[skip..],
+            }),
+        )
+    }
+
+    fn sink_health(&self) -> Vec<SinkStatus> {
+        self.sinks
+            .iter()
+            .flat_map(|s| s.health())
+            .map(|s| {
+                let (error, degraded_seconds) = match s.health {
+                    Health::Healthy => (None, None),
+                    Health::Degraded { error, since } => {
+                        (Some(error), Some(since.elapsed().as_secs()))
+                    }
+                };
+                SinkStatus {
+                    name: s.name,
+                    healthy: error.is_none(),
+                    error,
+                    degraded_seconds,
+                    breaker: s.breaker,
+                }
+            })
+            .collect()
+    }
+
+    fn status(&self) -> (u16, String) {
+        let state = lock(&self.state);
+        let status = Status {
+            uptime_seconds: self.started.elapsed().as_secs(),
+            readings: state.readings,
+            errors: state.errors,
+            last_error: state.last_error.as_deref(),
+            last_reading: state.latest.as_ref().and_then(|(_, m)| m.timestamp_secs()),
+            sensors: state
+                .sensors
+                .iter()
+                .map(|(port, id)| SensorStatus {
+                    port,
+                    device_id: id.map(|id| format!("{:04x}", id)),
+                })
+                .collect(),
+            sinks: self.sink_health(),
+        };
+        (200, versioned(status))
+    }
+
+    fn healthz(&self) -> (u16, String) {
+        let degraded = self.sinks.iter().any(|s| s.is_degraded());
+        let body = Healthz {
+            status: if degraded { "degraded" } else { "ok" },
+            sinks: self.sink_health(),
+        };
+        (if degraded { 503 } else { 200 }, versioned(body))
+    }
+
+    fn devices(&self, location: Option<&String>) -> (u16, String) {
+        let directory = match &self.directory {
+            Some(d) => d,
+            None => return (404, error("no device directory")),
+        };
+        let devices = match location {
+            Some(location) => directory
+                .find(location)
+                .into_iter()
+                .filter_map(|id| directory.lookup(id).map(|d| Entry::new(id, d)))
+                .collect(),
+            None => directory
+                .devices()
+                .into_iter()
+                .map(|(id, d)| Entry::new(id, d))
+                .collect(),
+        };
+        (200, versioned(Devices { devices }))
+    }
+
+    fn device(&self, id: &str) -> (u16, String) {
+        let directory = match &self.directory {
+            Some(d) => d,
+            None => return (404, error("no device directory")),
+        };
+        let id = match u16::from_str_radix(id, 16) {
+            Ok(id) => id,
+            Err(_) => return (400, error("expected a hex device ID")),
+        };
+        match directory.lookup(id) {
+            Some(d) => (200, versioned(Entry::new(id, d))),
+            None => (404, error("no such device")),
+        }
+    }
+}
+
+/// JSON of a reading with the port it came from
+fn reading_json(port: &str, m: &Message) -> Result<String> {
+    let mut fields = Map::new();
+    fields.insert("port".to_string(), Value::from(port));
+    Ok(crate::sink::with_fields(schema::to_json(m)?, &fields))
+}
+
+/// Body of `/history`
+#[derive(Serialize)]
+struct Readings<'a> {
+    readings: &'a [&'a Message],
+}
+
+/// Health of a sink on `/status` and `/healthz`
+#[derive(Serialize)]
+struct SinkStatus {
+    name: String,
+    healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    degraded_seconds: Option<u64>,
+    breaker: breaker::Stats,
+}
+
+#[derive(Serialize)]
+struct SensorStatus<'a> {
+    port: &'a str,
+    device_id: Option<String>,
+}
+
+/// Body of `/status`
+#[derive(Serialize)]
+struct Status<'a> {
+    uptime_seconds: u64,
+    readings: u64,
+    errors: u64,
+    last_error: Option<&'a str>,
+    last_reading: Option<u64>,
+    sensors: Vec<SensorStatus<'a>>,
+    sinks: Vec<SinkStatus>,
+}
+
+/// Body of `/healthz`
+#[derive(Serialize)]
+struct Healthz {
+    status: &'static str,
+    sinks: Vec<SinkStatus>,
+}
+
+/// Directory entry with its hex ID
+#[derive(Serialize)]
+struct Entry {
+    device_id: String,
+    #[serde(flatten)]
+    device: Device,
+}
+
+impl Entry {
+    fn new(id: u16, device: Device) -> Entry {
+        Entry {
+            device_id: format!("{:04x}", id),
+            device,
+        }
+    }
+}
+
+/// Body of `/devices`
+#[derive(Serialize)]
+struct Devices {
+    devices: Vec<Entry>,
+}
+
+/// JSON of `record` with the schema version
+fn versioned<T: Serialize>(record: T) -> String {
+    serde_json::to_string(&Versioned::new(record)).unwrap_or_default()
+}
+
+fn error(message: &str) -> String {
+    versioned(json!({ "error": message }))
+}
+
+fn json_header() -> Header {
+    // Both parts are valid ASCII, so this can't fail
+    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
+}
+
+/// Splits `a=1&b=2` into decoded pairs
+fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (decode(k), decode(v)))
+        .collect()
+}
+
+/// Decodes `%XX` escapes and `+` as a space
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        decoded.push(b);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    match state.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
@@ -0,0 +1,92 @@
+//! Sensor handle shared between threads.
+//!
+//! The SDS011 answers one command at a time over a single serial line, so
+//! concurrent users have to take turns. `SharedSensor` serializes whole
+//! request/reply exchanges behind a mutex. Build with `--cfg loom` to
+//! model check it with loom, see `tests/loom.rs`.
+
+use crate::{Message, Result, SDS011};
+
+#[cfg(loom)]
+use loom::sync::{Arc, Mutex};
+#[cfg(not(loom))]
+use std::sync::{Arc, Mutex};
+
+/// Cloneable handle to a sensor used from several threads
+///
+/// Guarantees:
+/// - each operation writes its command and reads its reply while holding
+///   the lock, so frames of different operations never interleave and a
+///   reply always goes to the caller that sent the command;
+/// - operations issued by one thread run in program order;
+/// - operations from different threads run one after another in the order
+///   they acquire the lock, which is not necessarily the order they were
+///   called in.
+///
+/// If a closure passed to `exclusive()` panics, the handle stays usable
+/// but a reply of the interrupted exchange may still be in flight, so the
+/// next operation can fail with `Error::BadChecksum`.
+///
+/// # Example
+/// ```
+/// use sds011::emulator::Emulator;
+/// use sds011::shared::SharedSensor;
+/// use sds011::SDS011;
+/// use std::thread;
+///
+/// let sensor = SDS011::from_transport(Box::new(Emulator::new(0xa160))).unwrap();
+/// let shared = SharedSensor::new(sensor);
+///
+/// let poller = shared.clone();
+/// let t = thread::spawn(move || poller.query().map(|m| m.pm25));
+/// assert_eq!(shared.device_id().unwrap(), 0xa160);
+/// assert!(t.join().unwrap().is_ok());
+/// ```
+#[derive(Clone)]
+pub struct SharedSensor {
+    inner: Arc<Mutex<SDS011>>,
+}
+
+impl SharedSensor {
+    /// Shares `sensor` between threads
+    pub fn new(sensor: SDS011) -> SharedSensor {
+        SharedSensor {
+            inner: Arc::new(Mutex::new(sensor)),
+        }
+    }
+
+    /// Runs `f` with exclusive access to the sensor, for sequences of
+    /// operations that must not be interleaved with other threads
+    pub fn exclusive<T, F: FnOnce(&mut SDS011) -> T>(&self, f: F) -> T {
+        let mut sensor = match self.inner.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        f(&mut sensor)
+    }
+
+    /// See `SDS011::query()`
+    pub fn query(&self) -> Result<Message> {
+        self.exclusive(|s| s.query())
+    }
+
+    /// See `SDS011::device_id()`
+    pub fn device_id(&self) -> Result<u16> {
+        self.exclusive(|s| s.device_id())
+    }
+
+    /// See `SDS011::set_work_period()`
+    pub fn set_work_period(&self, work_time: u8) -> Result<()> {
+        self.exclusive(|s| s.set_work_period(work_time))
+    }
+
+    /// See `SDS011::sleep()`
+    pub fn sleep(&self) -> Result<()> {
+        self.exclusive(|s| s.sleep())
+    }
+
+    /// See `SDS011::wake()`
+    pub fn wake(&self) -> Result<()> {
+        self.exclusive(|s| s.wake())
+    }
+}
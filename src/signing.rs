@@ -0,0 +1,100 @@
+//! Ed25519 signing of exported records.
+//!
+//! Community aggregation projects can verify that a record came from a
+//! registered station and wasn't modified on the way. The signature covers
+//! the JSON serialization of the measurement, without `schema_version`,
+//! so records wrapped with `schema::to_json()` still verify.
+
+use crate::{Error, Message, Result};
+use ed25519_dalek::{Signature, Signer as _, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+/// Measurement with its signature and the key to verify it
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SignedRecord {
+    #[serde(flatten)]
+    pub message: Message,
+    /// Hex encoded Ed25519 public key
+    pub public_key: String,
+    /// Hex encoded Ed25519 signature
+    pub signature: String,
+}
+
+/// Signs measurements with a station's secret key
+///
+/// # Example
+/// ```
+/// use sds011::signing::Signer;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let signer = Signer::from_hex(&"11".repeat(32)).unwrap();
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.0), pm10: MicrogramsPerCubicMeter(8.0) };
+///
+/// let record = signer.sign(&m).unwrap();
+/// assert!(record.verify().is_ok());
+/// ```
+pub struct Signer {
+    key: SigningKey,
+}
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SignatureError(e.to_string())
+}
+
+/// Bytes covered by the signature
+fn signed_bytes(m: &Message) -> Result<Vec<u8>> {
+    serde_json::to_vec(m).map_err(err)
+}
+
+impl Signer {
+    /// Creates a signer from a 32 byte secret key
+    pub fn from_bytes(secret: &[u8; 32]) -> Signer {
+        Signer {
+            key: SigningKey::from_bytes(secret),
+        }
+    }
+
+    /// Creates a signer from a hex encoded 32 byte secret key
+    pub fn from_hex(secret: &str) -> Result<Signer> {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(secret.trim(), &mut bytes).map_err(err)?;
+        Ok(Signer::from_bytes(&bytes))
+    }
+
+    /// Reads a hex encoded secret key from a file
+    pub fn load(path: &str) -> Result<Signer> {
+        let text = std::fs::read_to_string(path).map_err(|e| err(format!("{}: {}", path, e)))?;
+        Signer::from_hex(&text)
+    }
+
+    /// Hex encoded public key to register with an aggregation project
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key.verifying_key().as_bytes())
+    }
+
+    /// Signs a measurement
+    pub fn sign(&self, m: &Message) -> Result<SignedRecord> {
+        let signature = self.key.sign(&signed_bytes(m)?);
+        Ok(SignedRecord {
+            message: m.clone(),
+            public_key: self.public_key(),
+            signature: hex::encode(signature.to_bytes()),
+        })
+    }
+}
+
+impl SignedRecord {
+    /// Checks the signature against the embedded public key
+    /// Callers must also check that the key belongs to a known station
+    pub fn verify(&self) -> Result<()> {
+        let mut key = [0u8; 32];
+        hex::decode_to_slice(&self.public_key, &mut key).map_err(err)?;
+        let mut sig = [0u8; 64];
+        hex::decode_to_slice(&self.signature, &mut sig).map_err(err)?;
+
+        let key = VerifyingKey::from_bytes(&key).map_err(err)?;
+        key.verify(&signed_bytes(&self.message)?, &Signature::from_bytes(&sig))
+            .map_err(err)
+    }
+}
@@ -0,0 +1,175 @@
+//! Circuit breaker keeping a dead sink from stalling every publish.
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// State of a circuit breaker
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    /// Sends go through
+    Closed,
+    /// Sends are skipped until the cooldown elapses
+    Open,
+    /// The next send is a probe: success closes the breaker,
+    /// failure opens it again
+    HalfOpen,
+}
+
+/// Breaker counters
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct Stats {
+    pub state: State,
+    /// Failures since the last success
+    pub consecutive_failures: u32,
+    /// How many times the breaker opened
+    pub opened: u64,
+    /// Sends skipped while open
+    pub rejected: u64,
+    /// Half-open probes sent
+    pub probes: u64,
+}
+
+/// Opens after `threshold` consecutive failures and lets a probe through
+/// every `cooldown`
+///
+/// # Example
+/// ```
+/// use sds011::sink::breaker::{Breaker, State};
+/// use std::time::Duration;
+///
+/// let mut breaker = Breaker::new(2, Duration::from_secs(60));
+/// breaker.record(false);
+/// assert!(breaker.allow());
+/// breaker.record(false);
+///
+/// assert_eq!(breaker.stats().state, State::Open);
+/// assert!(!breaker.allow());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Breaker {
+    threshold: u32,
+    cooldown: Duration,
+    opened_at: Option<Instant>,
+    stats: Stats,
+}
+
+impl Breaker {
+    /// Creates a closed breaker, `threshold` is at least 1
+    pub fn new(threshold: u32, cooldown: Duration) -> Breaker {
+        Breaker {
+            threshold: threshold.max(1),
+            cooldown,
+            opened_at: None,
+            stats: Stats {
+                state: State::Closed,
+                consecutive_failures: 0,
+                opened: 0,
+                rejected: 0,
+                probes: 0,
+            },
+        }
+    }
+
+    /// Returns `true` if a send may be attempted now
+    pub fn allow(&mut self) -> bool {
+        match self.stats.state {
+            State::Closed => true,
+            State::HalfOpen => {
+                // Only one probe at a time
+                self.stats.rejected += 1;
+                false
+            }
+            State::Open => {
+                let cooled = match self.opened_at {
+                    Some(t) => t.elapsed() >= self.cooldown,
+                    None => true,
+                };
+                if cooled {
+                    self.stats.state = State::HalfOpen;
+                    self.stats.probes += 1;
+                } else {
+                    self.stats.rejected += 1;
+                }
+                cooled
+            }
+        }
+    }
+
+    /// Records the outcome of an allowed send
+    pub fn record(&mut self, success: bool) {
+        if success {
+            self.stats.consecutive_failures = 0;
+            self.stats.state = State::Closed;
+            self.opened_at = None;
+            return;
+        }
+
+        self.stats.consecutive_failures = self.stats.consecutive_failures.saturating_add(1);
+        let trip = self.stats.state == State::HalfOpen
+            || self.stats.consecutive_failures >= self.threshold;
+        if trip {
+            if self.stats.state != State::Open {
+                self.stats.opened += 1;
+            }
+            self.stats.state = State::Open;
+            self.opened_at = Some(Instant::now());
+        }
+    }
+
+    /// Current state and counters
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Breaker opened by a failure, whose cooldown elapses at once
+    fn opened() -> Breaker {
+        let mut breaker = Breaker::new(1, Duration::from_secs(0));
+        breaker.record(false);
+        assert_eq!(breaker.stats().state, State::Open);
+        breaker
+    }
+
+    #[test]
+    fn failed_probe_opens_again() {
+        let mut breaker = opened();
+        assert!(breaker.allow());
+        assert_eq!(breaker.stats().state, State::HalfOpen);
+        // Only the probe goes through
+        assert!(!breaker.allow());
+
+        breaker.record(false);
+        let stats = breaker.stats();
+        assert_eq!(stats.state, State::Open);
+        assert_eq!(stats.opened, 2);
+        assert_eq!(stats.probes, 1);
+        assert_eq!(stats.rejected, 1);
+    }
+
+    #[test]
+    fn successful_probe_closes() {
+        let mut breaker = opened();
+        assert!(breaker.allow());
+        breaker.record(true);
+        let stats = breaker.stats();
+        assert_eq!(stats.state, State::Closed);
+        assert_eq!(stats.consecutive_failures, 0);
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn stays_open_during_the_cooldown() {
+        let mut breaker = Breaker::new(1, Duration::from_secs(60));
+        breaker.record(false);
+        assert!(!breaker.allow());
+        assert!(!breaker.allow());
+        assert_eq!(breaker.stats().state, State::Open);
+        assert_eq!(breaker.stats().rejected, 2);
+        assert_eq!(breaker.stats().probes, 0);
+    }
+}
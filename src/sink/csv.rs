@@ -0,0 +1,210 @@
+//! CSV file sink.
+//!
+//! Files start with a header naming the columns. Appending to an existing
+//! file is allowed as long as its header names the same columns, so a
+//! restarted logger keeps adding to the same file, but one with different
+//! columns doesn't silently produce misaligned rows.
+
+use super::Sink;
+use crate::aqi::AqiScale;
+use crate::durable::{AppendFile, SyncPolicy};
+use crate::timestamp::TimestampFormat;
+use crate::{Error, Message, Result};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+
+fn err<E: std::fmt::Display>(path: &str, e: E) -> Error {
+    Error::SinkError(format!("{}: {}", path, e))
+}
+
+/// Sink appending measurements to a CSV file
+///
+/// Columns are `timestamp`, then `device_id` when set, `pm25`, `pm10`,
+/// then `aqi` and `aqi_category` when enabled, then fields added with
+/// `field()`. The file is opened on the first measurement.
+///
+/// # Example
+/// ```
+/// use sds011::aqi::AqiScale;
+/// use sds011::sink::csv::CsvWriter;
+/// use sds011::sink::Sink;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::UNIX_EPOCH;
+///
+/// let path = std::env::temp_dir().join("sds011-csv-example.csv");
+/// let path = path.to_str().unwrap();
+/// # let _ = std::fs::remove_file(path);
+/// let mut csv = CsvWriter::new(path).device_id(0xa160).aqi(AqiScale::Us);
+///
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(35.4), pm10: MicrogramsPerCubicMeter(40.0) };
+/// csv.send(&m).unwrap();
+///
+/// let text = std::fs::read_to_string(path).unwrap();
+/// assert_eq!(text, "timestamp,device_id,pm25,pm10,aqi,aqi_category\n\
+///                   0,a160,35.4,40,100,Moderate\n");
+/// ```
+pub struct CsvWriter {
+    path: String,
+    device_id: Option<u16>,
+    aqi: Option<AqiScale>,
+    fields: Vec<(String, String)>,
+    format: TimestampFormat,
+    policy: SyncPolicy,
+    file: Option<AppendFile>,
+}
+
+impl CsvWriter {
+    /// Writes to the file at `path`, appending if it exists
+    pub fn new(path: &str) -> CsvWriter {
+        CsvWriter {
+            path: path.to_string(),
+            device_id: None,
+            aqi: None,
+            fields: Vec::new(),
+            format: TimestampFormat::default(),
+            policy: SyncPolicy::Always,
+            file: None,
+        }
+    }
+
+    /// Adds a `device_id` column with `id` in hex
+    pub fn device_id(mut self, id: u16) -> CsvWriter {
+        self.device_id = Some(id);
+        self
+    }
+
+    /// Adds `aqi` and `aqi_category` columns on `scale`
+    pub fn aqi(mut self, scale: AqiScale) -> CsvWriter {
+        self.aqi = Some(scale);
+        self
+    }
+
+    /// Adds a column `name` with the same `value` in every row, e.g. the
+    /// sensor's location from a `directory::Directory`
+    pub fn field<V: Into<Value>>(mut self, name: &str, value: V) -> CsvWriter {
+        let value = match value.into() {
+            Value::String(s) => s,
+            other => other.to_string(),
+        };
+        self.fields.push((name.to_string(), value));
+        self
+    }
+
+    /// Formats timestamps with `format`, UNIX seconds by default
+    pub fn timestamp_format(mut self, format: TimestampFormat) -> CsvWriter {
+        self.format = format;
+        self
+    }
+
+    /// Syncs the file as `policy` asks, every row by default
+    pub fn sync_policy(mut self, policy: SyncPolicy) -> CsvWriter {
+        self.policy = policy;
+        self
+    }
+
+    /// Column names
+    pub fn header(&self) -> Vec<&str> {
+        let mut columns = vec!["timestamp"];
+        if self.device_id.is_some() {
+            columns.push("device_id");
+        }
+        columns.extend(["pm25", "pm10"]);
+        if self.aqi.is_some() {
+            columns.extend(["aqi", "aqi_category"]);
+        }
+        columns.extend(self.fields.iter().map(|(name, _)| name.as_str()));
+        columns
+    }
+
+    /// Header as written to the file, without a line terminator
+    pub fn header_line(&self) -> Result<String> {
+        encode(&self.header()).map_err(|e| err(&self.path, e))
+    }
+
+    /// Row of `m` as written to the file, without a line terminator
+    pub fn line(&self, m: &Message) -> Result<String> {
+        encode(&self.row(m)).map_err(|e| err(&self.path, e))
+    }
+
+    fn row(&self, m: &Message) -> Vec<String> {
+        let mut row = vec![m.format_timestamp(&self.format)];
+        if let Some(id) = self.device_id {
+            row.push(format!("{:04x}", id));
+        }
+        row.push(m.pm25.value().to_string());
+        row.push(m.pm10.value().to_string());
+        if let Some(scale) = self.aqi {
+            let aqi = m.aqi(scale);
+            row.push(aqi.value.to_string());
+            row.push(aqi.category.to_string());
+        }
+        row.extend(self.fields.iter().map(|(_, value)| value.clone()));
+        row
+    }
+
+    /// Opens the file, writing the header to a new or empty file and
+    /// checking it otherwise
+    fn open(&mut self) -> Result<&mut AppendFile> {
+        if self.file.is_none() {
+            let mut file = AppendFile::open(&self.path, self.policy)?;
+            let header = self.header_line()?;
+            match first_line(&self.path)? {
+                None => file.append(&header)?,
+                Some(line) if line == header => {}
+                Some(line) => {
+                    return Err(err(
+                        &self.path,
+                        format!("has columns {}, expected {}", line, header),
+                    ))
+                }
+            }
+            self.file = Some(file);
+        }
+        match self.file.as_mut() {
+            Some(file) => Ok(file),
+            None => Err(err(&self.path, "not open")),
+        }
+    }
+}
+
+impl Sink for CsvWriter {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        let row = self.line(m)?;
+        self.open()?.append(&row)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.sync(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Encodes one CSV record, quoting fields as needed, without a terminator
+fn encode<T: AsRef<[u8]>>(fields: &[T]) -> std::result::Result<String, ::csv::Error> {
+    let mut writer = ::csv::WriterBuilder::new()
+        .terminator(::csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    writer.write_record(fields)?;
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    let line = String::from_utf8_lossy(&bytes);
+    Ok(line.trim_end_matches('\n').to_string())
+}
+
+/// First line of the file at `path`, `None` if it's missing or empty
+fn first_line(path: &str) -> Result<Option<String>> {
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(err(path, e)),
+    };
+    let mut line = String::new();
+    BufReader::new(file)
+        .read_line(&mut line)
+        .map_err(|e| err(path, e))?;
+    match line.trim_end_matches(&['\r', '\n'][..]) {
+        "" => Ok(None),
+        l => Ok(Some(l.to_string())),
+    }
+}
@@ -0,0 +1,178 @@
+//! JSON Lines file sink with rotation.
+//!
+//! A long-running logger shouldn't grow a single unbounded file on a
+//! small SD card. Files are rotated by day when the path is a strftime
+//! pattern, e.g. `readings-%Y-%m-%d.ndjson`, and by size with
+//! `FileLogger::max_size()`, keeping numbered predecessors like logrotate.
+
+use super::Sink;
+use crate::durable::{AppendFile, SyncPolicy};
+use crate::timestamp::{TimestampFormat, Zone};
+use crate::{schema, Error, Message, Result};
+use serde_json::{Map, Value};
+
+fn err<E: std::fmt::Display>(path: &str, e: E) -> Error {
+    Error::SinkError(format!("{}: {}", path, e))
+}
+
+/// Rotated files kept by default
+const KEEP: usize = 5;
+
+/// Sink appending one `schema::to_json()` object per measurement to a file
+///
+/// With a strftime pattern in the path, the file name is taken from the
+/// measurement's timestamp, so a new file starts every day, hour or month
+/// the pattern distinguishes. A file that would grow beyond `max_size()`
+/// is renamed to `<path>.1`, older ones to `<path>.2` and so on, up to
+/// `keep()` of them. Fields added with `field()` are written into every
+/// object.
+///
+/// # Example
+/// ```
+/// use sds011::sink::file::FileLogger;
+/// use sds011::sink::Sink;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let dir = std::env::temp_dir().join("sds011-file-example");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let pattern = dir.join("readings-%Y-%m-%d.ndjson");
+/// let mut logger = FileLogger::new(pattern.to_str().unwrap()).unwrap();
+///
+/// let day = |d: u64| Message { timestamp: UNIX_EPOCH + Duration::from_secs(1587384000 + d * 86400), pm25: MicrogramsPerCubicMeter(4.0), pm10: MicrogramsPerCubicMeter(8.0) };
+/// logger.send(&day(0)).unwrap();
+/// logger.send(&day(1)).unwrap();
+///
+/// assert!(dir.join("readings-2020-04-20.ndjson").exists());
+/// assert_eq!(logger.path(), Some(dir.join("readings-2020-04-21.ndjson").to_str().unwrap()));
+/// ```
+pub struct FileLogger {
+    pattern: String,
+    names: Option<TimestampFormat>,
+    max_size: Option<u64>,
+    keep: usize,
+    fields: Map<String, Value>,
+    policy: SyncPolicy,
+    current: Option<(String, AppendFile)>,
+    size: u64,
+}
+
+impl FileLogger {
+    /// Logs to `path`, a strftime pattern if it contains `%`
+    /// Fails on unknown strftime specifiers
+    pub fn new(path: &str) -> Result<FileLogger> {
+        let names = if path.contains('%') {
+            Some(TimestampFormat::strftime(path)?)
+        } else {
+            None
+        };
+        Ok(FileLogger {
+            pattern: path.to_string(),
+            names,
+            max_size: None,
+            keep: KEEP,
+            fields: Map::new(),
+            policy: SyncPolicy::Always,
+            current: None,
+            size: 0,
+        })
+    }
+
+    /// Rotates a file before it grows beyond `bytes`
+    pub fn max_size(mut self, bytes: u64) -> FileLogger {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Keeps `n` rotated files, 5 by default
+    pub fn keep(mut self, n: usize) -> FileLogger {
+        self.keep = n;
+        self
+    }
+
+    /// Adds `name` with the same `value` to every object, e.g. the
+    /// sensor's location from a `directory::Directory`
+    pub fn field<V: Into<Value>>(mut self, name: &str, value: V) -> FileLogger {
+        self.fields.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Names files by dates in `zone` instead of UTC
+    pub fn zone(mut self, zone: Zone) -> FileLogger {
+        self.names = self.names.map(|f| f.zone(zone));
+        self
+    }
+
+    /// Syncs the file as `policy` asks, every line by default
+    pub fn sync_policy(mut self, policy: SyncPolicy) -> FileLogger {
+        self.policy = policy;
+        self
+    }
+
+    /// Path of the file written last
+    pub fn path(&self) -> Option<&str> {
+        self.current.as_ref().map(|(path, _)| path.as_str())
+    }
+
+    /// Opens `path`, closing the previous file
+    fn open(&mut self, path: String) -> Result<()> {
+        self.current = None;
+        let file = AppendFile::open(&path, self.policy)?;
+        self.size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        self.current = Some((path, file));
+        Ok(())
+    }
+
+    /// Shifts `path` to `path.1`, `path.1` to `path.2` and so on,
+    /// dropping the oldest
+    fn rotate(&mut self, path: &str) -> Result<()> {
+        self.current = None;
+        if self.keep == 0 {
+            return std::fs::remove_file(path).map_err(|e| err(path, e));
+        }
+        for i in (1..self.keep).rev() {
+            let from = format!("{}.{}", path, i);
+            match std::fs::rename(&from, format!("{}.{}", path, i + 1)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(err(&from, e)),
+                _ => {}
+            }
+        }
+        std::fs::rename(path, format!("{}.1", path)).map_err(|e| err(path, e))
+    }
+}
+
+impl Sink for FileLogger {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        let path = match &self.names {
+            Some(names) => names.apply(m.timestamp),
+            None => self.pattern.clone(),
+        };
+        let line = super::with_fields(schema::to_json(m)?, &self.fields);
+        let len = line.len() as u64 + 1;
+
+        if self.path() != Some(path.as_str()) {
+            self.open(path.clone())?;
+        }
+        if let Some(max) = self.max_size {
+            if self.size > 0 && self.size + len > max {
+                self.rotate(&path)?;
+                self.open(path)?;
+            }
+        }
+
+        match self.current.as_mut() {
+            Some((_, file)) => file.append(&line)?,
+            None => return Err(err(&self.pattern, "not open")),
+        }
+        self.size += len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.current.as_mut() {
+            Some((_, file)) => file.sync(),
+            None => Ok(()),
+        }
+    }
+}
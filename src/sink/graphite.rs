@@ -0,0 +1,252 @@
+//! Graphite plaintext and StatsD emitters.
+//!
+//! Both send `<prefix>.pm25` and `<prefix>.pm10` for every measurement,
+//! `sds011.pm25` by default. `Graphite` writes the plaintext protocol over
+//! TCP, with tags in the Graphite 1.1 `name;tag=value` form. `StatsD`
+//! sends gauges in UDP datagrams, with tags in the DogStatsD `|#tag:value`
+//! form that Telegraf and the Datadog agent understand.
+//!
+//! Characters the protocols reserve, and whitespace, are replaced with
+//! `_` in names and tags.
+
+use super::Sink;
+use crate::{Error, Message, Result};
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+fn err<E: std::fmt::Display>(addr: &str, e: E) -> Error {
+    Error::SinkError(format!("{}: {}", addr, e))
+}
+
+/// Default prefix of metric names
+const PREFIX: &str = "sds011";
+/// Timeout of connecting and writing to Graphite
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Replaces whitespace and the characters in `reserved` with `_`
+fn sanitize(s: &str, reserved: &[char]) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_whitespace() || reserved.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Sink writing measurements to Graphite's plaintext listener, port 2003
+///
+/// The connection is opened on the first measurement and reopened on the
+/// next one after a failure.
+///
+/// # Example
+/// ```
+/// use sds011::sink::graphite::Graphite;
+/// use sds011::sink::Sink;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::io::Read;
+/// use std::net::TcpListener;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let carbon = TcpListener::bind("127.0.0.1:0").unwrap();
+/// let addr = carbon.local_addr().unwrap().to_string();
+/// let mut graphite = Graphite::new(&addr).prefix("home.air").tag("room", "kitchen");
+///
+/// let m = Message { timestamp: UNIX_EPOCH + Duration::from_secs(1587384000), pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) };
+/// graphite.send(&m).unwrap();
+/// drop(graphite);
+///
+/// let mut text = String::new();
+/// carbon.accept().unwrap().0.read_to_string(&mut text).unwrap();
+/// assert_eq!(text, "home.air.pm25;room=kitchen 4.5 1587384000\n\
+///                   home.air.pm10;room=kitchen 8 1587384000\n");
+/// ```
+pub struct Graphite {
+    addr: String,
+    prefix: String,
+    tags: Vec<(String, String)>,
+    stream: Option<TcpStream>,
+}
+
+impl Graphite {
+    /// Writes to the plaintext listener at `addr`, e.g. `localhost:2003`
+    pub fn new(addr: &str) -> Graphite {
+        Graphite {
+            addr: addr.to_string(),
+            prefix: PREFIX.to_string(),
+            tags: Vec::new(),
+            stream: None,
+        }
+    }
+
+    /// Prefixes metric names with `prefix` instead of `sds011`
+    pub fn prefix(mut self, prefix: &str) -> Graphite {
+        self.prefix = sanitize(prefix, &[';']);
+        self
+    }
+
+    /// Adds a tag to every metric
+    pub fn tag(mut self, key: &str, value: &str) -> Graphite {
+        let reserved = [';', '!', '^', '=', '~'];
+        self.tags
+            .push((sanitize(key, &reserved), sanitize(value, &reserved)));
+        self
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let e = |e| err(&self.addr, e);
+        let mut last = None;
+        for addr in self.addr.to_socket_addrs().map_err(e)? {
+            match TcpStream::connect_timeout(&addr, TIMEOUT) {
+                Ok(stream) => {
+                    stream.set_write_timeout(Some(TIMEOUT)).map_err(e)?;
+                    return Ok(stream);
+                }
+                Err(error) => last = Some(error),
+            }
+        }
+        Err(match last {
+            Some(error) => e(error),
+            None => err(&self.addr, "no address"),
+        })
+    }
+
+    /// Plaintext lines of a measurement
+    fn lines(&self, m: &Message) -> String {
+        let mut tags = String::new();
+        for (key, value) in self.tags.iter() {
+            tags.push_str(&format!(";{}={}", key, value));
+        }
+        let secs = m.timestamp_secs().unwrap_or(0);
+        format!(
+            "{prefix}.pm25{tags} {} {secs}\n{prefix}.pm10{tags} {} {secs}\n",
+            m.pm25.value(),
+            m.pm10.value(),
+            prefix = self.prefix,
+            tags = tags,
+            secs = secs
+        )
+    }
+}
+
+impl Sink for Graphite {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        let lines = self.lines(m);
+        let mut stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => self.connect()?,
+        };
+        // A failed stream is dropped, so the next send reconnects
+        stream
+            .write_all(lines.as_bytes())
+            .map_err(|e| err(&self.addr, e))?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
+
+/// Sink sending measurements as StatsD gauges, to port 8125 usually
+///
+/// Each measurement is a single datagram; like any StatsD client, it
+/// doesn't learn whether the datagram arrived.
+///
+/// # Example
+/// ```
+/// use sds011::sink::graphite::StatsD;
+/// use sds011::sink::Sink;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::net::UdpSocket;
+/// use std::time::UNIX_EPOCH;
+///
+/// let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let mut statsd = StatsD::new(&agent.local_addr().unwrap().to_string()).tag("room", "kitchen");
+///
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.5), pm10: MicrogramsPerCubicMeter(8.0) };
+/// statsd.send(&m).unwrap();
+///
+/// let mut buf = [0; 512];
+/// let n = agent.recv(&mut buf).unwrap();
+/// assert_eq!(&buf[..n], &b"sds011.pm25:4.5|g|#room:kitchen\nsds011.pm10:8|g|#room:kitchen"[..]);
+/// ```
+pub struct StatsD {
+    addr: String,
+    prefix: String,
+    tags: Vec<String>,
+    socket: Option<UdpSocket>,
+}
+
+impl StatsD {
+    /// Sends to the StatsD server at `addr`, e.g. `localhost:8125`
+    pub fn new(addr: &str) -> StatsD {
+        StatsD {
+            addr: addr.to_string(),
+            prefix: PREFIX.to_string(),
+            tags: Vec::new(),
+            socket: None,
+        }
+    }
+
+    /// Prefixes metric names with `prefix` instead of `sds011`
+    pub fn prefix(mut self, prefix: &str) -> StatsD {
+        self.prefix = sanitize(prefix, &[':', '|', '@', '#']);
+        self
+    }
+
+    /// Adds a tag to every metric
+    pub fn tag(mut self, key: &str, value: &str) -> StatsD {
+        let reserved = [':', '|', '@', '#', ','];
+        self.tags.push(format!(
+            "{}:{}",
+            sanitize(key, &reserved),
+            sanitize(value, &reserved)
+        ));
+        self
+    }
+
+    fn connect(&self) -> Result<UdpSocket> {
+        let e = |e| err(&self.addr, e);
+        let addr = self
+            .addr
+            .to_socket_addrs()
+            .map_err(e)?
+            .next()
+            .ok_or_else(|| err(&self.addr, "no address"))?;
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local).map_err(e)?;
+        socket.connect(addr).map_err(e)?;
+        Ok(socket)
+    }
+}
+
+impl Sink for StatsD {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        let tags = if self.tags.is_empty() {
+            String::new()
+        } else {
+            format!("|#{}", self.tags.join(","))
+        };
+        let datagram = format!(
+            "{prefix}.pm25:{}|g{tags}\n{prefix}.pm10:{}|g{tags}",
+            m.pm25.value(),
+            m.pm10.value(),
+            prefix = self.prefix,
+            tags = tags
+        );
+        if self.socket.is_none() {
+            self.socket = Some(self.connect()?);
+        }
+        if let Some(socket) = &self.socket {
+            socket
+                .send(datagram.as_bytes())
+                .map_err(|e| err(&self.addr, e))?;
+        }
+        Ok(())
+    }
+}
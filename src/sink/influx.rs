@@ -0,0 +1,290 @@
+//! InfluxDB writer sink.
+//!
+//! Measurements are buffered and written in batches of line protocol, see
+//! `Message::to_line_protocol()`, to the v2 `/api/v2/write` or the v1
+//! `/write` endpoint. A failed write is retried with backoff; if it still
+//! fails, the batch is kept for the next write, up to a limit, so a short
+//! outage of the database doesn't lose data.
+
+use super::Sink;
+use crate::{Error, Message, Result};
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SinkError(e.to_string())
+}
+
+/// Default measurement name
+const MEASUREMENT: &str = "sds011";
+/// Default number of lines written at once
+const BATCH_SIZE: usize = 100;
+/// Default longest time a line waits in the buffer
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+/// Default number of lines kept while the database is unreachable
+const MAX_BUFFERED: usize = 10_000;
+/// Default attempts of a write
+const ATTEMPTS: u32 = 3;
+/// First delay between attempts, doubled after each
+const BACKOFF: Duration = Duration::from_millis(500);
+/// Timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Write API version and its target
+#[derive(Debug, PartialEq, Clone)]
+pub enum Api {
+    /// InfluxDB 2.x and Cloud, authenticated with an API token
+    V2 {
+        org: String,
+        bucket: String,
+        token: String,
+    },
+    /// InfluxDB 1.x, optionally authenticated with a user and password
+    V1 {
+        database: String,
+        credentials: Option<(String, String)>,
+    },
+}
+
+/// Sink writing measurements to InfluxDB in batches
+///
+/// # Example
+/// ```no_run
+/// use sds011::sink::influx::InfluxSink;
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+/// use std::time::Duration;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let mut influx = InfluxSink::v2("http://localhost:8086", "home", "air", "s3cret")
+///     .tag("room", "kitchen")
+///     .batch(10, Duration::from_secs(60));
+/// loop {
+///     influx.send(&sensor.query().unwrap()).unwrap();
+/// }
+/// ```
+pub struct InfluxSink {
+    url: String,
+    api: Api,
+    measurement: String,
+    tags: Vec<(String, String)>,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_buffered: usize,
+    attempts: u32,
+    buffer: VecDeque<String>,
+    flushed: Instant,
+    /// The last write failed, wait for the flush interval before the next
+    failing: bool,
+    agent: ureq::Agent,
+}
+
+impl InfluxSink {
+    fn new(url: &str, api: Api) -> InfluxSink {
+        InfluxSink {
+            url: url.trim_end_matches('/').to_string(),
+            api,
+            measurement: MEASUREMENT.to_string(),
+            tags: Vec::new(),
+            batch_size: BATCH_SIZE,
+            flush_interval: FLUSH_INTERVAL,
+            max_buffered: MAX_BUFFERED,
+            attempts: ATTEMPTS,
+            buffer: VecDeque::new(),
+            flushed: Instant::now(),
+            failing: false,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Writes to `bucket` of `org` on an InfluxDB 2.x server at `url`
+    pub fn v2(url: &str, org: &str, bucket: &str, token: &str) -> InfluxSink {
+        let api = Api::V2 {
+            org: org.to_string(),
+            bucket: bucket.to_string(),
+            token: token.to_string(),
+        };
+        InfluxSink::new(url, api)
+    }
+
+    /// Writes to `database` on an InfluxDB 1.x server at `url`
+    pub fn v1(url: &str, database: &str) -> InfluxSink {
+        let api = Api::V1 {
+            database: database.to_string(),
+            credentials: None,
+        };
+        InfluxSink::new(url, api)
+    }
+
+    /// Authenticates to a 1.x server, ignored by 2.x
+    pub fn credentials(mut self, user: &str, password: &str) -> InfluxSink {
+        if let Api::V1 { credentials, .. } = &mut self.api {
+            *credentials = Some((user.to_string(), password.to_string()));
+        }
+        self
+    }
+
+    /// Names the measurement, `sds011` by default
+    pub fn measurement(mut self, name: &str) -> InfluxSink {
+        self.measurement = name.to_string();
+        self
+    }
+
+    /// Adds a tag to every line
+    pub fn tag(mut self, key: &str, value: &str) -> InfluxSink {
+        self.tags.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Writes once `size` lines are buffered or the oldest waited for
+    /// `interval`, by default 100 lines or 10 seconds
+    pub fn batch(mut self, size: usize, interval: Duration) -> InfluxSink {
+        self.batch_size = size.max(1);
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Keeps at most `lines` while the server is unreachable, dropping
+    /// the oldest, 10000 by default
+    pub fn max_buffered(mut self, lines: usize) -> InfluxSink {
+        self.max_buffered = lines.max(1);
+        self
+    }
+
+    /// Tries a write `attempts` times before giving up, 3 by default
+    pub fn attempts(mut self, attempts: u32) -> InfluxSink {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Number of lines waiting to be written
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn request(&self) -> ureq::Request {
+        match &self.api {
+            Api::V2 { org, bucket, token } => self
+                .agent
+                .post(&format!("{}/api/v2/write", self.url))
+                .query("org", org)
+                .query("bucket", bucket)
+                .query("precision", "ns")
+                .set("Authorization", &format!("Token {}", token)),
+            Api::V1 {
+                database,
+                credentials,
+            } => {
+                let request = self
+                    .agent
+                    .post(&format!("{}/write", self.url))
+                    .query("db", database)
+                    .query("precision", "ns");
+                match credentials {
+                    Some((user, password)) => request.query("u", user).query("p", password),
+                    None => request,
+                }
+            }
+        }
+    }
+
+    /// Writes the buffer, retrying with backoff
+    fn write(&mut self) -> Result<()> {
+        self.flushed = Instant::now();
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let body = self.buffer.iter().cloned().collect::<Vec<_>>().join("\n");
+
+        let mut delay = BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match self.request().send_string(&body) {
+                Ok(_) => {
+                    self.buffer.clear();
+                    self.failing = false;
+                    return Ok(());
+                }
+                // The server rejected the data or the credentials, sending
+                // the same batch again won't help
+                Err(ureq::Error::Status(code, response)) if code != 429 && code < 500 => {
+                    let lines = self.buffer.len();
+                    self.buffer.clear();
+                    let message = response.into_string().unwrap_or_default();
+                    return Err(err(format!(
+                        "{} lines rejected with {}: {}",
+                        lines,
+                        code,
+                        message.trim()
+                    )));
+                }
+                Err(e) if attempt >= self.attempts => {
+                    self.failing = true;
+                    return Err(err(format!(
+                        "{}: {} lines kept",
+                        describe(&e),
+                        self.buffer.len()
+                    )));
+                }
+                Err(e) => {
+                    log::warn!(
+                        "influx: {}, attempt {} of {}, retrying in {:?}",
+                        describe(&e),
+                        attempt,
+                        self.attempts,
+                        delay
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Describes a failed request without its URL, which may hold a password
+fn describe(e: &ureq::Error) -> String {
+    match e {
+        ureq::Error::Status(code, _) => format!("HTTP status {}", code),
+        ureq::Error::Transport(t) => match t.message() {
+            Some(message) => format!("{}: {}", t.kind(), message),
+            None => t.kind().to_string(),
+        },
+    }
+}
+
+impl Sink for InfluxSink {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        let tags: Vec<(&str, &str)> = self
+            .tags
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let line = m.to_line_protocol(&self.measurement, &tags);
+        if self.buffer.len() >= self.max_buffered {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(line);
+
+        let full = self.buffer.len() >= self.batch_size && !self.failing;
+        if full || self.flushed.elapsed() >= self.flush_interval {
+            self.write()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write()
+    }
+}
+
+impl Drop for InfluxSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.write() {
+            log::warn!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! [IPFS](https://ipfs.tech) publishing.
+//!
+//! Readings are collected into a document, JSON Lines of schema records or
+//! CSV, which is added to IPFS through the HTTP API of a node, e.g. a
+//! local Kubo daemon. The API returns the document's CID, which networks
+//! anchor on-chain so anyone can fetch and check the data behind it.
+//! Adding fails if the node is down; the readings are kept and added with
+//! the next batch.
+
+use super::Sink;
+use crate::{schema, Error, Message, Result};
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::Duration;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SinkError(format!("ipfs: {}", e))
+}
+
+/// Default readings per document
+const BATCH: usize = 60;
+/// Timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Separator of the multipart body, never found in a document
+const BOUNDARY: &str = "sds011-ipfs-document";
+
+/// Format of the documents added to IPFS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One schema record per line, like the JSON Lines log
+    Json,
+    /// `timestamp,pm25,pm10` rows under a header, timestamps in UNIX
+    /// seconds
+    Csv,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "jsonl",
+            Format::Csv => "csv",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/x-ndjson",
+            Format::Csv => "text/csv",
+        }
+    }
+
+    /// Document holding `readings`
+    fn document(&self, readings: &[Message]) -> Result<String> {
+        let mut doc = String::new();
+        match self {
+            Format::Json => {
+                for m in readings {
+                    doc.push_str(&schema::to_json(m)?);
+                    doc.push('\n');
+                }
+            }
+            Format::Csv => {
+                doc.push_str("timestamp,pm25,pm10\n");
+                for m in readings {
+                    doc.push_str(&format!(
+                        "{},{},{}\n",
+                        m.timestamp_secs().unwrap_or(0),
+                        m.pm25.value(),
+                        m.pm10.value()
+                    ));
+                }
+            }
+        }
+        Ok(doc)
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format> {
+        match s {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(err(format!("{}: expected json or csv", s))),
+        }
+    }
+}
+
+/// Reply of `/api/v0/add`
+#[derive(Deserialize)]
+struct Added {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+/// Called with the CID of every document added
+pub type OnCid = Box<dyn FnMut(&str) + Send>;
+
+/// Sink adding batches of readings to IPFS
+///
+/// # Example
+/// ```no_run
+/// use sds011::sink::ipfs::{Format, Ipfs};
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let mut ipfs = Ipfs::new("http://127.0.0.1:5001")
+///     .format(Format::Csv)
+///     .batch(10)
+///     .on_cid(|cid| println!("added {}", cid));
+/// for _ in 0..25 {
+///     ipfs.send(&sensor.query().unwrap()).unwrap();
+/// }
+/// // The last 5 readings
+/// let cid = ipfs.publish().unwrap();
+/// ```
+pub struct Ipfs {
+    api: String,
+    format: Format,
+    batch: usize,
+    readings: Vec<Message>,
+    last_cid: Option<String>,
+    on_cid: Option<OnCid>,
+    agent: ureq::Agent,
+}
+
+impl Ipfs {
+    /// Adds documents through the HTTP API at `api`, e.g.
+    /// `http://127.0.0.1:5001` for a local node
+    pub fn new(api: &str) -> Ipfs {
+        Ipfs {
+            api: api.trim_end_matches('/').to_string(),
+            format: Format::Json,
+            batch: BATCH,
+            readings: Vec::new(),
+            last_cid: None,
+            on_cid: None,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Sets the format of the documents, `Format::Json` by default
+    pub fn format(mut self, format: Format) -> Ipfs {
+        self.format = format;
+        self
+    }
+
+    /// Adds a document every `readings` readings, 60 by default
+    pub fn batch(mut self, readings: usize) -> Ipfs {
+        self.batch = readings.max(1);
+        self
+    }
+
+    /// Calls `f` with the CID of every document added, e.g. to record it
+    /// on-chain
+    pub fn on_cid<F: FnMut(&str) + Send + 'static>(mut self, f: F) -> Ipfs {
+        self.on_cid = Some(Box::new(f));
+        self
+    }
+
+    /// CID of the last document added
+    pub fn last_cid(&self) -> Option<&str> {
+        self.last_cid.as_deref()
+    }
+
+    /// Adds the readings collected so far as a document and returns its
+    /// CID, `None` if there are none; they are kept if adding fails
+    pub fn publish(&mut self) -> Result<Option<String>> {
+        let first = match self.readings.first() {
+            Some(m) => m,
+            None => return Ok(None),
+        };
+        let name = format!(
+            "sds011-{}.{}",
+            first.timestamp_secs().unwrap_or(0),
+            self.format.extension()
+        );
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\n\
+             Content-Type: {ct}\r\n\r\n{doc}\r\n--{b}--\r\n",
+            b = BOUNDARY,
+            name = name,
+            ct = self.format.content_type(),
+            doc = self.format.document(&self.readings)?
+        );
+        let added: Added = self
+            .agent
+            .post(&format!("{}/api/v0/add?pin=true&cid-version=1", self.api))
+            .set(
+                "Content-Type",
+                &format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .send_string(&body)
+            .map_err(err)?
+            .into_json()
+            .map_err(err)?;
+        log::debug!(
+            "ipfs: {} readings added as {}",
+            self.readings.len(),
+            added.hash
+        );
+        self.readings.clear();
+        if let Some(f) = self.on_cid.as_mut() {
+            f(&added.hash);
+        }
+        self.last_cid = Some(added.hash.clone());
+        Ok(Some(added.hash))
+    }
+}
+
+impl Sink for Ipfs {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        self.readings.push(m.clone());
+        if self.readings.len() >= self.batch {
+            self.publish()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.publish().map(|_| ())
+    }
+}
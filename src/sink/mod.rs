@@ -0,0 +1,355 @@
+//! Destinations measurements are published to.
+//!
+//! A `SinkSet` fans measurements out to several sinks. A sink that fails
+//! to start doesn't stop the others: it's reported as degraded and
+//! reconnected in the background, so a typo in one remote URL doesn't
+//! take down local logging. A sink that keeps failing to send is skipped
+//! by its circuit breaker for a while, so a dead broker doesn't stall
+//! every publish on connection timeouts.
+
+pub mod breaker;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod file;
+pub mod graphite;
+#[cfg(feature = "influx")]
+pub mod influx;
+#[cfg(feature = "ipfs")]
+pub mod ipfs;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "opensensemap")]
+pub mod opensensemap;
+pub mod rate;
+#[cfg(feature = "robonomics")]
+pub mod robonomics;
+#[cfg(feature = "sensor-community")]
+pub mod sensor_community;
+#[cfg(feature = "thingspeak")]
+pub mod thingspeak;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+use crate::events::{Event, EventBus};
+use crate::{Message, Result};
+use breaker::Breaker;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Destination of measurements
+pub trait Sink: Send {
+    /// Publishes a measurement
+    fn send(&mut self, m: &Message) -> Result<()>;
+
+    /// Publishes a lifecycle event, see `Event::is_lifecycle()`
+    /// Sinks that only carry measurements ignore them
+    fn event(&mut self, _event: &Event) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flushes buffered measurements, if the sink buffers any
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates a sink, called again after a failure to start
+pub type Connect = Box<dyn FnMut() -> Result<Box<dyn Sink>> + Send>;
+
+/// First delay before retrying to start a sink, doubled on every failure
+const RETRY_MIN: Duration = Duration::from_secs(1);
+/// Longest delay between retries
+const RETRY_MAX: Duration = Duration::from_secs(300);
+
+/// Consecutive send failures opening a sink's circuit breaker by default
+const BREAKER_THRESHOLD: u32 = 5;
+/// Default time an open circuit breaker waits before a probe
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Health of a sink
+#[derive(Debug, PartialEq, Clone)]
+pub enum Health {
+    /// Started and the last send succeeded
+    Healthy,
+    /// Failed to start or send, `error` is the latest failure
+    Degraded { error: String, since: Instant },
+}
+
+/// Health of a named sink, as reported by `SinkSet::health()`
+#[derive(Debug, PartialEq, Clone)]
+pub struct SinkHealth {
+    pub name: String,
+    pub health: Health,
+    pub breaker: breaker::Stats,
+}
+
+struct Slot {
+    sink: Option<Box<dyn Sink>>,
+    health: Health,
+    breaker: Breaker,
+}
+
+impl Slot {
+    fn fail(&mut self, error: String) {
+        if let Health::Degraded { since, .. } = self.health {
+            self.health = Health::Degraded { error, since };
+        } else {
+            self.health = Health::Degraded {
+                error,
+                since: Instant::now(),
+            };
+        }
+    }
+}
+
+/// Set of sinks measurements are published to
+///
+/// # Example
+/// ```
+/// use sds011::sink::{Sink, SinkSet};
+/// use sds011::{Error, Message, MicrogramsPerCubicMeter, Result};
+/// use std::time::UNIX_EPOCH;
+///
+/// struct Print;
+///
+/// impl Sink for Print {
+///     fn send(&mut self, m: &Message) -> Result<()> {
+///         println!("{}", m);
+///         Ok(())
+///     }
+/// }
+///
+/// let mut sinks = SinkSet::new();
+/// sinks.add("stdout", Box::new(|| Ok(Box::new(Print) as Box<dyn Sink>)));
+/// sinks.add("broken", Box::new(|| Err(Error::SinkError("bad URL".to_string()))));
+///
+/// let m = Message { timestamp: UNIX_EPOCH, pm25: MicrogramsPerCubicMeter(4.0), pm10: MicrogramsPerCubicMeter(8.0) };
+/// sinks.publish(&m);
+/// assert!(sinks.is_degraded());
+/// ```
+pub struct SinkSet {
+    sinks: Vec<(String, Arc<Mutex<Slot>>)>,
+    breaker: Breaker,
+    events: Option<EventBus>,
+    lifecycle: bool,
+}
+
+impl Default for SinkSet {
+    fn default() -> Self {
+        SinkSet {
+            sinks: Vec::new(),
+            breaker: Breaker::new(BREAKER_THRESHOLD, BREAKER_COOLDOWN),
+            events: None,
+            lifecycle: false,
+        }
+    }
+}
+
+impl SinkSet {
+    /// Creates an empty set whose sinks' breakers open after 5 consecutive
+    /// failures and probe every minute
+    pub fn new() -> SinkSet {
+        SinkSet::default()
+    }
+
+    /// Sets the circuit breaker of sinks added afterwards
+    pub fn with_breaker(mut self, threshold: u32, cooldown: Duration) -> SinkSet {
+        self.breaker = Breaker::new(threshold, cooldown);
+        self
+    }
+
+    /// Publishes `Event::SinkDegraded` on `bus` whenever a sink fails to
+    /// start or a healthy sink fails to send
+    pub fn with_events(mut self, bus: EventBus) -> SinkSet {
+        self.events = Some(bus);
+        self
+    }
+
+    /// Makes `handle()` forward lifecycle events to sinks besides
+    /// measurements, so fleet operators can audit stations remotely
+    pub fn with_lifecycle_events(mut self) -> SinkSet {
+        self.lifecycle = true;
+        self
+    }
+
+    fn degraded(&self, sink: &str, error: String) {
+        if let Some(bus) = &self.events {
+            bus.publish(Event::SinkDegraded {
+                sink: sink.to_string(),
+                error,
+            });
+        }
+    }
+
+    /// Starts a sink with `connect`
+    /// If that fails, the error is logged and `connect` is retried in a
+    /// background thread with exponential backoff until it succeeds
+    pub fn add(&mut self, name: &str, mut connect: Connect) {
+        let slot = match connect() {
+            Ok(sink) => Slot {
+                sink: Some(sink),
+                health: Health::Healthy,
+                breaker: self.breaker.clone(),
+            },
+            Err(e) => {
+                log::warn!(
+                    "sink {} failed to start: {}, retrying in the background",
+                    name,
+                    e
+                );
+                self.degraded(name, e.to_string());
+                Slot {
+                    sink: None,
+                    health: Health::Degraded {
+                        error: e.to_string(),
+                        since: Instant::now(),
+                    },
+                    breaker: self.breaker.clone(),
+                }
+            }
+        };
+
+        let started = slot.sink.is_some();
+        let slot = Arc::new(Mutex::new(slot));
+        if !started {
+            retry(name.to_string(), Arc::clone(&slot), connect);
+        }
+        self.sinks.push((name.to_string(), slot));
+    }
+
+    /// Sends `m` to every started sink whose breaker isn't open
+    /// Failures are logged and mark the sink degraded until a send succeeds
+    pub fn publish(&self, m: &Message) {
+        self.each(|sink| sink.send(m));
+    }
+
+    /// Publishes measurement events, and lifecycle events if enabled with
+    /// `with_lifecycle_events()`, other events are ignored
+    /// Meant for a thread draining `EventBus::subscribe()`
+    pub fn handle(&self, event: &Event) {
+        match event {
+            Event::Measurement { message, .. } => self.publish(message),
+            e if self.lifecycle && e.is_lifecycle() => self.each(|sink| sink.event(e)),
+            _ => {}
+        }
+    }
+
+    /// Flushes every started sink
+    pub fn flush(&self) {
+        self.each(|sink| sink.flush());
+    }
+
+    fn each<F: FnMut(&mut dyn Sink) -> Result<()>>(&self, mut f: F) {
+        for (name, slot) in self.sinks.iter() {
+            let mut slot = lock(slot);
+            if slot.sink.is_none() || !slot.breaker.allow() {
+                continue;
+            }
+            let result = match slot.sink.as_mut() {
+                Some(sink) => f(sink.as_mut()),
+                None => continue,
+            };
+
+            let opened = slot.breaker.stats().opened;
+            slot.breaker.record(result.is_ok());
+            match result {
+                Ok(()) => slot.health = Health::Healthy,
+                Err(e) => {
+                    log::warn!("sink {}: {}", name, e);
+                    if slot.health == Health::Healthy {
+                        self.degraded(name, e.to_string());
+                    }
+                    slot.fail(e.to_string());
+                }
+            }
+            if slot.breaker.stats().opened > opened {
+                log::warn!("sink {}: circuit breaker open", name);
+            }
+        }
+    }
+
+    /// Health of every sink, in the order they were added
+    pub fn health(&self) -> Vec<SinkHealth> {
+        self.sinks
+            .iter()
+            .map(|(name, slot)| {
+                let slot = lock(slot);
+                SinkHealth {
+                    name: name.clone(),
+                    health: slot.health.clone(),
+                    breaker: slot.breaker.stats(),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns `true` if any sink is degraded
+    pub fn is_degraded(&self) -> bool {
+        self.sinks
+            .iter()
+            .any(|(_, slot)| lock(slot).health != Health::Healthy)
+    }
+
+    /// Number of sinks
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Returns `true` if there are no sinks
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+}
+
+/// Adds `fields` to the JSON object `line`
+/// They are spliced in, so the record keeps its own formatting
+pub fn with_fields(mut line: String, fields: &serde_json::Map<String, Value>) -> String {
+    if !fields.is_empty() && line.ends_with('}') {
+        let fields = Value::Object(fields.clone()).to_string();
+        line.pop();
+        line.push(',');
+        line.push_str(&fields[1..]);
+    }
+    line
+}
+
+/// Locks a slot, a panicking sink doesn't make the others unusable
+fn lock(slot: &Mutex<Slot>) -> std::sync::MutexGuard<'_, Slot> {
+    match slot.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Keeps calling `connect` until it succeeds and installs the sink
+fn retry(name: String, slot: Arc<Mutex<Slot>>, mut connect: Connect) {
+    thread::spawn(move || {
+        let mut delay = RETRY_MIN;
+        loop {
+            thread::sleep(delay);
+            match connect() {
+                Ok(sink) => {
+                    let mut slot = lock(&slot);
+                    slot.sink = Some(sink);
+                    slot.health = Health::Healthy;
+                    log::info!("sink {} started", name);
+                    return;
+                }
+                Err(e) => {
+                    lock(&slot).fail(e.to_string());
+                    delay = (delay * 2).min(RETRY_MAX);
+                    log::warn!(
+                        "sink {} failed to start: {}, retrying in {:?}",
+                        name,
+                        e,
+                        delay
+                    );
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,492 @@
+//! MQTT publisher sink.
+//!
+//! Measurements are published as JSON with their schema version, see
+//! `schema::to_json()`, and lifecycle events go to `<topic>/events`. The
+//! connection is kept up by a background thread. While it's down, sends
+//! fail instead of piling up in memory, so a `SinkSet` reports the sink
+//! degraded.
+//!
+//! With `discovery()`, Home Assistant finds the sensor on its own: entity
+//! configurations are published, retained, under its discovery prefix
+//! whenever the connection comes up, and `<topic>/status` tells it whether
+//! the sensor is online, backed by a last will for crashes.
+//! `availability()` publishes that status without discovery, e.g. to
+//! another topic.
+
+use super::Sink;
+use crate::aqi::AqiScale;
+use crate::events::Event;
+use crate::{schema, Error, Message, Result};
+use rumqttc::{Client, Connection, LastWill, MqttOptions, TlsConfiguration, Transport};
+use serde_json::{json, Map, Value};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub use rumqttc::QoS;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SinkError(e.to_string())
+}
+
+/// Delay before reconnecting after a connection error
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Keep-alive interval of the connection
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+/// Longest time dropping the sink waits for queued messages to go out
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Messages queued for the background thread before sends fail
+const CAPACITY: usize = 64;
+/// CA bundles of common Linux distributions, the first found is used
+const SYSTEM_CA_FILES: [&str; 3] = [
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+];
+
+/// Certificate authorities the broker's certificate is checked against
+#[derive(Debug, PartialEq, Clone)]
+pub enum Tls {
+    /// The system's CA bundle
+    System,
+    /// CAs in a PEM file, e.g. a private CA of the broker
+    CaFile(String),
+}
+
+impl Tls {
+    pub(crate) fn ca(&self) -> Result<Vec<u8>> {
+        match self {
+            Tls::CaFile(path) => std::fs::read(path).map_err(|e| err(format!("{}: {}", path, e))),
+            Tls::System => SYSTEM_CA_FILES
+                .iter()
+                .find_map(|path| std::fs::read(path).ok())
+                .ok_or_else(|| err("no system CA bundle found, set a CA file")),
+        }
+    }
+}
+
+/// Payload of the availability topic while connected
+const ONLINE: &str = "online";
+/// Payload of the availability topic after disconnecting
+const OFFLINE: &str = "offline";
+
+/// Home Assistant MQTT discovery of a sensor
+///
+/// # Example
+/// ```
+/// use sds011::sink::mqtt::Discovery;
+///
+/// let discovery = Discovery::new(0xa160).area("Kitchen");
+/// let messages = discovery.messages("home/kitchen/air", "home/kitchen/air/status", true);
+///
+/// let (topic, config) = &messages[0];
+/// assert_eq!(topic, "homeassistant/sensor/sds011_a160/pm25/config");
+/// assert!(config.contains(r#""value_template":"{{ value_json.pm25 }}""#));
+/// assert_eq!(messages.len(), 3);
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Discovery {
+    prefix: String,
+    device_id: u16,
+    name: Option<String>,
+    area: Option<String>,
+}
+
+impl Discovery {
+    /// Discovery of the sensor with `device_id` under the default
+    /// `homeassistant` prefix
+    pub fn new(device_id: u16) -> Discovery {
+        Discovery {
+            prefix: "homeassistant".to_string(),
+            device_id,
+            name: None,
+            area: None,
+        }
+    }
+
+    /// Sets the discovery prefix configured in Home Assistant
+    pub fn prefix(mut self, prefix: &str) -> Discovery {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Names the device, `SDS011 <device ID>` by default
+    pub fn name(mut self, name: &str) -> Discovery {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Suggests the area the device is put in, e.g. its directory location
+    pub fn area(mut self, area: &str) -> Discovery {
+        self.area = Some(area.to_string());
+        self
+    }
+
+    /// Topics and payloads of the PM2.5, PM10 and, with `aqi`, AQI sensor
+    /// entities reading `state_topic`
+    pub fn messages(
+        &self,
+        state_topic: &str,
+        availability_topic: &str,
+        aqi: bool,
+    ) -> Vec<(String, String)> {
+        let node = format!("sds011_{:04x}", self.device_id);
+        let mut device = json!({
+            "identifiers": [node],
+            "name": self.name.clone().unwrap_or_else(|| format!("SDS011 {:04x}", self.device_id)),
+            "manufacturer": "Nova Fitness",
+            "model": "SDS011",
+        });
+        if let Some(area) = &self.area {
+            device["suggested_area"] = Value::from(area.as_str());
+        }
+
+        let mut entities = vec![
+            ("pm25", "PM2.5", "pm25", Some("µg/m³")),
+            ("pm10", "PM10", "pm10", Some("µg/m³")),
+        ];
+        if aqi {
+            entities.push(("aqi", "AQI", "aqi", None));
+        }
+        entities
+            .into_iter()
+            .map(|(object, name, class, unit)| {
+                let mut config = json!({
+                    "name": name,
+                    "unique_id": format!("{}_{}", node, object),
+                    "object_id": format!("{}_{}", node, object),
+                    "state_topic": state_topic,
+                    "value_template": format!("{{{{ value_json.{} }}}}", object),
+                    "device_class": class,
+                    "state_class": "measurement",
+                    "availability_topic": availability_topic,
+                    "device": device,
+                });
+                if let Some(unit) = unit {
+                    config["unit_of_measurement"] = Value::from(unit);
+                }
+                let topic = format!("{}/sensor/{}/{}/config", self.prefix, node, object);
+                (topic, config.to_string())
+            })
+            .collect()
+    }
+}
+
+/// State of the connection, updated by the background thread
+#[derive(Debug, PartialEq, Clone)]
+enum Status {
+    Connecting,
+    Connected,
+    Failed(String),
+    /// The disconnect went out, after everything queued before it
+    Disconnected,
+}
+
+/// Sink publishing measurements to an MQTT broker
+///
+/// The connection is opened on the first measurement.
+///
+/// # Example
+/// ```no_run
+/// use sds011::sink::mqtt::{MqttSink, QoS};
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let mut mqtt = MqttSink::new("broker.local", 8883, "home/kitchen/air")
+///     .credentials("station", "s3cret")
+///     .tls()
+///     .qos(QoS::AtLeastOnce)
+///     .retain(true)
+///     .availability("home/kitchen/air/online");
+/// loop {
+///     mqtt.send(&sensor.query().unwrap()).unwrap();
+/// }
+/// ```
+pub struct MqttSink {
+    host: String,
+    port: u16,
+    topic: String,
+    client_id: String,
+    credentials: Option<(String, String)>,
+    tls: Option<Tls>,
+    qos: QoS,
+    retain: bool,
+    aqi: Option<AqiScale>,
+    fields: Map<String, Value>,
+    discovery: Option<Discovery>,
+    availability: Option<String>,
+    connection: Option<(Client, Arc<Mutex<Status>>)>,
+}
+
+impl MqttSink {
+    /// Publishes to `topic` on the broker at `host:port`
+    pub fn new(host: &str, port: u16, topic: &str) -> MqttSink {
+        MqttSink {
+            host: host.to_string(),
+            port,
+            topic: topic.to_string(),
+            client_id: format!("sds011-sink-{}", std::process::id()),
+            credentials: None,
+            tls: None,
+            qos: QoS::AtMostOnce,
+            retain: false,
+            aqi: None,
+            fields: Map::new(),
+            discovery: None,
+            availability: None,
+            connection: None,
+        }
+    }
+
+    /// Authenticates with a user name and password
+    pub fn credentials(mut self, user: &str, password: &str) -> MqttSink {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    /// Connects over TLS, checking the broker against the system's CAs
+    pub fn tls(mut self) -> MqttSink {
+        self.tls = Some(Tls::System);
+        self
+    }
+
+    /// Connects over TLS, checking the broker against the CAs in the PEM
+    /// file at `path`
+    pub fn ca_file(mut self, path: &str) -> MqttSink {
+        self.tls = Some(Tls::CaFile(path.to_string()));
+        self
+    }
+
+    /// Publishes with `qos`, at most once by default
+    pub fn qos(mut self, qos: QoS) -> MqttSink {
+        self.qos = qos;
+        self
+    }
+
+    /// Asks the broker to keep the last measurement for new subscribers
+    pub fn retain(mut self, retain: bool) -> MqttSink {
+        self.retain = retain;
+        self
+    }
+
+    /// Adds `aqi` and `aqi_category` fields on `scale`
+    pub fn aqi(mut self, scale: AqiScale) -> MqttSink {
+        self.aqi = Some(scale);
+        self
+    }
+
+    /// Announces the sensor to Home Assistant and keeps its availability
+    /// on `<topic>/status`
+    pub fn discovery(mut self, discovery: Discovery) -> MqttSink {
+        self.discovery = Some(discovery);
+        self
+    }
+
+    /// Publishes `online` to the retained `topic` while connected and
+    /// `offline` after disconnecting, or after a crash through a last will.
+    /// Discovery uses `<topic>/status` unless set
+    pub fn availability(mut self, topic: &str) -> MqttSink {
+        self.availability = Some(topic.to_string());
+        self
+    }
+
+    /// Sets the client ID, `sds011-sink-<pid>` by default
+    pub fn client_id(mut self, id: &str) -> MqttSink {
+        self.client_id = id.to_string();
+        self
+    }
+
+    /// Adds a field with the same `value` to every measurement, e.g. the
+    /// sensor's location from a `directory::Directory`
+    pub fn field<V: Into<Value>>(mut self, name: &str, value: V) -> MqttSink {
+        self.fields.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Topic measurements are published to
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Topic Home Assistant reads the availability from
+    pub fn availability_topic(&self) -> String {
+        match &self.availability {
+            Some(topic) => topic.clone(),
+            None => format!("{}/status", self.topic),
+        }
+    }
+
+    /// Whether the availability topic is kept
+    fn has_availability(&self) -> bool {
+        self.discovery.is_some() || self.availability.is_some()
+    }
+
+    /// Retained messages published whenever the connection comes up
+    fn birth(&self) -> Vec<(String, String)> {
+        if !self.has_availability() {
+            return Vec::new();
+        }
+        let status = self.availability_topic();
+        let mut messages = match &self.discovery {
+            Some(d) => d.messages(&self.topic, &status, self.aqi.is_some()),
+            None => Vec::new(),
+        };
+        messages.push((status, ONLINE.to_string()));
+        messages
+    }
+
+    /// Starts the connection unless it's started
+    fn connect(&mut self) -> Result<()> {
+        if self.connection.is_some() {
+            return Ok(());
+        }
+        let mut options = MqttOptions::new(self.client_id.as_str(), self.host.as_str(), self.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        if let Some((user, password)) = &self.credentials {
+            options.set_credentials(user.as_str(), password.as_str());
+        }
+        if let Some(tls) = &self.tls {
+            options.set_transport(Transport::tls_with_config(TlsConfiguration::Simple {
+                ca: tls.ca()?,
+                alpn: None,
+                client_auth: None,
+            }));
+        }
+        if self.has_availability() {
+            options.set_last_will(LastWill::new(
+                self.availability_topic(),
+                OFFLINE,
+                QoS::AtLeastOnce,
+                true,
+            ));
+        }
+
+        let (client, connection) = Client::new(options, CAPACITY);
+        let status = Arc::new(Mutex::new(Status::Connecting));
+        // Queued ahead of any measurement, so they go out first
+        let birth = self.birth();
+        announce(&client, &birth);
+        watch(connection, Arc::clone(&status), (client.clone(), birth));
+        self.connection = Some((client, status));
+        Ok(())
+    }
+
+    fn publish(&mut self, topic: String, payload: String, retain: bool) -> Result<()> {
+        self.connect()?;
+        let (client, status) = match self.connection.as_ref() {
+            Some(c) => c,
+            None => return Err(err("not connected")),
+        };
+        if let Status::Failed(e) = &*lock(status) {
+            return Err(err(format!("{}:{}: {}", self.host, self.port, e)));
+        }
+        // Only fails when the queue is full, i.e. the broker isn't keeping up
+        client
+            .try_publish(topic, self.qos, retain, payload)
+            .map_err(|_| {
+                err(format!(
+                    "{}:{}: broker is not keeping up",
+                    self.host, self.port
+                ))
+            })
+    }
+}
+
+impl Sink for MqttSink {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        let mut fields = self.fields.clone();
+        if let Some(scale) = self.aqi {
+            let aqi = m.aqi(scale);
+            fields.insert("aqi".to_string(), Value::from(aqi.value));
+            fields.insert(
+                "aqi_category".to_string(),
+                serde_json::to_value(aqi.category).map_err(err)?,
+            );
+        }
+        let payload = super::with_fields(schema::to_json(m)?, &fields);
+        self.publish(self.topic.clone(), payload, self.retain)
+    }
+
+    fn event(&mut self, event: &Event) -> Result<()> {
+        let payload = schema::to_json(event)?;
+        self.publish(format!("{}/events", self.topic), payload, false)
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        if let Some((client, status)) = self.connection.as_ref() {
+            // A clean disconnect doesn't trigger the last will
+            if self.has_availability() {
+                let topic = self.availability_topic();
+                let _ = client.try_publish(topic, QoS::AtLeastOnce, true, OFFLINE);
+            }
+            if client.try_disconnect().is_err() || *lock(status) != Status::Connected {
+                return;
+            }
+            // The process may exit right after, e.g. on SIGTERM
+            let deadline = Instant::now() + DISCONNECT_TIMEOUT;
+            while *lock(status) != Status::Disconnected && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+}
+
+/// Drives `connection` in a background thread, reporting its state in
+/// `status` and publishing the `birth` messages again on reconnection,
+/// until the sink disconnects or is dropped
+fn watch(
+    mut connection: Connection,
+    status: Arc<Mutex<Status>>,
+    birth: (Client, Vec<(String, String)>),
+) {
+    use rumqttc::{Event as MqttEvent, Outgoing, Packet};
+
+    thread::spawn(move || {
+        let (client, messages) = birth;
+        for event in connection.iter() {
+            // The client kept here holds the connection open, stop once
+            // the sink is gone
+            if Arc::strong_count(&status) == 1 {
+                return;
+            }
+            match event {
+                Ok(MqttEvent::Outgoing(Outgoing::Disconnect)) => {
+                    *lock(&status) = Status::Disconnected;
+                    return;
+                }
+                Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
+                    let mut status = lock(&status);
+                    if matches!(*status, Status::Failed(_)) {
+                        announce(&client, &messages);
+                    }
+                    *status = Status::Connected;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    *lock(&status) = Status::Failed(e.to_string());
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        }
+    });
+}
+
+/// Publishes retained `messages`, e.g. discovery configurations
+fn announce(client: &Client, messages: &[(String, String)]) {
+    for (topic, payload) in messages.iter() {
+        let publish = client.try_publish(topic.as_str(), QoS::AtLeastOnce, true, payload.as_str());
+        if let Err(e) = publish {
+            log::warn!("mqtt: {}: {}", topic, e);
+        }
+    }
+}
+
+fn lock(status: &Mutex<Status>) -> MutexGuard<'_, Status> {
+    match status.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
@@ -0,0 +1,476 @@
+//! [NATS](https://nats.io) publisher.
+//!
+//! Every measurement is published to a subject as its JSON record, see
+//! `schema::to_json()`, and lifecycle events go to `<subject>.events`.
+//! The client speaks the core protocol over plain TCP; servers requiring
+//! TLS are refused. Each publish is confirmed with a `PING`, so an error
+//! such as a permission violation fails the send instead of passing
+//! unnoticed.
+//!
+//! With JetStream, publishes wait for the stream's acknowledgement
+//! instead: the reading is stored once `send()` returns. A stream must
+//! capture the subject, e.g. `nats stream add AIR --subjects "air.>"`.
+
+use super::Sink;
+use crate::events::Event;
+use crate::{schema, Error, Message, Result};
+use serde_json::{json, Map, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SinkError(format!("nats: {}", e))
+}
+
+/// Timeout of connecting and of every reply
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How the client authenticates
+enum Auth {
+    None,
+    Token(String),
+    User(String, String),
+}
+
+/// What the server sent, besides pings and `+OK`
+enum Op {
+    Pong,
+    Msg(Vec<u8>),
+}
+
+/// Open connection to a server
+struct Connection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+    /// Prefix of the subjects JetStream acknowledgements come back on
+    inbox: Option<String>,
+    published: u64,
+}
+
+impl Connection {
+    fn open(addr: &str, auth: &Auth, jetstream: bool) -> Result<Connection> {
+        let mut last = None;
+        let mut stream = None;
+        for addr in addr.to_socket_addrs().map_err(err)? {
+            match TcpStream::connect_timeout(&addr, TIMEOUT) {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(e) => last = Some(e),
+            }
+        }
+        let stream = match (stream, last) {
+            (Some(s), _) => s,
+            (None, Some(e)) => return Err(err(e)),
+            (None, None) => return Err(err(format!("{}: no address", addr))),
+        };
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(err)?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(err)?;
+        let mut c = Connection {
+            writer: stream.try_clone().map_err(err)?,
+            reader: BufReader::new(stream),
+            inbox: None,
+            published: 0,
+        };
+
+        let mut line = String::new();
+        c.reader.read_line(&mut line).map_err(err)?;
+        let info: Value = match line.strip_prefix("INFO ") {
+            Some(info) => serde_json::from_str(info).map_err(err)?,
+            None => return Err(err(format!("not a NATS server: {}", line.trim()))),
+        };
+        if info["tls_required"] == json!(true) {
+            return Err(err("the server requires TLS"));
+        }
+
+        let mut connect = json!({
+            "verbose": false,
+            "pedantic": false,
+            "name": "sds011",
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+            "protocol": 1,
+        });
+        match auth {
+            Auth::None => {}
+            Auth::Token(token) => connect["auth_token"] = json!(token),
+            Auth::User(user, pass) => {
+                connect["user"] = json!(user);
+                connect["pass"] = json!(pass);
+            }
+        }
+        let mut hello = format!("CONNECT {}\r\n", connect);
+        if jetstream {
+            let nonce = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+                ^ std::process::id() as u64;
+            let inbox = format!("_INBOX.sds011.{:x}", nonce);
+            hello.push_str(&format!("SUB {}.* 1\r\n", inbox));
+            c.inbox = Some(inbox);
+        }
+        hello.push_str("PING\r\n");
+        c.writer.write_all(hello.as_bytes()).map_err(err)?;
+        // Bad credentials are reported before the PONG
+        c.pong()?;
+        Ok(c)
+    }
+
+    /// Reads up to the next `PONG` or message, answering the server's pings
+    fn next(&mut self) -> io::Result<Op> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed",
+                ));
+            }
+            let line = line.trim_end();
+            if line == "PING" {
+                self.writer.write_all(b"PONG\r\n")?;
+            } else if line == "PONG" {
+                return Ok(Op::Pong);
+            } else if let Some(e) = line.strip_prefix("-ERR") {
+                return Err(io::Error::other(e.trim().trim_matches('\'').to_string()));
+            } else if let Some(msg) = line.strip_prefix("MSG ") {
+                // MSG <subject> <sid> [reply-to] <bytes>
+                let len = msg
+                    .split_whitespace()
+                    .last()
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .ok_or_else(|| io::Error::other(format!("bad message: {}", line)))?;
+                let mut payload = vec![0; len + 2];
+                self.reader.read_exact(&mut payload)?;
+                payload.truncate(len);
+                return Ok(Op::Msg(payload));
+            }
+            // +OK and INFO updates need nothing
+        }
+    }
+
+    fn pong(&mut self) -> Result<()> {
+        loop {
+            if let Op::Pong = self.next().map_err(err)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Publishes `payload` and waits until the server, or the stream,
+    /// confirms it
+    fn publish(&mut self, subject: &str, payload: &str) -> Result<()> {
+        self.published += 1;
+        let frame = match &self.inbox {
+            Some(inbox) => format!(
+                "PUB {} {}.{} {}\r\n{}\r\n",
+                subject,
+                inbox,
+                self.published,
+                payload.len(),
+                payload
+            ),
+            None => format!(
+                "PUB {} {}\r\n{}\r\nPING\r\n",
+                subject,
+                payload.len(),
+                payload
+            ),
+        };
+        self.writer.write_all(frame.as_bytes()).map_err(err)?;
+        if self.inbox.is_none() {
+            return self.pong();
+        }
+        let ack = loop {
+            match self.next() {
+                Ok(Op::Msg(ack)) => break ack,
+                Ok(Op::Pong) => {}
+                // Read timeouts are WouldBlock on Unix, TimedOut on Windows
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    return Err(err(format!(
+                        "no JetStream ack, does a stream capture {}?",
+                        subject
+                    )))
+                }
+                Err(e) => return Err(err(e)),
+            }
+        };
+        let ack: Value = serde_json::from_slice(&ack).map_err(err)?;
+        match ack.get("error") {
+            Some(e) => Err(err(format!(
+                "JetStream: {}",
+                e["description"].as_str().unwrap_or("error")
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Sink publishing every measurement to a NATS subject
+///
+/// The connection is opened on the first measurement and reopened on the
+/// next one after a failure.
+///
+/// # Example
+/// ```no_run
+/// use sds011::sink::nats::Nats;
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let mut nats = Nats::new("localhost:4222", "air.kitchen")
+///     .token("s3cret")
+///     .field("room", "kitchen")
+///     .jetstream(true);
+/// loop {
+///     nats.send(&sensor.query().unwrap()).unwrap();
+/// }
+/// ```
+pub struct Nats {
+    addr: String,
+    subject: String,
+    auth: Auth,
+    fields: Map<String, Value>,
+    jetstream: bool,
+    connection: Option<Connection>,
+}
+
+impl Nats {
+    /// Publishes to `subject` on the server at `addr`, e.g. `localhost:4222`
+    pub fn new(addr: &str, subject: &str) -> Nats {
+        Nats {
+            addr: addr.to_string(),
+            subject: subject.to_string(),
+            auth: Auth::None,
+            fields: Map::new(),
+            jetstream: false,
+            connection: None,
+        }
+    }
+
+    /// Authenticates with a token
+    pub fn token(mut self, token: &str) -> Nats {
+        self.auth = Auth::Token(token.to_string());
+        self
+    }
+
+    /// Authenticates with a user name and password
+    pub fn credentials(mut self, user: &str, password: &str) -> Nats {
+        self.auth = Auth::User(user.to_string(), password.to_string());
+        self
+    }
+
+    /// Adds a field to every measurement, e.g. the device ID
+    pub fn field<V: Into<Value>>(mut self, name: &str, value: V) -> Nats {
+        self.fields.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Waits for JetStream to store every publish, off by default
+    pub fn jetstream(mut self, jetstream: bool) -> Nats {
+        self.jetstream = jetstream;
+        self
+    }
+
+    /// Subject measurements are published to
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    fn publish(&mut self, subject: &str, payload: &str) -> Result<()> {
+        let mut connection = match self.connection.take() {
+            Some(c) => c,
+            None => Connection::open(&self.addr, &self.auth, self.jetstream)?,
+        };
+        // A failed connection is dropped, so the next send reconnects
+        connection.publish(subject, payload)?;
+        self.connection = Some(connection);
+        Ok(())
+    }
+}
+
+impl Sink for Nats {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        let payload = super::with_fields(schema::to_json(m)?, &self.fields);
+        let subject = self.subject.clone();
+        self.publish(&subject, &payload)
+    }
+
+    fn event(&mut self, event: &Event) -> Result<()> {
+        let subject = format!("{}.events", self.subject);
+        self.publish(&subject, &schema::to_json(event)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// Server side of a test connection
+    struct Server {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Server {
+        fn line(&mut self) -> String {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            line.trim_end().to_string()
+        }
+
+        fn send(&mut self, data: &str) {
+            self.writer.write_all(data.as_bytes()).unwrap();
+        }
+
+        /// Reads up to the client's `PING`, returning the lines before it
+        fn until_ping(&mut self) -> Vec<String> {
+            let mut lines = Vec::new();
+            loop {
+                match self.line() {
+                    ping if ping == "PING" => return lines,
+                    line => lines.push(line),
+                }
+            }
+        }
+
+        /// Reads a `PUB`, returning its reply subject if any
+        fn publish(&mut self) -> Option<String> {
+            let line = self.line();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(words[0], "PUB");
+            self.line();
+            match words.len() {
+                4 => Some(words[2].to_string()),
+                _ => None,
+            }
+        }
+    }
+
+    /// Runs `script` as the server of one connection, after the `INFO`
+    fn server<T: Send + 'static>(
+        script: impl FnOnce(Server) -> T + Send + 'static,
+    ) -> (String, JoinHandle<T>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut server = Server {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            };
+            server.send("INFO {\"server_id\":\"test\",\"max_payload\":1048576}\r\n");
+            script(server)
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn err_fails_the_publish() {
+        let (addr, server) = server(|mut s| {
+            s.until_ping();
+            s.send("PONG\r\n");
+            s.publish();
+            s.until_ping();
+            s.send("-ERR 'Permissions Violation for Publish to air'\r\n");
+        });
+        let mut c = Connection::open(&addr, &Auth::None, false).unwrap();
+        let e = c.publish("air", "{}").unwrap_err().to_string();
+        assert!(e.ends_with("Permissions Violation for Publish to air"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn err_fails_connecting() {
+        let (addr, server) = server(|mut s| {
+            let lines = s.until_ping();
+            s.send("-ERR 'Authorization Violation'\r\n");
+            lines
+        });
+        let auth = Auth::Token("wrong".to_string());
+        let e = Connection::open(&addr, &auth, false)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(e.ends_with("Authorization Violation"));
+        let lines = server.join().unwrap();
+        assert!(lines[0].starts_with("CONNECT ") && lines[0].contains("\"auth_token\":\"wrong\""));
+    }
+
+    #[test]
+    fn answers_pings_and_skips_ok() {
+        let (addr, server) = server(|mut s| {
+            s.until_ping();
+            s.send("+OK\r\nPING\r\nPONG\r\n");
+            s.line()
+        });
+        Connection::open(&addr, &Auth::None, false).unwrap();
+        assert_eq!(server.join().unwrap(), "PONG");
+    }
+
+    #[test]
+    fn msg_is_framed_by_its_length() {
+        let (addr, server) = server(|mut s| {
+            s.until_ping();
+            s.send("PONG\r\n");
+            // The payload has a line break and the frame a reply subject
+            s.send("MSG air 1 _INBOX.x 11\r\nline\r\nbreak\r\nMSG air 1 3\r\nend\r\nMSG air\r\n");
+        });
+        let mut c = Connection::open(&addr, &Auth::None, false).unwrap();
+        assert!(matches!(c.next().unwrap(), Op::Msg(p) if p == b"line\r\nbreak"));
+        assert!(matches!(c.next().unwrap(), Op::Msg(p) if p == b"end"));
+        let e = c.next().err().unwrap().to_string();
+        assert_eq!(e, "bad message: MSG air");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn jetstream_acks() {
+        let (addr, server) = server(|mut s| {
+            let lines = s.until_ping();
+            s.send("PONG\r\n");
+            let reply = s.publish().unwrap();
+            let ack = "{\"stream\":\"AIR\",\r\n\"seq\":1}";
+            s.send(&format!("MSG {} 1 {}\r\n{}\r\n", reply, ack.len(), ack));
+            let reply = s.publish().unwrap();
+            let nak = r#"{"error":{"code":503,"description":"no responders"}}"#;
+            s.send(&format!("MSG {} 1 {}\r\n{}\r\n", reply, nak.len(), nak));
+            lines
+        });
+        let mut c = Connection::open(&addr, &Auth::None, true).unwrap();
+        c.publish("air", "{}").unwrap();
+        let e = c.publish("air", "{}").unwrap_err().to_string();
+        assert!(e.ends_with("JetStream: no responders"));
+        let lines = server.join().unwrap();
+        assert!(lines[1].starts_with("SUB _INBOX.sds011."));
+    }
+
+    #[test]
+    fn jetstream_times_out_without_a_stream() {
+        let (addr, server) = server(|mut s| {
+            s.until_ping();
+            s.send("PONG\r\n");
+            s.publish();
+            // No stream, so no ack; wait for the client to give up
+            s.line()
+        });
+        let mut c = Connection::open(&addr, &Auth::None, true).unwrap();
+        c.reader
+            .get_ref()
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let e = c.publish("air", "{}").unwrap_err().to_string();
+        assert!(e.ends_with("no JetStream ack, does a stream capture air?"));
+        drop(c);
+        assert_eq!(server.join().unwrap(), "");
+    }
+}
@@ -0,0 +1,150 @@
+//! [openSenseMap](https://opensensemap.org) uploads.
+//!
+//! PM2.5 and PM10 are posted to the senseBox's data endpoint as two
+//! measurements of the box's sensors, identified by the sensor IDs shown
+//! on the box's page. Boxes created with authentication need the access
+//! token from the box settings. Readings between uploads are averaged.
+
+use super::rate::{Pending, RateLimiter};
+use super::Sink;
+use crate::time::to_rfc3339;
+use crate::{Error, Message, Result};
+use serde::Serialize;
+use std::time::Duration;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SinkError(format!("openSenseMap: {}", e))
+}
+
+/// Base URL of the API
+const URL: &str = "https://api.opensensemap.org";
+/// Time between uploads of the senseBox firmware, a good rate for
+/// `OpenSenseMap::new()`'s limiter
+pub const INTERVAL: Duration = Duration::from_secs(60);
+/// Timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Measurement<'a> {
+    sensor: &'a str,
+    value: String,
+    created_at: &'a str,
+}
+
+/// Sink uploading the mean of its readings to an openSenseMap box
+///
+/// # Example
+/// ```no_run
+/// use sds011::sink::opensensemap::OpenSenseMap;
+/// use sds011::sink::rate::RateLimiter;
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+/// use std::time::Duration;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let limiter = RateLimiter::new(1, Duration::from_secs(300));
+/// let mut upload = OpenSenseMap::new("5a0a1b2c3d4e5f6a7b8c9d0e", "5a0a1b2c3d4e5f6a7b8c9d0f", "5a0a1b2c3d4e5f6a7b8c9d10", limiter)
+///     .token("0123456789abcdef");
+/// loop {
+///     upload.send(&sensor.query().unwrap()).unwrap();
+///     std::thread::sleep(Duration::from_secs(60));
+/// }
+/// ```
+pub struct OpenSenseMap {
+    box_id: String,
+    pm25_sensor: String,
+    pm10_sensor: String,
+    token: Option<String>,
+    url: String,
+    limiter: RateLimiter,
+    pending: Pending,
+    agent: ureq::Agent,
+}
+
+impl OpenSenseMap {
+    /// Uploads to the sensors `pm25_sensor` and `pm10_sensor` of the box
+    /// `box_id` as often as `limiter` allows, share it between the sinks
+    /// uploading to openSenseMap
+    pub fn new(
+        box_id: &str,
+        pm25_sensor: &str,
+        pm10_sensor: &str,
+        limiter: RateLimiter,
+    ) -> OpenSenseMap {
+        OpenSenseMap {
+            box_id: box_id.to_string(),
+            pm25_sensor: pm25_sensor.to_string(),
+            pm10_sensor: pm10_sensor.to_string(),
+            token: None,
+            url: URL.to_string(),
+            limiter,
+            pending: Pending::default(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Authenticates with the box's access token
+    pub fn token(mut self, token: &str) -> OpenSenseMap {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Posts to the API at `url` instead of the public one, e.g. a
+    /// self-hosted openSenseMap
+    pub fn url(mut self, url: &str) -> OpenSenseMap {
+        self.url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Box the readings are uploaded to
+    pub fn box_id(&self) -> &str {
+        &self.box_id
+    }
+
+    /// Uploads the mean of the pending readings, keeping them if it fails
+    fn upload(&mut self) -> Result<()> {
+        let m = match self.pending.mean() {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        let created_at = to_rfc3339(m.timestamp_secs().unwrap_or(0));
+        let body = [
+            Measurement {
+                sensor: &self.pm25_sensor,
+                value: format!("{:.2}", m.pm25.value()),
+                created_at: &created_at,
+            },
+            Measurement {
+                sensor: &self.pm10_sensor,
+                value: format!("{:.2}", m.pm10.value()),
+                created_at: &created_at,
+            },
+        ];
+        let request = self
+            .agent
+            .post(&format!("{}/boxes/{}/data", self.url, self.box_id));
+        let request = match &self.token {
+            Some(token) => request.set("Authorization", token),
+            None => request,
+        };
+        request.send_json(&body).map_err(err)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Sink for OpenSenseMap {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        self.pending.push(m);
+        if self.limiter.try_acquire() {
+            self.upload()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.upload()
+    }
+}
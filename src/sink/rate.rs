@@ -0,0 +1,270 @@
+//! Token bucket rate limiting shared between sinks.
+//!
+//! Several sinks posting to the same service, e.g. a ThingSpeak channel
+//! that accepts one update per 15 seconds, can share one `RateLimiter`
+//! by cloning it.
+
+use super::Sink;
+use crate::events::Event;
+use crate::{Message, Result};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    /// Tokens added per second
+    rate: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+}
+
+/// Token bucket, clones share the same bucket
+///
+/// # Example
+/// ```
+/// use sds011::sink::rate::RateLimiter;
+/// use std::time::Duration;
+///
+/// // ThingSpeak: one update per 15 seconds
+/// let limiter = RateLimiter::new(1, Duration::from_secs(15));
+/// let shared = limiter.clone();
+///
+/// assert!(limiter.try_acquire());
+/// assert!(!shared.try_acquire());
+/// assert!(shared.time_until_available() > Duration::from_secs(14));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    /// Allows bursts of `capacity` requests and one more every `period`
+    /// The bucket starts full
+    pub fn new(capacity: u32, period: Duration) -> RateLimiter {
+        let capacity = capacity.max(1) as f64;
+        let period = period.as_secs_f64().max(f64::MIN_POSITIVE);
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                capacity,
+                tokens: capacity,
+                rate: 1.0 / period,
+                updated: Instant::now(),
+            })),
+        }
+    }
+
+    fn bucket(&self) -> std::sync::MutexGuard<'_, Bucket> {
+        match self.bucket.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Takes a token if one is available
+    pub fn try_acquire(&self) -> bool {
+        let mut bucket = self.bucket();
+        bucket.refill();
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Time until a token is available, zero if one is available now
+    /// Async callers can wait this long with their runtime's timer and
+    /// then call `try_acquire()` instead of blocking in `acquire()`
+    pub fn time_until_available(&self) -> Duration {
+        let mut bucket = self.bucket();
+        bucket.refill();
+        if bucket.tokens >= 1.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.rate)
+        }
+    }
+
+    /// Blocks until a token is available and takes it
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            thread::sleep(self.time_until_available());
+        }
+    }
+}
+
+/// Sink that waits for a token before every send
+pub struct RateLimited<S> {
+    sink: S,
+    limiter: RateLimiter,
+}
+
+impl<S: Sink> RateLimited<S> {
+    /// Limits `sink` with `limiter`, possibly shared with other sinks
+    pub fn new(sink: S, limiter: RateLimiter) -> RateLimited<S> {
+        RateLimited { sink, limiter }
+    }
+}
+
+impl<S: Sink> Sink for RateLimited<S> {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        self.limiter.acquire();
+        self.sink.send(m)
+    }
+
+    fn event(&mut self, event: &Event) -> Result<()> {
+        self.limiter.acquire();
+        self.sink.event(event)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sink.flush()
+    }
+}
+
+/// Readings held back by a rate limit, uploaded as their mean once a
+/// token is available
+///
+/// Sinks of services that accept an update every few minutes use it
+/// instead of `RateLimited`, so they neither block the sensor loop nor
+/// drop readings.
+#[cfg(any(
+    feature = "sensor-community",
+    feature = "opensensemap",
+    feature = "thingspeak",
+    feature = "robonomics"
+))]
+#[derive(Debug, Default)]
+pub(crate) struct Pending {
+    pm25: f64,
+    pm10: f64,
+    count: u32,
+    last: Option<std::time::SystemTime>,
+}
+
+#[cfg(any(
+    feature = "sensor-community",
+    feature = "opensensemap",
+    feature = "thingspeak",
+    feature = "robonomics"
+))]
+impl Pending {
+    pub(crate) fn push(&mut self, m: &Message) {
+        self.pm25 += m.pm25.value() as f64;
+        self.pm10 += m.pm10.value() as f64;
+        self.count += 1;
+        self.last = Some(m.timestamp);
+    }
+
+    /// Mean of the readings, timestamped with the latest
+    pub(crate) fn mean(&self) -> Option<Message> {
+        let n = self.count as f64;
+        self.last.map(|timestamp| Message {
+            timestamp,
+            pm25: crate::MicrogramsPerCubicMeter((self.pm25 / n) as f32),
+            pm10: crate::MicrogramsPerCubicMeter((self.pm10 / n) as f32),
+        })
+    }
+
+    /// Forgets the readings once they are uploaded
+    pub(crate) fn clear(&mut self) {
+        *self = Pending::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MicrogramsPerCubicMeter;
+    use std::time::UNIX_EPOCH;
+
+    /// Moves the last refill of `limiter`'s bucket `elapsed` back
+    fn wait(limiter: &RateLimiter, elapsed: Duration) {
+        let mut bucket = limiter.bucket();
+        bucket.updated -= elapsed;
+    }
+
+    #[test]
+    fn refills_one_token_per_period_up_to_the_capacity() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(10));
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+
+        wait(&limiter, Duration::from_secs(10));
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+
+        wait(&limiter, Duration::from_secs(60));
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn time_until_available_counts_partial_refills() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(10));
+        assert_eq!(limiter.time_until_available(), Duration::from_secs(0));
+        assert!(limiter.try_acquire());
+
+        wait(&limiter, Duration::from_secs(4));
+        let left = limiter.time_until_available();
+        assert!(left > Duration::from_millis(5900) && left <= Duration::from_secs(6));
+    }
+
+    #[test]
+    fn clones_share_the_bucket() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let shared = limiter.clone();
+        assert!(limiter.try_acquire());
+        assert!(!shared.try_acquire());
+
+        // A refill seen through one clone is seen through all
+        wait(&shared, Duration::from_secs(60));
+        assert!(limiter.try_acquire());
+        assert!(!shared.try_acquire());
+    }
+
+    /// Counts the readings sent
+    struct Counter(Arc<Mutex<u32>>);
+
+    impl Sink for Counter {
+        fn send(&mut self, _m: &Message) -> Result<()> {
+            *self.0.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rate_limited_sinks_wait_for_the_shared_token() {
+        let period = Duration::from_millis(100);
+        let limiter = RateLimiter::new(1, period);
+        let sent = Arc::new(Mutex::new(0));
+        let mut a = RateLimited::new(Counter(Arc::clone(&sent)), limiter.clone());
+        let mut b = RateLimited::new(Counter(Arc::clone(&sent)), limiter);
+        let m = Message {
+            timestamp: UNIX_EPOCH,
+            pm25: MicrogramsPerCubicMeter(4.5),
+            pm10: MicrogramsPerCubicMeter(8.0),
+        };
+
+        let start = Instant::now();
+        a.send(&m).unwrap();
+        b.send(&m).unwrap();
+        a.send(&m).unwrap();
+        assert!(start.elapsed() >= period * 2);
+        assert_eq!(*sent.lock().unwrap(), 3);
+    }
+}
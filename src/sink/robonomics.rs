@@ -0,0 +1,129 @@
+//! [Robonomics](https://robonomics.network) datalog publishing.
+//!
+//! The Robonomics parachain keeps a datalog per account, records of up to
+//! 512 bytes written with the `datalog.record` extrinsic. Stations don't
+//! hold parachain keys: readings are averaged, signed with the station's
+//! Ed25519 key, see `signing`, and POSTed as a `SignedRecord` to a gateway
+//! that holds the account. The gateway checks the signature against the
+//! stations it knows and submits the record's JSON as is. Every record
+//! costs a fee, so readings are sent every 5 minutes by default.
+
+use super::rate::{Pending, RateLimiter};
+use super::Sink;
+use crate::signing::Signer;
+use crate::{Error, Message, Result};
+use std::time::Duration;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SinkError(format!("robonomics: {}", e))
+}
+
+/// Largest record the datalog pallet accepts
+pub const MAX_RECORD: usize = 512;
+/// Time between records keeping the fees low, a good rate for
+/// `Robonomics::new()`'s limiter
+pub const INTERVAL: Duration = Duration::from_secs(300);
+/// Timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Sink sending signed means of its readings to a Robonomics datalog
+/// gateway
+///
+/// # Example
+/// ```no_run
+/// use sds011::signing::Signer;
+/// use sds011::sink::rate::RateLimiter;
+/// use sds011::sink::robonomics::Robonomics;
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+/// use std::time::Duration;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let signer = Signer::load("/etc/sds011/station.key").unwrap();
+/// let limiter = RateLimiter::new(1, Duration::from_secs(600));
+/// let mut datalog = Robonomics::new("https://gateway.example.com/datalog", signer, limiter);
+/// loop {
+///     datalog.send(&sensor.query().unwrap()).unwrap();
+///     std::thread::sleep(Duration::from_secs(60));
+/// }
+/// ```
+pub struct Robonomics {
+    url: String,
+    signer: Signer,
+    token: Option<String>,
+    limiter: RateLimiter,
+    pending: Pending,
+    agent: ureq::Agent,
+}
+
+impl Robonomics {
+    /// POSTs records signed by `signer` to the gateway at `url` as often as
+    /// `limiter` allows, share it between the sinks sending to the gateway
+    pub fn new(url: &str, signer: Signer, limiter: RateLimiter) -> Robonomics {
+        Robonomics {
+            url: url.to_string(),
+            signer,
+            token: None,
+            limiter,
+            pending: Pending::default(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Authenticates with the gateway as a bearer token
+    pub fn token(mut self, token: &str) -> Robonomics {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Gateway records are sent to
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Hex encoded public key the gateway knows the station by
+    pub fn public_key(&self) -> String {
+        self.signer.public_key()
+    }
+
+    /// Sends the mean of the pending readings, keeping them if it fails
+    fn upload(&mut self) -> Result<()> {
+        let m = match self.pending.mean() {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        let record = serde_json::to_string(&self.signer.sign(&m)?).map_err(err)?;
+        if record.len() > MAX_RECORD {
+            return Err(err(format!(
+                "record of {} bytes, the datalog takes {}",
+                record.len(),
+                MAX_RECORD
+            )));
+        }
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json");
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        request.send_string(&record).map_err(err)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Sink for Robonomics {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        self.pending.push(m);
+        if self.limiter.try_acquire() {
+            self.upload()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.upload()
+    }
+}
@@ -0,0 +1,139 @@
+//! [sensor.community](https://sensor.community) (formerly Luftdaten)
+//! uploads.
+//!
+//! Readings are pushed the way the network's own firmware does: a JSON
+//! object of `P1` (PM10) and `P2` (PM2.5) values with two decimals,
+//! posted with the node's sensor ID in the `X-Sensor` header and the
+//! SDS011's pin, 1, in `X-Pin`. The API expects an update every 145
+//! seconds at most, readings in between are averaged.
+
+use super::rate::{Pending, RateLimiter};
+use super::Sink;
+use crate::{Error, Message, Result};
+use serde::Serialize;
+use std::time::Duration;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SinkError(format!("sensor.community: {}", e))
+}
+
+/// Push endpoint of the API
+const URL: &str = "https://api.sensor.community/v1/push-sensor-data/";
+/// Pin the network expects SDS011 readings on
+const PIN: &str = "1";
+/// Measuring interval of the network's firmware, the rate for
+/// `SensorCommunity::new()`'s limiter
+pub const INTERVAL: Duration = Duration::from_secs(145);
+/// Timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Serialize)]
+struct Push<'a> {
+    software_version: &'a str,
+    sensordatavalues: [Value; 2],
+}
+
+#[derive(Serialize)]
+struct Value {
+    value_type: &'static str,
+    value: String,
+}
+
+/// Sink uploading the mean of its readings to sensor.community
+///
+/// The sensor ID is the one the node was registered with, e.g.
+/// `raspi-00000000a1b2c3d4` for a Raspberry Pi, whose serial number is in
+/// `/proc/cpuinfo`, or `esp8266-12345678` to keep a node's ID after
+/// replacing its firmware.
+///
+/// # Example
+/// ```no_run
+/// use sds011::sink::rate::RateLimiter;
+/// use sds011::sink::sensor_community::{self, SensorCommunity};
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let limiter = RateLimiter::new(1, sensor_community::INTERVAL);
+/// let mut upload = SensorCommunity::new("raspi-00000000a1b2c3d4", limiter);
+/// loop {
+///     upload.send(&sensor.query().unwrap()).unwrap();
+///     std::thread::sleep(std::time::Duration::from_secs(60));
+/// }
+/// ```
+pub struct SensorCommunity {
+    sensor_id: String,
+    url: String,
+    limiter: RateLimiter,
+    pending: Pending,
+    agent: ureq::Agent,
+}
+
+impl SensorCommunity {
+    /// Uploads as the node `sensor_id` as often as `limiter` allows, share
+    /// it between the sinks uploading to sensor.community
+    pub fn new(sensor_id: &str, limiter: RateLimiter) -> SensorCommunity {
+        SensorCommunity {
+            sensor_id: sensor_id.to_string(),
+            url: URL.to_string(),
+            limiter,
+            pending: Pending::default(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Posts to `url` instead of the public API, e.g. a local proxy
+    pub fn url(mut self, url: &str) -> SensorCommunity {
+        self.url = url.to_string();
+        self
+    }
+
+    /// Node the readings are uploaded as
+    pub fn sensor_id(&self) -> &str {
+        &self.sensor_id
+    }
+
+    /// Uploads the mean of the pending readings, keeping them if it fails
+    fn upload(&mut self) -> Result<()> {
+        let m = match self.pending.mean() {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        let body = Push {
+            software_version: concat!("sds011-rs-", env!("CARGO_PKG_VERSION")),
+            sensordatavalues: [
+                Value {
+                    value_type: "P1",
+                    value: format!("{:.2}", m.pm10.value()),
+                },
+                Value {
+                    value_type: "P2",
+                    value: format!("{:.2}", m.pm25.value()),
+                },
+            ],
+        };
+        self.agent
+            .post(&self.url)
+            .set("X-Pin", PIN)
+            .set("X-Sensor", &self.sensor_id)
+            .send_json(&body)
+            .map_err(err)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Sink for SensorCommunity {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        self.pending.push(m);
+        if self.limiter.try_acquire() {
+            self.upload()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.upload()
+    }
+}
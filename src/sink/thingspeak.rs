@@ -0,0 +1,143 @@
+//! [ThingSpeak](https://thingspeak.com) channel updates.
+//!
+//! PM2.5 and PM10 are written to two fields of a channel, 1 and 2 by
+//! default, with the channel's write API key. The key is sent in the
+//! request body rather than the URL, so it doesn't end up in error
+//! messages or proxy logs. Free accounts accept an update every 15
+//! seconds, readings in between are averaged.
+
+use super::rate::{Pending, RateLimiter};
+use super::Sink;
+use crate::time::to_rfc3339;
+use crate::{Error, Message, Result};
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SinkError(format!("ThingSpeak: {}", e))
+}
+
+/// Update endpoint of the API
+const URL: &str = "https://api.thingspeak.com/update.json";
+/// Time between updates free accounts are limited to, the rate for
+/// `ThingSpeak::new()`'s limiter unless the account is paid
+pub const INTERVAL: Duration = Duration::from_secs(15);
+/// Timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Sink writing the mean of its readings to a ThingSpeak channel
+///
+/// # Example
+/// ```no_run
+/// use sds011::sink::rate::RateLimiter;
+/// use sds011::sink::thingspeak::{self, ThingSpeak};
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let limiter = RateLimiter::new(1, thingspeak::INTERVAL);
+/// let mut channel = ThingSpeak::new("XXXXXXXXXXXXXXXX", limiter).fields(3, 4).unwrap();
+/// loop {
+///     channel.send(&sensor.query().unwrap()).unwrap();
+///     std::thread::sleep(std::time::Duration::from_secs(20));
+/// }
+/// ```
+pub struct ThingSpeak {
+    api_key: String,
+    pm25_field: u8,
+    pm10_field: u8,
+    url: String,
+    limiter: RateLimiter,
+    pending: Pending,
+    agent: ureq::Agent,
+}
+
+impl ThingSpeak {
+    /// Writes to the channel of the write API key `api_key` as often as
+    /// `limiter` allows, share it between the sinks writing to ThingSpeak
+    pub fn new(api_key: &str, limiter: RateLimiter) -> ThingSpeak {
+        ThingSpeak {
+            api_key: api_key.to_string(),
+            pm25_field: 1,
+            pm10_field: 2,
+            url: URL.to_string(),
+            limiter,
+            pending: Pending::default(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Writes PM2.5 and PM10 to the channel fields `pm25` and `pm10`,
+    /// numbered 1 to 8
+    pub fn fields(mut self, pm25: u8, pm10: u8) -> Result<ThingSpeak> {
+        for field in [pm25, pm10].iter() {
+            if !(1..=8).contains(field) {
+                return Err(err(format!("field {}: expected 1 to 8", field)));
+            }
+        }
+        if pm25 == pm10 {
+            return Err(err("PM2.5 and PM10 need different fields"));
+        }
+        self.pm25_field = pm25;
+        self.pm10_field = pm10;
+        Ok(self)
+    }
+
+    /// Posts to `url` instead of the public API, e.g. a self-hosted
+    /// ThingSpeak server
+    pub fn url(mut self, url: &str) -> ThingSpeak {
+        self.url = url.to_string();
+        self
+    }
+
+    /// Writes the mean of the pending readings, keeping them if it fails
+    fn update(&mut self) -> Result<()> {
+        let m = match self.pending.mean() {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        let mut body = Map::new();
+        body.insert("api_key".to_string(), Value::from(self.api_key.as_str()));
+        body.insert(
+            format!("field{}", self.pm25_field),
+            Value::from(format!("{:.2}", m.pm25.value())),
+        );
+        body.insert(
+            format!("field{}", self.pm10_field),
+            Value::from(format!("{:.2}", m.pm10.value())),
+        );
+        body.insert(
+            "created_at".to_string(),
+            Value::from(to_rfc3339(m.timestamp_secs().unwrap_or(0))),
+        );
+
+        let response = self
+            .agent
+            .post(&self.url)
+            .send_json(Value::Object(body))
+            .map_err(err)?;
+        // A rejected update, e.g. one sent too early, is answered with a
+        // plain `0` instead of the new entry
+        let text = response.into_string().map_err(err)?;
+        if text.trim() == "0" {
+            return Err(err("update rejected, likely sent within the rate limit"));
+        }
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Sink for ThingSpeak {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        self.pending.push(m);
+        if self.limiter.try_acquire() {
+            self.update()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.update()
+    }
+}
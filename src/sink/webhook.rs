@@ -0,0 +1,187 @@
+//! Webhook sink.
+//!
+//! Every measurement is POSTed to a URL as its JSON record, see `schema`,
+//! with any extra fields and headers, e.g. an API key. Lifecycle events
+//! are POSTed to the same URL and carry a `type` field, which
+//! measurements don't. A failed request is retried with backoff unless
+//! the server rejected it with a 4xx status; if every attempt fails, the
+//! measurement is dropped and the error returned.
+
+use super::Sink;
+#[cfg(feature = "encryption")]
+use crate::encryption::Encryptor;
+use crate::events::Event;
+use crate::{schema, Error, Message, Result};
+use serde_json::{Map, Value};
+use std::thread;
+use std::time::Duration;
+
+fn err<E: std::fmt::Display>(e: E) -> Error {
+    Error::SinkError(format!("webhook: {}", e))
+}
+
+/// Default attempts of a request
+const ATTEMPTS: u32 = 3;
+/// Default first delay between attempts, doubled after each
+const BACKOFF: Duration = Duration::from_secs(1);
+/// Longest delay between attempts, also caps `Retry-After`
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sink POSTing every measurement to a URL
+///
+/// # Example
+/// ```no_run
+/// use sds011::sink::webhook::Webhook;
+/// use sds011::sink::Sink;
+/// use sds011::SDS011;
+/// use std::time::Duration;
+///
+/// let mut sensor = SDS011::open("/dev/ttyUSB0").unwrap();
+/// let mut webhook = Webhook::new("https://example.com/hooks/air")
+///     .header("Authorization", "Bearer s3cret")
+///     .field("room", "kitchen")
+///     .attempts(5, Duration::from_secs(2));
+/// loop {
+///     webhook.send(&sensor.query().unwrap()).unwrap();
+/// }
+/// ```
+pub struct Webhook {
+    url: String,
+    headers: Vec<(String, String)>,
+    fields: Map<String, Value>,
+    attempts: u32,
+    backoff: Duration,
+    #[cfg(feature = "encryption")]
+    encryptor: Option<Encryptor>,
+    agent: ureq::Agent,
+}
+
+impl Webhook {
+    /// POSTs to `url`
+    pub fn new(url: &str) -> Webhook {
+        Webhook {
+            url: url.to_string(),
+            headers: Vec::new(),
+            fields: Map::new(),
+            attempts: ATTEMPTS,
+            backoff: BACKOFF,
+            #[cfg(feature = "encryption")]
+            encryptor: None,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Sets a header on every request, replacing an earlier one of the
+    /// same name, including `Content-Type`
+    pub fn header(mut self, name: &str, value: &str) -> Webhook {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds a field to every measurement, e.g. the device ID
+    pub fn field<V: Into<Value>>(mut self, name: &str, value: V) -> Webhook {
+        self.fields.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Tries a request `attempts` times, waiting `backoff` before the
+    /// second and twice as long before each next one, by default 3 times
+    /// starting at a second
+    pub fn attempts(mut self, attempts: u32, backoff: Duration) -> Webhook {
+        self.attempts = attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    /// Seals bodies to the receiver's key, see `encryption`; they are
+    /// sent base64 encoded as `text/plain`
+    #[cfg(feature = "encryption")]
+    pub fn encrypt(mut self, encryptor: Encryptor) -> Webhook {
+        self.encryptor = Some(encryptor);
+        self
+    }
+
+    /// URL requests are sent to
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    #[cfg(feature = "encryption")]
+    fn body(&self, json: String) -> Result<(String, &'static str)> {
+        match &self.encryptor {
+            Some(encryptor) => Ok((encryptor.seal(json.as_bytes())?, "text/plain")),
+            None => Ok((json, "application/json")),
+        }
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn body(&self, json: String) -> Result<(String, &'static str)> {
+        Ok((json, "application/json"))
+    }
+
+    /// POSTs `json`, retrying with backoff
+    fn post(&self, json: String) -> Result<()> {
+        let (body, content_type) = self.body(json)?;
+        let mut delay = self.backoff;
+        let mut attempt = 1;
+        loop {
+            let mut request = self.agent.post(&self.url).set("Content-Type", content_type);
+            for (name, value) in self.headers.iter() {
+                request = request.set(name, value);
+            }
+            let wait = match request.send_string(&body) {
+                Ok(_) => return Ok(()),
+                // Sending the same body again won't help, except after a
+                // timeout or being throttled
+                Err(ureq::Error::Status(code, response))
+                    if code < 500 && code != 408 && code != 429 =>
+                {
+                    let message = response.into_string().unwrap_or_default();
+                    return Err(err(format!("rejected with {}: {}", code, message.trim())));
+                }
+                Err(e) if attempt >= self.attempts => {
+                    return Err(err(format!("{} after {} attempts", describe(&e), attempt)))
+                }
+                Err(ureq::Error::Status(code, response)) => {
+                    log::warn!("webhook: HTTP status {}, attempt {}", code, attempt);
+                    response
+                        .header("Retry-After")
+                        .and_then(|secs| secs.trim().parse().ok())
+                        .map(Duration::from_secs)
+                        .unwrap_or(delay)
+                }
+                Err(e) => {
+                    log::warn!("webhook: {}, attempt {}", describe(&e), attempt);
+                    delay
+                }
+            };
+            thread::sleep(wait.min(MAX_BACKOFF));
+            delay = (delay * 2).min(MAX_BACKOFF);
+            attempt += 1;
+        }
+    }
+}
+
+/// Describes a failed request without its URL, which may hold a secret
+fn describe(e: &ureq::Error) -> String {
+    match e {
+        ureq::Error::Status(code, _) => format!("HTTP status {}", code),
+        ureq::Error::Transport(t) => match t.message() {
+            Some(message) => format!("{}: {}", t.kind(), message),
+            None => t.kind().to_string(),
+        },
+    }
+}
+
+impl Sink for Webhook {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        self.post(super::with_fields(schema::to_json(m)?, &self.fields))
+    }
+
+    fn event(&mut self, event: &Event) -> Result<()> {
+        self.post(schema::to_json(event)?)
+    }
+}
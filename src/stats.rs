@@ -0,0 +1,28 @@
+//! Counters of the traffic with a sensor, to tell a degrading sensor from a
+//! flaky cable in long-running deployments.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// What happened on the link to a sensor since it was opened, see
+/// `SDS011::stats()`
+///
+/// Checksum failures and resyncs point at the line, e.g. a long cable or a
+/// bad adapter, timeouts at a sensor that stopped answering.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Stats {
+    /// Readings requested with `query()`
+    pub queries: u64,
+    /// Frames dropped for a wrong checksum
+    pub checksum_failures: u64,
+    /// Replies that didn't arrive within the read timeout
+    pub timeouts: u64,
+    /// Extra frames read waiting for a command's reply, e.g. readings the
+    /// sensor sent on its own in active mode
+    pub retries: u64,
+    /// Times bytes had to be skipped to find the start of a frame
+    pub resyncs: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
@@ -0,0 +1,348 @@
+//! SQLite storage of readings.
+//!
+//! A `Store` keeps readings in a local database file that survives
+//! reboots and can be queried by time range and device, or summarized
+//! per interval, without loading it into memory. The database is in WAL
+//! mode and synced according to a `durable::SyncPolicy`, so a power cut
+//! loses at most the readings since the last sync and never corrupts it.
+//!
+//! The schema is plain enough to query with the `sqlite3` shell:
+//!
+//! ```sql
+//! CREATE TABLE readings (
+//!     timestamp INTEGER NOT NULL, -- UNIX seconds
+//!     device_id INTEGER,          -- NULL when unknown
+//!     pm25 REAL NOT NULL,         -- µg/m³
+//!     pm10 REAL NOT NULL          -- µg/m³
+//! );
+//! CREATE INDEX readings_timestamp ON readings (timestamp);
+//! CREATE INDEX readings_device ON readings (device_id, timestamp);
+//! ```
+//!
+//! Its version is kept in `PRAGMA user_version`, a database written by
+//! a newer version of this crate is refused.
+
+use crate::aggregate::{Stats, Summary};
+use crate::durable::SyncPolicy;
+use crate::sink::Sink;
+use crate::{time, Error, Message, MicrogramsPerCubicMeter, Result};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, Row};
+use std::time::{Duration, Instant};
+
+fn err<E: std::fmt::Display>(path: &str, e: E) -> Error {
+    Error::StorageError(format!("{}: {}", path, e))
+}
+
+/// Version of the schema written by this crate
+const VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS readings (
+    timestamp INTEGER NOT NULL,
+    device_id INTEGER,
+    pm25 REAL NOT NULL,
+    pm10 REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS readings_timestamp ON readings (timestamp);
+CREATE INDEX IF NOT EXISTS readings_device ON readings (device_id, timestamp);
+PRAGMA user_version = 1;
+";
+
+/// How long a write waits for a reader, e.g. the `sqlite3` shell, to
+/// release the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A stored reading
+#[derive(Debug, PartialEq, Clone)]
+pub struct Record {
+    /// Device ID of the sensor, if it was known
+    pub device_id: Option<u16>,
+    pub message: Message,
+}
+
+/// Readings database
+///
+/// Queries take UNIX seconds, `from` inclusive and `to` exclusive, and a
+/// device ID, `None` to include every device.
+///
+/// # Example
+/// ```
+/// use sds011::store::Store;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let path = std::env::temp_dir().join("sds011-store-example.db");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut store = Store::open(path.to_str().unwrap()).unwrap();
+///
+/// for (secs, pm25) in [(0, 4.0), (60, 6.0), (3600, 10.0)].iter() {
+///     let m = Message { timestamp: UNIX_EPOCH + Duration::from_secs(1587384000 + secs), pm25: MicrogramsPerCubicMeter(*pm25), pm10: MicrogramsPerCubicMeter(8.0) };
+///     store.insert(&m, Some(0xa160)).unwrap();
+/// }
+///
+/// let first_hour = store.range(Some(0xa160), 1587384000, 1587387600).unwrap();
+/// assert_eq!(first_hour.len(), 2);
+///
+/// let hourly = store.buckets(None, 1587384000, 1587391200, Duration::from_secs(3600)).unwrap();
+/// assert_eq!(hourly.len(), 2);
+/// assert_eq!(hourly[0].pm25.mean, 5.0);
+/// assert_eq!(hourly[1].count, 1);
+/// ```
+pub struct Store {
+    conn: Connection,
+    path: String,
+    device_id: Option<u16>,
+    policy: SyncPolicy,
+    synced: Instant,
+}
+
+impl Store {
+    /// Opens or creates the database at `path`, syncing every reading
+    pub fn open(path: &str) -> Result<Store> {
+        Store::open_with(path, SyncPolicy::Always)
+    }
+
+    /// Opens or creates the database at `path`, syncing readings
+    /// according to `policy`
+    pub fn open_with(path: &str, policy: SyncPolicy) -> Result<Store> {
+        let conn = Connection::open(path).map_err(|e| err(path, e))?;
+        Store::init(conn, path, policy)
+    }
+
+    /// Creates a database in memory, lost when the store is dropped
+    pub fn in_memory() -> Result<Store> {
+        let conn = Connection::open_in_memory().map_err(|e| err(":memory:", e))?;
+        Store::init(conn, ":memory:", SyncPolicy::Never)
+    }
+
+    fn init(conn: Connection, path: &str, policy: SyncPolicy) -> Result<Store> {
+        let e = |e| err(path, e);
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(e)?;
+        let version: i64 = conn
+            .query_row("PRAGMA user_version", [], |r| r.get(0))
+            .map_err(e)?;
+        if version > VERSION {
+            return Err(err(
+                path,
+                format!("schema version {} is newer than this build", version),
+            ));
+        }
+        let _: String = conn
+            .query_row("PRAGMA journal_mode = WAL", [], |r| r.get(0))
+            .map_err(e)?;
+        // In WAL mode NORMAL only syncs on checkpoints, which `sync()` runs
+        let synchronous = match policy {
+            SyncPolicy::Always => "FULL",
+            SyncPolicy::Interval(_) => "NORMAL",
+            SyncPolicy::Never => "OFF",
+        };
+        conn.pragma_update(None, "synchronous", synchronous)
+            .map_err(e)?;
+        conn.execute_batch(SCHEMA).map_err(e)?;
+
+        Ok(Store {
+            conn,
+            path: path.to_string(),
+            device_id: None,
+            policy,
+            synced: Instant::now(),
+        })
+    }
+
+    /// Device ID stored with readings sent through `Sink::send()`
+    pub fn device_id(mut self, id: u16) -> Store {
+        self.device_id = Some(id);
+        self
+    }
+
+    /// Path of the database file
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Stores a reading
+    pub fn insert(&mut self, m: &Message, device_id: Option<u16>) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO readings (timestamp, device_id, pm25, pm10) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    secs(m.timestamp_secs().unwrap_or(0)),
+                    device_id,
+                    real(m.pm25.value()),
+                    real(m.pm10.value())
+                ],
+            )
+            .map_err(|e| err(&self.path, e))?;
+        if let SyncPolicy::Interval(interval) = self.policy {
+            if self.synced.elapsed() >= interval {
+                self.sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Readings taken from `from` until `to`, oldest first
+    pub fn range(&self, device_id: Option<u16>, from: u64, to: u64) -> Result<Vec<Record>> {
+        let e = |e| err(&self.path, e);
+        let mut statement = self
+            .conn
+            .prepare_cached(
+                "SELECT timestamp, device_id, pm25, pm10 FROM readings
+                 WHERE timestamp >= ?1 AND timestamp < ?2 AND (?3 IS NULL OR device_id = ?3)
+                 ORDER BY timestamp",
+            )
+            .map_err(e)?;
+        let rows = statement
+            .query_map(params![secs(from), secs(to), device_id], record)
+            .map_err(e)?;
+        rows.collect::<rusqlite::Result<Vec<Record>>>().map_err(e)
+    }
+
+    /// Statistics of the readings taken from `from` until `to`, `None` if
+    /// there are none
+    pub fn stats(&self, device_id: Option<u16>, from: u64, to: u64) -> Result<Option<Stats>> {
+        let width = to.saturating_sub(from).max(1);
+        let mut stats = self.aggregate(device_id, from, to, from, width)?;
+        Ok(stats.pop().map(|s| Stats {
+            start: from,
+            end: to,
+            ..s
+        }))
+    }
+
+    /// Statistics of the readings taken from `from` until `to` in windows
+    /// of `width` aligned to the UNIX epoch, like `aggregate::Tumbling`
+    /// Windows without readings are left out.
+    pub fn buckets(
+        &self,
+        device_id: Option<u16>,
+        from: u64,
+        to: u64,
+        width: Duration,
+    ) -> Result<Vec<Stats>> {
+        self.aggregate(device_id, from, to, 0, width.as_secs().max(1))
+    }
+
+    /// Statistics in windows of `width` seconds starting at `origin`
+    fn aggregate(
+        &self,
+        device_id: Option<u16>,
+        from: u64,
+        to: u64,
+        origin: u64,
+        width: u64,
+    ) -> Result<Vec<Stats>> {
+        let e = |e| err(&self.path, e);
+        let mut statement = self
+            .conn
+            .prepare_cached(
+                "SELECT (timestamp - ?4) / ?5, COUNT(*),
+                        MIN(pm25), MAX(pm25), AVG(pm25), MIN(pm10), MAX(pm10), AVG(pm10)
+                 FROM readings
+                 WHERE timestamp >= ?1 AND timestamp < ?2 AND (?3 IS NULL OR device_id = ?3)
+                 GROUP BY 1 ORDER BY 1",
+            )
+            .map_err(e)?;
+        let rows = statement
+            .query_map(
+                params![secs(from), secs(to), device_id, secs(origin), secs(width)],
+                |r| {
+                    let start = origin + r.get::<_, i64>(0)? as u64 * width;
+                    let summary = |i| -> rusqlite::Result<Summary> {
+                        Ok(Summary {
+                            min: r.get::<_, f64>(i)? as f32,
+                            max: r.get::<_, f64>(i + 1)? as f32,
+                            mean: r.get::<_, f64>(i + 2)? as f32,
+                        })
+                    };
+                    Ok(Stats {
+                        start,
+                        end: start + width,
+                        count: r.get::<_, i64>(1)? as usize,
+                        pm25: summary(2)?,
+                        pm10: summary(5)?,
+                    })
+                },
+            )
+            .map_err(e)?;
+        rows.collect::<rusqlite::Result<Vec<Stats>>>().map_err(e)
+    }
+
+    /// Device IDs with stored readings
+    pub fn devices(&self) -> Result<Vec<u16>> {
+        let e = |e| err(&self.path, e);
+        let mut statement = self
+            .conn
+            .prepare_cached(
+                "SELECT DISTINCT device_id FROM readings WHERE device_id IS NOT NULL ORDER BY 1",
+            )
+            .map_err(e)?;
+        let rows = statement.query_map([], |r| r.get(0)).map_err(e)?;
+        rows.collect::<rusqlite::Result<Vec<u16>>>().map_err(e)
+    }
+
+    /// Number of stored readings
+    pub fn count(&self) -> Result<u64> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM readings", [], |r| r.get::<_, i64>(0))
+            .map(|n| n as u64)
+            .map_err(|e| err(&self.path, e))
+    }
+
+    /// Deletes readings taken before `before`, returning how many, so a
+    /// long-running logger can keep the database within a retention period
+    pub fn prune(&mut self, before: u64) -> Result<usize> {
+        self.conn
+            .execute(
+                "DELETE FROM readings WHERE timestamp < ?1",
+                params![secs(before)],
+            )
+            .map_err(|e| err(&self.path, e))
+    }
+
+    /// Checkpoints the write-ahead log into the database, syncing it
+    pub fn sync(&mut self) -> Result<()> {
+        self.synced = Instant::now();
+        self.conn
+            .query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |_| Ok(()))
+            .map_err(|e| err(&self.path, e))
+    }
+}
+
+/// Converts UNIX seconds to an SQLite integer, clamping times too far
+/// in the future to fit
+fn secs(t: u64) -> i64 {
+    t.min(i64::MAX as u64) as i64
+}
+
+/// Reads a `Record` from a `timestamp, device_id, pm25, pm10` row
+fn record(r: &Row) -> rusqlite::Result<Record> {
+    let secs: i64 = r.get(0)?;
+    let timestamp = time::from_unix(secs.max(0) as u64)
+        .ok_or(rusqlite::Error::IntegralValueOutOfRange(0, secs))?;
+    Ok(Record {
+        device_id: r.get(1)?,
+        message: Message {
+            timestamp,
+            pm25: MicrogramsPerCubicMeter(r.get::<_, f64>(2)? as f32),
+            pm10: MicrogramsPerCubicMeter(r.get::<_, f64>(3)? as f32),
+        },
+    })
+}
+
+/// Widens a reading to the nearest `f64` of its decimal form, so `11.1`
+/// is stored as `11.1` rather than `11.100000381469727`
+fn real(v: f32) -> Value {
+    Value::Real(v.to_string().parse().unwrap_or(v as f64))
+}
+
+impl Sink for Store {
+    fn send(&mut self, m: &Message) -> Result<()> {
+        self.insert(m, self.device_id)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sync()
+    }
+}
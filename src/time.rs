@@ -0,0 +1,144 @@
+//! Calendar helpers for UNIX timestamps.
+
+#[cfg(feature = "serde")]
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Latest timestamp accepted, the end of year 9999, past which dates can't
+/// be formatted
+pub const MAX_SECS: u64 = 253_402_300_799;
+
+/// Current time truncated to whole seconds, the resolution of timestamps
+pub fn now() -> SystemTime {
+    to_unix(SystemTime::now())
+        .and_then(from_unix)
+        .unwrap_or(UNIX_EPOCH)
+}
+
+/// Converts UNIX seconds to a `SystemTime`, `None` after `MAX_SECS`
+pub fn from_unix(secs: u64) -> Option<SystemTime> {
+    if secs > MAX_SECS {
+        return None;
+    }
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Converts a `SystemTime` to UNIX seconds, `None` before 1970
+pub fn to_unix(t: SystemTime) -> Option<u64> {
+    t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Formats UNIX seconds as an RFC 3339 UTC date, e.g. `2020-04-20T12:00:00Z`
+/// Later dates are formatted as `MAX_SECS`
+#[cfg(feature = "serde")]
+pub fn to_rfc3339(secs: u64) -> String {
+    DateTime::<Utc>::from_timestamp(secs.min(MAX_SECS) as i64, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Serde format of timestamps: UNIX seconds as a number
+/// Strings of digits, the format before schema version 2, and fractional
+/// seconds are accepted when reading
+#[cfg(feature = "serde")]
+pub mod unix_secs {
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    const OUT_OF_RANGE: &str = "timestamp out of range";
+
+    pub fn serialize<S: Serializer>(t: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(super::to_unix(*t).unwrap_or(0))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        deserializer.deserialize_any(SecsVisitor)
+    }
+
+    struct SecsVisitor;
+
+    impl<'de> Visitor<'de> for SecsVisitor {
+        type Value = SystemTime;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("UNIX seconds")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<SystemTime, E> {
+            super::from_unix(v).ok_or_else(|| E::custom(OUT_OF_RANGE))
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<SystemTime, E> {
+            if v < 0 {
+                return Err(E::custom("timestamp before 1970"));
+            }
+            self.visit_u64(v as u64)
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<SystemTime, E> {
+            if v < 0.0 {
+                return Err(E::custom("timestamp before 1970"));
+            }
+            Duration::try_from_secs_f64(v)
+                .ok()
+                .filter(|d| d.as_secs() <= super::MAX_SECS)
+                .and_then(|d| UNIX_EPOCH.checked_add(d))
+                .ok_or_else(|| E::custom(OUT_OF_RANGE))
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<SystemTime, E> {
+            match v.trim().parse::<u64>() {
+                Ok(secs) => self.visit_u64(secs),
+                Err(_) => self.visit_f64(v.trim().parse().map_err(E::custom)?),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use crate::Message;
+    use std::time::UNIX_EPOCH;
+
+    fn timestamp(json: &str) -> Result<u64, String> {
+        let record = format!(r#"{{"timestamp":{},"pm25":1.0,"pm10":2.0}}"#, json);
+        serde_json::from_str::<Message>(&record)
+            .map(|m| m.timestamp.duration_since(UNIX_EPOCH).unwrap().as_secs())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn accepts_numbers_and_strings() {
+        assert_eq!(timestamp("1587384000"), Ok(1_587_384_000));
+        assert_eq!(timestamp("1587384000.5"), Ok(1_587_384_000));
+        assert_eq!(timestamp(r#""1587384000""#), Ok(1_587_384_000));
+        assert_eq!(timestamp(&super::MAX_SECS.to_string()), Ok(super::MAX_SECS));
+    }
+
+    #[test]
+    fn rejects_out_of_range_without_panicking() {
+        for json in [
+            "18446744073709551615",
+            "253402300800",
+            "-1",
+            "-0.5",
+            "1e300",
+            r#""18446744073709551615""#,
+            r#""1e300""#,
+            r#""NaN""#,
+            r#""inf""#,
+        ]
+        .iter()
+        {
+            assert!(timestamp(json).is_err(), "{} was accepted", json);
+        }
+    }
+
+    #[test]
+    fn formats_out_of_range_dates() {
+        assert_eq!(super::to_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(super::to_rfc3339(u64::MAX), "9999-12-31T23:59:59Z");
+    }
+}
@@ -0,0 +1,142 @@
+//! Configurable formatting of measurement timestamps.
+//!
+//! Downstream systems want different formats: InfluxDB takes UNIX
+//! seconds, spreadsheets a local date and sensor.community RFC 3339.
+
+use crate::{Error, Message, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// How a timestamp is written
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Format {
+    /// UNIX seconds, e.g. `1587384000`
+    Unix,
+    /// RFC 3339, e.g. `2020-04-20T12:00:00Z`
+    Rfc3339,
+    /// strftime pattern, e.g. `%d.%m.%Y %H:%M`
+    Strftime(String),
+}
+
+/// Time zone of formatted timestamps, ignored by `Format::Unix`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Zone {
+    Utc,
+    /// Time zone of the host
+    Local,
+}
+
+/// Timestamp format with its time zone, UNIX seconds by default
+///
+/// Parses from `unix`, `rfc3339` or a strftime pattern.
+///
+/// # Example
+/// ```
+/// use sds011::timestamp::TimestampFormat;
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let m = Message { timestamp: UNIX_EPOCH + Duration::from_secs(1587384000), pm25: MicrogramsPerCubicMeter(4.0), pm10: MicrogramsPerCubicMeter(8.0) };
+///
+/// let rfc3339: TimestampFormat = "rfc3339".parse().unwrap();
+/// assert_eq!(m.format_timestamp(&rfc3339), "2020-04-20T12:00:00Z");
+///
+/// let sheet = TimestampFormat::strftime("%d.%m.%Y %H:%M").unwrap();
+/// assert_eq!(m.format_timestamp(&sheet), "20.04.2020 12:00");
+///
+/// assert!(TimestampFormat::strftime("%Q").is_err());
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TimestampFormat {
+    format: Format,
+    zone: Zone,
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        TimestampFormat {
+            format: Format::Unix,
+            zone: Zone::Utc,
+        }
+    }
+}
+
+impl TimestampFormat {
+    /// UNIX seconds
+    pub fn unix() -> TimestampFormat {
+        TimestampFormat::default()
+    }
+
+    /// RFC 3339 in UTC
+    pub fn rfc3339() -> TimestampFormat {
+        TimestampFormat {
+            format: Format::Rfc3339,
+            zone: Zone::Utc,
+        }
+    }
+
+    /// strftime `pattern` in UTC, fails on unknown specifiers
+    pub fn strftime(pattern: &str) -> Result<TimestampFormat> {
+        if StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) {
+            return Err(Error::TimestampFormatError(format!(
+                "invalid strftime pattern \"{}\"",
+                pattern
+            )));
+        }
+        Ok(TimestampFormat {
+            format: Format::Strftime(pattern.to_string()),
+            zone: Zone::Utc,
+        })
+    }
+
+    /// Writes dates in `zone` instead of UTC
+    pub fn zone(mut self, zone: Zone) -> TimestampFormat {
+        self.zone = zone;
+        self
+    }
+
+    /// Format of this timestamp format
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
+    /// Formats `t`
+    pub fn apply(&self, t: SystemTime) -> String {
+        match (&self.format, self.zone) {
+            (Format::Unix, _) => crate::time::to_unix(t).unwrap_or(0).to_string(),
+            (Format::Rfc3339, Zone::Utc) => {
+                DateTime::<Utc>::from(t).to_rfc3339_opts(SecondsFormat::AutoSi, true)
+            }
+            (Format::Rfc3339, Zone::Local) => {
+                DateTime::<Local>::from(t).to_rfc3339_opts(SecondsFormat::AutoSi, false)
+            }
+            (Format::Strftime(p), Zone::Utc) => DateTime::<Utc>::from(t).format(p).to_string(),
+            (Format::Strftime(p), Zone::Local) => DateTime::<Local>::from(t).format(p).to_string(),
+        }
+    }
+}
+
+impl FromStr for TimestampFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<TimestampFormat> {
+        match s {
+            "unix" => Ok(TimestampFormat::unix()),
+            "rfc3339" => Ok(TimestampFormat::rfc3339()),
+            p if p.contains('%') => TimestampFormat::strftime(p),
+            _ => Err(Error::TimestampFormatError(format!(
+                "\"{}\": expected unix, rfc3339 or a strftime pattern",
+                s
+            ))),
+        }
+    }
+}
+
+impl Message {
+    /// Timestamp written in `format`
+    pub fn format_timestamp(&self, format: &TimestampFormat) -> String {
+        format.apply(self.timestamp)
+    }
+}
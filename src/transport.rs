@@ -0,0 +1,310 @@
+//! Byte transports a sensor can be reached through.
+
+use crate::Result;
+use serialport::{DataBits, FlowControl, Parity, SerialPort, SerialPortSettings, StopBits};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Read timeout sensors are opened with
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Bidirectional byte stream to a sensor
+pub trait Transport: Read + Write + Send {
+    /// Sets the read timeout
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()>;
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        SerialPort::set_timeout(self.as_mut(), timeout)?;
+        Ok(())
+    }
+}
+
+impl Transport for TcpStream {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        Ok(())
+    }
+}
+
+/// Opens a serial port with the sensor's 9600 8N1 settings
+pub fn serial(port: &str, timeout: Duration) -> Result<Box<dyn SerialPort>> {
+    let s = SerialPortSettings {
+        baud_rate: 9600,
+        data_bits: DataBits::Eight,
+        flow_control: FlowControl::None,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+        timeout,
+    };
+
+    Ok(serialport::open_with_settings(port, &s)?)
+}
+
+/// Opens a serial port or, for `tcp://host:port` and `rfc2217://host:port`,
+/// a network connection
+pub fn open(target: &str, timeout: Duration) -> Result<Box<dyn Transport>> {
+    if let Some(addr) = target.strip_prefix("tcp://") {
+        Ok(Box::new(tcp(addr, timeout)?))
+    } else if let Some(addr) = target.strip_prefix("rfc2217://") {
+        Ok(Box::new(Rfc2217::connect(addr, timeout)?))
+    } else {
+        Ok(Box::new(serial(target, timeout)?))
+    }
+}
+
+/// Opens a raw TCP connection, e.g. to ser2net in `raw` mode or ESP-Link
+pub fn tcp<A: ToSocketAddrs>(addr: A, timeout: Duration) -> Result<TcpStream> {
+    let stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const BINARY: u8 = 0;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const COM_PORT_OPTION: u8 = 44;
+
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+
+enum State {
+    Data,
+    Iac,
+    Option(u8),
+    Sub,
+    SubIac,
+}
+
+/// RFC 2217 (Telnet COM port control) client
+///
+/// Negotiates 9600 8N1 with the server and handles Telnet escaping,
+/// e.g. for ser2net in `telnet` mode with `remctl` enabled.
+pub struct Rfc2217 {
+    stream: TcpStream,
+    state: State,
+    buf: Vec<u8>,
+}
+
+impl Rfc2217 {
+    /// Connects and configures the remote serial port
+    pub fn connect<A: ToSocketAddrs>(addr: A, timeout: Duration) -> Result<Rfc2217> {
+        let mut stream = tcp(addr, timeout)?;
+
+        let mut negotiation = vec![
+            IAC,
+            WILL,
+            BINARY,
+            IAC,
+            DO,
+            BINARY,
+            IAC,
+            WILL,
+            COM_PORT_OPTION,
+        ];
+        let baud = 9600u32.to_be_bytes();
+        for (cmd, value) in [
+            (SET_BAUDRATE, &baud[..]),
+            (SET_DATASIZE, &[8u8][..]),
+            // 1 means no parity and one stop bit
+            (SET_PARITY, &[1u8][..]),
+            (SET_STOPSIZE, &[1u8][..]),
+        ]
+        .iter()
+        {
+            negotiation.extend_from_slice(&[IAC, SB, COM_PORT_OPTION, *cmd]);
+            negotiation.extend_from_slice(value);
+            negotiation.extend_from_slice(&[IAC, SE]);
+        }
+        stream.write_all(&negotiation)?;
+
+        Ok(Rfc2217 {
+            stream,
+            state: State::Data,
+            buf: vec![0u8; 256],
+        })
+    }
+
+    /// Answers an option request, accepting only the options we use
+    fn negotiate(&mut self, cmd: u8, option: u8) -> io::Result<()> {
+        let reply = match (cmd, option) {
+            (DO, BINARY) | (DO, COM_PORT_OPTION) => return Ok(()),
+            (WILL, BINARY) | (WILL, SUPPRESS_GO_AHEAD) | (WILL, COM_PORT_OPTION) => return Ok(()),
+            (DO, _) => WONT,
+            (WILL, _) => DONT,
+            _ => return Ok(()),
+        };
+        self.stream.write_all(&[IAC, reply, option])
+    }
+}
+
+impl Read for Rfc2217 {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let max = out.len().min(self.buf.len());
+            let n = self.stream.read(&mut self.buf[..max])?;
+            if n == 0 {
+                return Ok(0);
+            }
+
+            let mut written = 0;
+            for i in 0..n {
+                let b = self.buf[i];
+                self.state = match self.state {
+                    State::Data if b == IAC => State::Iac,
+                    State::Data => {
+                        out[written] = b;
+                        written += 1;
+                        State::Data
+                    }
+                    State::Iac => match b {
+                        IAC => {
+                            out[written] = IAC;
+                            written += 1;
+                            State::Data
+                        }
+                        SB => State::Sub,
+                        WILL | WONT | DO | DONT => State::Option(b),
+                        _ => State::Data,
+                    },
+                    State::Option(cmd) => {
+                        self.negotiate(cmd, b)?;
+                        State::Data
+                    }
+                    State::Sub if b == IAC => State::SubIac,
+                    State::Sub => State::Sub,
+                    State::SubIac if b == SE => State::Data,
+                    State::SubIac => State::Sub,
+                };
+            }
+
+            // Only Telnet commands were received, wait for data
+            if written > 0 {
+                return Ok(written);
+            }
+        }
+    }
+}
+
+impl Write for Rfc2217 {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut escaped = Vec::with_capacity(data.len() + 4);
+        for b in data.iter() {
+            if *b == IAC {
+                escaped.push(IAC);
+            }
+            escaped.push(*b);
+        }
+        self.stream.write_all(&escaped)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for Rfc2217 {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.stream.set_timeout(timeout)
+    }
+}
+
+/// Length of the frames the sensor sends
+const REPLY_LEN: usize = 10;
+
+/// Direction of a frame through a `Tap`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Transport passing every frame sent and received to an observer, e.g.
+/// to print hexdumps when debugging a sensor
+///
+/// Received bytes are handed over once a whole frame arrived, or when a
+/// read fails with a partial one.
+///
+/// # Example
+/// ```no_run
+/// use sds011::transport::{self, Tap};
+/// use sds011::SDS011;
+///
+/// let port = transport::open("/dev/ttyUSB0", transport::DEFAULT_TIMEOUT).unwrap();
+/// let tap = Tap::new(port, |direction, frame: &[u8]| println!("{:?} {:02x?}", direction, frame));
+/// let mut sensor = SDS011::from_transport(Box::new(tap)).unwrap();
+/// sensor.query().unwrap();
+/// ```
+pub struct Tap<F> {
+    inner: Box<dyn Transport>,
+    observer: F,
+    received: Vec<u8>,
+}
+
+impl<F: FnMut(Direction, &[u8]) + Send> Tap<F> {
+    /// Passes the frames through `inner` to `observer`
+    pub fn new(inner: Box<dyn Transport>, observer: F) -> Tap<F> {
+        Tap {
+            inner,
+            observer,
+            received: Vec::with_capacity(REPLY_LEN),
+        }
+    }
+
+    fn flush_received(&mut self) {
+        if !self.received.is_empty() {
+            (self.observer)(Direction::Received, &self.received);
+            self.received.clear();
+        }
+    }
+}
+
+impl<F: FnMut(Direction, &[u8]) + Send> Read for Tap<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = match self.inner.read(buf) {
+            Ok(n) => n,
+            Err(e) => {
+                self.flush_received();
+                return Err(e);
+            }
+        };
+        for byte in buf[..n].iter() {
+            self.received.push(*byte);
+            if self.received.len() == REPLY_LEN {
+                self.flush_received();
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<F: FnMut(Direction, &[u8]) + Send> Write for Tap<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        (self.observer)(Direction::Sent, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<F: FnMut(Direction, &[u8]) + Send> Transport for Tap<F> {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+}
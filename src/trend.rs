@@ -0,0 +1,99 @@
+//! Trend detection over recent measurements.
+//!
+//! The slope is a least squares fit of concentration against time, so a
+//! single spike moves it less than comparing the first and last readings.
+
+use crate::aqi::Pollutant;
+use crate::Message;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Direction of a trend
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Direction {
+    Rising,
+    Falling,
+    Stable,
+}
+
+/// Trend of one pollutant
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Trend {
+    pub pollutant: Pollutant,
+    pub direction: Direction,
+    /// Change rate in µg/m³ per hour
+    pub slope: f32,
+    /// Number of measurements the trend is based on
+    pub samples: usize,
+}
+
+/// Fits a trend of `pollutant` to `messages`
+///
+/// It's `Stable` while the slope stays within `threshold` µg/m³ per hour
+/// either way. Needs at least two measurements with valid, distinct
+/// timestamps.
+///
+/// # Example
+/// ```
+/// use sds011::aqi::Pollutant;
+/// use sds011::trend::{trend, Direction};
+/// use sds011::{Message, MicrogramsPerCubicMeter};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let readings: Vec<Message> = (0..4)
+///     .map(|i| Message { timestamp: UNIX_EPOCH + Duration::from_secs(i * 600), pm25: MicrogramsPerCubicMeter(10.0 + i as f32), pm10: MicrogramsPerCubicMeter(20.0) })
+///     .collect();
+///
+/// let t = trend(&readings, Pollutant::Pm25, 1.0).unwrap();
+/// assert_eq!(t.direction, Direction::Rising);
+/// assert_eq!(t.slope, 6.0);
+/// ```
+pub fn trend<'a, I>(messages: I, pollutant: Pollutant, threshold: f32) -> Option<Trend>
+where
+    I: IntoIterator<Item = &'a Message>,
+{
+    let points: Vec<(f64, f64)> = messages
+        .into_iter()
+        .filter_map(|m| {
+            let value = match pollutant {
+                Pollutant::Pm25 => m.pm25.value(),
+                Pollutant::Pm10 => m.pm10.value(),
+            };
+            m.timestamp_secs()
+                .map(|t| (t as f64 / 3600.0, value as f64))
+        })
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_v = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for (t, v) in points.iter() {
+        cov += (t - mean_t) * (v - mean_v);
+        var += (t - mean_t) * (t - mean_t);
+    }
+    if var == 0.0 {
+        return None;
+    }
+
+    let slope = (cov / var) as f32;
+    let direction = if slope > threshold {
+        Direction::Rising
+    } else if slope < -threshold {
+        Direction::Falling
+    } else {
+        Direction::Stable
+    };
+    Some(Trend {
+        pollutant,
+        direction,
+        slope,
+        samples: points.len(),
+    })
+}
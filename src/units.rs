@@ -0,0 +1,110 @@
+//! Units of measured values.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+
+/// Mass concentration in µg/m³, serialized as a plain number
+///
+/// Keeps concentrations from being mixed up with index values like AQI,
+/// which are unitless.
+///
+/// # Example
+/// ```
+/// use sds011::aqi;
+/// use sds011::MicrogramsPerCubicMeter;
+///
+/// let pm25 = MicrogramsPerCubicMeter(35.4);
+/// assert_eq!(aqi::pm25_us(pm25).value, 100);
+/// assert_eq!(pm25 * 2.0, MicrogramsPerCubicMeter(70.8));
+/// assert_eq!(pm25.to_string(), "35.4 µg/m³");
+/// ```
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct MicrogramsPerCubicMeter(pub f32);
+
+impl MicrogramsPerCubicMeter {
+    /// Value in µg/m³
+    pub fn value(self) -> f32 {
+        self.0
+    }
+
+    /// The larger of two concentrations
+    pub fn max(self, other: MicrogramsPerCubicMeter) -> MicrogramsPerCubicMeter {
+        MicrogramsPerCubicMeter(self.0.max(other.0))
+    }
+
+    /// The smaller of two concentrations
+    pub fn min(self, other: MicrogramsPerCubicMeter) -> MicrogramsPerCubicMeter {
+        MicrogramsPerCubicMeter(self.0.min(other.0))
+    }
+}
+
+impl fmt::Display for MicrogramsPerCubicMeter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} µg/m³", self.0)
+    }
+}
+
+impl From<MicrogramsPerCubicMeter> for f32 {
+    fn from(c: MicrogramsPerCubicMeter) -> f32 {
+        c.0
+    }
+}
+
+impl Add for MicrogramsPerCubicMeter {
+    type Output = MicrogramsPerCubicMeter;
+
+    fn add(self, other: MicrogramsPerCubicMeter) -> MicrogramsPerCubicMeter {
+        MicrogramsPerCubicMeter(self.0 + other.0)
+    }
+}
+
+impl AddAssign for MicrogramsPerCubicMeter {
+    fn add_assign(&mut self, other: MicrogramsPerCubicMeter) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for MicrogramsPerCubicMeter {
+    type Output = MicrogramsPerCubicMeter;
+
+    fn sub(self, other: MicrogramsPerCubicMeter) -> MicrogramsPerCubicMeter {
+        MicrogramsPerCubicMeter(self.0 - other.0)
+    }
+}
+
+/// Scaling, e.g. by a calibration factor
+impl Mul<f32> for MicrogramsPerCubicMeter {
+    type Output = MicrogramsPerCubicMeter;
+
+    fn mul(self, k: f32) -> MicrogramsPerCubicMeter {
+        MicrogramsPerCubicMeter(self.0 * k)
+    }
+}
+
+impl Div<f32> for MicrogramsPerCubicMeter {
+    type Output = MicrogramsPerCubicMeter;
+
+    fn div(self, k: f32) -> MicrogramsPerCubicMeter {
+        MicrogramsPerCubicMeter(self.0 / k)
+    }
+}
+
+/// Ratio of two concentrations, unitless
+impl Div for MicrogramsPerCubicMeter {
+    type Output = f32;
+
+    fn div(self, other: MicrogramsPerCubicMeter) -> f32 {
+        self.0 / other.0
+    }
+}
+
+impl Sum for MicrogramsPerCubicMeter {
+    fn sum<I: Iterator<Item = MicrogramsPerCubicMeter>>(iter: I) -> MicrogramsPerCubicMeter {
+        MicrogramsPerCubicMeter(iter.map(|c| c.0).sum())
+    }
+}
@@ -0,0 +1,82 @@
+//! WHO 2021 air quality guideline exceedances.
+//!
+//! The guideline values are defined for 24-hour and annual means, so they
+//! should be checked against aggregates of that length. Checking single
+//! readings only gives an early hint.
+
+use crate::aggregate::Stats;
+use crate::Message;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Averaging period of a guideline value
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Period {
+    Daily,
+    Annual,
+}
+
+impl Period {
+    /// `(PM2.5, PM10)` guideline values in µg/m³
+    pub fn guideline(self) -> (f32, f32) {
+        match self {
+            Period::Daily => (15.0, 45.0),
+            Period::Annual => (5.0, 15.0),
+        }
+    }
+}
+
+/// Result of checking concentrations against a guideline
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Exceedance {
+    pub period: Period,
+    /// PM2.5 is above the guideline value
+    pub pm25: bool,
+    /// PM10 is above the guideline value
+    pub pm10: bool,
+    /// PM2.5 as a multiple of the guideline value
+    pub pm25_ratio: f32,
+    /// PM10 as a multiple of the guideline value
+    pub pm10_ratio: f32,
+}
+
+impl Exceedance {
+    /// Checks mean concentrations in µg/m³ against the `period` guideline
+    ///
+    /// # Example
+    /// ```
+    /// use sds011::who::{Exceedance, Period};
+    ///
+    /// let e = Exceedance::check(30.0, 40.0, Period::Daily);
+    /// assert!(e.pm25 && !e.pm10);
+    /// assert_eq!(e.pm25_ratio, 2.0);
+    /// ```
+    pub fn check(pm25: f32, pm10: f32, period: Period) -> Exceedance {
+        let (pm25_limit, pm10_limit) = period.guideline();
+        Exceedance {
+            period,
+            pm25: pm25 > pm25_limit,
+            pm10: pm10 > pm10_limit,
+            pm25_ratio: pm25 / pm25_limit,
+            pm10_ratio: pm10 / pm10_limit,
+        }
+    }
+
+    /// Checks a single measurement against the `period` guideline
+    pub fn of_message(m: &Message, period: Period) -> Exceedance {
+        Exceedance::check(m.pm25.value(), m.pm10.value(), period)
+    }
+
+    /// Checks the means of a window, e.g. a day from `aggregate::Tumbling`
+    pub fn of_stats(stats: &Stats, period: Period) -> Exceedance {
+        Exceedance::check(stats.pm25.mean, stats.pm10.mean, period)
+    }
+
+    /// Returns `true` if any pollutant exceeds the guideline
+    pub fn any(&self) -> bool {
+        self.pm25 || self.pm10
+    }
+}
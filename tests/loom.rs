@@ -0,0 +1,50 @@
+//! Model checks of `SharedSensor`, run with
+//! `RUSTFLAGS="--cfg loom" cargo test --test loom --release`
+#![cfg(loom)]
+
+use loom::thread;
+use sds011::emulator::Emulator;
+use sds011::shared::SharedSensor;
+use sds011::SDS011;
+
+fn shared() -> SharedSensor {
+    let mut emulator = Emulator::new(0xa160);
+    emulator.set_reading(12.3, 45.6);
+    SharedSensor::new(SDS011::from_transport(Box::new(emulator)).unwrap())
+}
+
+/// Concurrent exchanges never interleave, so every reply is well formed
+/// and answers its own command
+#[test]
+fn exchanges_do_not_interleave() {
+    loom::model(|| {
+        let sensor = shared();
+
+        let other = sensor.clone();
+        let t = thread::spawn(move || {
+            assert_eq!(other.query().unwrap().pm25.value(), 12.3);
+            other.set_work_period(3).unwrap();
+        });
+
+        assert_eq!(sensor.device_id().unwrap(), 0xa160);
+        sensor.set_work_period(7).unwrap();
+        t.join().unwrap();
+    });
+}
+
+/// `exclusive()` sequences aren't split by other threads
+#[test]
+fn exclusive_sequences_are_atomic() {
+    loom::model(|| {
+        let sensor = shared();
+
+        let other = sensor.clone();
+        let t = thread::spawn(move || other.sleep().unwrap());
+
+        sensor.exclusive(|s| {
+            s.wake().unwrap();
+            assert!(s.query().is_ok());
+        });
+        t.join().unwrap();
+    });
+}